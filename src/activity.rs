@@ -0,0 +1,134 @@
+//! Live feed of the bot's activity, like new completions and posted reports, that is streamed to
+//! wallboard displays as server-sent events.
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{api::codewars::Level, stats::Stats};
+
+/// Amount of activities kept for subscribers that fall behind, before they miss any.
+const CAPACITY: usize = 64;
+
+/// A single activity of the bot.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Activity {
+    /// A user completed a challenge.
+    Completion {
+        username: String,
+        challenge: String,
+        rank: Option<Level>,
+        languages: Vec<String>,
+        completed_at: DateTime<Utc>,
+    },
+    /// A report was posted to the channel.
+    Report { kind: ReportKind, text: String },
+}
+
+impl Activity {
+    /// Name of the activity, used as event type in the stream.
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Completion { .. } => "completion",
+            Self::Report { .. } => "report",
+        }
+    }
+}
+
+/// The different reports that the bot posts.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportKind {
+    /// The scheduled statistics.
+    Stats,
+    /// A list of recently completed challenges.
+    Notification,
+    /// A digest of all challenges completed since the last one.
+    Digest,
+}
+
+/// Sender of activities to all current subscribers.
+#[derive(Clone)]
+pub struct Feed {
+    tx: broadcast::Sender<Activity>,
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+
+    /// Send an activity to all subscribers. Without any subscribers, it is dropped.
+    fn publish(&self, activity: Activity) {
+        self.tx.send(activity).ok();
+    }
+
+    /// Send all challenges listed in the statistics as completions.
+    pub fn publish_completions(&self, stats: &Stats) {
+        for user in &stats.users {
+            for challenge in &user.challenges {
+                self.publish(Activity::Completion {
+                    username: user.username.clone(),
+                    challenge: challenge.name.clone(),
+                    rank: challenge.rank,
+                    languages: challenge.languages.clone(),
+                    completed_at: challenge.completed_at,
+                });
+            }
+        }
+    }
+
+    /// Send a posted report.
+    pub fn publish_report(&self, kind: ReportKind, text: &str) {
+        self.publish(Activity::Report {
+            kind,
+            text: text.to_owned(),
+        });
+    }
+
+    /// Subscribe to all activities from now on, as server-sent events. Subscribers that fall too
+    /// far behind skip the activities they missed.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<warp::sse::Event, serde_json::Error>> {
+        futures::stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(activity) => return Some((activity, rx)),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Activity stream fell behind and missed {} events", missed);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .map(|activity| {
+            warp::sse::Event::default()
+                .event(activity.name())
+                .json_data(&activity)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stream_activities() {
+        let feed = Feed::new();
+        feed.publish_report(ReportKind::Stats, "before");
+
+        let mut stream = Box::pin(feed.subscribe());
+        feed.publish_report(ReportKind::Digest, "after");
+
+        let event = stream.next().await.unwrap().unwrap().to_string();
+        assert_eq!(
+            "event:report\ndata:{\"type\":\"report\",\"kind\":\"digest\",\"text\":\"after\"}\n\n",
+            event
+        );
+    }
+}
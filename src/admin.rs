@@ -0,0 +1,342 @@
+//! Admin API to manage the watchlist and settings over HTTP, for operators and scripts that don't
+//! want to go through Slack. Every request must carry the configured admin token as bearer token.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc::UnboundedSender, RwLock};
+use tracing::warn;
+use warp::http::StatusCode;
+
+use crate::{
+    api::codewars,
+    fetch::FetchCache,
+    lease::Leader,
+    scheduling::Frequency,
+    storage::{NotifyFilter, QuietHours, Repository, Schedule, UserInfo},
+};
+
+/// A single request to the admin API.
+#[derive(Debug)]
+pub enum Request {
+    /// List all watched users.
+    ListUsers,
+    /// Add a user to the watchlist.
+    AddUser(String),
+    /// Remove a user from the watchlist.
+    RemoveUser(String),
+    /// List all schedules.
+    GetSchedule,
+    /// Replace all schedules.
+    SetSchedule(Vec<Schedule>),
+    /// Show the current configuration.
+    GetConfig,
+    /// List the most recently executed commands, latest first, optionally limited to an amount.
+    GetAudit(Option<usize>),
+}
+
+impl Request {
+    /// Whether the request changes the state, which only the leader may do.
+    const fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Self::AddUser(_) | Self::RemoveUser(_) | Self::SetSchedule(_)
+        )
+    }
+}
+
+/// Parameter of requests that name a single user.
+#[derive(Debug, Deserialize)]
+pub struct UserParam {
+    pub username: String,
+}
+
+/// Parameters of the request for the audit log.
+#[derive(Debug, Deserialize)]
+pub struct AuditParam {
+    pub last: Option<usize>,
+}
+
+/// A watched user.
+#[derive(Serialize)]
+struct User<'a> {
+    username: &'a str,
+    #[serde(flatten)]
+    info: Option<&'a UserInfo>,
+}
+
+/// Current configuration of the bot.
+#[derive(Serialize)]
+struct Config<'a> {
+    users: Vec<User<'a>>,
+    schedules: &'a [Schedule],
+    notify: bool,
+    notify_interval_hours: u8,
+    digest_hours: Option<u32>,
+    quiet_hours: Option<QuietHours>,
+    notify_filter: &'a NotifyFilter,
+    exclude_retrains: bool,
+    maintenance: bool,
+    goal: Option<u32>,
+}
+
+/// Whether the value of an `Authorization` header carries the given bearer token. The hashes of
+/// both tokens are compared, so the time it takes doesn't reveal how much of the token was correct.
+pub fn bearer_matches(token: &str, authorization: Option<&str>) -> bool {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| Sha256::digest(value.as_bytes()) == Sha256::digest(token.as_bytes()))
+}
+
+/// Shared state of the admin API.
+#[derive(Clone)]
+pub struct Admin {
+    /// Token that requests must carry.
+    token: Arc<str>,
+    /// Persisted bot settings.
+    repo: Arc<RwLock<Repository>>,
+    /// Recently fetched Codewars data, to check that new users exist.
+    fetch: Arc<FetchCache>,
+    /// Sender to update the weekly stats schedules.
+    schedules: UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    /// Whether this instance is the leader and may change the state.
+    leader: Leader,
+}
+
+impl Admin {
+    /// Create the admin API state, that accepts requests with the given token.
+    pub fn new(
+        token: &str,
+        repo: Arc<RwLock<Repository>>,
+        fetch: Arc<FetchCache>,
+        schedules: UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+        leader: Leader,
+    ) -> Self {
+        Self {
+            token: token.into(),
+            repo,
+            fetch,
+            schedules,
+            leader,
+        }
+    }
+
+    /// Whether the value of an `Authorization` header carries the admin token.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        bearer_matches(&self.token, authorization)
+    }
+
+    /// Handle a request and create the status and JSON body of the response. Changes are rejected
+    /// while this instance is on standby.
+    pub async fn handle(&self, request: Request) -> Result<(StatusCode, Value)> {
+        if request.is_change() && !self.leader.is_leader() {
+            return Ok(error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "this instance is on standby",
+            ));
+        }
+
+        match request {
+            Request::ListUsers => Ok((StatusCode::OK, json!(users(&*self.repo.read().await)))),
+            Request::AddUser(username) => self.add_user(&username).await,
+            Request::RemoveUser(username) => {
+                Ok(if self.repo.write().await.remove_user(&username).await? {
+                    (StatusCode::NO_CONTENT, Value::Null)
+                } else {
+                    error(StatusCode::NOT_FOUND, "user isn't in the watchlist")
+                })
+            }
+            Request::GetSchedule => Ok((StatusCode::OK, json!(self.repo.read().await.schedules()))),
+            Request::SetSchedule(schedules) => self.set_schedule(schedules).await,
+            Request::GetConfig => Ok((StatusCode::OK, json!(config(&*self.repo.read().await)))),
+            Request::GetAudit(last) => {
+                let repo = self.repo.read().await;
+                let entries = repo
+                    .audit()
+                    .iter()
+                    .rev()
+                    .take(last.unwrap_or(usize::MAX))
+                    .collect::<Vec<_>>();
+                Ok((StatusCode::OK, json!(entries)))
+            }
+        }
+    }
+
+    /// Add a user after making sure it exists on Codewars, like the `add` command does.
+    async fn add_user(&self, username: &str) -> Result<(StatusCode, Value)> {
+        if let Some(user) = self.repo.read().await.find_user(username) {
+            return Ok((StatusCode::OK, json!({ "username": user })));
+        }
+
+        match self.fetch.user(username).await {
+            Err(codewars::Error::UserNotFound(_)) => {
+                return Ok(error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "user doesn't exist on Codewars",
+                ));
+            }
+            Err(e) => warn!("Error checking that user {} exists: {}", username, e),
+            Ok(_) => {}
+        }
+
+        let mut repo = self.repo.write().await;
+        let status = if repo.add_user(username, None).await? {
+            StatusCode::CREATED
+        } else {
+            StatusCode::OK
+        };
+
+        Ok((status, json!({ "username": repo.find_user(username) })))
+    }
+
+    /// Replace all schedules and update the scheduler right away.
+    async fn set_schedule(&self, schedules: Vec<Schedule>) -> Result<(StatusCode, Value)> {
+        if schedules
+            .iter()
+            .any(|s| matches!(s.frequency, Frequency::Monthly(day) if !(1..=31).contains(&day)))
+        {
+            return Ok(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "day must be between 1 and 31",
+            ));
+        }
+
+        let mut repo = self.repo.write().await;
+        if repo.set_schedules(schedules).await? {
+            self.schedules
+                .send(repo.schedules().iter().map(Schedule::input).collect())
+                .ok();
+        }
+
+        Ok((StatusCode::OK, json!(repo.schedules())))
+    }
+}
+
+/// Response with the given status and an error message.
+fn error(status: StatusCode, message: &str) -> (StatusCode, Value) {
+    (status, json!({ "error": message }))
+}
+
+/// All watched users with their details.
+fn users(repo: &Repository) -> Vec<User<'_>> {
+    repo.users()
+        .map(|username| User {
+            username,
+            info: repo.user_info(username),
+        })
+        .collect()
+}
+
+/// The current configuration of the bot.
+fn config(repo: &Repository) -> Config<'_> {
+    Config {
+        users: users(repo),
+        schedules: repo.schedules(),
+        notify: repo.notify(),
+        notify_interval_hours: repo.notify_interval(),
+        digest_hours: repo.digest_hours(),
+        quiet_hours: repo.quiet_hours(),
+        notify_filter: repo.notify_filter(),
+        exclude_retrains: repo.exclude_retrains(),
+        maintenance: repo.maintenance(),
+        goal: repo.goal(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Weekday;
+    use reqwest::Client;
+
+    use super::*;
+    use crate::api::codewars::CodewarsClient;
+
+    fn admin() -> (
+        Admin,
+        tokio::sync::mpsc::UnboundedReceiver<Vec<(Frequency, NaiveTime)>>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let fetch = FetchCache::new(CodewarsClient::new(Client::new()), Duration::ZERO, None);
+
+        (
+            Admin::new(
+                "secret",
+                Arc::new(RwLock::new(Repository::in_memory())),
+                Arc::new(fetch),
+                tx,
+                Leader::always(),
+            ),
+            rx,
+        )
+    }
+
+    #[test]
+    fn check_token() {
+        let (admin, _) = admin();
+
+        assert!(admin.authorized(Some("Bearer secret")));
+        assert!(!admin.authorized(Some("Bearer wrong")));
+        assert!(!admin.authorized(Some("secret")));
+        assert!(!admin.authorized(None));
+    }
+
+    #[tokio::test]
+    async fn manage_schedules_and_users() {
+        let (admin, mut rx) = admin();
+        let friday = Schedule {
+            frequency: Frequency::Weekly(Weekday::Fri),
+            time: NaiveTime::from_hms(16, 0, 0),
+        };
+
+        let (status, _) = admin
+            .handle(Request::SetSchedule(vec![friday]))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec![friday.input()], rx.try_recv().unwrap());
+
+        let invalid = Schedule {
+            frequency: Frequency::Monthly(32),
+            ..friday
+        };
+        let (status, _) = admin
+            .handle(Request::SetSchedule(vec![invalid]))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+
+        admin
+            .repo
+            .write()
+            .await
+            .add_user("him", None)
+            .await
+            .unwrap();
+        let (status, users) = admin.handle(Request::ListUsers).await.unwrap();
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("him", users[0]["username"]);
+        assert_eq!(Value::Null, users[0]["display_name"]);
+        assert!(users[0]["added_at"].is_string(), "{users}");
+
+        let (status, _) = admin
+            .handle(Request::RemoveUser("HIM".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        let (status, _) = admin
+            .handle(Request::RemoveUser("him".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let (_, config) = admin.handle(Request::GetConfig).await.unwrap();
+        assert_eq!(json!([]), config["users"]);
+        assert_eq!(json!("16:00:00"), config["schedules"][0]["time"]);
+    }
+}
@@ -1,14 +1,220 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    sync::{LazyLock, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use chrono::{DateTime, Utc};
-use once_cell::sync::Lazy;
-use serde::{de::DeserializeOwned, Deserialize};
+use futures::{stream, StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
+use tracing::warn;
 use url::Url;
 
-static BASE_URL: Lazy<Url> = Lazy::new(|| Url::parse("https://codewars.com/api/v1/").unwrap());
+static BASE_URL: LazyLock<Url> =
+    LazyLock::new(|| Url::parse("https://codewars.com/api/v1/").unwrap());
+static WEB_URL: LazyLock<Url> = LazyLock::new(|| Url::parse("https://www.codewars.com/").unwrap());
+
+/// Retry policy for all API calls, set once at start up.
+static RETRY: OnceLock<Retry> = OnceLock::new();
+
+/// Maximum amount of pages that are fetched at the same time, set once at start up.
+static MAX_IN_FLIGHT: OnceLock<usize> = OnceLock::new();
+
+/// Default amount of pages that are fetched at the same time.
+const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// Policy to retry failed API calls that might succeed on another attempt, like server errors or
+/// timeouts.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct Retry {
+    /// Amount of retries after the first failed attempt.
+    pub retries: u8,
+    /// Base delay in milliseconds before the first retry, doubled for each further retry.
+    pub delay_ms: u64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            delay_ms: 500,
+        }
+    }
+}
+
+/// Configure the retry policy for all API calls. Can only be set once, further calls are ignored.
+pub fn set_retry(retry: Retry) {
+    RETRY.set(retry).ok();
+}
+
+/// Configure how many pages are fetched at the same time, when fetching all pages of a paginated
+/// resource. Can only be set once, further calls are ignored.
+pub fn set_max_in_flight(max: usize) {
+    MAX_IN_FLIGHT.set(max.max(1)).ok();
+}
+
+/// Client for the Codewars API. The base URL defaults to the official API and can be changed, for
+/// example to run tests against a local mock server.
+#[derive(Clone, Debug)]
+pub struct CodewarsClient {
+    client: Client,
+    base_url: Url,
+}
+
+impl CodewarsClient {
+    /// Create a new client for the official Codewars API.
+    #[must_use]
+    pub fn new(client: Client) -> Self {
+        Self::with_base_url(client, BASE_URL.clone())
+    }
+
+    /// Create a new client that sends all API calls to the given base URL. The URL should end with
+    /// a slash, as the API paths are joined to it.
+    #[must_use]
+    pub const fn with_base_url(client: Client, base_url: Url) -> Self {
+        Self { client, base_url }
+    }
+
+    /// Get the profile of a user.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::UserNotFound`] if the user doesn't exist, or if the request fails.
+    pub async fn user(&self, username: &str) -> Result<User> {
+        self.get_user_data(username, self.url(&["users", username])?)
+            .await
+    }
+
+    /// Get a single page of the challenges that a user completed, latest first.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::UserNotFound`] if the user doesn't exist, or if the request fails.
+    pub async fn completed_challenges(
+        &self,
+        username: &str,
+        page: u32,
+    ) -> Result<CompletedChallenges> {
+        let mut url = self.url(&["users", username, "code-challenges", "completed"])?;
+        url.query_pairs_mut().append_pair("page", &page.to_string());
+
+        self.get_user_data(username, url).await
+    }
+
+    /// Get all the challenges that a user completed, latest first. The first page tells how many
+    /// pages there are, and the remaining pages are then fetched several at a time.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::UserNotFound`] if the user doesn't exist, or if any of the pages can't
+    /// be fetched.
+    pub async fn completed_challenges_all(&self, username: &str) -> Result<CompletedChallenges> {
+        let mut challenges = self.completed_challenges(username, 0).await?;
+        let max_in_flight = MAX_IN_FLIGHT
+            .get()
+            .copied()
+            .unwrap_or(DEFAULT_MAX_IN_FLIGHT);
+
+        let pages = stream::iter(1..challenges.total_pages)
+            .map(|page| self.completed_challenges(username, page))
+            .buffered(max_in_flight)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        for page in pages {
+            challenges.data.extend(page.data);
+        }
+
+        Ok(challenges)
+    }
+
+    /// Get the challenges that a user authored.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::UserNotFound`] if the user doesn't exist, or if the request fails.
+    pub async fn authored_challenges(&self, username: &str) -> Result<AuthoredChallenges> {
+        self.get_user_data(
+            username,
+            self.url(&["users", username, "code-challenges", "authored"])?,
+        )
+        .await
+    }
+
+    /// Get the details of a challenge by its slug or ID.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the challenge doesn't exist or the request fails.
+    pub async fn code_challenge(&self, slug_or_id: &str) -> Result<CodeChallenge> {
+        self.get_data(self.url(&["code-challenges", slug_or_id])?)
+            .await
+    }
+
+    /// Same as [`Self::get_data`], but for data of a user, where a missing resource means that the
+    /// user doesn't exist.
+    async fn get_user_data<T: DeserializeOwned>(&self, username: &str, url: Url) -> Result<T> {
+        match self.get_data(url).await {
+            Err(Error::UnsuccessfulStatus(404)) => Err(Error::UserNotFound(username.to_owned())),
+            res => res,
+        }
+    }
+
+    /// Build the URL of an API method from its path segments. Each segment is percent-encoded, so
+    /// usernames or slugs with characters like `/` or `?` can't change the path or add a query.
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .extend(segments);
+
+        Ok(url)
+    }
+
+    async fn get_data<T: DeserializeOwned>(&self, url: Url) -> Result<T> {
+        let retry = RETRY.get().copied().unwrap_or_default();
+        let mut delay = Duration::from_millis(retry.delay_ms);
+
+        for attempt in 0..=retry.retries {
+            match self.try_get_data(&url).await {
+                Err(e) if e.is_transient() && attempt < retry.retries => {
+                    warn!(
+                        "Request to `{}` failed (attempt {}), retrying: {}",
+                        url.path(),
+                        attempt + 1,
+                        e
+                    );
+                    tokio::time::sleep(delay + jitter(delay)).await;
+                    delay *= 2;
+                }
+                Err(e) if e.is_transient() => {
+                    return Err(Error::RetriesExhausted(attempt + 1, Box::new(e)))
+                }
+                res => return res,
+            }
+        }
+
+        unreachable!("the last attempt always returns")
+    }
+
+    async fn try_get_data<T: DeserializeOwned>(&self, url: &Url) -> Result<T> {
+        let resp = self.client.get(url.clone()).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(Error::UnsuccessfulStatus(resp.status().as_u16()));
+        }
+
+        Ok(resp.json().await?)
+    }
+}
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -20,6 +226,21 @@ pub enum Error {
     UrlParse(#[from] url::ParseError),
     #[error("Status code didn't indicate success (code {0})")]
     UnsuccessfulStatus(u16),
+    #[error("User `{0}` doesn't exist")]
+    UserNotFound(String),
+    #[error("Giving up after {0} attempts")]
+    RetriesExhausted(u8, #[source] Box<Self>),
+}
+
+impl Error {
+    /// Whether the error is temporary and the request should be tried again.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Self::UnsuccessfulStatus(code) => *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,9 +266,7 @@ pub struct Ranks {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Language {
-    pub rank: i32,
-    pub name: String,
-    pub color: String,
+    pub rank: Level,
     pub score: u32,
 }
 
@@ -58,10 +277,6 @@ pub struct CodeChallenges {
     pub total_completed: u32,
 }
 
-pub async fn user(username: &str) -> Result<User> {
-    get_data(&format!("users/{}", username)).await
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompletedChallenges {
@@ -70,7 +285,7 @@ pub struct CompletedChallenges {
     pub data: Vec<CompletedChallenge>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompletedChallenge {
     pub id: String,
@@ -80,10 +295,6 @@ pub struct CompletedChallenge {
     pub completed_languages: HashSet<String>,
 }
 
-pub async fn completed_challenges(username: &str) -> Result<CompletedChallenges> {
-    get_data(&format!("users/{}/code-challenges/completed", username)).await
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthoredChallenges {
@@ -96,16 +307,11 @@ pub struct AuthoredChallenge {
     pub id: String,
     pub name: String,
     pub description: String,
-    pub rank: i32,
-    pub rank_name: String,
+    pub rank: Level,
     pub tags: HashSet<String>,
     pub languages: HashSet<String>,
 }
 
-pub async fn authored_challenges(username: &str) -> Result<AuthoredChallenges> {
-    get_data(&format!("users/{}/code-challenges/authored", username)).await
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CodeChallenge {
@@ -135,11 +341,84 @@ pub struct CodeChallenge {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Rank {
-    pub id: i32,
-    pub name: String,
-    pub color: String,
+    pub id: Level,
+}
+
+/// Rank of a challenge or user, from 8 kyu as the easiest up to 1 kyu, followed by 1 dan up to 8
+/// dan as the hardest.
+///
+/// The API represents ranks as integers, with `-8` to `-1` for kyu and `1` to `8` for dan ranks.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "i32", into = "i32")]
+pub enum Level {
+    Kyu(u8),
+    Dan(u8),
+}
+
+impl Level {
+    /// Color that Codewars uses for the rank.
+    #[must_use]
+    pub const fn color(self) -> &'static str {
+        match self {
+            Self::Kyu(7..) => "white",
+            Self::Kyu(5..) => "yellow",
+            Self::Kyu(3..) => "blue",
+            Self::Kyu(_) => "purple",
+            Self::Dan(..=2) => "black",
+            Self::Dan(_) => "red",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kyu(kyu) => write!(f, "{kyu} kyu"),
+            Self::Dan(dan) => write!(f, "{dan} dan"),
+        }
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> Ordering {
+        i32::from(*self).cmp(&i32::from(*other))
+    }
+}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
+impl From<Level> for i32 {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Kyu(kyu) => -Self::from(kyu),
+            Level::Dan(dan) => Self::from(dan),
+        }
+    }
+}
+
+impl TryFrom<i32> for Level {
+    type Error = InvalidLevel;
+
+    fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+        let level = u8::try_from(value.unsigned_abs()).map_err(|_| InvalidLevel(value))?;
+
+        match (value.signum(), level) {
+            (-1, 1..=8) => Ok(Self::Kyu(level)),
+            (1, 1..=8) => Ok(Self::Dan(level)),
+            _ => Err(InvalidLevel(value)),
+        }
+    }
+}
+
+/// Error for rank values outside of the known kyu and dan ranks.
+#[derive(Debug, Error)]
+#[error("invalid rank {0}, expected -8 to -1 for kyu or 1 to 8 for dan")]
+pub struct InvalidLevel(pub i32);
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ShortUser {
@@ -154,29 +433,206 @@ pub struct Unresolved {
     pub suggestions: u32,
 }
 
-pub async fn code_challenge(slug_or_id: &str) -> Result<CodeChallenge> {
-    get_data(&format!("code-challenges/{}", slug_or_id)).await
-}
+/// Random extra delay of up to half the given delay, to spread out retries.
+fn jitter(delay: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let max = u64::try_from(delay.as_millis() / 2)
+        .unwrap_or(u64::MAX)
+        .max(1);
 
-async fn get_data<T: DeserializeOwned>(path: &str) -> Result<T> {
-    let resp = reqwest::Client::new()
-        .get(BASE_URL.join(path)?)
-        .send()
-        .await?;
-
-    if !resp.status().is_success() {
-        return Err(Error::UnsuccessfulStatus(resp.status().as_u16()));
-    }
+    Duration::from_millis(u64::from(nanos) % max)
+}
 
-    Ok(resp.json().await?)
+/// Link to the solutions of a kata in one language, where the kata is given by its slug or ID.
+/// Users that solved the kata find their own solution at the top, next to their teammates'.
+///
+/// # Errors
+///
+/// Fails if the slug or language don't form a valid URL path.
+pub fn solutions_url(kata: &str, language: &str) -> Result<Url> {
+    Ok(WEB_URL.join(&format!("kata/{kata}/solutions/{language}"))?)
 }
 
 #[cfg(test)]
 mod tests {
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
     use super::*;
 
+    async fn mock_client(path_: &str, response: ResponseTemplate) -> (MockServer, CodewarsClient) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(path_))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/v1/", server.uri())).unwrap();
+        let client = CodewarsClient::with_base_url(Client::new(), base_url);
+
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn fetch_user() {
+        let (_server, client) = mock_client(
+            "/api/v1/users/some_user",
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "username": "some_user",
+                "name": null,
+                "honor": 544,
+                "clan": "",
+                "leaderboardPosition": 134_006,
+                "skills": null,
+                "ranks": {
+                    "overall": { "rank": -4, "name": "4 kyu", "color": "blue", "score": 1600 },
+                    "languages": {
+                        "rust": { "rank": -4, "name": "4 kyu", "color": "blue", "score": 1600 }
+                    }
+                },
+                "codeChallenges": { "totalAuthored": 0, "totalCompleted": 55 }
+            })),
+        )
+        .await;
+
+        let user = client.user("some_user").await.unwrap();
+        assert_eq!("some_user", user.username);
+        assert_eq!(544, user.honor);
+        assert_eq!(55, user.code_challenges.total_completed);
+        assert_eq!(Level::Kyu(4), user.ranks.languages["rust"].rank);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_pages() {
+        let server = MockServer::start().await;
+        for page in 0..3 {
+            Mock::given(method("GET"))
+                .and(path("/api/v1/users/some_user/code-challenges/completed"))
+                .and(query_param("page", page.to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "totalPages": 3,
+                    "totalItems": 3,
+                    "data": [{
+                        "id": page.to_string(),
+                        "completedAt": "2020-01-01T00:00:00Z",
+                        "completedLanguages": ["rust"]
+                    }]
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let base_url = Url::parse(&format!("{}/api/v1/", server.uri())).unwrap();
+        let client = CodewarsClient::with_base_url(Client::new(), base_url);
+
+        let challenges = client.completed_challenges_all("some_user").await.unwrap();
+        let ids = challenges
+            .data
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["0", "1", "2"], ids);
+    }
+
+    #[tokio::test]
+    async fn user_not_found() {
+        let (_server, client) = mock_client(
+            "/api/v1/users/nobody",
+            ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "success": false,
+                "reason": "not found"
+            })),
+        )
+        .await;
+
+        assert!(matches!(
+            client.user("nobody").await,
+            Err(Error::UserNotFound(user)) if user == "nobody"
+        ));
+    }
+
+    #[tokio::test]
+    async fn encode_username() {
+        let (server, client) = mock_client(
+            "/api/v1/users/a%2Fb%3Fc",
+            ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "success": false,
+                "reason": "not found"
+            })),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/a%2Fb%3Fc/code-challenges/completed"))
+            .and(query_param("page", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalPages": 1,
+                "totalItems": 0,
+                "data": []
+            })))
+            .mount(&server)
+            .await;
+
+        assert!(matches!(
+            client.user("a/b?c").await,
+            Err(Error::UserNotFound(user)) if user == "a/b?c"
+        ));
+        let challenges = client.completed_challenges("a/b?c", 0).await.unwrap();
+        assert_eq!(0, challenges.total_items);
+    }
+
+    #[tokio::test]
+    async fn malformed_json() {
+        let (_server, client) = mock_client(
+            "/api/v1/code-challenges/multiply",
+            ResponseTemplate::new(200).set_body_string("{\"id\": "),
+        )
+        .await;
+
+        assert!(matches!(
+            client.code_challenge("multiply").await,
+            Err(Error::Http(e)) if e.is_decode()
+        ));
+    }
+
     #[test]
     fn check_lazy() {
-        Lazy::force(&BASE_URL);
+        LazyLock::force(&BASE_URL);
+        LazyLock::force(&WEB_URL);
+    }
+
+    #[test]
+    fn build_solutions_url() {
+        assert_eq!(
+            "https://www.codewars.com/kata/multiply/solutions/rust",
+            solutions_url("multiply", "rust").unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn level_order_and_format() {
+        let levels = [-8, -1, 1, 8]
+            .into_iter()
+            .map(|id| Level::try_from(id).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![Level::Kyu(8), Level::Kyu(1), Level::Dan(1), Level::Dan(8)],
+            levels
+        );
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!("4 kyu", Level::Kyu(4).to_string());
+        assert_eq!("2 dan", Level::Dan(2).to_string());
+        assert_eq!("blue", Level::Kyu(3).color());
+        assert_eq!("red", Level::Dan(3).color());
+        assert!(Level::try_from(0).is_err());
+        assert!(Level::try_from(-9).is_err());
+        assert_eq!(-3, i32::from(serde_json::from_str::<Level>("-3").unwrap()));
     }
 }
@@ -0,0 +1,21 @@
+//! Discord API for sending messages via webhooks.
+
+use thiserror::Error;
+
+pub mod webhook;
+
+/// Shorthand for results in this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A list of errors that can happen while interacting with the API.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error during HTTP handling")]
+    Http(#[from] reqwest::Error),
+    #[error("JSON (de)serialization failed")]
+    Json(#[from] serde_json::Error),
+    #[error("Status code didn't indicate success (code {0})")]
+    UnsuccessfulStatus(u16),
+    #[error("Webhook destination is gone")]
+    WebhookGone,
+}
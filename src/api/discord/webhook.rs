@@ -0,0 +1,66 @@
+//! Functions for sending messages via web hooks.
+
+use reqwest::{
+    multipart::{Form, Part},
+    Client, IntoUrl, Response, StatusCode,
+};
+use serde::Serialize;
+
+use super::{Error, Result};
+
+/// The representation of a Discord message in it's simplest form with only the text content.
+#[derive(Debug, Serialize)]
+pub struct Message<'a> {
+    /// Text content of the message.
+    pub content: &'a str,
+}
+
+/// Send given message to a web hook URL. The message can be plain text but also Discord style
+/// Markdown content.
+///
+/// # Errors
+///
+/// Fails if the request can't be sent or Discord answers with an error status.
+pub async fn send<U: IntoUrl + Send>(client: &Client, url: U, content: &str) -> Result<()> {
+    let resp = client.post(url).json(&Message { content }).send().await?;
+    check(&resp)
+}
+
+/// Send given message to a web hook URL, together with a plain text file attachment.
+///
+/// # Errors
+///
+/// Fails if the message can't be serialized, the request can't be sent or Discord answers with an
+/// error status.
+pub async fn send_file<U: IntoUrl + Send>(
+    client: &Client,
+    url: U,
+    content: &str,
+    file_name: &str,
+    file: String,
+) -> Result<()> {
+    let form = Form::new()
+        .text("payload_json", serde_json::to_string(&Message { content })?)
+        .part(
+            "files[0]",
+            Part::text(file)
+                .file_name(file_name.to_owned())
+                .mime_str("text/plain")?,
+        );
+
+    let resp = client.post(url).multipart(form).send().await?;
+    check(&resp)
+}
+
+/// Check the response of a webhook call for errors.
+fn check(resp: &Response) -> Result<()> {
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Err(Error::WebhookGone);
+    }
+
+    if !resp.status().is_success() {
+        return Err(Error::UnsuccessfulStatus(resp.status().as_u16()));
+    }
+
+    Ok(())
+}
@@ -1,4 +1,140 @@
 //! APIs to external services.
 
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, Proxy,
+};
+use serde::Deserialize;
+use url::Url;
+
 pub mod codewars;
+pub mod discord;
 pub mod slack;
+
+/// Default user agent that identifies the bot and where to find out more about it.
+const USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+",
+    env!("CARGO_PKG_REPOSITORY"),
+    ")"
+);
+
+/// Settings for the HTTP client that is shared between all API calls.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Http {
+    /// Maximum time in seconds for a whole request, from connecting until the response body is
+    /// read.
+    pub timeout_secs: u64,
+    /// Maximum time in seconds to establish a connection.
+    pub connect_timeout_secs: u64,
+    /// Proxy to send all HTTP and HTTPS requests through, like `http://proxy.local:3128`.
+    pub proxy: Option<Url>,
+    /// User agent to identify the bot to API operators. Defaults to the crate name and version,
+    /// with a link to the repository.
+    pub user_agent: Option<String>,
+    /// Extra headers that are sent with every request, like a contact address in `From`.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            proxy: None,
+            user_agent: None,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Http {
+    /// User agent that is sent with every request.
+    #[must_use]
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(USER_AGENT)
+    }
+
+    /// Build a new HTTP client with these settings.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any of the extra headers has an invalid name or value.
+    pub fn client(&self) -> Result<Client> {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    HeaderName::try_from(name.as_str())
+                        .with_context(|| format!("invalid header name `{name}`"))?,
+                    HeaderValue::try_from(value.as_str())
+                        .with_context(|| format!("invalid value for header `{name}`"))?,
+                ))
+            })
+            .collect::<Result<HeaderMap>>()?;
+
+        let mut builder = Client::builder()
+            .user_agent(self.user_agent())
+            .default_headers(headers)
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs));
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.clone())?);
+        }
+
+        builder.build().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn identify_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("user-agent", USER_AGENT))
+            .and(header("from", "ops@example.com"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = Http {
+            headers: [("From".to_owned(), "ops@example.com".to_owned())].into(),
+            ..Http::default()
+        };
+        let res = http
+            .client()
+            .unwrap()
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        assert!(USER_AGENT.starts_with("codewars-bot/"));
+    }
+
+    #[test]
+    fn reject_invalid_headers() {
+        let http = Http {
+            headers: [("bad header".to_owned(), "value".to_owned())].into(),
+            ..Http::default()
+        };
+        assert!(http.client().is_err());
+    }
+}
@@ -1,5 +1,8 @@
 //! Events that are sent from Slack to a server endpoint to notify about various changes in a team
 //! chat.
+//!
+//! Callbacks are parsed with [`parse_callback`], while the contained events stay raw JSON, so each
+//! handler can parse them into the type for its event, like [`AppMention`].
 
 use hmac::{Hmac, Mac};
 use serde::Deserialize;
@@ -25,11 +28,87 @@ pub struct AppMention {
     pub text: String,
     /// The channel where this message was sent.
     pub channel: String,
+    /// Timestamp of the message, which identifies it within the channel.
+    pub ts: String,
 }
 
-/// Verify the signature of a HTTP request to make sure it really came from Slack. The system sends
-/// a signature and timestamp with every request. The signature is a HMAC over the timestamp and
-/// message payload with an apps private key.
+impl AppMention {
+    /// Event type of app mentions.
+    pub const TYPE: &'static str = "app_mention";
+}
+
+/// A message in any conversation that the app is part of, which includes direct messages to it.
+#[derive(Debug, Deserialize)]
+pub struct MessageEvent {
+    /// ID of the user who sent the message. Missing for some kinds of messages, like edits.
+    #[serde(default)]
+    pub user: String,
+    /// Message content.
+    #[serde(default)]
+    pub text: String,
+    /// The conversation where this message was sent.
+    pub channel: String,
+    /// Type of the conversation, like `im` for direct messages.
+    pub channel_type: Option<String>,
+    /// Timestamp of the message, which identifies it within the conversation.
+    #[serde(default)]
+    pub ts: String,
+    /// Kind of message, that is only set for special messages, like edits or deletions.
+    pub subtype: Option<String>,
+    /// ID of the bot that sent the message, if it wasn't a user.
+    pub bot_id: Option<String>,
+}
+
+impl MessageEvent {
+    /// Event type of messages.
+    pub const TYPE: &'static str = "message";
+
+    /// Whether a user sent this message to the app in a direct message conversation. Messages of
+    /// bots, including the app's own replies, and changes like edits are excluded.
+    #[must_use]
+    pub fn is_direct(&self) -> bool {
+        self.channel_type.as_deref() == Some("im")
+            && self.subtype.is_none()
+            && self.bot_id.is_none()
+    }
+}
+
+/// A user opened the app's Home, Messages or About tab.
+#[derive(Debug, Deserialize)]
+pub struct AppHomeOpened {
+    /// ID of the user who opened the tab.
+    pub user: String,
+    /// The tab that was opened, like `home` or `messages`.
+    pub tab: String,
+}
+
+impl AppHomeOpened {
+    /// Event type of opened tabs.
+    pub const TYPE: &'static str = "app_home_opened";
+}
+
+/// A user joined a channel that the app is a member of.
+#[derive(Debug, Deserialize)]
+pub struct MemberJoinedChannel {
+    /// ID of the user who joined.
+    pub user: String,
+    /// The channel that was joined.
+    pub channel: String,
+}
+
+impl MemberJoinedChannel {
+    /// Event type of users joining a channel.
+    pub const TYPE: &'static str = "member_joined_channel";
+}
+
+/// Verify the signature of a HTTP request to make sure it really came from Slack.
+///
+/// The system sends a signature and timestamp with every request. The signature is a HMAC over the
+/// timestamp and message payload with an apps private key.
+///
+/// # Errors
+///
+/// Fails if the signature has an unsupported version, isn't valid hex or doesn't match the body.
 pub fn verify_signature(key: &[u8], signature: &str, timestamp: &str, body: &[u8]) -> Result<()> {
     if !signature.starts_with("v0=") {
         return Err(Error::UnsupportedSignatureVersion);
@@ -61,11 +140,16 @@ pub enum Callback {
     /// URL verification that is used by Slack to make sure the service is running and can
     /// authenticate as the app registered in the platform.
     UrlVerification(UrlVerification),
-    /// Callback for any kind of events that Slack might notify about.
-    Event(Value),
+    /// Callback for any kind of events that Slack might notify about, with the unique ID of the
+    /// event.
+    Event(String, Value),
 }
 
 /// Parse a JSON content into a Slack callback.
+///
+/// # Errors
+///
+/// Fails if the content lacks the callback type or the fields that the type requires.
 pub fn parse_callback(mut event: Value) -> Result<Callback> {
     Ok(
         match event
@@ -79,45 +163,18 @@ pub fn parse_callback(mut event: Value) -> Result<Callback> {
                 Callback::UrlVerification(event)
             }
             CALLBACK_EVENT_CALLBACK => {
+                let id = event
+                    .get("event_id")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned();
                 let event = event
                     .get_mut("event")
                     .ok_or(Error::JsonMissingProperty("event"))?;
 
-                Callback::Event(event.take())
+                Callback::Event(id, event.take())
             }
             callback_type => Callback::Unknown(callback_type.to_owned()),
         },
     )
 }
-
-/// Event type for any mentions of the app.
-const EVENT_APP_MENTION: &str = "app_mention";
-
-/// Different events that Slack can notify about.
-pub enum Event {
-    /// Fallback for any unsupported events.
-    Unknown(String),
-    /// The app was mentioned by a user directly like `@bot hello`.
-    AppMention(AppMention),
-}
-
-/// Parse from raw JSON content into a Slack event.
-#[allow(clippy::module_name_repetitions)]
-pub fn parse_event(mut event: Value) -> Result<Event> {
-    Ok(
-        match event
-            .as_object()
-            .ok_or(Error::JsonWrongType("event", "object"))?
-            .get("type")
-            .ok_or(Error::JsonMissingProperty("type"))?
-            .as_str()
-            .ok_or(Error::JsonWrongType("type", "string"))?
-        {
-            EVENT_APP_MENTION => {
-                let event: AppMention = serde_json::from_value(event.take())?;
-                Event::AppMention(event)
-            }
-            event_type => Event::Unknown(event_type.to_owned()),
-        },
-    )
-}
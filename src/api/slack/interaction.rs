@@ -0,0 +1,163 @@
+//! Interactions of users with messages and views, like clicking a button or submitting a modal,
+//! which Slack sends to the app's interactivity endpoint.
+
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use super::{Error, Result};
+
+/// Interaction type for clicks on interactive elements of a message, like buttons.
+const INTERACTION_BLOCK_ACTIONS: &str = "block_actions";
+/// Interaction type for submitted modals.
+const INTERACTION_VIEW_SUBMISSION: &str = "view_submission";
+
+/// Supported kinds of interactions.
+#[derive(Debug)]
+pub enum Interaction {
+    /// A user clicked on elements of a message or view.
+    BlockActions(BlockActions),
+    /// A user submitted a modal.
+    ViewSubmission(ViewSubmission),
+}
+
+/// A user clicked on one or more interactive elements of a message or view.
+#[derive(Debug, Deserialize)]
+pub struct BlockActions {
+    /// The user who clicked the elements.
+    pub user: User,
+    /// Clicked elements, usually just one.
+    pub actions: Vec<Action>,
+    /// URL to update or reply to the message that contained the elements. Clicks in views, like
+    /// the Home tab, don't have one.
+    pub response_url: Option<Url>,
+    /// Short-lived ID to open a modal in response to the click.
+    #[serde(default)]
+    pub trigger_id: String,
+}
+
+/// A user submitted a modal.
+#[derive(Debug, Deserialize)]
+pub struct ViewSubmission {
+    /// The user who submitted the modal.
+    pub user: User,
+    /// The submitted modal.
+    pub view: View,
+}
+
+/// Minimal information about a submitted modal.
+#[derive(Debug, Deserialize)]
+pub struct View {
+    /// Identifier of the modal, as defined when opening it.
+    pub callback_id: String,
+    /// Values of the modal's inputs.
+    pub state: ViewState,
+}
+
+/// Values of all inputs of a modal.
+#[derive(Debug, Deserialize)]
+pub struct ViewState {
+    /// Values by the IDs of their blocks and elements.
+    pub values: Value,
+}
+
+/// Minimal information about the user of an interaction.
+#[derive(Debug, Deserialize)]
+pub struct User {
+    /// ID of the user.
+    pub id: String,
+}
+
+/// A single clicked element.
+#[derive(Debug, Deserialize)]
+pub struct Action {
+    /// Identifier of the element, as defined when sending the message.
+    pub action_id: String,
+    /// Value that was attached to the element when sending the message.
+    #[serde(default)]
+    pub value: String,
+}
+
+/// Parse the form encoded body of an interaction request. Only block actions and view submissions
+/// are supported and any other kind of interaction results in `None`.
+///
+/// # Errors
+///
+/// Fails if the body has no payload or the payload isn't valid JSON for its interaction type.
+pub fn parse(body: &[u8]) -> Result<Option<Interaction>> {
+    let payload = url::form_urlencoded::parse(body)
+        .find(|(key, _)| key == "payload")
+        .ok_or(Error::JsonMissingProperty("payload"))?
+        .1;
+    let payload = serde_json::from_str::<serde_json::Value>(&payload)?;
+
+    Ok(match payload.get("type").and_then(Value::as_str) {
+        Some(INTERACTION_BLOCK_ACTIONS) => {
+            Some(Interaction::BlockActions(serde_json::from_value(payload)?))
+        }
+        Some(INTERACTION_VIEW_SUBMISSION) => Some(Interaction::ViewSubmission(
+            serde_json::from_value(payload)?,
+        )),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_block_actions() {
+        let payload = serde_json::json!({
+            "type": "block_actions",
+            "user": { "id": "U01", "name": "someone" },
+            "actions": [{ "action_id": "confirm", "value": "Ev01-0", "type": "button" }],
+            "response_url": "https://hooks.slack.com/actions/T01/1/abc",
+        });
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("payload", &payload.to_string())
+            .finish();
+
+        let Some(Interaction::BlockActions(actions)) = parse(body.as_bytes()).unwrap() else {
+            panic!("expected block actions");
+        };
+        assert_eq!("U01", actions.user.id);
+        assert_eq!("confirm", actions.actions[0].action_id);
+        assert_eq!("Ev01-0", actions.actions[0].value);
+
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("payload", r#"{"type":"shortcut"}"#)
+            .finish();
+        assert!(parse(body.as_bytes()).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_view_submission() {
+        let payload = serde_json::json!({
+            "type": "view_submission",
+            "user": { "id": "U01", "name": "someone" },
+            "view": {
+                "id": "V01",
+                "callback_id": "user",
+                "state": {
+                    "values": {
+                        "input": { "input": { "type": "plain_text_input", "value": "him" } },
+                    },
+                },
+            },
+        });
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("payload", &payload.to_string())
+            .finish();
+
+        let Some(Interaction::ViewSubmission(submission)) = parse(body.as_bytes()).unwrap() else {
+            panic!("expected view submission");
+        };
+        assert_eq!("U01", submission.user.id);
+        assert_eq!("user", submission.view.callback_id);
+        assert_eq!(
+            "him",
+            submission.view.state.values["input"]["input"]["value"]
+        );
+    }
+}
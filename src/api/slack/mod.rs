@@ -1,8 +1,11 @@
-//! Slack API for parsing events received from the platform and webhooks to send messages.
+//! Slack API for parsing events received from the platform, and webhooks and the Web API to send
+//! messages.
 
 use thiserror::Error;
 
 pub mod event;
+pub mod interaction;
+pub mod web;
 pub mod webhook;
 
 /// Shorthand for results in this module.
@@ -31,4 +34,6 @@ pub enum Error {
     JsonWrongType(&'static str, &'static str),
     #[error("Unsupported signature version")]
     UnsupportedSignatureVersion,
+    #[error("Webhook destination is gone ({0})")]
+    WebhookGone(String),
 }
@@ -0,0 +1,364 @@
+//! Client for the Slack Web API, to post messages as the bot user. Unlike webhooks, which are bound
+//! to a single channel, this allows to reply in any conversation, like direct messages.
+
+use std::sync::LazyLock;
+
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use super::{Error, Result};
+
+static BASE_URL: LazyLock<Url> = LazyLock::new(|| Url::parse("https://slack.com/api/").unwrap());
+
+/// Client for the Slack Web API, authenticated with a bot token. The base URL defaults to the
+/// official API and can be changed, for example to run tests against a local mock server.
+#[derive(Clone, Debug)]
+pub struct SlackClient {
+    client: Client,
+    token: String,
+    base_url: Url,
+}
+
+/// Common response of all API calls, that reports errors with a successful HTTP status.
+#[derive(Deserialize)]
+struct Response {
+    ok: bool,
+    error: Option<String>,
+}
+
+impl SlackClient {
+    /// Create a new client for the official Slack API, that uses the given bot token (starting
+    /// with `xoxb-`).
+    #[must_use]
+    pub fn new(client: Client, token: String) -> Self {
+        Self::with_base_url(client, token, BASE_URL.clone())
+    }
+
+    /// Create a new client that sends all API calls to the given base URL. The URL should end with
+    /// a slash, as the API methods are joined to it.
+    #[must_use]
+    pub const fn with_base_url(client: Client, token: String, base_url: Url) -> Self {
+        Self {
+            client,
+            token,
+            base_url,
+        }
+    }
+
+    /// Post a message to a channel, which can also be the ID of a direct message conversation.
+    /// The bot must be a member of the channel and have the `chat:write` scope.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack reports an unsuccessful call, like a missing
+    /// scope.
+    pub async fn post_message(&self, channel: &str, text: &str) -> Result<()> {
+        self.call(
+            "chat.postMessage",
+            &json!({ "channel": channel, "text": text }),
+        )
+        .await
+    }
+
+    /// Post a message with layout blocks, like buttons, to a channel. The text is only used as
+    /// fallback, like in notifications.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack reports an unsuccessful call, like a missing
+    /// scope.
+    pub async fn post_blocks(&self, channel: &str, text: &str, blocks: &Value) -> Result<()> {
+        self.call(
+            "chat.postMessage",
+            &json!({ "channel": channel, "text": text, "blocks": blocks }),
+        )
+        .await
+    }
+
+    /// Add an emoji reaction, given by name without colons, to the message with the timestamp
+    /// `ts`. The bot needs the `reactions:write` scope.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack reports an unsuccessful call, like a missing
+    /// scope.
+    pub async fn add_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()> {
+        self.call(
+            "reactions.add",
+            &json!({ "channel": channel, "timestamp": ts, "name": name }),
+        )
+        .await
+    }
+
+    /// Remove an emoji reaction of the bot from the message with the timestamp `ts` again.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack reports an unsuccessful call, like a missing
+    /// scope.
+    pub async fn remove_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()> {
+        self.call(
+            "reactions.remove",
+            &json!({ "channel": channel, "timestamp": ts, "name": name }),
+        )
+        .await
+    }
+
+    /// Open a direct message conversation with a user, or get the existing one, and return its
+    /// channel ID. The bot needs the `im:write` scope.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent, Slack reports an unsuccessful call or the response
+    /// lacks the channel ID.
+    pub async fn open_conversation(&self, user: &str) -> Result<String> {
+        let resp = self
+            .send("conversations.open", |req| {
+                req.json(&json!({ "users": user }))
+            })
+            .await?;
+
+        resp.pointer("/channel/id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or(Error::JsonMissingProperty("channel.id"))
+    }
+
+    /// Publish a view, like the app's Home tab, for a single user. The view must be a JSON
+    /// object as described in the Block Kit reference.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack rejects the view.
+    pub async fn publish_view(&self, user: &str, view: &Value) -> Result<()> {
+        self.call("views.publish", &json!({ "user_id": user, "view": view }))
+            .await
+    }
+
+    /// Open a modal view for the user who clicked a button. The trigger ID of the click is only
+    /// valid for a few seconds.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request can't be sent or Slack rejects the view, like for an expired trigger.
+    pub async fn open_view(&self, trigger_id: &str, view: &Value) -> Result<()> {
+        self.call(
+            "views.open",
+            &json!({ "trigger_id": trigger_id, "view": view }),
+        )
+        .await
+    }
+
+    /// Upload a file and share it in a channel, together with a comment. To share it in a direct
+    /// message conversation, the channel must be opened with [`Self::open_conversation`] first.
+    /// Slack detects the file type from the name's extension. The bot needs the `files:write`
+    /// scope.
+    ///
+    /// The content is sent to a separate upload URL that Slack hands out, and the file is only
+    /// shared once the upload is completed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any of the requests can't be sent or Slack reports an unsuccessful call, like a
+    /// missing scope.
+    pub async fn upload_file(
+        &self,
+        channel: &str,
+        file_name: &str,
+        content: Vec<u8>,
+        comment: &str,
+    ) -> Result<()> {
+        let resp = self
+            .send("files.getUploadURLExternal", |req| {
+                req.form(&[
+                    ("filename", file_name.to_owned()),
+                    ("length", content.len().to_string()),
+                ])
+            })
+            .await?;
+        let upload_url = resp
+            .get("upload_url")
+            .and_then(Value::as_str)
+            .ok_or(Error::JsonMissingProperty("upload_url"))?;
+        let file_id = resp
+            .get("file_id")
+            .and_then(Value::as_str)
+            .ok_or(Error::JsonMissingProperty("file_id"))?;
+
+        self.client
+            .post(upload_url)
+            .body(content)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        self.call(
+            "files.completeUploadExternal",
+            &json!({
+                "files": [{ "id": file_id, "title": file_name }],
+                "channel_id": channel,
+                "initial_comment": comment,
+            }),
+        )
+        .await
+    }
+
+    /// Call an API method with the given JSON arguments.
+    async fn call(&self, method: &'static str, args: &Value) -> Result<()> {
+        self.send(method, |req| req.json(args)).await.map(drop)
+    }
+
+    /// Call an API method with the arguments that `body` attaches to the request, and return the
+    /// full response.
+    async fn send(
+        &self,
+        method: &'static str,
+        body: impl FnOnce(RequestBuilder) -> RequestBuilder + Send,
+    ) -> Result<Value> {
+        let resp = body(
+            self.client
+                .post(self.base_url.join(method)?)
+                .bearer_auth(&self.token),
+        )
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Value>()
+        .await?;
+        let status = Response::deserialize(&resp)?;
+
+        if status.ok {
+            Ok(resp)
+        } else {
+            Err(Error::UnsuccessfulRequest(
+                method,
+                status.error.unwrap_or_default(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_json, body_string, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn add_reaction() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/reactions.add"))
+            .and(body_json(
+                json!({ "channel": "C01", "timestamp": "1.2", "name": "x" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/", server.uri())).unwrap();
+        let client = SlackClient::with_base_url(Client::new(), "xoxb-token".to_owned(), base_url);
+
+        client.add_reaction("C01", "1.2", "x").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn open_conversation() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/conversations.open"))
+            .and(body_json(json!({ "users": "U01" })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "ok": true, "channel": { "id": "D01" } })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/", server.uri())).unwrap();
+        let client = SlackClient::with_base_url(Client::new(), "xoxb-token".to_owned(), base_url);
+
+        assert_eq!("D01", client.open_conversation("U01").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn upload_file() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/files.getUploadURLExternal"))
+            .and(header("authorization", "Bearer xoxb-token"))
+            .and(body_string("filename=export.csv&length=4"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "ok": true,
+                "upload_url": format!("{}/upload/F01", server.uri()),
+                "file_id": "F01"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/upload/F01"))
+            .and(body_string("a,b\n"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/files.completeUploadExternal"))
+            .and(header("authorization", "Bearer xoxb-token"))
+            .and(body_json(json!({
+                "files": [{ "id": "F01", "title": "export.csv" }],
+                "channel_id": "D01",
+                "initial_comment": "Here you go"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/", server.uri())).unwrap();
+        let client = SlackClient::with_base_url(Client::new(), "xoxb-token".to_owned(), base_url);
+
+        client
+            .upload_file("D01", "export.csv", b"a,b\n".to_vec(), "Here you go")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn post_message() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat.postMessage"))
+            .and(header("authorization", "Bearer xoxb-token"))
+            .and(body_json(json!({ "channel": "D01", "text": "hello" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat.postMessage"))
+            .and(body_json(json!({ "channel": "D02", "text": "hello" })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "ok": false, "error": "channel_not_found" })),
+            )
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/", server.uri())).unwrap();
+        let client = SlackClient::with_base_url(Client::new(), "xoxb-token".to_owned(), base_url);
+
+        client.post_message("D01", "hello").await.unwrap();
+        assert!(matches!(
+            client.post_message("D02", "hello").await,
+            Err(Error::UnsuccessfulRequest(_, reason)) if reason == "channel_not_found"
+        ));
+    }
+}
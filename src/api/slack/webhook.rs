@@ -1,31 +1,74 @@
 //! Functions for sending messages via web hooks.
 
-use reqwest::IntoUrl;
+use reqwest::{Client, IntoUrl};
 use serde::Serialize;
+use serde_json::Value;
 
 use super::{Error, Result};
 
-/// The representation of a Slack message in it's simplest form with only the text content.
-#[derive(Debug, Serialize)]
+/// Error codes returned by Slack when the webhook's channel or the webhook itself doesn't exist
+/// anymore. Retrying the delivery will never succeed in these cases.
+const GONE_ERRORS: &[&str] = &[
+    "action_prohibited",
+    "channel_is_archived",
+    "channel_not_found",
+    "invalid_token",
+    "no_active_hooks",
+    "no_service",
+    "team_disabled",
+];
+
+/// The representation of a Slack message with the text content and optional layout blocks.
+#[derive(Debug, Default, Serialize)]
 pub struct Message<'a> {
-    /// Text content of the message.
+    /// Text content of the message. If blocks are given, it is only used as fallback, like in
+    /// notifications.
     pub text: &'a str,
+    /// Layout blocks, like buttons, that are shown instead of the text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<&'a Value>,
+    /// Whether to replace the original message. Only supported when responding to an interaction
+    /// through its response URL.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub replace_original: bool,
 }
 
 /// Send given message to a web hook URL. The message can be plain text but also Slack style
 /// Markdown content.
-pub async fn send<U: IntoUrl + Send>(url: U, text: &str) -> Result<()> {
-    let resp = reqwest::Client::new()
-        .post(url)
-        .json(&Message { text })
-        .send()
-        .await?;
+///
+/// # Errors
+///
+/// Fails under the same conditions as [`post`].
+pub async fn send<U: IntoUrl + Send>(client: &Client, url: U, text: &str) -> Result<()> {
+    post(
+        client,
+        url,
+        &Message {
+            text,
+            ..Message::default()
+        },
+    )
+    .await
+}
+
+/// Post a full message, possibly with blocks, to a web hook URL or the response URL of an
+/// interaction.
+///
+/// # Errors
+///
+/// Fails if the request can't be sent or Slack answers with an error status. A removed web hook
+/// results in [`Error::WebhookGone`].
+pub async fn post<U: IntoUrl + Send>(client: &Client, url: U, message: &Message<'_>) -> Result<()> {
+    let resp = client.post(url).json(message).send().await?;
 
     if !resp.status().is_success() {
-        return Err(Error::UnsuccessfulRequest(
-            "webhook",
-            "Failed posting to webhook".to_owned(),
-        ));
+        let reason = resp.text().await.unwrap_or_default();
+
+        return Err(if GONE_ERRORS.contains(&reason.trim()) {
+            Error::WebhookGone(reason.trim().to_owned())
+        } else {
+            Error::UnsuccessfulRequest("webhook", format!("Failed posting to webhook: {reason}"))
+        });
     }
 
     Ok(())
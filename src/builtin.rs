@@ -0,0 +1,1382 @@
+//! Implementation of the built-in commands, that each create the response message to a command.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+use codewars_bot::{scheduling, storage};
+use tokio::sync::{mpsc::UnboundedSender, Mutex, RwLock};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    api::codewars,
+    commands::Command,
+    dispatch::{Dispatcher, Route},
+    fetch::FetchCache,
+    handler::{Context, MAINTENANCE_NOTICE},
+    help,
+    history::History,
+    home, languages,
+    mrkdwn::{self, escape},
+    poll, ranks, review,
+    scheduling::{Frequency, Scheduler},
+    stats::{self, Period},
+    storage::{Outcome, QuietHours, Repository},
+    suggest,
+    tasks::{goal_progress, schedule_inputs, Report, StatsTask},
+};
+
+/// Execute a single built-in command from the given Slack user and create the response message.
+pub async fn execute(ctx: &Context, user: &str, cmd: Command) -> Result<String> {
+    let Context {
+        fetch,
+        settings,
+        dispatcher,
+        stats_task,
+        aliases,
+        started,
+        s_tx,
+        n_tx,
+        history,
+        suggestions,
+        admins,
+        plugins,
+        ..
+    } = ctx;
+
+    let maintenance = settings.read().await.maintenance();
+
+    match cmd {
+        _ if cmd.is_admin_only() && !admins.iter().any(|admin| admin == user) => Ok(String::from(
+            "Sorry, only admins from the `admins` setting may run this command",
+        )),
+        Command::Maintenance(on_off) => maintenance_mode(settings, on_off).await,
+        _ if maintenance => Ok(String::from(MAINTENANCE_NOTICE)),
+        Command::AddUser(username) => add_user(settings, fetch, user, username).await,
+        Command::RemoveUser(username) => remove_user(settings, username).await,
+        Command::RenameUser(from, to) => {
+            rename_user(settings, fetch, history.as_deref(), from, to).await
+        }
+        Command::Clear(all) => clear(settings, s_tx, n_tx, all).await,
+        Command::DisplayName(username, name) => display_name(settings, username, name).await,
+        Command::Note(username, text) => note(settings, username, text).await,
+        Command::ListUsers => list_users(settings).await,
+        Command::Stats {
+            since,
+            until,
+            top,
+            direct: false,
+        } => stats(ctx, since, until, top).await,
+        Command::Stats {
+            since,
+            until,
+            top,
+            direct: true,
+        } => stats_to_user(ctx, user, since, until, top).await,
+        Command::StatsByTag { since, until } => stats_by_tag(settings, fetch, since, until).await,
+        Command::Languages => {
+            let summaries = languages::collect(settings, fetch).await;
+            Ok(languages::render(
+                &summaries,
+                &settings.read().await.display_names(),
+            ))
+        }
+        Command::Help => help::render(1, aliases, &plugins.usage()),
+        Command::HelpPage(page) => help::render(page, aliases, &plugins.usage()),
+        Command::Status => status(settings, dispatcher, *started).await,
+        Command::BotStats => botstats(settings).await,
+        Command::Audit(limit) => audit(settings, limit.unwrap_or(AUDIT_DEFAULT)).await,
+        Command::RunNow(mark) => run_now(stats_task, mark).await,
+        Command::Schedule(frequency, time) => {
+            schedule(settings, s_tx, storage::Schedule { frequency, time }).await
+        }
+        Command::ScheduleAdd(frequency, time) => {
+            schedule_add(settings, s_tx, storage::Schedule { frequency, time }).await
+        }
+        Command::ScheduleRemove(index) => schedule_remove(settings, s_tx, index).await,
+        Command::ScheduleList => schedule_list(settings).await,
+        Command::Notify(on_off) => notify(settings, n_tx, on_off).await,
+        Command::NotifyDigest(interval) => notify_digest(settings, n_tx, interval).await,
+        Command::NotifyEvery(hours) => notify_every(settings, n_tx, hours).await,
+        Command::NotifySnooze(duration) => notify_snooze(settings, duration).await,
+        Command::NotifyQuiet(range) => notify_quiet(settings, range).await,
+        Command::NotifyFilterKyu(kyu) => notify_filter_kyu(settings, kyu).await,
+        Command::NotifyFilterLang(languages) => notify_filter_lang(settings, languages).await,
+        Command::Subscribe(username) => {
+            subscribe(settings, fetch, dispatcher, user, username).await
+        }
+        Command::Unsubscribe => unsubscribe(settings, user).await,
+        Command::Retrains(on_off) => retrains(settings, on_off).await,
+        Command::Setup => setup(settings, dispatcher).await,
+        Command::SetWebhook(url) => set_webhook(settings, dispatcher, url).await,
+        Command::WebhookAdd(url) => webhook_add(settings, dispatcher, url).await,
+        Command::WebhookRemove(url) => webhook_remove(settings, dispatcher, url).await,
+        Command::Search(term) => search(history.as_deref(), &term).await,
+        Command::WhoSolved(kata) => who_solved(settings, fetch, &kata).await,
+        Command::Suggest(kyu) => suggest_kata(settings, fetch, suggestions, kyu).await,
+        Command::PollStart(duration) => poll_start(settings, fetch, suggestions, duration).await,
+        Command::PollStatus => poll_status(settings, fetch).await,
+        Command::Vote(choice) => vote(settings, fetch, user, choice).await,
+        Command::GoalSet(target) => set_goal(settings, target).await,
+        Command::GoalStatus => goal_status(settings, fetch).await,
+        Command::Review(year) => {
+            review(settings, fetch, year.unwrap_or_else(|| Local::now().year())).await
+        }
+    }
+}
+
+/// Add a user to the watchlist after making sure it exists on Codewars. If Codewars can't be
+/// reached, the user is added without the check.
+async fn add_user(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    user: &str,
+    username: String,
+) -> Result<String> {
+    if let Some(user) = settings.read().await.find_user(&username) {
+        return Ok(format!(
+            "User `{}` is already in the watchlist",
+            mrkdwn::code(user)
+        ));
+    }
+
+    match fetch.user(&username).await {
+        Err(codewars::Error::UserNotFound(_)) => {
+            return Ok(format!(
+                "User `{}` doesn't exist on Codewars, please check the spelling",
+                mrkdwn::code(&username)
+            ));
+        }
+        Err(e) => warn!("Error checking that user {} exists: {}", username, e),
+        Ok(_) => {}
+    }
+
+    Ok(
+        if settings
+            .write()
+            .await
+            .add_user(&username, Some(user))
+            .await?
+        {
+            format!("Added user `{}` to watchlist", mrkdwn::code(&username))
+        } else {
+            format!(
+                "User `{}` is already in the watchlist",
+                mrkdwn::code(&username)
+            )
+        },
+    )
+}
+
+async fn remove_user(settings: &Arc<RwLock<Repository>>, username: String) -> Result<String> {
+    Ok(if settings.write().await.remove_user(&username).await? {
+        format!("Removed user `{}` from watchlist", mrkdwn::code(&username))
+    } else {
+        format!("User `{}` is not in the watchlist", mrkdwn::code(&username))
+    })
+}
+
+/// Change the username of a watched user, after making sure that the new one exists on Codewars.
+async fn rename_user(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    history: Option<&Mutex<History>>,
+    from: String,
+    to: String,
+) -> Result<String> {
+    {
+        let settings = settings.read().await;
+        let Some(from) = settings.find_user(&from) else {
+            return Ok(format!(
+                "User `{}` is not in the watchlist",
+                mrkdwn::code(&from)
+            ));
+        };
+        if let Some(existing) = settings.find_user(&to).filter(|user| *user != from) {
+            return Ok(format!(
+                "User `{}` is already in the watchlist",
+                mrkdwn::code(existing)
+            ));
+        }
+    }
+
+    match fetch.user(&to).await {
+        Err(codewars::Error::UserNotFound(_)) => {
+            return Ok(format!(
+                "User `{}` doesn't exist on Codewars, please check the spelling",
+                mrkdwn::code(&to)
+            ));
+        }
+        Err(e) => warn!("Error checking that user {} exists: {}", to, e),
+        Ok(_) => {}
+    }
+
+    let mut settings = settings.write().await;
+    let Some(from) = settings.find_user(&from).map(ToOwned::to_owned) else {
+        return Ok(format!(
+            "User `{}` is not in the watchlist",
+            mrkdwn::code(&from)
+        ));
+    };
+    if !settings.rename_user(&from, &to).await? {
+        return Ok(format!(
+            "User `{}` is already called `{}`",
+            mrkdwn::code(&from),
+            mrkdwn::code(&to)
+        ));
+    }
+    let to = settings.find_user(&to).unwrap_or(&to).to_owned();
+
+    if let Some(history) = history {
+        history.lock().await.rename_user(&from, &to).await?;
+    }
+
+    Ok(format!(
+        "Renamed user `{}` to `{}`",
+        mrkdwn::code(&from),
+        mrkdwn::code(&to)
+    ))
+}
+
+/// Remove all users from the watchlist, and if `all` is set, go back to the default schedule and
+/// turn off notifications.
+async fn clear(
+    settings: &Arc<RwLock<Repository>>,
+    s_tx: &UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    n_tx: &UnboundedSender<Option<u8>>,
+    all: bool,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    let mut response = match settings.clear_users().await? {
+        0 => String::from("The watchlist is already empty"),
+        count => format!("Removed all {count} users from the watchlist"),
+    };
+
+    if all {
+        if settings
+            .set_schedules(vec![storage::Schedule::default()])
+            .await?
+        {
+            s_tx.send(schedule_inputs(&settings)).ok();
+        }
+        let digest = settings.set_digest_hours(None).await?;
+        if settings.set_notify(false).await? || digest {
+            n_tx.send(None).ok();
+        }
+        response.push_str(", the schedule is back to the default and notifications are disabled");
+    }
+
+    Ok(response)
+}
+
+async fn display_name(
+    settings: &Arc<RwLock<Repository>>,
+    username: String,
+    name: Option<String>,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    let Some(username) = settings.find_user(&username).map(ToOwned::to_owned) else {
+        return Ok(format!(
+            "User `{}` is not in the watchlist",
+            mrkdwn::code(&username)
+        ));
+    };
+
+    Ok(
+        if settings.set_display_name(&username, name.clone()).await? {
+            name.map_or_else(
+                || {
+                    format!(
+                        "User `{}` is shown with the Codewars username again",
+                        mrkdwn::code(&username)
+                    )
+                },
+                |name| {
+                    format!(
+                        "User `{}` is now shown as {}",
+                        mrkdwn::code(&username),
+                        escape(&name)
+                    )
+                },
+            )
+        } else {
+            format!("User `{}` already uses this name", mrkdwn::code(&username))
+        },
+    )
+}
+
+async fn note(
+    settings: &Arc<RwLock<Repository>>,
+    username: String,
+    text: Option<String>,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    let Some(username) = settings.find_user(&username).map(ToOwned::to_owned) else {
+        return Ok(format!(
+            "User `{}` is not in the watchlist",
+            mrkdwn::code(&username)
+        ));
+    };
+
+    Ok(if settings.set_note(&username, text.clone()).await? {
+        if text.is_some() {
+            format!("Saved the note about `{}`", mrkdwn::code(&username))
+        } else {
+            format!("Removed the note about `{}`", mrkdwn::code(&username))
+        }
+    } else {
+        format!("User `{}` already has this note", mrkdwn::code(&username))
+    })
+}
+
+/// List all tracked users with their display name, who added them and when, and their note.
+async fn list_users(settings: &Arc<RwLock<Repository>>) -> Result<String> {
+    let settings = settings.read().await;
+
+    if settings.users().next().is_none() {
+        return Ok(String::from(
+            "Nobody is tracked yet, add someone with `add <user>`",
+        ));
+    }
+
+    let mut response = format!("Here are all {} tracked users:", settings.users().count());
+    for user in settings.users() {
+        write!(response, "\n• `{}`", mrkdwn::code(user))?;
+
+        let Some(info) = settings.user_info(user) else {
+            continue;
+        };
+        if let Some(name) = &info.display_name {
+            write!(response, " shown as {}", escape(name))?;
+        }
+        if let Some(added_by) = &info.added_by {
+            write!(response, ", added by <@{added_by}>")?;
+        }
+        if let Some(added_at) = info.added_at {
+            write!(
+                response,
+                " on {}",
+                added_at.with_timezone(&Local).format("%Y/%m/%d")
+            )?;
+        }
+        if let Some(note) = &info.note {
+            write!(response, "\n      _{}_", escape(note))?;
+        }
+    }
+
+    Ok(response)
+}
+
+/// Create the statistics for the `stats` command. If the same statistics are created for the stats
+/// API right now, their result is shared instead of fetching everything twice.
+async fn stats(
+    ctx: &Context,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    top: Option<usize>,
+) -> Result<String> {
+    stats::record_first_completions(&ctx.settings, &ctx.fetch).await?;
+    let stats = stats::collect_shared(
+        &ctx.stats_flight,
+        &ctx.settings,
+        &ctx.fetch,
+        (since, until, top),
+    )
+    .await;
+
+    stats::render(&stats)
+}
+
+/// Amount of days covered by `stats by tag` without a start date, including today.
+const TAG_DAYS: i64 = 7;
+
+/// Count the challenges that the team completed for each kata tag. Without a start date, the last
+/// few days are covered.
+async fn stats_by_tag(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<String> {
+    let since =
+        since.unwrap_or_else(|| Local::today().naive_local() - Duration::days(TAG_DAYS - 1));
+    let tags = stats::collect_tags(settings, fetch, Period::dates(Some(since), until)).await;
+
+    Ok(stats::render_tags(&tags))
+}
+
+/// Create the statistics like [`stats`], but send them to the user as direct message, so that
+/// personal checks don't clutter the channel.
+async fn stats_to_user(
+    ctx: &Context,
+    user: &str,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    top: Option<usize>,
+) -> Result<String> {
+    let report = stats(ctx, since, until, top).await?;
+
+    Ok(match ctx.dispatcher.send_to_user(user, &report).await {
+        Ok(()) => format!("<@{user}> sent you the stats as direct message :mailbox_with_mail:"),
+        Err(e) => {
+            warn!("Error sending stats to user {}: {:#}", user, e);
+            format!("Sorry <@{user}>, I couldn't send you a direct message. Here are the stats:\n{report}")
+        }
+    })
+}
+
+/// Maximum amount of challenges listed in search results.
+const SEARCH_LIMIT: usize = 10;
+
+async fn search(history: Option<&Mutex<History>>, term: &str) -> Result<String> {
+    let Some(history) = history else {
+        return Ok(String::from(
+            "Searching needs the local challenge history, which is turned off",
+        ));
+    };
+
+    let history = history.lock().await;
+    let found = history.search(term);
+
+    if found.is_empty() {
+        return Ok(format!(
+            "Nobody here completed a challenge matching `{}` yet",
+            mrkdwn::code(term)
+        ));
+    }
+
+    let mut response = format!("Here are the challenges matching `{}`:", mrkdwn::code(term));
+    for (challenge, users) in found.iter().take(SEARCH_LIMIT) {
+        write!(
+            &mut response,
+            "\n• *{}* solved by {}",
+            escape(challenge.name.as_deref().unwrap_or(&challenge.id)),
+            users
+                .iter()
+                .map(|u| format!("`{}`", mrkdwn::code(u)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+    }
+
+    if found.len() > SEARCH_LIMIT {
+        write!(
+            &mut response,
+            "\n_…and {} more, try a longer search term._",
+            found.len() - SEARCH_LIMIT
+        )?;
+    }
+
+    Ok(response)
+}
+
+async fn who_solved(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    kata: &str,
+) -> Result<String> {
+    let users = settings
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    // Resolve slugs and IDs to the kata's ID, otherwise match against names and slugs directly.
+    let challenge = fetch.client().code_challenge(kata).await.ok();
+    let matches = |c: &codewars::CompletedChallenge| {
+        challenge.as_ref().map_or_else(
+            || {
+                [&c.name, &c.slug]
+                    .into_iter()
+                    .flatten()
+                    .any(|v| v.eq_ignore_ascii_case(kata))
+            },
+            |challenge| c.id == challenge.id,
+        )
+    };
+
+    let mut name = challenge.as_ref().map(|c| c.name.clone());
+    let mut solvers = Vec::new();
+
+    for user in users {
+        let challenges = fetch.completed_challenges(&user).await?;
+        if let Some(found) = challenges.data.iter().find(|c| matches(c)) {
+            name = name.or_else(|| found.name.clone());
+
+            let mut languages = found
+                .completed_languages
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            languages.sort_unstable();
+
+            solvers.push(format!(
+                "\n• `{}` in {}",
+                mrkdwn::code(&user),
+                languages.join(", ")
+            ));
+        }
+    }
+
+    let name = escape(name.as_deref().unwrap_or(kata));
+
+    Ok(if solvers.is_empty() {
+        format!("Nobody here solved *{name}* yet")
+    } else {
+        format!("*{name}* was solved by:{}", solvers.concat())
+    })
+}
+
+async fn suggest_kata(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    pool: &[String],
+    kyu: Option<std::ops::RangeInclusive<u8>>,
+) -> Result<String> {
+    let users = settings
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    let Some(kata) = suggest::suggest(fetch, &users, pool, kyu, 1).await?.pop() else {
+        return Ok(String::from(
+            "I couldn't find a kata that nobody here solved yet. Add more katas to the \
+             `suggestions` setting to give me some ideas.",
+        ));
+    };
+
+    let mut languages = kata
+        .languages
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    languages.sort_unstable();
+
+    Ok(format!(
+        ":dart: *Challenge of the day:* <{}|{}> {} {}, available in {}. Nobody here solved it yet!",
+        kata.url,
+        escape(&kata.name),
+        ranks::emoji(kata.rank.id.color()),
+        kata.rank.id,
+        languages.join(", ")
+    ))
+}
+
+async fn poll_start(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    pool: &[String],
+    duration: Duration,
+) -> Result<String> {
+    if settings.read().await.poll().is_some() {
+        return Ok(String::from(
+            "A poll is already running, wait for it to end before starting a new one",
+        ));
+    }
+
+    let users = settings
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    let katas = suggest::suggest(fetch, &users, pool, None, poll::CANDIDATES).await?;
+    if katas.len() < 2 {
+        return Ok(String::from(
+            "I couldn't find enough katas that nobody here solved yet. Add more katas to the \
+             `suggestions` setting to give me some ideas.",
+        ));
+    }
+
+    let new_poll = storage::Poll {
+        ends_at: Utc::now() + duration,
+        candidates: katas.iter().map(|kata| kata.id.clone()).collect(),
+        votes: BTreeMap::new(),
+    };
+
+    if !settings.write().await.start_poll(new_poll.clone()).await? {
+        return Ok(String::from(
+            "A poll is already running, wait for it to end before starting a new one",
+        ));
+    }
+
+    Ok(format!(
+        ":ballot_box_with_ballot: *Vote for the next kata of the week* until `{}` with \
+         `vote <number>`:\n{}",
+        new_poll
+            .ends_at
+            .with_timezone(&Local)
+            .format("%Y/%m/%d %H:%M"),
+        poll::render(fetch, &new_poll).await?
+    ))
+}
+
+async fn poll_status(settings: &Arc<RwLock<Repository>>, fetch: &FetchCache) -> Result<String> {
+    let (current, kata_of_the_week) = {
+        let settings = settings.read().await;
+        (
+            settings.poll().cloned(),
+            settings.kata_of_the_week().map(ToOwned::to_owned),
+        )
+    };
+
+    let mut msg = String::new();
+
+    if let Some(id) = kata_of_the_week {
+        let kata = fetch.code_challenge(&id).await?;
+        writeln!(
+            msg,
+            ":star: The current kata of the week is <{}|{}>",
+            kata.url,
+            escape(&kata.name)
+        )?;
+    }
+
+    match current {
+        Some(current) => write!(
+            msg,
+            ":ballot_box_with_ballot: *Current votes*, the poll ends on `{}`:\n{}",
+            current
+                .ends_at
+                .with_timezone(&Local)
+                .format("%Y/%m/%d %H:%M"),
+            poll::render(fetch, &current).await?
+        )?,
+        None => msg.push_str("There is no poll running right now"),
+    }
+
+    Ok(msg)
+}
+
+async fn vote(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    user: &str,
+    choice: usize,
+) -> Result<String> {
+    let candidate = |settings: &Repository| {
+        settings
+            .poll()
+            .and_then(|p| p.candidates.get(choice - 1))
+            .cloned()
+    };
+
+    let id = {
+        let settings = settings.read().await;
+        let Some(id) = candidate(&settings) else {
+            return Ok(settings.poll().map_or_else(
+                || String::from("There is no poll running right now"),
+                |p| {
+                    format!(
+                        "Please vote for one of the katas from 1 to {}",
+                        p.candidates.len()
+                    )
+                },
+            ));
+        };
+        id
+    };
+
+    // The kata is fetched without holding the lock, so other commands aren't blocked meanwhile.
+    let name = escape(&fetch.code_challenge(&id).await?.name);
+
+    let mut settings = settings.write().await;
+    if candidate(&settings).as_ref() != Some(&id) {
+        return Ok(String::from(
+            "The poll changed while voting, please check it and vote again",
+        ));
+    }
+
+    Ok(if settings.vote(user, choice - 1).await? {
+        format!("<@{user}> voted for *{name}*")
+    } else {
+        format!("<@{user}> already voted for *{name}*")
+    })
+}
+
+async fn set_goal(settings: &Arc<RwLock<Repository>>, target: u32) -> Result<String> {
+    let goal = (target > 0).then_some(target);
+
+    Ok(if settings.write().await.set_goal(goal).await? {
+        goal.map_or_else(
+            || String::from("The team goal was removed"),
+            |target| format!("The team goal is now {target} katas per week"),
+        )
+    } else {
+        String::from("The team goal is unchanged")
+    })
+}
+
+async fn goal_status(settings: &Arc<RwLock<Repository>>, fetch: &FetchCache) -> Result<String> {
+    Ok(goal_progress(settings, fetch).await?.map_or_else(
+        || String::from("There is no team goal yet, set one with `goal set <count> per week`"),
+        |progress| format!("Progress of the team goal this week: {progress}"),
+    ))
+}
+
+pub async fn review(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    year: i32,
+) -> Result<String> {
+    let (users, names) = {
+        let settings = settings.read().await;
+        (
+            settings.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            settings.display_names(),
+        )
+    };
+
+    review::create(fetch, &users, &names, year).await
+}
+
+async fn schedule(
+    settings: &Arc<RwLock<Repository>>,
+    s_tx: &UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    schedule: storage::Schedule,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    Ok(if settings.set_schedule(schedule).await? {
+        s_tx.send(schedule_inputs(&settings)).ok();
+        format!("Schedule updated to send stats `{schedule}`")
+    } else {
+        format!("Schedule already set to send stats `{schedule}`")
+    })
+}
+
+async fn schedule_add(
+    settings: &Arc<RwLock<Repository>>,
+    s_tx: &UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    schedule: storage::Schedule,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    Ok(if settings.add_schedule(schedule).await? {
+        s_tx.send(schedule_inputs(&settings)).ok();
+        format!("Added a schedule to send stats `{schedule}`")
+    } else {
+        format!("A schedule to send stats `{schedule}` exists already")
+    })
+}
+
+async fn schedule_remove(
+    settings: &Arc<RwLock<Repository>>,
+    s_tx: &UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    index: usize,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    let removed = settings.remove_schedule(index - 1).await?;
+    if removed.is_some() {
+        s_tx.send(schedule_inputs(&settings)).ok();
+    }
+
+    Ok(removed.map_or_else(
+        || format!("There is no schedule number `{index}`, see `schedule list`"),
+        |schedule| format!("Removed the schedule to send stats `{schedule}`"),
+    ))
+}
+
+async fn schedule_list(settings: &Arc<RwLock<Repository>>) -> Result<String> {
+    let settings = settings.read().await;
+
+    if settings.schedules().is_empty() {
+        return Ok(String::from(
+            "No reports are scheduled, add one with `schedule add <frequency> [at <time>]`",
+        ));
+    }
+
+    let mut response = String::from("Here are all schedules:");
+    for (idx, schedule) in settings.schedules().iter().enumerate() {
+        let next_run = scheduling::FrequencyScheduler::next(schedule.input());
+        write!(
+            &mut response,
+            "\n{}. `{}`, next run on `{}`",
+            idx + 1,
+            schedule,
+            next_run.format("%Y/%m/%d %H:%M"),
+        )?;
+    }
+
+    Ok(response)
+}
+
+async fn notify(
+    settings: &Arc<RwLock<Repository>>,
+    n_tx: &UnboundedSender<Option<u8>>,
+    on_off: bool,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+    let digest = settings.set_digest_hours(None).await?;
+
+    Ok(if settings.set_notify(on_off).await? || digest {
+        let msg = on_off.then(|| settings.notify_interval());
+        n_tx.send(msg).ok();
+        format!(
+            "Notifications {}",
+            if on_off { "enabled" } else { "disabled" }
+        )
+    } else {
+        format!(
+            "Notifications already {}",
+            if settings.notify() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )
+    })
+}
+
+async fn notify_digest(
+    settings: &Arc<RwLock<Repository>>,
+    n_tx: &UnboundedSender<Option<u8>>,
+    interval: Duration,
+) -> Result<String> {
+    let hours = u32::try_from(interval.num_hours()).unwrap_or(u32::MAX);
+    if hours == 0 {
+        return Ok(String::from("The digest interval must be at least 1 hour"));
+    }
+    let interval = humantime::format_duration(interval.to_std()?);
+    let mut settings = settings.write().await;
+    let enabled = settings.set_notify(true).await?;
+    let changed = settings.set_digest_hours(Some(hours)).await?;
+
+    if enabled {
+        n_tx.send(Some(settings.notify_interval())).ok();
+    }
+
+    Ok(if enabled || changed {
+        format!("Notifications enabled as digest every `{interval}`")
+    } else {
+        format!("Notifications already sent as digest every `{interval}`")
+    })
+}
+
+async fn notify_every(
+    settings: &Arc<RwLock<Repository>>,
+    n_tx: &UnboundedSender<Option<u8>>,
+    hours: u8,
+) -> Result<String> {
+    let mut settings = settings.write().await;
+
+    Ok(if settings.set_notify_interval(hours).await? {
+        if settings.notify() {
+            n_tx.send(Some(hours)).ok();
+        }
+        format!("New challenges are now checked every `{hours}` hours")
+    } else {
+        format!("New challenges are already checked every `{hours}` hours")
+    })
+}
+
+async fn notify_snooze(
+    settings: &Arc<RwLock<Repository>>,
+    duration: Option<Duration>,
+) -> Result<String> {
+    let until = duration.map(|duration| Utc::now() + duration);
+    let changed = settings.write().await.set_snooze_until(until).await?;
+
+    Ok(match until {
+        Some(until) => format!(
+            "Notifications are snoozed until `{}`",
+            until.with_timezone(&Local).format("%Y/%m/%d %H:%M")
+        ),
+        None if changed => String::from(
+            "Notifications are no longer snoozed, anything held back follows with the next check",
+        ),
+        None => String::from("Notifications aren't snoozed"),
+    })
+}
+
+async fn notify_quiet(
+    settings: &Arc<RwLock<Repository>>,
+    range: Option<(NaiveTime, NaiveTime)>,
+) -> Result<String> {
+    let quiet_hours = range.map(|(start, end)| QuietHours { start, end });
+
+    Ok(
+        if settings.write().await.set_quiet_hours(quiet_hours).await? {
+            quiet_hours.map_or_else(
+                || String::from("Quiet hours removed, notifications are sent at any time again"),
+                |quiet| {
+                    format!(
+                        "Notifications are held back every day from `{}` to `{}` and sent \
+                         afterwards",
+                        quiet.start.format("%R"),
+                        quiet.end.format("%R")
+                    )
+                },
+            )
+        } else {
+            String::from("The quiet hours are already set like this")
+        },
+    )
+}
+
+async fn notify_filter_kyu(settings: &Arc<RwLock<Repository>>, kyu: Option<u8>) -> Result<String> {
+    Ok(if settings.write().await.set_notify_min_kyu(kyu).await? {
+        kyu.map_or_else(
+            || String::from("Notifications include challenges of any kyu again"),
+            |kyu| format!("Notifications only include challenges of {kyu} kyu or harder"),
+        )
+    } else {
+        String::from("The kyu filter of notifications is already set like this")
+    })
+}
+
+async fn notify_filter_lang(
+    settings: &Arc<RwLock<Repository>>,
+    languages: BTreeSet<String>,
+) -> Result<String> {
+    let list = languages
+        .iter()
+        .map(|lang| format!("`{lang}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Ok(
+        if settings
+            .write()
+            .await
+            .set_notify_languages(languages)
+            .await?
+        {
+            if list.is_empty() {
+                String::from("Notifications include challenges in any language again")
+            } else {
+                format!("Notifications only include challenges completed in {list}")
+            }
+        } else {
+            String::from("The language filter of notifications is already set like this")
+        },
+    )
+}
+
+/// Give a short hint about how to use the bot, with the most used commands as examples.
+pub async fn hint(settings: &Arc<RwLock<Repository>>) -> Result<String> {
+    let settings = settings.read().await;
+    let mut usage = settings
+        .usage()
+        .iter()
+        .filter(|(command, _)| *command != "help")
+        .collect::<Vec<_>>();
+    usage.sort_by_key(|(_, u)| std::cmp::Reverse(u.count));
+
+    let commands = if usage.is_empty() {
+        String::from("`stats`, `add <user>`, `status`")
+    } else {
+        usage
+            .iter()
+            .take(3)
+            .map(|(command, _)| format!("`{command}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    Ok(format!(
+        "Hi there :wave: I didn't catch a command in that. Popular ones are {commands}, or say \
+         `help` to see everything I can do."
+    ))
+}
+
+async fn botstats(settings: &Arc<RwLock<Repository>>) -> Result<String> {
+    let settings = settings.read().await;
+    let mut usage = settings.usage().iter().collect::<Vec<_>>();
+    usage.sort_by_key(|(_, u)| std::cmp::Reverse(u.count));
+
+    if usage.is_empty() {
+        return Ok(String::from("No commands were used yet"));
+    }
+
+    let mut response = String::from("Here is how often each command was used:");
+    for (command, usage) in usage {
+        write!(&mut response, "\n• `{}`: {}", command, usage.count)?;
+
+        if !usage.options.is_empty() {
+            let options = usage
+                .options
+                .iter()
+                .map(|(option, count)| format!("`{option}` {count}"))
+                .collect::<Vec<_>>();
+            write!(&mut response, " ({})", options.join(", "))?;
+        }
+    }
+
+    Ok(response)
+}
+
+/// Amount of commands that `audit` shows, if not given.
+const AUDIT_DEFAULT: usize = 10;
+
+/// List the most recently executed commands, latest first.
+async fn audit(settings: &Arc<RwLock<Repository>>, limit: usize) -> Result<String> {
+    let settings = settings.read().await;
+
+    if settings.audit().is_empty() {
+        return Ok(String::from("No commands were run yet"));
+    }
+
+    let mut response = String::from("Here are the latest commands:");
+    for entry in settings.audit().iter().rev().take(limit) {
+        write!(
+            &mut response,
+            "\n• {} <@{}> `{}` {}",
+            entry.at.with_timezone(&Local).format("%Y/%m/%d %H:%M"),
+            entry.user,
+            mrkdwn::code(&entry.command),
+            match entry.outcome {
+                Outcome::Success => ":white_check_mark:",
+                Outcome::Failure => ":x:",
+                Outcome::Timeout => ":hourglass:",
+            },
+        )?;
+    }
+
+    Ok(response)
+}
+
+async fn run_now(task: &StatsTask, mark: bool) -> Result<String> {
+    if let Report::Skipped(reason) = task.report(mark).await? {
+        return Ok(format!("Scheduled report skipped, {reason}"));
+    }
+
+    Ok(if mark {
+        String::from("Scheduled report sent and last run time updated")
+    } else {
+        String::from("Scheduled report sent, last run time left unchanged")
+    })
+}
+
+async fn retrains(settings: &Arc<RwLock<Repository>>, include: bool) -> Result<String> {
+    Ok(
+        if settings
+            .write()
+            .await
+            .set_exclude_retrains(!include)
+            .await?
+        {
+            format!(
+                "Re-trained challenges are now {} reports",
+                if include {
+                    "included in"
+                } else {
+                    "left out of"
+                }
+            )
+        } else {
+            String::from("Reports already use this setting for re-trained challenges")
+        },
+    )
+}
+
+async fn maintenance_mode(settings: &Arc<RwLock<Repository>>, on: bool) -> Result<String> {
+    Ok(if settings.write().await.set_maintenance(on).await? {
+        if on {
+            String::from(
+                ":construction: Maintenance started, scheduled messages are suspended until it ends",
+            )
+        } else {
+            String::from("Maintenance ended, everything is back to normal")
+        }
+    } else {
+        format!(
+            "Maintenance is already {}",
+            if on { "running" } else { "over" }
+        )
+    })
+}
+
+/// Switch reports and notifications to a new webhook URL, after a test message was delivered to it.
+async fn set_webhook(
+    settings: &Arc<RwLock<Repository>>,
+    dispatcher: &Dispatcher,
+    url: Url,
+) -> Result<String> {
+    if !is_slack_webhook(&url) {
+        return Ok(String::from(SLACK_WEBHOOKS_ONLY));
+    }
+
+    if settings.read().await.webhook_url() == Some(&url) {
+        return Ok(String::from("Reports are already posted to this webhook"));
+    }
+
+    if let Err(e) = dispatcher
+        .set_primary(
+            url.clone(),
+            ":wave: Reports and notifications of the Codewars bot are posted to this channel from \
+             now on",
+        )
+        .await
+    {
+        // The error can contain the response of any server, which isn't shown in the channel.
+        warn!("Error probing new webhook: {:#}", e);
+        return Ok(String::from(
+            "The webhook doesn't work, keeping the current one. Please check the URL and that the \
+             app is still installed in the channel",
+        ));
+    }
+
+    settings.write().await.set_webhook_url(url).await?;
+
+    Ok(String::from(
+        "Switched to the new webhook, reports and notifications are posted there from now on",
+    ))
+}
+
+/// Message for webhook URLs that don't belong to Slack.
+const SLACK_WEBHOOKS_ONLY: &str = "Only Slack webhook URLs starting with \
+                                   `https://hooks.slack.com/` are supported";
+
+/// Whether the URL is a Slack webhook, so the bot doesn't post to arbitrary servers.
+fn is_slack_webhook(url: &Url) -> bool {
+    url.scheme() == "https" && url.host_str() == Some("hooks.slack.com")
+}
+
+/// Post reports and notifications to another webhook URL, after a test message was delivered to it.
+async fn webhook_add(
+    settings: &Arc<RwLock<Repository>>,
+    dispatcher: &Dispatcher,
+    url: Url,
+) -> Result<String> {
+    if !is_slack_webhook(&url) {
+        return Ok(String::from(SLACK_WEBHOOKS_ONLY));
+    }
+
+    if dispatcher.stats().iter().any(|(target, ..)| *target == url) {
+        return Ok(String::from("Reports are already posted to this webhook"));
+    }
+
+    if let Err(e) = dispatcher
+        .add_target(
+            url.clone(),
+            ":wave: Reports and notifications of the Codewars bot are posted to this channel as \
+             well from now on",
+        )
+        .await
+    {
+        warn!("Error probing additional webhook: {:#}", e);
+        return Ok(String::from(
+            "The webhook doesn't work. Please check the URL and that the app is installed in the \
+             channel",
+        ));
+    }
+
+    settings.write().await.add_webhook(url).await?;
+
+    Ok(String::from(
+        "Added the webhook, reports and notifications are posted there as well from now on",
+    ))
+}
+
+/// Stop posting to a webhook URL that was added with the `webhook add` command.
+async fn webhook_remove(
+    settings: &Arc<RwLock<Repository>>,
+    dispatcher: &Dispatcher,
+    url: Url,
+) -> Result<String> {
+    if !settings.write().await.remove_webhook(&url).await? {
+        return Ok(String::from(
+            "This webhook wasn't added with `webhook add`, webhooks from the settings can only be \
+             removed there",
+        ));
+    }
+
+    dispatcher.remove_target(&url);
+
+    Ok(String::from(
+        "Removed the webhook, nothing is posted there anymore",
+    ))
+}
+
+/// Subscribe a member to the progress of their own Codewars account, after making sure it exists
+/// and that the member can receive direct messages.
+async fn subscribe(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    dispatcher: &Dispatcher,
+    user: &str,
+    username: String,
+) -> Result<String> {
+    let username = match fetch.user(&username).await {
+        Ok(profile) => profile.username.clone(),
+        Err(codewars::Error::UserNotFound(_)) => {
+            return Ok(format!(
+                "User `{}` doesn't exist on Codewars, please check the spelling",
+                mrkdwn::code(&username)
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let welcome = format!(
+        ":wave: You'll get a direct message whenever `{}` completes a kata or ranks up. Use \
+         `unsubscribe me` to stop them again.",
+        mrkdwn::code(&username)
+    );
+    if let Err(e) = dispatcher.send_to_user(user, &welcome).await {
+        warn!("Error sending direct message to {}: {:#}", user, e);
+        return Ok(format!(
+            "Sorry <@{user}>, I can't send you direct messages, so you can't subscribe"
+        ));
+    }
+
+    settings.write().await.subscribe(user, username).await?;
+
+    Ok(format!(
+        "<@{user}> you're subscribed to your own progress :bell:"
+    ))
+}
+
+/// Stop the direct messages about the member's own progress.
+async fn unsubscribe(settings: &Arc<RwLock<Repository>>, user: &str) -> Result<String> {
+    Ok(if settings.write().await.unsubscribe(user).await? {
+        format!("<@{user}> you won't get direct messages about your progress anymore")
+    } else {
+        format!("<@{user}> you aren't subscribed to your progress")
+    })
+}
+
+async fn status(
+    settings: &Arc<RwLock<Repository>>,
+    dispatcher: &Dispatcher,
+    started: Instant,
+) -> Result<String> {
+    let settings = settings.read().await;
+    let next_run = settings
+        .schedules()
+        .iter()
+        .map(|s| scheduling::FrequencyScheduler::next(s.input()))
+        .min()
+        .map_or_else(
+            || String::from("none"),
+            |next| next.format("%Y/%m/%d %H:%M").to_string(),
+        );
+    let uptime = std::time::Duration::from_secs(started.elapsed().as_secs());
+
+    let mut response = format!(
+        "Here is the current configuration:\n\
+        • Schedules: {}, next run on `{}`\n\
+        • Notifications: `{}`{}{}, checked every `{}` hours\n\
+        • Tracked users: `{}`\n\
+        • Maintenance: `{}`\n\
+        • Uptime: `{}`",
+        schedule_names(settings.schedules()),
+        next_run,
+        if settings.notify() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        settings
+            .digest_hours()
+            .map(|hours| format!(" as digest every `{hours}h`"))
+            .unwrap_or_default(),
+        notify_info(&settings),
+        settings.notify_interval(),
+        settings.users().count(),
+        if settings.maintenance() { "on" } else { "off" },
+        humantime::format_duration(uptime),
+    );
+
+    for (url, route, sent, failed, paused) in dispatcher.stats() {
+        write!(
+            &mut response,
+            "\n• Webhook `{}`{}: `{}` delivered, `{}` failed{}",
+            url.host_str().unwrap_or_default(),
+            match route {
+                Some(Route::Stats) => " (stats only)",
+                Some(Route::Notify) => " (notifications only)",
+                None => "",
+            },
+            sent,
+            failed,
+            if paused { ", *paused*" } else { "" }
+        )?;
+    }
+
+    Ok(response)
+}
+
+/// List the weekday and time of all schedules in a single line.
+pub fn schedule_names(schedules: &[storage::Schedule]) -> String {
+    if schedules.is_empty() {
+        return String::from("`none`");
+    }
+
+    schedules
+        .iter()
+        .map(|s| format!("`{s}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Describe the restrictions of the notification filter and when notifications are held back, if
+/// there are any.
+fn notify_info(settings: &Repository) -> String {
+    let filter = settings.notify_filter();
+    let mut info = String::new();
+
+    if let Some(kyu) = filter.min_kyu {
+        write!(info, " for `{kyu} kyu` or harder").ok();
+    }
+    if !filter.languages.is_empty() {
+        write!(
+            info,
+            " in `{}`",
+            filter
+                .languages
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .ok();
+    }
+    if let Some(quiet) = settings.quiet_hours() {
+        write!(
+            info,
+            ", quiet from `{}` to `{}`",
+            quiet.start.format("%R"),
+            quiet.end.format("%R")
+        )
+        .ok();
+    }
+    if let Some(until) = settings.snooze_until().filter(|until| *until > Utc::now()) {
+        write!(
+            info,
+            ", snoozed until `{}`",
+            until.with_timezone(&Local).format("%Y/%m/%d %H:%M")
+        )
+        .ok();
+    }
+
+    info
+}
+
+/// Create a checklist of all steps to fully configure the bot, to guide new admins through the
+/// setup.
+async fn setup(settings: &Arc<RwLock<Repository>>, dispatcher: &Dispatcher) -> Result<String> {
+    let steps = home::checklist(&*settings.read().await, dispatcher.any_paused());
+    let mut response = String::from("Here is the setup checklist:");
+
+    for step in steps {
+        write!(&mut response, "\n{}", step.line())?;
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,121 @@
+//! Before and after views of the bot configuration, so changes by an admin are transparent to the
+//! whole team.
+
+use crate::{mrkdwn, storage::Repository};
+
+/// Readable values of all settings that are worth announcing when they change.
+pub struct Snapshot(Vec<(&'static str, String)>);
+
+impl Snapshot {
+    /// Capture the current configuration of the repository.
+    pub fn new(repo: &Repository) -> Self {
+        let filter = repo.notify_filter();
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_owned();
+
+        Self(vec![
+            (
+                "Schedules",
+                if repo.schedules().is_empty() {
+                    "off".to_owned()
+                } else {
+                    repo.schedules()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ),
+            ("Notifications", on_off(repo.notify())),
+            ("Check interval", format!("{}h", repo.notify_interval())),
+            (
+                "Digest",
+                repo.digest_hours()
+                    .map_or_else(|| "off".to_owned(), |hours| format!("every {hours}h")),
+            ),
+            (
+                "Quiet hours",
+                repo.quiet_hours().map_or_else(
+                    || "off".to_owned(),
+                    |quiet| format!("{}-{}", quiet.start.format("%R"), quiet.end.format("%R")),
+                ),
+            ),
+            (
+                "Kyu filter",
+                filter
+                    .min_kyu
+                    .map_or_else(|| "off".to_owned(), |kyu| format!("{kyu} kyu or harder")),
+            ),
+            (
+                "Language filter",
+                if filter.languages.is_empty() {
+                    "off".to_owned()
+                } else {
+                    filter
+                        .languages
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ),
+            ("Re-trains", on_off(!repo.exclude_retrains())),
+            (
+                "Team goal",
+                repo.goal()
+                    .map_or_else(|| "off".to_owned(), |goal| format!("{goal} katas per week")),
+            ),
+        ])
+    }
+}
+
+/// List every setting that differs between the two snapshots, as `old → new`.
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    before
+        .0
+        .iter()
+        .zip(&after.0)
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((name, old), (_, new))| {
+            format!(
+                "• {name}: `{}` → `{}`",
+                mrkdwn::code(old),
+                mrkdwn::code(new)
+            )
+        })
+        .collect()
+}
+
+/// Announce the configuration changes made by a Slack user, if there are any.
+pub fn announce(user: &str, before: &Snapshot, after: &Snapshot) -> Option<String> {
+    let changes = diff(before, after);
+
+    (!changes.is_empty()).then(|| {
+        format!(
+            ":gear: <@{user}> changed the configuration:\n{}",
+            changes.join("\n")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_changed_values_only() {
+        let before = Snapshot(vec![
+            ("Schedule", "Sundays at 10:00".to_owned()),
+            ("Notifications", "off".to_owned()),
+        ]);
+        let after = Snapshot(vec![
+            ("Schedule", "Mondays at 09:30".to_owned()),
+            ("Notifications", "off".to_owned()),
+        ]);
+
+        assert_eq!(
+            vec!["• Schedule: `Sundays at 10:00` → `Mondays at 09:30`"],
+            diff(&before, &after)
+        );
+        assert!(announce("U123", &before, &before).is_none());
+    }
+}
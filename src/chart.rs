@@ -0,0 +1,165 @@
+//! Simple bar charts of the completed challenges per user, rendered as PNG image. The chart itself
+//! has no labels, instead each bar's color matches a square emoji in the legend that is posted
+//! together with the image.
+
+use std::{fmt::Write as _, io::Write as _};
+
+use anyhow::Result;
+use flate2::{write::ZlibEncoder, Compression};
+
+/// Width of the image in pixels.
+const WIDTH: usize = 600;
+/// Height of a single bar in pixels.
+const BAR_HEIGHT: usize = 24;
+/// Space around the chart and between the bars in pixels.
+const PADDING: usize = 12;
+
+/// Background color of the chart.
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+/// Color of the grid lines.
+const GRID: [u8; 3] = [0xe0, 0xe0, 0xe0];
+
+/// Colors of the bars, together with the Slack emoji of the same color, used in turn for each
+/// user.
+const PALETTE: &[([u8; 3], &str)] = &[
+    ([0x55, 0xac, 0xee], ":large_blue_square:"),
+    ([0x78, 0xb1, 0x59], ":large_green_square:"),
+    ([0xf4, 0x90, 0x0c], ":large_orange_square:"),
+    ([0xaa, 0x8e, 0xd6], ":large_purple_square:"),
+    ([0xdd, 0x2e, 0x44], ":large_red_square:"),
+    ([0xfd, 0xcb, 0x58], ":large_yellow_square:"),
+    ([0xc1, 0x69, 0x4f], ":large_brown_square:"),
+];
+
+/// Render a horizontal bar for each of the labeled counts, from top to bottom, as PNG image.
+/// Vertical grid lines help to estimate the counts.
+pub fn render(counts: &[(String, usize)]) -> Result<Vec<u8>> {
+    let height = PADDING + counts.len().max(1) * (BAR_HEIGHT + PADDING);
+    let max = counts
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let scale = |count: usize| count * (WIDTH - 2 * PADDING) / max;
+
+    let mut pixels = vec![BACKGROUND; WIDTH * height];
+
+    for line in (0..=max).step_by(max.div_ceil(10)) {
+        let x = PADDING + scale(line);
+        for y in 0..height {
+            pixels[y * WIDTH + x] = GRID;
+        }
+    }
+
+    for (i, (_, count)) in counts.iter().enumerate() {
+        let top = PADDING + i * (BAR_HEIGHT + PADDING);
+        let width = scale(*count);
+        let color = PALETTE[i % PALETTE.len()].0;
+
+        for y in top..top + BAR_HEIGHT {
+            pixels[y * WIDTH + PADDING..y * WIDTH + PADDING + width].fill(color);
+        }
+    }
+
+    encode(&pixels, height)
+}
+
+/// Legend of the chart, that lists the labels with the emoji of their bar's color and the count.
+pub fn legend(counts: &[(String, usize)]) -> Result<String> {
+    let mut legend = String::from("Completed challenges per user:");
+
+    for (i, (label, count)) in counts.iter().enumerate() {
+        write!(
+            legend,
+            "\n{} {label}: {count}",
+            PALETTE[i % PALETTE.len()].1
+        )?;
+    }
+
+    Ok(legend)
+}
+
+/// Encode RGB pixels, row by row, as PNG image.
+fn encode(pixels: &[[u8; 3]], height: usize) -> Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&u32::try_from(WIDTH)?.to_be_bytes());
+    header.extend_from_slice(&u32::try_from(height)?.to_be_bytes());
+    // 8 bits per channel, RGB, default compression and filters, no interlacing.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut data = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(WIDTH) {
+        // Each row starts with its filter type, which is none.
+        data.write_all(&[0])?;
+        data.write_all(row.as_flattened())?;
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, *b"IHDR", &header)?;
+    write_chunk(&mut png, *b"IDAT", &data.finish()?)?;
+    write_chunk(&mut png, *b"IEND", &[])?;
+
+    Ok(png)
+}
+
+/// Append a single PNG chunk, with its length and checksum.
+fn write_chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&kind);
+    crc.update(data);
+
+    png.extend_from_slice(&u32::try_from(data.len())?.to_be_bytes());
+    png.extend_from_slice(&kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    #[test]
+    fn render_bars() {
+        let counts = [("him".to_owned(), 4), ("her".to_owned(), 2)];
+        let png = render(&counts).unwrap();
+
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!(600, u32::from_be_bytes(png[16..20].try_into().unwrap()));
+        assert_eq!(84, u32::from_be_bytes(png[20..24].try_into().unwrap()));
+        assert_eq!(b"IEND", &png[png.len() - 8..png.len() - 4]);
+
+        let len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&png[41..41 + len])
+            .read_to_end(&mut raw)
+            .unwrap();
+        let pixel = |x: usize, y: usize| &raw[y * (WIDTH * 3 + 1) + 1 + x * 3..][..3];
+
+        // The first bar spans the whole width, the second only half of it.
+        assert_eq!(&PALETTE[0].0, pixel(WIDTH - PADDING - 1, PADDING));
+        assert_eq!(
+            &PALETTE[1].0,
+            pixel(WIDTH / 2 - 1, 2 * PADDING + BAR_HEIGHT)
+        );
+        assert_eq!(&BACKGROUND, pixel(WIDTH / 2 + 20, 2 * PADDING + BAR_HEIGHT));
+    }
+
+    #[test]
+    fn render_legend() {
+        let counts = [("`him`".to_owned(), 4), ("Her".to_owned(), 0)];
+
+        assert_eq!(
+            "Completed challenges per user:\n:large_blue_square: `him`: 4\n\
+             :large_green_square: Her: 0",
+            legend(&counts).unwrap()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Command line interface to run the service or use some of its functionality locally.
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+
+use crate::report::Format;
+
+/// A Slack bot to report Codewars statistics.
+#[derive(Parser)]
+#[command(about, version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub cmd: Option<Cmd>,
+    /// Only log outgoing messages instead of posting them, and keep any changes to the state in
+    /// memory. Applies to `serve` and `report`.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+}
+
+/// All available subcommands. If none is given, the server is started.
+#[derive(Subcommand)]
+pub enum Cmd {
+    /// Run the bot server (default).
+    Serve,
+    /// Load and validate the settings and state file, then print a summary of them.
+    CheckConfig,
+    /// Send the scheduled stats report to the webhooks once and exit, to drive the schedule from
+    /// an external scheduler like cron.
+    ///
+    /// With `--from`, a report over the given dates is rendered from the local history instead,
+    /// without sending anything to Slack.
+    Report {
+        /// Keep the time of the last run unchanged.
+        #[arg(long, conflicts_with = "from")]
+        no_mark: bool,
+        /// First day of the offline report, in `YYYY-MM-DD` format.
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day of the offline report, in `YYYY-MM-DD` format. Defaults to today.
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        /// Output format of the offline report.
+        #[arg(long, value_enum, default_value = "md", requires = "from")]
+        format: Format,
+        /// File to write the offline report to, instead of stdout.
+        #[arg(short, long, requires = "from")]
+        output: Option<PathBuf>,
+    },
+    /// Print the statistics report to stdout instead of sending it to Slack.
+    Stats {
+        /// Codewars user to include in the report. Can be repeated and defaults to all tracked
+        /// users.
+        #[arg(short, long)]
+        user: Vec<String>,
+        /// Only include challenges completed since this date, in `YYYY/MM/DD` format.
+        #[arg(short, long, value_parser = parse_date)]
+        since: Option<NaiveDate>,
+        /// Only include challenges completed until this date, including the day itself.
+        #[arg(long, value_parser = parse_date, requires = "since")]
+        until: Option<NaiveDate>,
+        /// Only include the given amount of users with the most completed challenges.
+        #[arg(short, long)]
+        top: Option<usize>,
+    },
+}
+
+/// Parse a date argument in the same format that Slack commands use.
+fn parse_date(value: &str) -> Result<NaiveDate, chrono::ParseError> {
+    NaiveDate::parse_from_str(value, "%Y/%m/%d")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+}
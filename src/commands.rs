@@ -2,10 +2,18 @@
 
 #![allow(clippy::upper_case_acronyms)]
 
-use chrono::{NaiveDate, NaiveTime, Weekday};
-use pest::Parser;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
+use chrono::{Duration, NaiveDate, NaiveTime};
+use pest::{error::InputLocation, iterators::Pair, Parser};
 use pest_derive::Parser;
 use thiserror::Error;
+use url::Url;
+
+use crate::scheduling::Frequency;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -15,20 +23,48 @@ pub enum Error {
     CommandMissing,
     #[error("Username missing")]
     UsernameMissing,
-    #[error("Weekday missing")]
-    WeekdayMissing,
+    #[error("Search term missing")]
+    TermMissing,
     #[error("Boolean missing")]
     BooleanMissing,
     #[error("Failed parsing date or time")]
     InvalidDateTime(#[from] chrono::ParseError),
     #[error("Invalid weekday")]
     InvalidWeekday(chrono::ParseWeekdayError),
+    #[error("Kyu must be between 1 and 8")]
+    InvalidKyu,
+    #[error("Duration must be at least one hour")]
+    InvalidDuration,
+    #[error("Invalid poll choice")]
+    InvalidChoice,
+    #[error("Day must be between 1 and 31")]
+    InvalidDay,
+    #[error("Invalid schedule number")]
+    InvalidIndex,
+    #[error("Hours must be between 1 and 24")]
+    InvalidHours,
+    #[error("The end date must not be before the start date")]
+    InvalidRange,
+    #[error("Invalid year")]
+    InvalidYear,
+    #[error("Invalid goal target")]
+    InvalidTarget,
     #[error("Invalid boolean")]
     InvalidBoolean,
+    #[error("Invalid webhook URL")]
+    InvalidUrl,
     #[error("Unknown command")]
     UnknownCommand,
+    #[error("Invalid arguments, the syntax is `{0}`")]
+    InvalidArguments(&'static str),
     #[error("Invalid command input")]
-    InvalidInput(#[from] pest::error::Error<Rule>),
+    InvalidInput(Box<pest::error::Error<Rule>>),
+}
+
+impl From<pest::error::Error<Rule>> for Error {
+    fn from(e: pest::error::Error<Rule>) -> Self {
+        Self::InvalidInput(Box::new(e))
+    }
 }
 
 /// The actual parser that uses PEST grammar to parse text messages.
@@ -44,81 +80,711 @@ pub enum Command {
     AddUser(String),
     /// Stop tracking a user.
     RemoveUser(String),
-    /// Get and report Codewars statistics with optional start date.
-    Stats(Option<NaiveDate>),
+    /// Change the username of a tracked user, who renamed their Codewars account.
+    RenameUser(String, String),
+    /// Stop tracking all users, and if set, reset the schedules and notifications as well.
+    Clear(bool),
+    /// Get and report Codewars statistics with optional start and end date, optionally limited to
+    /// the most active users.
+    Stats {
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        top: Option<usize>,
+        /// Send the report as direct message to the requesting user, instead of the channel.
+        direct: bool,
+    },
+    /// Show how many challenges the team completed for each kata tag within the given dates.
+    StatsByTag {
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    },
+    /// Show the team's ranks and scores for each language.
+    Languages,
     /// Show a help message.
     Help,
-    /// Update the schedule for weekly reports.
-    Schedule(Weekday, NaiveTime),
+    /// Show a single page of the help message, counting from one.
+    HelpPage(usize),
+    /// Show the current configuration of the bot.
+    Status,
+    /// Show how often each command and option was used.
+    BotStats,
+    /// Show the most recently executed commands, optionally limited to the given amount.
+    Audit(Option<usize>),
+    /// Send the scheduled report immediately, optionally without updating the last run time.
+    RunNow(bool),
+    /// Update the schedule for reports.
+    Schedule(Frequency, NaiveTime),
+    /// Add another schedule for reports.
+    ScheduleAdd(Frequency, NaiveTime),
+    /// Remove one of the schedules for reports, counting from one.
+    ScheduleRemove(usize),
+    /// List all schedules for reports.
+    ScheduleList,
     /// Turn automatic notifications of new challenges on or off.
     Notify(bool),
+    /// Enable notifications, batched into a single digest for the given interval.
+    NotifyDigest(Duration),
+    /// Check for new challenges to notify about every given amount of hours.
+    NotifyEvery(u8),
+    /// Hold back notifications for the given duration, or resume them right away.
+    NotifySnooze(Option<Duration>),
+    /// Hold back notifications every day between the start and end time, or remove the window.
+    NotifyQuiet(Option<(NaiveTime, NaiveTime)>),
+    /// Only notify about challenges of the given kyu or harder, or remove the restriction.
+    NotifyFilterKyu(Option<u8>),
+    /// Only notify about challenges completed in one of the languages, where none removes the
+    /// restriction.
+    NotifyFilterLang(BTreeSet<String>),
+    /// Send the requesting member direct messages about the progress of their own Codewars account.
+    Subscribe(String),
+    /// Stop the direct messages about the member's own progress.
+    Unsubscribe,
+    /// Include re-trained challenges in reports or leave them out.
+    Retrains(bool),
+    /// Show a checklist of the steps to fully configure the bot.
+    Setup,
+    /// Turn the maintenance mode on or off.
+    Maintenance(bool),
+    /// Post reports and notifications to a new webhook URL instead of the configured one.
+    SetWebhook(Url),
+    /// Post reports and notifications to another webhook URL as well.
+    WebhookAdd(Url),
+    /// Stop posting to a webhook URL that was added before.
+    WebhookRemove(Url),
+    /// Set the name to show in reports for a user, or reset it if no name is given.
+    DisplayName(String, Option<String>),
+    /// Set a note about a user, or remove it if no note is given.
+    Note(String, Option<String>),
+    /// List all tracked users with their details.
+    ListUsers,
+    /// Search the completed challenges of all tracked users by name.
+    Search(String),
+    /// List the tracked users that completed a challenge, given by its name, slug or ID.
+    WhoSolved(String),
+    /// Suggest a kata that nobody solved yet, optionally within a range of kyu.
+    Suggest(Option<RangeInclusive<u8>>),
+    /// Start a poll for the next kata of the week, that ends after the given duration.
+    PollStart(Duration),
+    /// Show the current votes of the running poll.
+    PollStatus,
+    /// Vote for one of the poll's katas, counting from one.
+    Vote(usize),
+    /// Set the weekly goal of completed katas for the team, where zero removes it.
+    GoalSet(u32),
+    /// Show the team's progress towards the weekly goal.
+    GoalStatus,
+    /// Create the year in review for the given or the current year.
+    Review(Option<i32>),
+}
+
+impl Command {
+    /// Short name of the command, used to track its usage.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::AddUser(_) => "add",
+            Self::RemoveUser(_) => "remove",
+            Self::RenameUser(..) => "rename",
+            Self::Clear(_) => "clear",
+            Self::Stats { .. } => "stats",
+            Self::StatsByTag { .. } => "stats by tag",
+            Self::Languages => "languages",
+            Self::Help | Self::HelpPage(_) => "help",
+            Self::Status => "status",
+            Self::BotStats => "botstats",
+            Self::Audit(_) => "audit",
+            Self::RunNow(_) => "run now",
+            Self::Schedule(..) => "schedule",
+            Self::ScheduleAdd(..) => "schedule add",
+            Self::ScheduleRemove(_) => "schedule remove",
+            Self::ScheduleList => "schedule list",
+            Self::Notify(_) => "notify",
+            Self::NotifyDigest(_) => "notify digest",
+            Self::NotifyEvery(_) => "notify every",
+            Self::NotifySnooze(_) => "notify snooze",
+            Self::NotifyQuiet(_) => "notify quiet",
+            Self::NotifyFilterKyu(_) => "notify filter kyu",
+            Self::NotifyFilterLang(_) => "notify filter lang",
+            Self::Subscribe(_) => "subscribe",
+            Self::Unsubscribe => "unsubscribe",
+            Self::Retrains(_) => "retrains",
+            Self::Setup => "setup",
+            Self::Maintenance(_) => "maintenance",
+            Self::SetWebhook(_) => "set webhook",
+            Self::WebhookAdd(_) => "webhook add",
+            Self::WebhookRemove(_) => "webhook remove",
+            Self::DisplayName(..) => "displayname",
+            Self::Note(..) => "note",
+            Self::ListUsers => "list",
+            Self::Search(_) => "search",
+            Self::WhoSolved(_) => "who solved",
+            Self::Suggest(_) => "suggest",
+            Self::PollStart(_) => "poll start",
+            Self::PollStatus => "poll",
+            Self::Vote(_) => "vote",
+            Self::GoalSet(_) => "goal set",
+            Self::GoalStatus => "goal status",
+            Self::Review(_) => "review",
+        }
+    }
+
+    /// Whether the command is destructive or changes the whole team's reports, so it must be
+    /// confirmed before it runs.
+    #[must_use]
+    pub const fn needs_confirmation(&self) -> bool {
+        matches!(
+            self,
+            Self::RemoveUser(_)
+                | Self::Clear(_)
+                | Self::Schedule(..)
+                | Self::ScheduleRemove(_)
+                | Self::WebhookRemove(_)
+        )
+    }
+
+    /// Whether the command changes where the bot posts to, puts it under maintenance or shows the
+    /// audit log, so only admins may run it.
+    #[must_use]
+    pub const fn is_admin_only(&self) -> bool {
+        matches!(
+            self,
+            Self::SetWebhook(_)
+                | Self::WebhookAdd(_)
+                | Self::WebhookRemove(_)
+                | Self::Maintenance(_)
+                | Self::Audit(_)
+        )
+    }
+
+    /// Whether the command only fetches data from Codewars without changing anything, so it can
+    /// be cancelled safely when it takes too long.
+    #[must_use]
+    pub const fn is_fetch_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Stats { .. } | Self::StatsByTag { .. } | Self::Search(_) | Self::WhoSolved(_)
+        )
+    }
+
+    /// Whether the command reports over a range of dates.
+    #[must_use]
+    pub const fn has_dates(&self) -> bool {
+        matches!(self, Self::Stats { .. } | Self::StatsByTag { .. })
+    }
+
+    /// Optional arguments that were used with the command, to track their usage. Values like
+    /// usernames are never part of it.
+    #[must_use]
+    pub const fn option(&self) -> Option<&'static str> {
+        match self {
+            Self::Stats { direct: true, .. } => Some("to me"),
+            Self::Stats { top: Some(_), .. } => Some("top"),
+            Self::Stats { until: Some(_), .. } | Self::StatsByTag { until: Some(_), .. } => {
+                Some("until")
+            }
+            Self::Stats { since: Some(_), .. } | Self::StatsByTag { since: Some(_), .. } => {
+                Some("since")
+            }
+            Self::RunNow(false) => Some("--no-mark"),
+            Self::Clear(true) => Some("all"),
+            Self::DisplayName(_, None) | Self::Note(_, None) => Some("reset"),
+            Self::Suggest(Some(_)) => Some("kyu"),
+            Self::Review(Some(_)) => Some("year"),
+            Self::HelpPage(_) => Some("page"),
+            Self::Audit(Some(_)) => Some("last"),
+            Self::Notify(true) | Self::Retrains(true) | Self::Maintenance(true) => Some("on"),
+            Self::Notify(false)
+            | Self::Retrains(false)
+            | Self::Maintenance(false)
+            | Self::NotifySnooze(None)
+            | Self::NotifyQuiet(None)
+            | Self::NotifyFilterKyu(None) => Some("off"),
+            _ => None,
+        }
+    }
 }
 
 /// Parse a text message into one of the possible commands that the service understands.
+///
+/// Input that doesn't even start with a known command, like an empty message, results in an
+/// [`Error::UnknownCommand`] instead of a detailed parser error.
+///
+/// # Errors
+///
+/// Fails if the text isn't a valid command, with the position of the problem if possible.
 pub fn parse(cmd: &str) -> Result<Command> {
-    let command = CommandParser::parse(Rule::command, cmd)?
+    let command = CommandParser::parse(Rule::command, cmd)
+        .map_err(|e| match e.location {
+            InputLocation::Pos(pos) | InputLocation::Span((pos, _))
+                if cmd[..pos].trim().is_empty() =>
+            {
+                Error::UnknownCommand
+            }
+            _ => e.into(),
+        })?
         .next()
         .ok_or(Error::CommandMissing)?;
     let command = command.into_inner().next().ok_or(Error::CommandMissing)?;
 
     Ok(match command.as_rule() {
-        Rule::add => Command::AddUser(
+        Rule::add => Command::AddUser(parse_username(command.into_inner().next())?),
+        Rule::remove => Command::RemoveUser(parse_username(command.into_inner().next())?),
+        Rule::rename => {
+            let mut args = command.into_inner();
+            Command::RenameUser(parse_username(args.next())?, parse_username(args.next())?)
+        }
+        Rule::clear => Command::Clear(command.into_inner().next().is_some()),
+        Rule::displayname => {
+            let mut args = command.into_inner();
+            Command::DisplayName(
+                parse_username(args.next())?,
+                args.next().map(|name| name.as_str().trim().to_owned()),
+            )
+        }
+        Rule::note => {
+            let mut args = command.into_inner();
+            Command::Note(
+                parse_username(args.next())?,
+                args.next().map(|note| note.as_str().trim().to_owned()),
+            )
+        }
+        Rule::list => Command::ListUsers,
+        Rule::search => Command::Search(
             command
                 .into_inner()
                 .next()
-                .ok_or(Error::UsernameMissing)?
+                .ok_or(Error::TermMissing)?
                 .as_str()
+                .trim()
                 .to_owned(),
         ),
-        Rule::remove => Command::RemoveUser(
+        Rule::who_solved => Command::WhoSolved(
             command
                 .into_inner()
                 .next()
-                .ok_or(Error::UsernameMissing)?
+                .ok_or(Error::TermMissing)?
                 .as_str()
+                .trim()
                 .to_owned(),
         ),
-        Rule::stats => {
-            let mut args = command.into_inner();
-            Command::Stats(args.next().map_or_else(
-                || Ok(None),
-                |d| NaiveDate::parse_from_str(d.as_str(), "%Y/%m/%d").map(Some),
-            )?)
-        }
-        Rule::help => Command::Help,
-        Rule::schedule => {
-            let mut args = command.into_inner();
-            Command::Schedule(
-                args.next()
-                    .ok_or(Error::WeekdayMissing)?
-                    .as_str()
-                    .parse()
-                    .map_err(Error::InvalidWeekday)?,
-                args.next().map_or_else(
-                    || Ok(NaiveTime::from_hms(10, 0, 0)),
-                    |t| NaiveTime::parse_from_str(t.as_str(), "%R"),
-                )?,
-            )
-        }
-        Rule::notify => {
-            let boolean = command
+        Rule::suggest => Command::Suggest(parse_kyu_range(command)?),
+        Rule::poll => parse_poll(command)?,
+        Rule::vote => Command::Vote(parse_choice(command)?),
+        Rule::subscribe => Command::Subscribe(parse_username(command.into_inner().next())?),
+        Rule::unsubscribe => Command::Unsubscribe,
+        Rule::review => Command::Review(parse_year(command)?),
+        Rule::goal => parse_goal(command)?,
+        Rule::stats => parse_stats(command)?,
+        Rule::stats_by_tag => parse_stats_by_tag(command)?,
+        Rule::languages => Command::Languages,
+        Rule::help => command.into_inner().next().map_or(Command::Help, |page| {
+            Command::HelpPage(page.as_str().parse().unwrap_or_default())
+        }),
+        Rule::status => Command::Status,
+        Rule::botstats => Command::BotStats,
+        Rule::audit => Command::Audit(
+            command
                 .into_inner()
                 .next()
-                .ok_or(Error::BooleanMissing)?
-                .as_str();
-            let on_off = match boolean {
-                "on" => true,
-                "off" => false,
-                _ => return Err(Error::InvalidBoolean),
-            };
-            Command::Notify(on_off)
+                .map(|limit| limit.as_str().parse().unwrap_or_default()),
+        ),
+        Rule::setup => Command::Setup,
+        Rule::run => Command::RunNow(command.into_inner().next().is_none()),
+        Rule::schedule => {
+            let (frequency, time) = parse_schedule(command)?;
+            Command::Schedule(frequency, time)
+        }
+        Rule::schedule_add => {
+            let (frequency, time) = parse_schedule(command)?;
+            Command::ScheduleAdd(frequency, time)
         }
+        Rule::schedule_remove => Command::ScheduleRemove(parse_index(command)?),
+        Rule::schedule_list => Command::ScheduleList,
+        Rule::notify => Command::Notify(parse_bool(command)?),
+        Rule::notify_digest => Command::NotifyDigest(parse_duration(command)?),
+        Rule::notify_filter => parse_notify_filter(command)?,
+        Rule::notify_every => Command::NotifyEvery(parse_hours(command)?),
+        Rule::notify_snooze => Command::NotifySnooze(parse_optional_duration(command)?),
+        Rule::notify_quiet => Command::NotifyQuiet(parse_time_range(command)?),
+        Rule::retrains => Command::Retrains(parse_bool(command)?),
+        Rule::maintenance => Command::Maintenance(parse_bool(command)?),
+        Rule::set_webhook => Command::SetWebhook(parse_url(command)?),
+        Rule::webhook_add => Command::WebhookAdd(parse_url(command)?),
+        Rule::webhook_remove => Command::WebhookRemove(parse_url(command)?),
         _ => return Err(Error::UnknownCommand),
     })
 }
 
+/// Parse the HTTP or HTTPS URL argument of a command, which Slack formats like
+/// `<https://example.com>`, optionally with a label after a `|`.
+fn parse_url(command: Pair<'_, Rule>) -> Result<Url> {
+    let url = command
+        .into_inner()
+        .next()
+        .ok_or(Error::InvalidUrl)?
+        .as_str()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    let url = url.split_once('|').map_or(url, |(url, _)| url);
+
+    Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or(Error::InvalidUrl)
+}
+
+/// Parse the `on` or `off` argument of a command.
+fn parse_bool(command: Pair<'_, Rule>) -> Result<bool> {
+    let boolean = command
+        .into_inner()
+        .next()
+        .ok_or(Error::BooleanMissing)?
+        .as_str();
+
+    match boolean.to_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(Error::InvalidBoolean),
+    }
+}
+
+/// Parse the optional kyu range of a command, where a single kyu is a range of its own and the
+/// bounds can be given in any order.
+fn parse_kyu_range(command: Pair<'_, Rule>) -> Result<Option<RangeInclusive<u8>>> {
+    let kyu = command
+        .into_inner()
+        .map(|kyu| match kyu.as_str().parse() {
+            Ok(kyu @ 1..=8) => Ok(kyu),
+            _ => Err(Error::InvalidKyu),
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    Ok(match kyu[..] {
+        [] => None,
+        [kyu] => Some(kyu..=kyu),
+        [a, b, ..] => Some(a.min(b)..=a.max(b)),
+    })
+}
+
+/// Parse the frequency and optional time of the `schedule` commands, where the time defaults to
+/// 10:00.
+fn parse_schedule(command: Pair<'_, Rule>) -> Result<(Frequency, NaiveTime)> {
+    let mut args = command.into_inner();
+
+    Ok((
+        parse_frequency(args.next().ok_or(Error::CommandMissing)?)?,
+        args.next().map_or_else(
+            || Ok(NaiveTime::from_hms(10, 0, 0)),
+            |t| NaiveTime::parse_from_str(t.as_str(), "%R"),
+        )?,
+    ))
+}
+
+/// Parse how often a schedule runs, which is daily without any weekday or day of the month.
+fn parse_frequency(frequency: Pair<'_, Rule>) -> Result<Frequency> {
+    Ok(match frequency.into_inner().next() {
+        Some(weekday) if weekday.as_rule() == Rule::weekday => {
+            Frequency::Weekly(weekday.as_str().parse().map_err(Error::InvalidWeekday)?)
+        }
+        Some(day) => match day.as_str().parse() {
+            Ok(day @ 1..=31) => Frequency::Monthly(day),
+            _ => return Err(Error::InvalidDay),
+        },
+        None => Frequency::Daily,
+    })
+}
+
+/// Parse the position of an entry in a list, counting from one.
+fn parse_index(command: Pair<'_, Rule>) -> Result<usize> {
+    command
+        .into_inner()
+        .next()
+        .ok_or(Error::InvalidIndex)?
+        .as_str()
+        .parse()
+        .map_err(|_| Error::InvalidIndex)
+}
+
+/// Parse an amount of hours within a day.
+fn parse_hours(command: Pair<'_, Rule>) -> Result<u8> {
+    match command
+        .into_inner()
+        .next()
+        .map(|hours| hours.as_str().parse())
+    {
+        Some(Ok(hours @ 1..=24)) => Ok(hours),
+        _ => Err(Error::InvalidHours),
+    }
+}
+
+/// Parse a duration argument that can be left out, like for `off` instead of a duration.
+fn parse_optional_duration(command: Pair<'_, Rule>) -> Result<Option<Duration>> {
+    if command.clone().into_inner().next().is_some() {
+        parse_duration(command).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parse an optional range of times like `22:00-08:00`.
+fn parse_time_range(command: Pair<'_, Rule>) -> Result<Option<(NaiveTime, NaiveTime)>> {
+    let times = command
+        .into_inner()
+        .map(|t| NaiveTime::parse_from_str(t.as_str(), "%R"))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(match times[..] {
+        [start, end] => Some((start, end)),
+        _ => None,
+    })
+}
+
+/// Parse the `notify filter` command, which restricts either the kyu or the languages of
+/// challenges that trigger a notification.
+fn parse_notify_filter(command: Pair<'_, Rule>) -> Result<Command> {
+    let filter = command.into_inner().next().ok_or(Error::CommandMissing)?;
+
+    Ok(if filter.as_rule() == Rule::notify_filter_kyu {
+        Command::NotifyFilterKyu(parse_kyu_range(filter)?.map(|kyu| *kyu.start()))
+    } else {
+        Command::NotifyFilterLang(
+            filter
+                .into_inner()
+                .map(|lang| lang.as_str().to_lowercase())
+                .collect(),
+        )
+    })
+}
+
+/// Parse the action of the `poll` command, defaulting to the status. Polls run for a day, unless
+/// a different duration is given.
+fn parse_poll(command: Pair<'_, Rule>) -> Result<Command> {
+    Ok(match command.into_inner().next() {
+        Some(start) => {
+            Command::PollStart(parse_optional_duration(start)?.unwrap_or_else(|| Duration::days(1)))
+        }
+        None => Command::PollStatus,
+    })
+}
+
+/// Parse the chosen poll candidate of the `vote` command.
+fn parse_choice(command: Pair<'_, Rule>) -> Result<usize> {
+    command
+        .into_inner()
+        .next()
+        .ok_or(Error::InvalidChoice)?
+        .as_str()
+        .parse()
+        .map_err(|_| Error::InvalidChoice)
+}
+
+/// Parse the optional year argument of a command.
+fn parse_year(command: Pair<'_, Rule>) -> Result<Option<i32>> {
+    command
+        .into_inner()
+        .next()
+        .map(|year| year.as_str().parse())
+        .transpose()
+        .map_err(|_| Error::InvalidYear)
+}
+
+/// Parse a username, which is either a single word or a quoted string that may contain escaped
+/// quotes and backslashes.
+fn parse_username(username: Option<Pair<'_, Rule>>) -> Result<String> {
+    let username = username
+        .and_then(|username| username.into_inner().next())
+        .ok_or(Error::UsernameMissing)?;
+
+    if username.as_rule() != Rule::quoted_username {
+        return Ok(username.as_str().to_owned());
+    }
+
+    let quoted = username.as_str();
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted[1..quoted.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' {
+            chars.next().unwrap_or(c)
+        } else {
+            c
+        });
+    }
+
+    Ok(unescaped)
+}
+
+/// Parse the `stats` command with its optional limit, start and end date, and whether to send it
+/// as direct message.
+fn parse_stats(command: Pair<'_, Rule>) -> Result<Command> {
+    let mut dates = Vec::new();
+    let mut top = None;
+    let mut direct = false;
+
+    for arg in command.into_inner() {
+        match arg.as_rule() {
+            Rule::limit => top = Some(arg.as_str().parse().unwrap_or_default()),
+            Rule::to_me => direct = true,
+            Rule::date => dates.push(NaiveDate::parse_from_str(arg.as_str(), "%Y/%m/%d")?),
+            _ => {}
+        }
+    }
+
+    let (since, until) = date_range(&dates)?;
+
+    Ok(Command::Stats {
+        since,
+        until,
+        top,
+        direct,
+    })
+}
+
+/// Parse the `stats by tag` command with its optional start and end date.
+fn parse_stats_by_tag(command: Pair<'_, Rule>) -> Result<Command> {
+    let dates = command
+        .into_inner()
+        .map(|arg| NaiveDate::parse_from_str(arg.as_str(), "%Y/%m/%d"))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let (since, until) = date_range(&dates)?;
+
+    Ok(Command::StatsByTag { since, until })
+}
+
+/// Take the optional start and end date from the parsed dates, making sure the end isn't before
+/// the start.
+fn date_range(dates: &[NaiveDate]) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+    let (since, until) = (dates.first().copied(), dates.get(1).copied());
+    if since.zip(until).is_some_and(|(since, until)| until < since) {
+        return Err(Error::InvalidRange);
+    }
+
+    Ok((since, until))
+}
+
+/// Parse the `goal` command, which either sets the target or shows the status.
+fn parse_goal(command: Pair<'_, Rule>) -> Result<Command> {
+    Ok(match command.into_inner().next() {
+        Some(target) => {
+            Command::GoalSet(target.as_str().parse().map_err(|_| Error::InvalidTarget)?)
+        }
+        None => Command::GoalStatus,
+    })
+}
+
+/// Parse a duration argument like `12h`, `3d` or `2w`.
+fn parse_duration(command: Pair<'_, Rule>) -> Result<Duration> {
+    duration(
+        command
+            .into_inner()
+            .next()
+            .ok_or(Error::InvalidDuration)?
+            .as_str(),
+    )
+}
+
+/// Parse a duration like `12h`, `3d` or `2w`, with up to 4 digits and at least one hour, for
+/// commands that parse their arguments themselves.
+///
+/// # Errors
+///
+/// Fails with [`Error::InvalidDuration`] if the value is out of range or has an unknown unit.
+pub fn duration(value: &str) -> Result<Duration> {
+    let (amount, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let Ok(amount @ 1..=9999) = amount.parse() else {
+        return Err(Error::InvalidDuration);
+    };
+
+    match unit {
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(Error::InvalidDuration),
+    }
+}
+
+/// Name of the grammar rule that the message is parsed as, like `schedule_add`, for example to
+/// verify that a help text covers every command.
+#[must_use]
+pub fn rule(cmd: &str) -> Option<String> {
+    CommandParser::parse(Rule::command, cmd)
+        .ok()?
+        .next()?
+        .into_inner()
+        .next()
+        .map(|command| format!("{:?}", command.as_rule()))
+}
+
+/// Keywords of the grammar that have aliases, each with the main keyword first, followed by its
+/// aliases.
+#[must_use]
+pub fn keyword_aliases() -> Vec<Vec<&'static str>> {
+    include_str!("commands.pest")
+        .lines()
+        .filter(|line| line.starts_with("kw_"))
+        .map(|line| line.split('"').skip(1).step_by(2).collect())
+        .collect()
+}
+
+/// Replace the message with the expansion of an alias, if the whole message is the name of one of
+/// the given aliases. Otherwise the message is returned unchanged.
+pub fn expand<'a>(cmd: &'a str, aliases: &'a BTreeMap<String, String>) -> &'a str {
+    aliases.get(cmd.trim()).map_or(cmd, String::as_str)
+}
+
+/// Split a message into the separate commands it contains. Commands are separated by semicolons
+/// or line breaks, except within quotes, and empty commands are left out.
+#[must_use]
+pub fn split(text: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' | '\n' if !quoted => {
+                commands.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    commands.push(&text[start..]);
+
+    commands.retain(|command| !command.trim().is_empty());
+    commands
+}
+
+/// Verify that all aliases expand to valid commands and don't shadow any built-in command.
+///
+/// # Errors
+///
+/// Fails with a description of the first invalid alias.
+pub fn validate_aliases(aliases: &BTreeMap<String, String>) -> std::result::Result<(), String> {
+    for (name, expansion) in aliases {
+        if parse(name).is_ok() {
+            return Err(format!("alias `{name}` shadows a built-in command"));
+        }
+        if let Err(e) = parse(expansion) {
+            return Err(format!("alias `{name}` expands to an invalid command: {e}"));
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use chrono::Weekday;
+
     use super::*;
 
     #[test]
@@ -127,6 +793,45 @@ mod tests {
             Some(Command::AddUser("him".to_owned())),
             parse("add him").ok()
         );
+        assert_eq!(
+            Some(Command::AddUser("jo.hn-doe+1!".to_owned())),
+            parse("add jo.hn-doe+1!").ok()
+        );
+    }
+
+    #[test]
+    fn split_commands() {
+        assert_eq!(
+            vec!["add alice", " add bob", " stats"],
+            split("add alice; add bob; stats")
+        );
+        assert_eq!(vec!["add alice", "stats"], split("add alice\n\nstats\n"));
+        assert_eq!(
+            vec![r#"add "a;b""#, r#" add "c\";""#],
+            split(r#"add "a;b"; add "c\";""#)
+        );
+        assert!(split(" ; ").is_empty());
+    }
+
+    #[test]
+    fn parse_quoted_username() {
+        assert_eq!(
+            Some(Command::AddUser("John Doe".to_owned())),
+            parse(r#"add "John Doe""#).ok()
+        );
+        assert_eq!(
+            Some(Command::RemoveUser(r#"say "hi" \o/"#.to_owned())),
+            parse(r#"remove "say \"hi\" \\o/""#).ok()
+        );
+        assert_eq!(
+            Some(Command::DisplayName(
+                "John Doe".to_owned(),
+                Some("Johnny".to_owned())
+            )),
+            parse(r#"displayname "John Doe" Johnny"#).ok()
+        );
+        assert!(parse(r#"add "John Doe"#).is_err());
+        assert!(parse(r#"add """#).is_err());
     }
 
     #[test]
@@ -139,46 +844,487 @@ mod tests {
             Some(Command::RemoveUser("him".to_owned())),
             parse("rm him").ok()
         );
+        assert_eq!(
+            Some(Command::RemoveUser("him".to_owned())),
+            parse("del him").ok()
+        );
+    }
+
+    #[test]
+    fn parse_stats_by_tag() {
+        assert_eq!(
+            Some(Command::StatsByTag {
+                since: None,
+                until: None
+            }),
+            parse("stats by tag").ok()
+        );
+        assert_eq!(
+            Some(Command::StatsByTag {
+                since: Some(NaiveDate::from_ymd(2020, 1, 3)),
+                until: Some(NaiveDate::from_ymd(2020, 2, 1)),
+            }),
+            parse("stats by tag since 2020/01/03 until 2020/02/01").ok()
+        );
+        assert!(matches!(
+            parse("stats by tag since 2020/02/01 until 2020/01/03"),
+            Err(Error::InvalidRange)
+        ));
     }
 
     #[test]
     fn parse_stats() {
-        assert_eq!(Some(Command::Stats(None)), parse("stats").ok());
         assert_eq!(
-            Some(Command::Stats(Some(NaiveDate::from_ymd(2020, 2, 5)))),
+            Some(Command::Stats {
+                since: None,
+                until: None,
+                top: None,
+                direct: false
+            }),
+            parse("stats").ok()
+        );
+        assert_eq!(
+            Some(Command::Stats {
+                since: Some(NaiveDate::from_ymd(2020, 2, 5)),
+                until: None,
+                top: None,
+                direct: false
+            }),
             parse("stats since 2020/02/05").ok()
         );
         assert_eq!(
-            Some(Command::Stats(Some(NaiveDate::from_ymd(2020, 1, 3)))),
+            Some(Command::Stats {
+                since: Some(NaiveDate::from_ymd(2020, 1, 3)),
+                until: None,
+                top: None,
+                direct: false
+            }),
             parse("stats since 2020/1/3").ok()
         );
+        assert_eq!(
+            Some(Command::Stats {
+                since: None,
+                until: None,
+                top: Some(5),
+                direct: false
+            }),
+            parse("stats top 5").ok()
+        );
+        assert_eq!(
+            Some(Command::Stats {
+                since: Some(NaiveDate::from_ymd(2022, 1, 1)),
+                until: None,
+                top: Some(10),
+                direct: false
+            }),
+            parse("stats top 10 since 2022/01/01").ok()
+        );
+        assert!(parse("stats top 0").is_err());
+        assert_eq!(
+            Some(Command::Stats {
+                since: Some(NaiveDate::from_ymd(2022, 3, 1)),
+                until: Some(NaiveDate::from_ymd(2022, 3, 31)),
+                top: None,
+                direct: false
+            }),
+            parse("stats since 2022/03/01 until 2022/03/31").ok()
+        );
+        assert!(matches!(
+            parse("stats since 2022/03/31 until 2022/03/01"),
+            Err(Error::InvalidRange)
+        ));
+        assert!(parse("stats until 2022/03/31").is_err());
+        assert_eq!(
+            Some(Command::Stats {
+                since: Some(NaiveDate::from_ymd(2022, 1, 1)),
+                until: None,
+                top: None,
+                direct: true
+            }),
+            parse("stats since 2022/01/01 to me").ok()
+        );
+        assert_eq!(parse("stats to me").ok(), parse("stats --dm").ok());
     }
 
     #[test]
     fn parse_help() {
         assert_eq!(Some(Command::Help), parse("help").ok());
+        assert_eq!(Some(Command::HelpPage(2)), parse("help page 2").ok());
+    }
+
+    #[test]
+    fn parse_status() {
+        assert_eq!(Some(Command::Status), parse("status").ok());
+    }
+
+    #[test]
+    fn parse_botstats() {
+        assert_eq!(Some(Command::BotStats), parse("botstats").ok());
+    }
+
+    #[test]
+    fn parse_run() {
+        assert_eq!(Some(Command::RunNow(true)), parse("run now").ok());
+        assert_eq!(
+            Some(Command::RunNow(false)),
+            parse("run now --no-mark").ok()
+        );
     }
 
     #[test]
     fn parse_schedule() {
         assert_eq!(
             Some(Command::Schedule(
-                Weekday::Wed,
+                Frequency::Weekly(Weekday::Wed),
                 NaiveTime::from_hms(13, 5, 0)
             )),
             parse("schedule on Wednesday at 13:05").ok()
         );
         assert_eq!(
             Some(Command::Schedule(
-                Weekday::Tue,
+                Frequency::Weekly(Weekday::Tue),
                 NaiveTime::from_hms(10, 0, 0)
             )),
             parse("schedule on Tue").ok()
         );
+        assert_eq!(
+            Some(Command::Schedule(
+                Frequency::Daily,
+                NaiveTime::from_hms(9, 0, 0)
+            )),
+            parse("schedule daily at 09:00").ok()
+        );
+        assert_eq!(
+            Some(Command::Schedule(
+                Frequency::Monthly(1),
+                NaiveTime::from_hms(10, 0, 0)
+            )),
+            parse("schedule monthly on 1 at 10:00").ok()
+        );
+        assert!(parse("schedule monthly on 32").is_err());
+        assert!(parse("schedule monthly on 0").is_err());
+        assert_eq!(
+            Some(Command::ScheduleAdd(
+                Frequency::Weekly(Weekday::Fri),
+                NaiveTime::from_hms(16, 30, 0)
+            )),
+            parse("schedule add on fri at 16:30").ok()
+        );
+        assert_eq!(
+            Some(Command::ScheduleRemove(2)),
+            parse("schedule rm 2").ok()
+        );
+        assert!(parse("schedule remove 0").is_err());
+        assert_eq!(Some(Command::ScheduleList), parse("schedule list").ok());
+        assert_eq!(Some(Command::ScheduleList), parse("sched ls").ok());
+        assert_eq!(Some(Command::ScheduleRemove(1)), parse("sched del 1").ok());
+    }
+
+    #[test]
+    fn list_keyword_aliases() {
+        assert_eq!(
+            vec![
+                vec!["remove", "rm", "del"],
+                vec!["schedule", "sched"],
+                vec!["list", "ls"],
+            ],
+            keyword_aliases()
+        );
     }
 
     #[test]
     fn parse_notify() {
         assert_eq!(Some(Command::Notify(true)), parse("notify on").ok());
+        assert_eq!(
+            Some(Command::NotifyDigest(Duration::days(1))),
+            parse("notify digest 1d").ok()
+        );
+        assert!(parse("notify digest 30m").is_err());
+        assert!(parse("notify digest 0h").is_err());
+        assert_eq!(Some(Command::NotifyEvery(6)), parse("notify every 6").ok());
+        assert_eq!(
+            Some(Command::NotifyEvery(1)),
+            parse("notify every 1 hour").ok()
+        );
+        assert!(parse("notify every 0h").is_err());
+        assert!(parse("notify every 25 hours").is_err());
+        assert_eq!(
+            Some(Command::NotifySnooze(Some(Duration::hours(2)))),
+            parse("notify snooze 2h").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifySnooze(None)),
+            parse("notify snooze off").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifyQuiet(Some((
+                NaiveTime::from_hms(22, 0, 0),
+                NaiveTime::from_hms(8, 0, 0)
+            )))),
+            parse("notify quiet 22:00-08:00").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifyQuiet(None)),
+            parse("notify quiet off").ok()
+        );
+        assert!(parse("notify quiet 25:00-08:00").is_err());
+    }
+
+    #[test]
+    fn parse_notify_filter() {
+        assert_eq!(
+            Some(Command::NotifyFilterKyu(Some(5))),
+            parse("notify filter kyu >= 5").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifyFilterKyu(None)),
+            parse("notify filter kyu off").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifyFilterLang(BTreeSet::from([
+                "rust".to_owned(),
+                "cpp".to_owned()
+            ]))),
+            parse("notify filter lang Rust cpp").ok()
+        );
+        assert_eq!(
+            Some(Command::NotifyFilterLang(BTreeSet::new())),
+            parse("notify filter lang off").ok()
+        );
+        assert!(parse("notify filter kyu >= 9").is_err());
+        assert!(parse("notify filter lang").is_err());
+    }
+
+    #[test]
+    fn parse_retrains() {
+        assert_eq!(Some(Command::Retrains(false)), parse("retrains off").ok());
+    }
+
+    #[test]
+    fn parse_rename() {
+        assert_eq!(
+            Some(Command::RenameUser(
+                "alice".to_owned(),
+                "Alice Doe".to_owned()
+            )),
+            parse(r#"rename alice "Alice Doe""#).ok()
+        );
+        assert!(parse("rename alice").is_err());
+    }
+
+    #[test]
+    fn parse_clear() {
+        assert_eq!(Some(Command::Clear(false)), parse("clear").ok());
+        assert_eq!(Some(Command::Clear(true)), parse("clear all").ok());
+        assert!(parse("clear everything").is_err());
+        assert!(Command::Clear(false).needs_confirmation());
+        assert!(!Command::Clear(false).is_fetch_only());
+    }
+
+    #[test]
+    fn parse_audit() {
+        assert_eq!(Some(Command::Audit(None)), parse("audit").ok());
+        assert_eq!(Some(Command::Audit(Some(25))), parse("audit last 25").ok());
+        assert!(parse("audit last 0").is_err());
+    }
+
+    #[test]
+    fn parse_setup() {
+        assert_eq!(Some(Command::Setup), parse("setup").ok());
+    }
+
+    #[test]
+    fn parse_displayname() {
+        assert_eq!(
+            Some(Command::DisplayName(
+                "alice".to_owned(),
+                Some("🦀 Alice".to_owned())
+            )),
+            parse("displayname alice 🦀 Alice ").ok()
+        );
+        assert_eq!(
+            Some(Command::DisplayName("alice".to_owned(), None)),
+            parse("displayname alice").ok()
+        );
+    }
+
+    #[test]
+    fn parse_note() {
+        assert_eq!(
+            Some(Command::Note(
+                "alice".to_owned(),
+                Some("Joined for the Rust track".to_owned())
+            )),
+            parse("note alice Joined for the Rust track").ok()
+        );
+        assert_eq!(
+            Some(Command::Note("alice".to_owned(), None)),
+            parse("note alice").ok()
+        );
+        assert_eq!(Some(Command::ListUsers), parse("list").ok());
+        assert_eq!(Some(Command::ListUsers), parse("ls").ok());
+    }
+
+    #[test]
+    fn parse_search() {
+        assert_eq!(
+            Some(Command::Search("Sum of Intervals".to_owned())),
+            parse("search Sum of Intervals").ok()
+        );
+        assert!(parse("search").is_err());
+        assert!(Command::Search("kata".to_owned()).is_fetch_only());
+        assert!(!Command::Search("kata".to_owned()).has_dates());
+    }
+
+    #[test]
+    fn parse_languages() {
+        assert_eq!(Some(Command::Languages), parse("languages").ok());
+        assert!(parse("languages rust").is_err());
+    }
+
+    #[test]
+    fn parse_who_solved() {
+        assert_eq!(
+            Some(Command::WhoSolved("sum-of-intervals".to_owned())),
+            parse("who solved sum-of-intervals").ok()
+        );
+        assert!(parse("who solved").is_err());
+    }
+
+    #[test]
+    fn parse_suggest() {
+        assert_eq!(Some(Command::Suggest(None)), parse("suggest").ok());
+        assert_eq!(
+            Some(Command::Suggest(Some(5..=5))),
+            parse("suggest 5 kyu").ok()
+        );
+        assert_eq!(
+            Some(Command::Suggest(Some(4..=6))),
+            parse("suggest 6-4 kyu").ok()
+        );
+        assert!(parse("suggest 9 kyu").is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(Some(Duration::weeks(2)), duration("2w").ok());
+        assert_eq!(Some(Duration::hours(36)), duration("36h").ok());
+        assert!(duration("0d").is_err());
+        assert!(duration("3").is_err());
+        assert!(duration("12345d").is_err());
+        assert!(duration("3 days").is_err());
+    }
+
+    #[test]
+    fn parse_poll() {
+        assert_eq!(
+            Some(Command::PollStart(Duration::days(1))),
+            parse("poll start").ok()
+        );
+        assert_eq!(
+            Some(Command::PollStart(Duration::hours(12))),
+            parse("poll start 12h").ok()
+        );
+        assert_eq!(Some(Command::PollStatus), parse("poll").ok());
+        assert_eq!(Some(Command::PollStatus), parse("poll status").ok());
+        assert_eq!(Some(Command::Vote(2)), parse("vote 2").ok());
+        assert!(parse("vote 0").is_err());
+        assert!(parse("vote").is_err());
+    }
+
+    #[test]
+    fn parse_goal() {
+        assert_eq!(
+            Some(Command::GoalSet(15)),
+            parse("goal set 15 per week").ok()
+        );
+        assert_eq!(Some(Command::GoalStatus), parse("goal status").ok());
+        assert!(parse("goal set 15").is_err());
+    }
+
+    #[test]
+    fn parse_review() {
+        assert_eq!(Some(Command::Review(None)), parse("review").ok());
+        assert_eq!(Some(Command::Review(Some(2023))), parse("review 2023").ok());
+        assert!(parse("review 23").is_err());
+    }
+
+    #[test]
+    fn parse_maintenance() {
+        assert_eq!(
+            Some(Command::Maintenance(true)),
+            parse("maintenance on").ok()
+        );
+        assert_eq!(
+            Some(Command::Maintenance(false)),
+            parse("maintenance OFF").ok()
+        );
+    }
+
+    #[test]
+    fn parse_subscribe() {
+        assert_eq!(
+            Some(Command::Subscribe("alice".to_owned())),
+            parse("subscribe me alice").ok()
+        );
+        assert_eq!(Some(Command::Unsubscribe), parse("unsubscribe me").ok());
+        assert!(parse("subscribe alice").is_err());
+    }
+
+    #[test]
+    fn parse_set_webhook() {
+        let url = Url::parse("https://hooks.slack.com/services/T0/B0/XX").unwrap();
+
+        assert_eq!(
+            Some(Command::SetWebhook(url.clone())),
+            parse("set webhook https://hooks.slack.com/services/T0/B0/XX").ok()
+        );
+        assert_eq!(
+            Some(Command::SetWebhook(url)),
+            parse("set webhook <https://hooks.slack.com/services/T0/B0/XX>").ok()
+        );
+        assert!(matches!(
+            parse("set webhook ftp://example.com"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(parse("set webhook").is_err());
+
+        let url = Url::parse("https://example.com/hook").unwrap();
+        assert_eq!(
+            Some(Command::WebhookAdd(url.clone())),
+            parse("webhook add <https://example.com/hook|example.com/hook>").ok()
+        );
+        assert_eq!(
+            Some(Command::WebhookRemove(url)),
+            parse("webhook rm https://example.com/hook").ok()
+        );
+        assert!(
+            Command::WebhookRemove(Url::parse("https://example.com").unwrap()).needs_confirmation()
+        );
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert!(matches!(parse(""), Err(Error::UnknownCommand)));
+        assert!(matches!(parse("  hello"), Err(Error::UnknownCommand)));
+        assert!(matches!(
+            parse("schedule on someday"),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn expand_aliases() {
+        let aliases = BTreeMap::from([("week".to_owned(), "stats since 2020/1/3".to_owned())]);
+
+        assert_eq!("stats since 2020/1/3", expand(" week ", &aliases));
+        assert_eq!("stats", expand("stats", &aliases));
+        assert!(validate_aliases(&aliases).is_ok());
+
+        let aliases = BTreeMap::from([("help".to_owned(), "stats".to_owned())]);
+        assert!(validate_aliases(&aliases).is_err());
+
+        let aliases = BTreeMap::from([("week".to_owned(), "stats since".to_owned())]);
+        assert!(validate_aliases(&aliases).is_err());
     }
 }
@@ -0,0 +1,452 @@
+//! Competitions between the tracked users, that score the katas completed within a time window.
+//! Harder katas are worth more points, so solving a single 2 kyu beats a handful of 8 kyu katas.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use codewars_bot::commands;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::codewars::{CompletedChallenge, Level},
+    dispatch::Dispatcher,
+    fetch::FetchCache,
+    handler::Context,
+    help::{Category, Entry},
+    lease::Leader,
+    mrkdwn,
+    plugins::Plugin,
+    storage::{Competition, Repository},
+};
+
+/// Syntax of all `competition` commands, shown for invalid arguments.
+const SYNTAX: &str = "competition [start <duration> | end | status]";
+
+/// Help entries of the `competition` commands.
+const USAGE: &[Entry] = &[
+    Entry {
+        category: Category::Reports,
+        syntax: "competition start <duration>",
+        description: "Start a competition, that scores the katas completed by each tracked user \
+                      until it ends.",
+        details: &[
+            "The format of `<duration>` is a number with `h`, `d` or `w` for hours, days or \
+             weeks, for example `36h` or `2w`.",
+            "Harder katas are worth more points, from 1 point for 8 kyu up to 8 points for 1 kyu.",
+            "The final result is posted automatically when the competition ends.",
+        ],
+        example: "competition start 2w",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "competition [status]",
+        description: "Show the current scoreboard of the running competition.",
+        details: &[],
+        example: "competition",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "competition end",
+        description: "End the running competition early and post the final result.",
+        details: &[],
+        example: "competition end",
+    },
+];
+
+/// Commands to run a competition.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub enum Command {
+    /// Start a competition that lasts for the given duration.
+    Start(chrono::Duration),
+    /// End the running competition early.
+    End,
+    /// Show the current scoreboard of the running competition.
+    Status,
+}
+
+/// Plugin for the `competition` commands.
+pub struct CompetitionPlugin;
+
+#[async_trait]
+impl Plugin<Context> for CompetitionPlugin {
+    type Command = Command;
+
+    fn name(&self) -> &'static str {
+        "competition"
+    }
+
+    fn usage(&self) -> &'static [Entry] {
+        USAGE
+    }
+
+    fn parse(&self, args: &str) -> commands::Result<Command> {
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] | ["status"] => Ok(Command::Status),
+            ["end"] => Ok(Command::End),
+            ["start", duration] => commands::duration(duration).map(Command::Start),
+            _ => Err(commands::Error::InvalidArguments(SYNTAX)),
+        }
+    }
+
+    fn command_name(&self, cmd: &Command) -> &'static str {
+        match cmd {
+            Command::Start(_) => "competition start",
+            Command::End => "competition end",
+            Command::Status => "competition",
+        }
+    }
+
+    async fn execute(&self, ctx: &Context, _user: &str, cmd: Command) -> Result<String> {
+        match cmd {
+            Command::Start(duration) => start(&ctx.settings, &ctx.fetch, duration).await,
+            Command::End => end(&ctx.settings, &ctx.fetch).await,
+            Command::Status => status(&ctx.settings, &ctx.fetch).await,
+        }
+    }
+}
+
+/// Score of a single participant.
+pub struct Score<'a> {
+    /// Codewars username of the participant.
+    pub user: &'a str,
+    /// Sum of the points of all newly completed katas.
+    pub points: u32,
+    /// Amount of newly completed katas.
+    pub katas: usize,
+}
+
+/// Collect the katas that each user completed so far, as the baseline for a new competition.
+pub async fn baseline(
+    fetch: &FetchCache,
+    users: &[String],
+) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let mut baseline = BTreeMap::new();
+
+    for user in users {
+        let challenges = fetch.completed_challenges(user).await?;
+        baseline.insert(
+            user.clone(),
+            challenges.data.iter().map(|c| c.id.clone()).collect(),
+        );
+    }
+
+    Ok(baseline)
+}
+
+/// Points for a kata of the given rank, from 1 point for 8 kyu up to 8 points for 1 kyu and more
+/// for dan katas. Katas with an unknown rank are worth a single point.
+pub fn points(rank: Option<Level>) -> u32 {
+    match rank {
+        Some(Level::Kyu(kyu)) => 9 - u32::from(kyu),
+        Some(Level::Dan(dan)) => 8 + u32::from(dan),
+        None => 1,
+    }
+}
+
+/// Filter the challenges of a user down to the ones that count for the competition. That are all
+/// challenges completed within the time window that weren't part of the user's baseline, each
+/// counted once.
+pub fn new_completions<'a>(
+    competition: &Competition,
+    user: &str,
+    challenges: &'a [CompletedChallenge],
+) -> Vec<&'a CompletedChallenge> {
+    let baseline = competition.baseline.get(user);
+    let mut seen = HashSet::new();
+
+    challenges
+        .iter()
+        .filter(|c| {
+            (competition.started_at..=competition.ends_at).contains(&c.completed_at)
+                && !baseline.is_some_and(|b| b.contains(&c.id))
+                && seen.insert(c.id.as_str())
+        })
+        .collect()
+}
+
+/// Calculate the scores of all participants, with the highest score first.
+pub async fn scoreboard<'a>(
+    fetch: &FetchCache,
+    competition: &'a Competition,
+) -> Result<Vec<Score<'a>>> {
+    let mut scores = Vec::with_capacity(competition.baseline.len());
+
+    for user in competition.baseline.keys() {
+        let challenges = fetch.completed_challenges(user).await?;
+        let katas = new_completions(competition, user, &challenges.data);
+        let mut points = 0;
+
+        for kata in &katas {
+            let rank = match fetch.code_challenge(&kata.id).await {
+                Ok(details) => Some(details.rank.id),
+                Err(e) => {
+                    warn!("Error fetching rank of kata {}: {}", kata.id, e);
+                    None
+                }
+            };
+            points += self::points(rank);
+        }
+
+        scores.push(Score {
+            user,
+            points,
+            katas: katas.len(),
+        });
+    }
+
+    scores.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.user.cmp(b.user)));
+
+    Ok(scores)
+}
+
+/// Render the scoreboard as message, using the display names of the users where set.
+pub fn render(scores: &[Score<'_>], names: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::new();
+
+    for (place, score) in scores.iter().enumerate() {
+        writeln!(
+            out,
+            "{}. {} *{}* - {} points ({} katas)",
+            place + 1,
+            match place {
+                0 if score.points > 0 => ":first_place_medal:",
+                1 if score.points > 0 => ":second_place_medal:",
+                2 if score.points > 0 => ":third_place_medal:",
+                _ => ":runner:",
+            },
+            mrkdwn::escape(names.get(score.user).map_or(score.user, String::as_str)),
+            score.points,
+            score.katas,
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Start a new competition with the current completions of all users as baseline.
+async fn start(
+    settings: &RwLock<Repository>,
+    fetch: &FetchCache,
+    duration: chrono::Duration,
+) -> Result<String> {
+    let users = settings
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    if users.is_empty() {
+        return Ok(String::from(
+            "There is nobody to compete yet, add some users first",
+        ));
+    }
+
+    let baseline = baseline(fetch, &users).await?;
+    let started_at = Utc::now();
+    let ends_at = started_at + duration;
+
+    let started = settings
+        .write()
+        .await
+        .start_competition(Competition {
+            started_at,
+            ends_at,
+            baseline,
+        })
+        .await?;
+
+    if !started {
+        return Ok(String::from(
+            "A competition is already running, end it first to start a new one",
+        ));
+    }
+
+    Ok(format!(
+        ":checkered_flag: The competition has started and ends on `{}`. Every kata counts, the \
+         harder the better. Good luck everyone!",
+        ends_at.with_timezone(&Local).format("%Y/%m/%d %H:%M")
+    ))
+}
+
+/// End the running competition early and create its final result.
+async fn end(settings: &RwLock<Repository>, fetch: &FetchCache) -> Result<String> {
+    let competition = settings.write().await.end_competition().await?;
+
+    match competition {
+        Some(mut competition) => {
+            competition.ends_at = competition.ends_at.min(Utc::now());
+            result(settings, fetch, &competition).await
+        }
+        None => Ok(String::from("There is no competition running right now")),
+    }
+}
+
+/// Show the current scoreboard of the running competition.
+async fn status(settings: &RwLock<Repository>, fetch: &FetchCache) -> Result<String> {
+    let (competition, names) = {
+        let settings = settings.read().await;
+        (settings.competition().cloned(), settings.display_names())
+    };
+
+    let Some(competition) = competition else {
+        return Ok(String::from("There is no competition running right now"));
+    };
+
+    let scores = scoreboard(fetch, &competition).await?;
+    let remaining = (competition.ends_at - Utc::now())
+        .to_std()
+        .unwrap_or_default();
+
+    Ok(format!(
+        ":trophy: *Competition scoreboard*, ends in `{}`:\n{}",
+        humantime::format_duration(Duration::from_secs(remaining.as_secs() / 60 * 60)),
+        render(&scores, &names)?
+    ))
+}
+
+/// Interval to check whether the running competition ended.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically check whether the running competition ended and post its final result. Only the
+/// leader ends competitions, so the result is posted once.
+pub async fn run(
+    repo: Arc<RwLock<Repository>>,
+    fetch: Arc<FetchCache>,
+    dispatcher: Arc<Dispatcher>,
+    leader: Leader,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if !leader.is_leader() {
+            continue;
+        }
+
+        let competition = {
+            let mut repo = repo.write().await;
+            if repo.competition().is_none_or(|c| c.ends_at > Utc::now()) {
+                continue;
+            }
+
+            match repo.end_competition().await {
+                Ok(Some(competition)) => competition,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Error ending the competition: {}", e);
+                    continue;
+                }
+            }
+        };
+
+        info!("Competition ended");
+
+        match result(&repo, &fetch, &competition).await {
+            Ok(msg) => dispatcher.send(&msg).await,
+            Err(e) => error!("Error collecting the competition result: {}", e),
+        }
+    }
+}
+
+/// Create the final result message of an ended competition.
+pub async fn result(
+    repo: &RwLock<Repository>,
+    fetch: &FetchCache,
+    competition: &Competition,
+) -> Result<String> {
+    let scores = scoreboard(fetch, competition).await?;
+    let names = repo.read().await.display_names();
+
+    let mut msg = String::from(":checkered_flag: *The competition is over!* Final result:\n");
+    msg.push_str(&render(&scores, &names)?);
+
+    if let Some(winner) = scores.first().filter(|s| s.points > 0) {
+        write!(
+            msg,
+            "Congratulations {} :tada:",
+            mrkdwn::escape(names.get(winner.user).map_or(winner.user, String::as_str))
+        )?;
+    }
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let parse = |args| CompetitionPlugin.parse(args).ok();
+
+        assert_eq!(Some(Command::Start(Duration::weeks(2))), parse("start 2w"));
+        assert_eq!(
+            Some(Command::Start(Duration::hours(36))),
+            parse("start 36h")
+        );
+        assert_eq!(Some(Command::End), parse("end"));
+        assert_eq!(Some(Command::Status), parse(""));
+        assert_eq!(Some(Command::Status), parse("status"));
+        assert!(parse("start 0d").is_none());
+        assert!(parse("start 3").is_none());
+        assert!(parse("stop").is_none());
+
+        for entry in USAGE {
+            let args = entry.example.strip_prefix("competition").unwrap();
+            assert!(
+                parse(args.trim()).is_some(),
+                "example of `{}` doesn't parse",
+                entry.syntax
+            );
+        }
+    }
+
+    #[test]
+    fn points_by_rank() {
+        assert_eq!(1, points(Some(Level::Kyu(8))));
+        assert_eq!(4, points(Some(Level::Kyu(5))));
+        assert_eq!(8, points(Some(Level::Kyu(1))));
+        assert_eq!(10, points(Some(Level::Dan(2))));
+        assert_eq!(1, points(None));
+    }
+
+    #[test]
+    fn count_new_completions_only() {
+        let now = Utc::now();
+        let challenge = |id: &str, completed_at| CompletedChallenge {
+            id: id.to_owned(),
+            name: None,
+            slug: None,
+            completed_at,
+            completed_languages: HashSet::new(),
+        };
+        let competition = Competition {
+            started_at: now - Duration::days(1),
+            ends_at: now + Duration::days(1),
+            baseline: BTreeMap::from([("him".to_owned(), BTreeSet::from(["1".to_owned()]))]),
+        };
+        let challenges = [
+            challenge("1", now),
+            challenge("2", now),
+            challenge("2", now - Duration::hours(1)),
+            challenge("3", now - Duration::days(2)),
+        ];
+
+        let new = new_completions(&competition, "him", &challenges);
+        assert_eq!(1, new.len());
+        assert_eq!("2", new[0].id);
+    }
+}
@@ -0,0 +1,156 @@
+//! Confirmations for destructive or team-wide commands. Instead of running such a command right
+//! away, the bot asks with Yes/No buttons and only runs it once the same user clicks Yes.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::plugins::Invocation;
+
+/// Action ID of the button that confirms a command.
+pub const ACTION_CONFIRM: &str = "confirm";
+/// Action ID of the button that cancels a command.
+pub const ACTION_CANCEL: &str = "cancel";
+
+/// Time after which an unanswered confirmation expires.
+const TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A command that waits for confirmation.
+pub struct Pending {
+    /// ID of the user who sent the command and may confirm it.
+    pub user: String,
+    /// The command to run once confirmed.
+    pub command: Invocation,
+    /// Command as the user wrote it.
+    pub text: String,
+    /// Conversation that the command was sent in.
+    pub channel: String,
+    /// Whether the command was sent as direct message.
+    pub direct: bool,
+    /// Time when the confirmation was requested.
+    requested: Instant,
+}
+
+impl Pending {
+    /// Create a new pending command that was requested just now.
+    pub fn new(user: &str, command: Invocation, text: &str, channel: &str, direct: bool) -> Self {
+        Self {
+            user: user.to_owned(),
+            command,
+            text: text.trim().to_owned(),
+            channel: channel.to_owned(),
+            direct,
+            requested: Instant::now(),
+        }
+    }
+}
+
+/// Result of looking up a pending command for a click on one of its buttons.
+pub enum Lookup {
+    /// The command was found and removed, so it can't be answered twice.
+    Found(Pending),
+    /// The command is unknown, because it expired, was already answered or the bot restarted.
+    Expired,
+    /// Another user than the one who sent the command clicked the button.
+    OtherUser,
+}
+
+/// All commands that currently wait for confirmation, by a unique key that is attached to the
+/// buttons.
+#[derive(Default)]
+pub struct Confirmations(Mutex<HashMap<String, Pending>>);
+
+impl Confirmations {
+    /// Keep a command until it is answered, removing any expired ones at the same time.
+    pub async fn request(&self, key: String, pending: Pending) {
+        let mut pending_commands = self.0.lock().await;
+        pending_commands.retain(|_, pending| pending.requested.elapsed() < TIMEOUT);
+        pending_commands.insert(key, pending);
+    }
+
+    /// Take the pending command for the given key, if the user is allowed to answer it.
+    pub async fn take(&self, key: &str, user: &str) -> Lookup {
+        let mut pending_commands = self.0.lock().await;
+
+        match pending_commands.get(key) {
+            Some(pending) if pending.requested.elapsed() >= TIMEOUT => {
+                pending_commands.remove(key);
+                Lookup::Expired
+            }
+            Some(pending) if pending.user != user => Lookup::OtherUser,
+            Some(_) => pending_commands
+                .remove(key)
+                .map_or(Lookup::Expired, Lookup::Found),
+            None => Lookup::Expired,
+        }
+    }
+}
+
+/// Layout blocks of the question with Yes/No buttons, that both carry the key of the command.
+pub fn blocks(question: &str, key: &str) -> Value {
+    json!([
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": question },
+        },
+        {
+            "type": "actions",
+            "elements": [
+                {
+                    "type": "button",
+                    "action_id": ACTION_CONFIRM,
+                    "text": { "type": "plain_text", "text": "Yes" },
+                    "style": "danger",
+                    "value": key,
+                },
+                {
+                    "type": "button",
+                    "action_id": ACTION_CANCEL,
+                    "text": { "type": "plain_text", "text": "No" },
+                    "value": key,
+                },
+            ],
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use codewars_bot::commands::Command;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn only_sender_can_answer_once() {
+        let confirmations = Confirmations::default();
+        confirmations
+            .request(
+                "Ev01-0".to_owned(),
+                Pending::new(
+                    "U01",
+                    Invocation::Builtin(Command::RemoveUser("him".to_owned())),
+                    " remove him",
+                    "C01",
+                    false,
+                ),
+            )
+            .await;
+
+        assert!(matches!(
+            confirmations.take("Ev01-0", "U02").await,
+            Lookup::OtherUser
+        ));
+        assert!(matches!(
+            confirmations.take("Ev01-0", "U01").await,
+            Lookup::Found(Pending { text, .. }) if text == "remove him"
+        ));
+        assert!(matches!(
+            confirmations.take("Ev01-0", "U01").await,
+            Lookup::Expired
+        ));
+    }
+}
@@ -0,0 +1,1147 @@
+//! Fan-out dispatching of messages to several webhook destinations at once, like Slack, Discord or
+//! any generic service that accepts a JSON payload.
+
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use futures::future;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::RwLock, time::Duration};
+use tracing::{error, info, trace, warn};
+use url::Url;
+
+use crate::{
+    api::{
+        discord,
+        slack::{self, web::SlackClient},
+    },
+    lease::Leader,
+    storage::Repository,
+};
+
+/// Note appended to shortened messages, when the full message can't be delivered.
+const TRUNCATED_NOTE: &str =
+    "_…the message is too long and was shortened, try a shorter time range._";
+/// Note appended to shortened messages, when the full message is attached as a file.
+const ATTACHED_NOTE: &str = "_…the message is too long, the full version is attached._";
+/// Maximum amount of characters of the summary that is posted together with an uploaded message.
+const SUMMARY_LEN: usize = 3_000;
+
+/// Delay before the first retry of a failed delivery. Each further retry doubles the delay.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Interval in which the outbox is checked for messages that are due for another attempt.
+const OUTBOX_INTERVAL: Duration = Duration::from_secs(30);
+/// Delay in seconds before the first attempt to deliver a message from the outbox. Each further
+/// attempt doubles the delay.
+const OUTBOX_DELAY: i64 = 60;
+
+/// The kind of service behind a webhook, which defines the format of the posted payload.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookKind {
+    /// Slack incoming webhook.
+    #[default]
+    Slack,
+    /// Discord webhook.
+    Discord,
+    /// Any service that accepts the same simple `{"text": "..."}` payload as Slack.
+    Generic,
+}
+
+impl WebhookKind {
+    /// Maximum amount of characters that the service accepts for a single message.
+    const fn max_len(self) -> usize {
+        match self {
+            Self::Slack | Self::Generic => 40_000,
+            Self::Discord => 2_000,
+        }
+    }
+}
+
+/// Kind of scheduled message, to send it to dedicated webhooks instead of all of them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Route {
+    /// Scheduled stats reports and the year in review.
+    Stats,
+    /// Notifications about completed challenges, digests and streak reminders.
+    Notify,
+}
+
+/// A single webhook destination that messages are delivered to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookTarget {
+    /// Location to post messages to.
+    pub url: Url,
+    /// Service type of the webhook. Defaults to Slack if not set.
+    #[serde(default)]
+    pub kind: WebhookKind,
+    /// How many times a failed delivery is retried. Defaults to `3` if not set.
+    #[serde(default = "default_retries")]
+    pub retries: u8,
+    /// Kind of messages that are exclusively delivered to this webhook. Webhooks without a route
+    /// receive all other messages, as well as routed ones if no webhook has that route.
+    #[serde(default)]
+    pub route: Option<Route>,
+    /// ID of the Slack channel that the webhook posts to. With a bot token, messages that are too
+    /// long for a single webhook call are uploaded there as a file instead of being split.
+    #[serde(default)]
+    pub channel: Option<String>,
+}
+
+impl WebhookTarget {
+    /// Create a Slack webhook destination with the default amount of retries, that receives all
+    /// messages.
+    #[must_use]
+    pub const fn slack(url: Url) -> Self {
+        Self {
+            url,
+            kind: WebhookKind::Slack,
+            retries: default_retries(),
+            route: None,
+            channel: None,
+        }
+    }
+}
+
+/// Default value for the retries.
+const fn default_retries() -> u8 {
+    3
+}
+
+/// Delivery statistics and state of a single destination.
+#[derive(Debug, Default)]
+struct TargetState {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    /// Set once the destination is gone, to stop any further deliveries.
+    paused: AtomicBool,
+}
+
+/// A destination together with its delivery state, shared with deliveries that are in progress
+/// while the destination is replaced.
+type Target = Arc<(WebhookTarget, TargetState)>;
+
+/// Final result of a delivery attempt to a single destination.
+enum Delivery {
+    /// The message was delivered successfully.
+    Sent,
+    /// The delivery failed even after retrying.
+    Failed,
+    /// The destination doesn't exist anymore, like an archived channel or revoked webhook.
+    Gone(String),
+}
+
+/// The dispatcher duplicates messages to all configured destinations simultaneously and keeps
+/// track of successful and failed deliveries for each of them.
+///
+/// Destinations that are permanently gone are paused and an alert is sent to the optional ops
+/// webhook. A paused destination resumes once it is configured with a new URL, which can be done
+/// at runtime for the primary destination.
+///
+/// Messages that still fail after all retries are persisted in the outbox and delivered again
+/// later, until they either succeed or the maximum amount of outbox attempts is reached.
+pub struct Dispatcher {
+    client: Client,
+    targets: Mutex<Vec<Target>>,
+    ops: Option<WebhookTarget>,
+    repo: Arc<RwLock<Repository>>,
+    outbox_attempts: u8,
+    dry_run: bool,
+    slack: Option<SlackClient>,
+}
+
+impl Dispatcher {
+    /// Create a new dispatcher for the given list of destinations and optional ops webhook that
+    /// receives alerts about unavailable destinations.
+    ///
+    /// Failed messages are queued in the repository's outbox for up to `outbox_attempts` further
+    /// delivery attempts. A value of `0` disables the outbox.
+    pub fn new(
+        client: Client,
+        targets: impl IntoIterator<Item = WebhookTarget>,
+        ops: Option<WebhookTarget>,
+        repo: Arc<RwLock<Repository>>,
+        outbox_attempts: u8,
+    ) -> Self {
+        Self {
+            client,
+            targets: Mutex::new(
+                targets
+                    .into_iter()
+                    .map(|t| Arc::new((t, TargetState::default())))
+                    .collect(),
+            ),
+            ops,
+            repo,
+            outbox_attempts,
+            dry_run: false,
+            slack: None,
+        }
+    }
+
+    /// Only log all messages instead of posting them, to try out the bot without sending anything
+    /// to the destinations.
+    #[must_use]
+    pub const fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Use the Slack Web API to send direct messages. Without it, direct messages can't be
+    /// answered.
+    #[must_use]
+    pub fn slack(mut self, slack: Option<SlackClient>) -> Self {
+        self.slack = slack;
+        self
+    }
+
+    /// Send the message to all destinations without a route at once, retrying failed deliveries
+    /// for each target individually. Failures are only logged and don't affect delivery to other
+    /// destinations.
+    pub async fn send(&self, text: &str) {
+        self.broadcast(self.unrouted(), text).await;
+    }
+
+    /// Send the message to the destinations of the given route. If there are none, it is sent to
+    /// all destinations without a route instead, like [`Self::send`].
+    pub async fn send_routed(&self, route: Route, text: &str) {
+        let targets = self
+            .targets()
+            .into_iter()
+            .filter(|target| target.0.route == Some(route))
+            .collect::<Vec<_>>();
+
+        if targets.is_empty() {
+            self.send(text).await;
+        } else {
+            self.broadcast(targets, text).await;
+        }
+    }
+
+    /// Send the message to the given destinations at once, and log the outcome for each of them.
+    async fn broadcast(&self, targets: Vec<Target>, text: &str) {
+        let results = future::join_all(
+            targets
+                .iter()
+                .map(|target| self.send_to(&target.0, &target.1, text)),
+        )
+        .await;
+
+        for (i, (target, delivered)) in targets.iter().zip(results).enumerate() {
+            let outcome = outcome(i, &target.0.url, delivered);
+            if delivered {
+                info!("{}", outcome);
+            } else {
+                warn!("{}", outcome);
+            }
+        }
+    }
+
+    /// Send the message to the primary destination only, which is the first configured target.
+    pub async fn send_primary(&self, text: &str) {
+        if let Some(target) = self.primary() {
+            self.send_to(&target.0, &target.1, text).await;
+        }
+    }
+
+    /// Switch the primary destination to a new Slack webhook URL, which also resumes it if it was
+    /// paused. The message is sent to the new URL first, to make sure it works, and the primary
+    /// destination is only switched if it was delivered.
+    pub async fn set_primary(&self, url: Url, text: &str) -> Result<()> {
+        self.probe(&url, text).await?;
+
+        let target = Arc::new((
+            WebhookTarget {
+                retries: self.primary().map_or_else(default_retries, |t| t.0.retries),
+                ..WebhookTarget::slack(url)
+            },
+            TargetState::default(),
+        ));
+
+        let mut targets = self.targets.lock().unwrap();
+        if targets.is_empty() {
+            targets.push(target);
+        } else {
+            targets[0] = target;
+        }
+        drop(targets);
+
+        Ok(())
+    }
+
+    /// Add another Slack webhook URL as destination. Like for [`Self::set_primary`], the message is
+    /// sent to it first and the destination is only added if it was delivered.
+    pub async fn add_target(&self, url: Url, text: &str) -> Result<()> {
+        self.probe(&url, text).await?;
+
+        self.targets.lock().unwrap().push(Arc::new((
+            WebhookTarget::slack(url),
+            TargetState::default(),
+        )));
+
+        Ok(())
+    }
+
+    /// Remove a destination other than the primary one. Returns whether it existed.
+    pub fn remove_target(&self, url: &Url) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        let index = targets.iter().skip(1).position(|t| t.0.url == *url);
+        if let Some(index) = index {
+            targets.remove(index + 1);
+        }
+        drop(targets);
+
+        index.is_some()
+    }
+
+    /// Send a message straight to a Slack webhook URL without any retries, to make sure it works
+    /// before delivering further messages to it.
+    async fn probe(&self, url: &Url, text: &str) -> Result<()> {
+        if self.dry_run {
+            info!("Dry run, not sending to new webhook:\n{}", text);
+            return Ok(());
+        }
+
+        slack::webhook::send(&self.client, url.clone(), text)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Get a snapshot of all destinations, so they can be used without holding the lock.
+    fn targets(&self) -> Vec<Target> {
+        self.targets.lock().unwrap().clone()
+    }
+
+    /// Get a snapshot of all destinations without a route.
+    fn unrouted(&self) -> Vec<Target> {
+        self.targets()
+            .into_iter()
+            .filter(|target| target.0.route.is_none())
+            .collect()
+    }
+
+    /// Get the primary destination, which is the first configured target.
+    fn primary(&self) -> Option<Target> {
+        self.targets.lock().unwrap().first().cloned()
+    }
+
+    /// Send the message to a direct message conversation through the Slack Web API. Long messages
+    /// are split like for Slack webhooks, but failed messages are not retried.
+    pub async fn send_direct(&self, channel: &str, text: &str) {
+        if self.dry_run {
+            info!(
+                "Dry run, not sending to direct message {}:\n{}",
+                channel, text
+            );
+            return;
+        }
+
+        let Some(slack) = &self.slack else {
+            warn!(
+                "Can't reply to direct message {}, no bot token configured",
+                channel
+            );
+            return;
+        };
+
+        for part in split(text, WebhookKind::Slack.max_len()) {
+            if let Err(e) = slack.post_message(channel, part).await {
+                error!("Error sending direct message to {}: {}", channel, e);
+                return;
+            }
+        }
+    }
+
+    /// Send a message to a user as direct message, opening the conversation with the bot first
+    /// through the Slack Web API. Unlike other messages, failures are reported back, so the user
+    /// can be told about them somewhere else.
+    pub async fn send_to_user(&self, user: &str, text: &str) -> Result<()> {
+        if self.dry_run {
+            info!("Dry run, not sending to user {}:\n{}", user, text);
+            return Ok(());
+        }
+
+        let Some(slack) = &self.slack else {
+            bail!("no bot token configured");
+        };
+
+        let channel = slack.open_conversation(user).await?;
+        for part in split(text, WebhookKind::Slack.max_len()) {
+            slack.post_message(&channel, part).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send a message with layout blocks, like buttons, either to a direct message conversation
+    /// or the primary destination, if it is a Slack webhook. Failed messages are not retried, as
+    /// they are only useful right away.
+    pub async fn send_blocks(&self, channel: Option<&str>, text: &str, blocks: &Value) {
+        if self.dry_run {
+            info!(
+                "Dry run, not sending message with blocks:\n{}\n{}",
+                text, blocks
+            );
+            return;
+        }
+
+        let res = match (channel, &self.slack, self.primary()) {
+            (Some(channel), Some(slack), _) => slack.post_blocks(channel, text, blocks).await,
+            (None, _, Some(target)) if target.0.kind == WebhookKind::Slack => {
+                let message = slack::webhook::Message {
+                    text,
+                    blocks: Some(blocks),
+                    ..slack::webhook::Message::default()
+                };
+                slack::webhook::post(&self.client, target.0.url.clone(), &message).await
+            }
+            _ => {
+                warn!("Can't send message with blocks, no Slack destination available");
+                return;
+            }
+        };
+
+        if let Err(e) = res {
+            error!("Error sending message with blocks: {}", e);
+        }
+    }
+
+    /// Replace a message with a new text, through the response URL of an interaction.
+    pub async fn replace(&self, response_url: &Url, text: &str) {
+        if self.dry_run {
+            info!("Dry run, not replacing message:\n{}", text);
+            return;
+        }
+
+        let message = slack::webhook::Message {
+            text,
+            replace_original: true,
+            ..slack::webhook::Message::default()
+        };
+
+        if let Err(e) = slack::webhook::post(&self.client, response_url.clone(), &message).await {
+            error!("Error replacing message: {}", e);
+        }
+    }
+
+    /// Publish the Home tab view for a user through the Slack Web API.
+    pub async fn publish_home(&self, user: &str, view: &Value) {
+        if self.dry_run {
+            info!("Dry run, not publishing Home tab of {}:\n{}", user, view);
+            return;
+        }
+
+        let Some(slack) = &self.slack else {
+            warn!(
+                "Can't publish Home tab of {}, no bot token configured",
+                user
+            );
+            return;
+        };
+
+        if let Err(e) = slack.publish_view(user, view).await {
+            error!("Error publishing Home tab of {}: {}", user, e);
+        }
+    }
+
+    /// Open a modal view in response to a button click, through the Slack Web API.
+    pub async fn open_view(&self, trigger_id: &str, view: &Value) {
+        if self.dry_run {
+            info!("Dry run, not opening view:\n{}", view);
+            return;
+        }
+
+        let Some(slack) = &self.slack else {
+            warn!("Can't open view, no bot token configured");
+            return;
+        };
+
+        if let Err(e) = slack.open_view(trigger_id, view).await {
+            error!("Error opening view: {}", e);
+        }
+    }
+
+    /// Upload a file to a user's direct message conversation, opening the conversation with the
+    /// bot first. Failures are reported back like for [`Self::upload`].
+    pub async fn upload_to_user(
+        &self,
+        user: &str,
+        file_name: &str,
+        content: Vec<u8>,
+        comment: &str,
+    ) -> Result<()> {
+        // Dry runs and missing bot tokens are handled by the upload itself.
+        let channel = match &self.slack {
+            Some(slack) if !self.dry_run => slack.open_conversation(user).await?,
+            _ => user.to_owned(),
+        };
+
+        self.upload(&channel, file_name, content, comment).await
+    }
+
+    /// Upload a file to a channel through the Slack Web API. Unlike other messages, failures are
+    /// reported back to decide how to go on.
+    pub async fn upload(
+        &self,
+        channel: &str,
+        file_name: &str,
+        content: Vec<u8>,
+        comment: &str,
+    ) -> Result<()> {
+        if self.dry_run {
+            info!(
+                "Dry run, not uploading {} ({} bytes) to {}:\n{}",
+                file_name,
+                content.len(),
+                channel,
+                comment
+            );
+            return Ok(());
+        }
+
+        let Some(slack) = &self.slack else {
+            bail!("no bot token configured");
+        };
+
+        slack
+            .upload_file(channel, file_name, content, comment)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// React to a message with an emoji through the Slack Web API. Reactions are only a hint for
+    /// users, so they are skipped without a bot token and failures are only logged.
+    pub async fn react(&self, channel: &str, ts: &str, emoji: &str) {
+        if let Some(slack) = self.slack.as_ref().filter(|_| !self.dry_run) {
+            if let Err(e) = slack.add_reaction(channel, ts, emoji).await {
+                warn!("Error adding reaction to message {}: {}", ts, e);
+            }
+        }
+    }
+
+    /// Remove a previously added reaction from a message again.
+    pub async fn unreact(&self, channel: &str, ts: &str, emoji: &str) {
+        if let Some(slack) = self.slack.as_ref().filter(|_| !self.dry_run) {
+            if let Err(e) = slack.remove_reaction(channel, ts, emoji).await {
+                warn!("Error removing reaction from message {}: {}", ts, e);
+            }
+        }
+    }
+
+    /// Deliver a message to a single destination and update its state based on the outcome.
+    /// Returns whether the message was delivered, either as a whole or all of its parts.
+    ///
+    /// Messages that are too long for Slack or generic webhooks are split into several parts that
+    /// are delivered in order. If one part fails, it is queued in the outbox together with all
+    /// following parts. Slack webhooks with a known channel receive a short summary instead, with
+    /// the full message uploaded as a file, as long as the upload works.
+    async fn send_to(&self, target: &WebhookTarget, state: &TargetState, text: &str) -> bool {
+        if state.paused.load(Ordering::Relaxed) {
+            warn!("Skipping delivery to paused {:?} webhook", target.kind);
+            return false;
+        }
+
+        if self.dry_run {
+            info!(
+                "Dry run, not sending to {:?} webhook:\n{}",
+                target.kind, text
+            );
+            state.sent.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
+        if target.kind == WebhookKind::Slack && text.chars().count() > target.kind.max_len() {
+            if let Some(channel) = target.channel.as_deref().filter(|_| self.slack.is_some()) {
+                let summary = shorten(text, SUMMARY_LEN, ATTACHED_NOTE);
+                match self
+                    .upload(channel, "report.txt", text.as_bytes().to_vec(), &summary)
+                    .await
+                {
+                    Ok(()) => {
+                        state.sent.fetch_add(1, Ordering::Relaxed);
+                        return true;
+                    }
+                    Err(e) => warn!("Error uploading long message, splitting it instead: {}", e),
+                }
+            }
+        }
+
+        // Discord receives the full message as attachment instead.
+        let parts = match target.kind {
+            WebhookKind::Slack | WebhookKind::Generic => split(text, target.kind.max_len()),
+            WebhookKind::Discord => vec![text],
+        };
+
+        for (i, part) in parts.iter().enumerate() {
+            match deliver(&self.client, target, part).await {
+                Delivery::Sent => {}
+                Delivery::Failed => {
+                    state.failed.fetch_add(1, Ordering::Relaxed);
+                    for part in &parts[i..] {
+                        self.enqueue(target, part).await;
+                    }
+                    return false;
+                }
+                Delivery::Gone(reason) => {
+                    state.failed.fetch_add(1, Ordering::Relaxed);
+                    self.pause(target, state, &reason).await;
+                    return false;
+                }
+            }
+        }
+
+        state.sent.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Stop any further deliveries to a destination that is gone. Admins are alerted only once.
+    async fn pause(&self, target: &WebhookTarget, state: &TargetState, reason: &str) {
+        if !state.paused.swap(true, Ordering::Relaxed) {
+            self.alert(target, reason).await;
+        }
+    }
+
+    /// Put a failed message into the outbox, to deliver it again later.
+    async fn enqueue(&self, target: &WebhookTarget, text: &str) {
+        if self.outbox_attempts == 0 {
+            return;
+        }
+
+        let next_attempt = Utc::now() + chrono::Duration::seconds(OUTBOX_DELAY);
+        if let Err(e) = self
+            .repo
+            .write()
+            .await
+            .enqueue(target.url.clone(), text.to_owned(), 0, next_attempt)
+            .await
+        {
+            error!("Error putting message into the outbox: {}", e);
+        }
+    }
+
+    /// Periodically deliver messages from the outbox that are due for another attempt. Only the
+    /// leader delivers them, so they aren't sent twice.
+    pub async fn run_outbox(self: Arc<Self>, leader: Leader) {
+        let mut interval = tokio::time::interval(OUTBOX_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if !leader.is_leader() || self.dry_run {
+                continue;
+            }
+
+            if let Err(e) = self.flush_outbox().await {
+                error!("Error delivering messages from the outbox: {}", e);
+            }
+        }
+    }
+
+    /// Try to deliver all messages of the outbox that are due, once each. Messages are removed
+    /// after a successful delivery, when the destination is gone or when running out of attempts.
+    /// Nothing is delivered while the bot is under maintenance.
+    async fn flush_outbox(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let repo = self.repo.read().await;
+        if repo.maintenance() {
+            return Ok(());
+        }
+
+        let due = repo
+            .outbox()
+            .iter()
+            .filter(|m| m.next_attempt <= now)
+            .cloned()
+            .collect::<Vec<_>>();
+        drop(repo);
+
+        let targets = self.targets();
+        for message in due {
+            let target = targets
+                .iter()
+                .find(|t| t.0.url == message.url && !t.1.paused.load(Ordering::Relaxed));
+
+            let Some((target, state)) = target.map(|t| (&t.0, &t.1)) else {
+                warn!("Dropping queued message for a removed or paused webhook");
+                self.repo.write().await.dequeue(message.id).await?;
+                continue;
+            };
+
+            let single = WebhookTarget {
+                retries: 0,
+                ..target.clone()
+            };
+
+            match deliver(&self.client, &single, &message.text).await {
+                Delivery::Sent => {
+                    state.sent.fetch_add(1, Ordering::Relaxed);
+                    self.repo.write().await.dequeue(message.id).await?;
+                }
+                Delivery::Gone(reason) => {
+                    self.pause(target, state, &reason).await;
+                    self.repo.write().await.dequeue(message.id).await?;
+                }
+                Delivery::Failed if message.attempts + 1 < self.outbox_attempts => {
+                    let delay = OUTBOX_DELAY << (message.attempts + 1).min(16);
+                    self.repo
+                        .write()
+                        .await
+                        .postpone(message.id, now + chrono::Duration::seconds(delay))
+                        .await?;
+                }
+                Delivery::Failed => {
+                    error!(
+                        "Dropping queued message to {:?} webhook after {} attempts",
+                        target.kind, self.outbox_attempts
+                    );
+                    self.repo.write().await.dequeue(message.id).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notify admins through the ops webhook that a destination was paused.
+    async fn alert(&self, target: &WebhookTarget, reason: &str) {
+        error!(
+            "Pausing deliveries to {:?} webhook at {}, as it is gone ({})",
+            target.kind,
+            target.url.host_str().unwrap_or_default(),
+            reason
+        );
+
+        if let Some(ops) = &self.ops {
+            let text = format!(
+                ":warning: Deliveries to the {:?} webhook at `{}` are paused, as the destination \
+                 is gone (`{}`). Please configure a new webhook URL to resume.",
+                target.kind,
+                target.url.host_str().unwrap_or_default(),
+                reason
+            );
+            deliver(&self.client, ops, &text).await;
+        }
+    }
+
+    /// Whether any of the destinations is paused, because it is gone.
+    pub fn any_paused(&self) -> bool {
+        self.targets()
+            .iter()
+            .any(|target| target.1.paused.load(Ordering::Relaxed))
+    }
+
+    /// Get the route, amount of successful and failed deliveries for each destination, and whether
+    /// it is currently paused.
+    pub fn stats(&self) -> Vec<(Url, Option<Route>, u64, u64, bool)> {
+        self.targets()
+            .iter()
+            .map(|target| {
+                let (target, state) = &**target;
+                (
+                    target.url.clone(),
+                    target.route,
+                    state.sent.load(Ordering::Relaxed),
+                    state.failed.load(Ordering::Relaxed),
+                    state.paused.load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Describe the outcome of delivering a message to the target at the given position. Only the host
+/// of the URL is named, as the path of webhook URLs is secret.
+fn outcome(index: usize, url: &Url, delivered: bool) -> String {
+    format!(
+        "{} message to webhook {} at {}",
+        if delivered {
+            "Delivered"
+        } else {
+            "Failed delivering"
+        },
+        index + 1,
+        url.host_str().unwrap_or_default()
+    )
+}
+
+/// Deliver a message to a single target, with retries and an increasing delay in between.
+///
+/// Messages that exceed the service's length limit are shortened to the leading lines that fit. The
+/// full message is attached as a file for services that allow uploads through their webhooks.
+async fn deliver(client: &Client, target: &WebhookTarget, text: &str) -> Delivery {
+    let max_len = target.kind.max_len();
+    let oversized = text.chars().count() > max_len;
+    let mut delay = RETRY_DELAY;
+
+    if oversized {
+        warn!(
+            "Message exceeds the limit of {:?} webhooks and is shortened",
+            target.kind
+        );
+    }
+
+    for attempt in 0..=target.retries {
+        let res = match target.kind {
+            WebhookKind::Slack | WebhookKind::Generic => {
+                let text = if oversized {
+                    shorten(text, max_len, TRUNCATED_NOTE).into()
+                } else {
+                    Cow::Borrowed(text)
+                };
+
+                match slack::webhook::send(client, target.url.clone(), &text).await {
+                    Err(slack::Error::WebhookGone(reason)) => return Delivery::Gone(reason),
+                    res => res.map_err(anyhow::Error::from),
+                }
+            }
+            WebhookKind::Discord => {
+                let res = if oversized {
+                    discord::webhook::send_file(
+                        client,
+                        target.url.clone(),
+                        &shorten(text, max_len, ATTACHED_NOTE),
+                        "report.txt",
+                        text.to_owned(),
+                    )
+                    .await
+                } else {
+                    discord::webhook::send(client, target.url.clone(), text).await
+                };
+
+                match res {
+                    Err(discord::Error::WebhookGone) => {
+                        return Delivery::Gone("unknown_webhook".to_owned())
+                    }
+                    res => res.map_err(anyhow::Error::from),
+                }
+            }
+        };
+
+        match res {
+            Ok(()) => {
+                trace!("Delivered message to {:?} webhook", target.kind);
+                return Delivery::Sent;
+            }
+            Err(e) if attempt < target.retries => {
+                warn!(
+                    "Failed delivering to {:?} webhook (attempt {}): {}",
+                    target.kind,
+                    attempt + 1,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => error!("Giving up delivering to {:?} webhook: {}", target.kind, e),
+        }
+    }
+
+    Delivery::Failed
+}
+
+/// Split the text into parts of at most the given amount of characters, keeping their order.
+///
+/// Parts preferably end at blank lines, which separate the sections of a report like the ones of
+/// each user. Sections that are too long on their own are split at line ends, and single lines
+/// that are too long anywhere.
+fn split(text: &str, max_len: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    while let Some((limit, _)) = rest.char_indices().nth(max_len) {
+        // Look slightly beyond the limit, as a boundary right after it still allows for a part of
+        // the full length.
+        let window = rest[limit..]
+            .char_indices()
+            .nth(2)
+            .map_or(rest, |(i, _)| &rest[..limit + i]);
+        let boundary = |sep: &str| {
+            window
+                .rmatch_indices(sep)
+                .map(|(i, _)| i)
+                .find(|i| (1..=limit).contains(i))
+        };
+
+        let (end, skip) = boundary("\n\n")
+            .map(|i| (i, 2))
+            .or_else(|| boundary("\n").map(|i| (i, 1)))
+            .unwrap_or((limit, 0));
+
+        parts.push(&rest[..end]);
+        rest = &rest[end + skip..];
+    }
+
+    parts.push(rest);
+    parts
+}
+
+/// Cut the text down to its leading lines, so that the lines together with the appended note stay
+/// within the given maximum amount of characters.
+fn shorten(text: &str, max_len: usize, note: &str) -> String {
+    let budget = max_len.saturating_sub(note.chars().count());
+    let mut len = 0;
+    let mut short = String::new();
+
+    for line in text.lines() {
+        len += line.chars().count() + 1;
+        if len > budget {
+            break;
+        }
+
+        short.push_str(line);
+        short.push('\n');
+    }
+
+    short.push_str(note);
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[test]
+    fn shorten_at_line_boundary() {
+        let text = "header\nfirst line\nsecond line";
+
+        assert_eq!("header\nfirst line\n(cut)", shorten(text, 24, "(cut)"));
+        assert_eq!("header\n(cut)", shorten(text, 20, "(cut)"));
+        assert_eq!("(cut)", shorten(text, 5, "(cut)"));
+    }
+
+    #[test]
+    fn split_at_section_boundary() {
+        let text = "header\n\nfirst\nuser\n\nsecond\nuser";
+
+        assert_eq!(vec![text], split(text, 100));
+        assert_eq!(
+            vec!["header\n\nfirst\nuser", "second\nuser"],
+            split(text, 20)
+        );
+        assert_eq!(
+            vec!["header", "first\nuser", "second", "user"],
+            split(text, 10)
+        );
+        assert_eq!(
+            vec!["head", "er", "firs", "t", "user"],
+            split("header\n\nfirst\nuser", 4)
+        );
+        assert_eq!(vec!["äö", "ü"], split("äöü", 2));
+    }
+
+    #[tokio::test]
+    async fn dry_run_only_logs() {
+        let repo = Arc::new(RwLock::new(Repository::in_memory()));
+        let dispatcher = Dispatcher::new(
+            Client::new(),
+            [WebhookTarget {
+                url: "http://127.0.0.1:9/unreachable".parse().unwrap(),
+                kind: WebhookKind::Slack,
+                retries: 0,
+                route: None,
+                channel: None,
+            }],
+            None,
+            repo.clone(),
+            10,
+        )
+        .dry_run(true);
+
+        dispatcher.send("Hello").await;
+
+        assert_eq!(
+            vec![(1, 0, false)],
+            dispatcher
+                .stats()
+                .into_iter()
+                .map(|(_, _, sent, failed, paused)| (sent, failed, paused))
+                .collect::<Vec<_>>()
+        );
+        assert!(repo.read().await.outbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn manage_targets_at_runtime() {
+        let url = |path: &str| format!("http://127.0.0.1:9/{path}").parse::<Url>().unwrap();
+        let dispatcher = Dispatcher::new(
+            Client::new(),
+            [WebhookTarget::slack(url("first"))],
+            None,
+            Arc::new(RwLock::new(Repository::in_memory())),
+            0,
+        )
+        .dry_run(true);
+        let urls = || {
+            dispatcher
+                .stats()
+                .into_iter()
+                .map(|(url, ..)| url.path().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        dispatcher.add_target(url("second"), "Hello").await.unwrap();
+        assert_eq!(["/first", "/second"], urls().as_slice());
+
+        dispatcher.set_primary(url("third"), "Hello").await.unwrap();
+        assert_eq!(["/third", "/second"], urls().as_slice());
+
+        assert!(!dispatcher.remove_target(&url("third")));
+        assert!(!dispatcher.remove_target(&url("unknown")));
+        assert!(dispatcher.remove_target(&url("second")));
+        assert_eq!(["/third"], urls().as_slice());
+    }
+
+    #[tokio::test]
+    async fn deliver_to_each_target() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ok"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/broken"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("internal error"))
+            .mount(&server)
+            .await;
+
+        let target = |path: &str| WebhookTarget {
+            retries: 0,
+            ..WebhookTarget::slack(format!("{}/{path}", server.uri()).parse().unwrap())
+        };
+        let dispatcher = Dispatcher::new(
+            Client::new(),
+            [target("broken"), target("ok")],
+            None,
+            Arc::new(RwLock::new(Repository::in_memory())),
+            0,
+        );
+
+        dispatcher.send("Hello").await;
+
+        assert_eq!(
+            vec![("/broken".to_owned(), 0, 1), ("/ok".to_owned(), 1, 0)],
+            dispatcher
+                .stats()
+                .into_iter()
+                .map(|(url, _, sent, failed, _)| (url.path().to_owned(), sent, failed))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn describe_outcome_without_secret() {
+        let url = "https://hooks.slack.com/services/T000/B000/XXXX"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            "Delivered message to webhook 1 at hooks.slack.com",
+            outcome(0, &url, true)
+        );
+        assert_eq!(
+            "Failed delivering message to webhook 2 at hooks.slack.com",
+            outcome(1, &url, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_oversized_messages() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/files.getUploadURLExternal"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true,
+                "upload_url": format!("{}/upload/F01", server.uri()),
+                "file_id": "F01"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/upload/F01"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/files.completeUploadExternal"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "ok": true })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let slack = SlackClient::with_base_url(
+            Client::new(),
+            "xoxb-token".to_owned(),
+            format!("{}/api/", server.uri()).parse().unwrap(),
+        );
+        let dispatcher = Dispatcher::new(
+            Client::new(),
+            [WebhookTarget {
+                channel: Some("C01".to_owned()),
+                ..WebhookTarget::slack(format!("{}/hook", server.uri()).parse().unwrap())
+            }],
+            None,
+            Arc::new(RwLock::new(Repository::in_memory())),
+            0,
+        )
+        .slack(Some(slack));
+
+        dispatcher.send(&"line\n".repeat(10_000)).await;
+
+        assert_eq!(1, dispatcher.stats()[0].2);
+    }
+
+    #[tokio::test]
+    async fn route_to_dedicated_webhooks() {
+        let target = |path: &str, route| WebhookTarget {
+            route,
+            ..WebhookTarget::slack(format!("http://127.0.0.1:9/{path}").parse().unwrap())
+        };
+        let dispatcher = Dispatcher::new(
+            Client::new(),
+            [target("all", None), target("stats", Some(Route::Stats))],
+            None,
+            Arc::new(RwLock::new(Repository::in_memory())),
+            0,
+        )
+        .dry_run(true);
+
+        dispatcher.send_routed(Route::Stats, "Report").await;
+        dispatcher.send_routed(Route::Notify, "Notification").await;
+        dispatcher.send("Announcement").await;
+
+        assert_eq!(
+            vec![2, 1],
+            dispatcher
+                .stats()
+                .into_iter()
+                .map(|(_, _, sent, _, _)| sent)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Append-only log of all detected challenge completions, written as one JSON object per line. The
+//! log is independent of any message delivery, so other tools can consume the raw events even if
+//! posting to a chat fails.
+
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::prelude::*;
+use serde::Serialize;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::api::codewars::CompletedChallenge;
+
+/// A single challenge completion of a tracked user.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion<'a> {
+    /// Codewars username that completed the challenge.
+    pub user: &'a str,
+    /// Full details of the completed challenge.
+    #[serde(flatten)]
+    pub challenge: &'a CompletedChallenge,
+    /// Whether the user completed this challenge before already.
+    pub retrain: bool,
+    /// Whether the completion was found while syncing the user for the first time, in which case
+    /// it might have happened long before it was detected.
+    pub initial: bool,
+    /// Time when the completion was detected.
+    pub detected_at: DateTime<Utc>,
+}
+
+/// Append the completions to the event log at the given location, creating the file if needed.
+pub async fn append(path: &Path, completions: &[Completion<'_>]) -> Result<()> {
+    if completions.is_empty() {
+        return Ok(());
+    }
+
+    let mut lines = String::new();
+    for completion in completions {
+        lines.push_str(&serde_json::to_string(completion)?);
+        lines.push('\n');
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn append_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let challenge = CompletedChallenge {
+            id: "1".to_owned(),
+            name: Some("Kata".to_owned()),
+            slug: Some("kata".to_owned()),
+            completed_at: Utc::now(),
+            completed_languages: ["rust".to_owned()].into(),
+        };
+        let completion = Completion {
+            user: "him",
+            challenge: &challenge,
+            retrain: false,
+            initial: true,
+            detected_at: Utc::now(),
+        };
+
+        append(&path, &[completion]).await.unwrap();
+        append(&path, &[]).await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(1, lines.len());
+
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!("him", event["user"]);
+        assert_eq!("Kata", event["name"]);
+        assert_eq!("rust", event["completedLanguages"][0]);
+        assert_eq!(true, event["initial"]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Export of all completed challenges as CSV or JSON file, for team leads that want to crunch the
+//! numbers in a spreadsheet. The file is uploaded to the requesting user's direct messages.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use codewars_bot::commands;
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::{
+    handler::Context,
+    help::{Category, Entry},
+    mrkdwn,
+    plugins::Plugin,
+};
+
+/// Syntax of the `export` command, shown for invalid arguments.
+const SYNTAX: &str = "export (csv | json) [since <date>]";
+
+/// Help entries of the `export` command.
+const USAGE: &[Entry] = &[Entry {
+    category: Category::Reports,
+    syntax: SYNTAX,
+    description: "Send a file with all challenges that the tracked users completed, optionally \
+                  only since the given date.",
+    details: &[
+        "The format of `<date>` is `YYYY/MM/DD`.",
+        "The file is sent as direct message and needs the bot token to be configured.",
+    ],
+    example: "export csv since 2023/01/01",
+}];
+
+/// File format of the export.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// File extension of the format.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Command to export the completed challenges.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct Command {
+    format: Format,
+    /// Only export challenges completed on or after this date.
+    since: Option<NaiveDate>,
+}
+
+/// Plugin for the `export` command.
+pub struct ExportPlugin;
+
+#[async_trait]
+impl Plugin<Context> for ExportPlugin {
+    type Command = Command;
+
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static [Entry] {
+        USAGE
+    }
+
+    fn parse(&self, args: &str) -> commands::Result<Command> {
+        let (format, since) = match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [format] => (format, None),
+            [format, "since", date] => (format, Some(NaiveDate::parse_from_str(date, "%Y/%m/%d")?)),
+            _ => return Err(commands::Error::InvalidArguments(SYNTAX)),
+        };
+        let format = match format {
+            "csv" => Format::Csv,
+            "json" => Format::Json,
+            _ => return Err(commands::Error::InvalidArguments(SYNTAX)),
+        };
+
+        Ok(Command { format, since })
+    }
+
+    async fn execute(&self, ctx: &Context, user: &str, cmd: Command) -> Result<String> {
+        let (completions, failed) = collect(ctx, cmd.since).await;
+        let content = match cmd.format {
+            Format::Csv => csv(&completions)?.into_bytes(),
+            Format::Json => serde_json::to_vec_pretty(&completions)?,
+        };
+        let file_name = format!(
+            "completions-{}.{}",
+            Local::today().format("%Y-%m-%d"),
+            cmd.format.extension()
+        );
+
+        if let Err(e) = ctx
+            .dispatcher
+            .upload_to_user(
+                user,
+                &file_name,
+                content,
+                "Here is the export you asked for",
+            )
+            .await
+        {
+            error!("Error uploading export: {:#}", e);
+            return Ok(format!("Sorry, I couldn't upload the export: {e}"));
+        }
+
+        let mut response = format!(
+            "I sent you the export with {} completed challenges :inbox_tray:",
+            completions.len()
+        );
+        if !failed.is_empty() {
+            write!(
+                response,
+                "\n:warning: Some users couldn't be fetched and are missing: {}",
+                failed
+                    .iter()
+                    .map(|user| format!("`{}`", mrkdwn::code(user)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// A single completed challenge of a user.
+#[derive(Serialize)]
+struct Completion {
+    username: String,
+    display_name: Option<String>,
+    challenge_id: String,
+    challenge_name: Option<String>,
+    completed_at: DateTime<Utc>,
+    /// Languages that the challenge was completed in, sorted by name.
+    languages: Vec<String>,
+}
+
+/// Collect the completed challenges of all tracked users, oldest first per user, together with
+/// the users that couldn't be fetched.
+async fn collect(ctx: &Context, since: Option<NaiveDate>) -> (Vec<Completion>, Vec<String>) {
+    let (users, names) = {
+        let settings = ctx.settings.read().await;
+        (
+            settings.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            settings.display_names(),
+        )
+    };
+    let since = since.map(|date| Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+
+    let mut completions = Vec::new();
+    let mut failed = Vec::new();
+
+    for user in users {
+        let challenges = match ctx.fetch.completed_challenges(&user).await {
+            Ok(challenges) => challenges,
+            Err(e) => {
+                warn!("Skipping user {} in the export: {}", user, e);
+                failed.push(user);
+                continue;
+            }
+        };
+
+        let mut listed = challenges
+            .data
+            .iter()
+            .filter(|c| since.is_none_or(|since| c.completed_at >= since))
+            .collect::<Vec<_>>();
+        listed.sort_by_key(|c| c.completed_at);
+
+        completions.extend(listed.into_iter().map(|c| {
+            let mut languages = c.completed_languages.iter().cloned().collect::<Vec<_>>();
+            languages.sort_unstable();
+
+            Completion {
+                username: user.clone(),
+                display_name: names.get(&user).cloned(),
+                challenge_id: c.id.clone(),
+                challenge_name: c.name.clone(),
+                completed_at: c.completed_at,
+                languages,
+            }
+        }));
+    }
+
+    (completions, failed)
+}
+
+/// Render the completions as CSV with a header line. Multiple languages are separated by `;`.
+fn csv(completions: &[Completion]) -> Result<String> {
+    let mut out =
+        String::from("username,display_name,challenge_id,challenge_name,completed_at,languages\n");
+
+    for c in completions {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            csv_field(&c.username),
+            csv_field(c.display_name.as_deref().unwrap_or_default()),
+            csv_field(&c.challenge_id),
+            csv_field(c.challenge_name.as_deref().unwrap_or_default()),
+            c.completed_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            csv_field(&c.languages.join(";")),
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Quote a CSV field if it contains any separators, quotes or line breaks.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let plugin = ExportPlugin;
+
+        assert_eq!(
+            Command {
+                format: Format::Csv,
+                since: None
+            },
+            plugin.parse("csv").unwrap()
+        );
+        assert_eq!(
+            Command {
+                format: Format::Json,
+                since: Some(NaiveDate::from_ymd(2023, 1, 31))
+            },
+            plugin.parse("json since 2023/01/31").unwrap()
+        );
+        assert!(plugin.parse("").is_err());
+        assert!(plugin.parse("xml").is_err());
+        assert!(plugin.parse("csv since 2023-01-31").is_err());
+    }
+
+    #[test]
+    fn render_csv() {
+        let completions = [Completion {
+            username: "him".to_owned(),
+            display_name: Some("Him, \"the\" best".to_owned()),
+            challenge_id: "1".to_owned(),
+            challenge_name: Some("Multiply".to_owned()),
+            completed_at: Utc.ymd(2023, 1, 31).and_hms(12, 0, 0),
+            languages: vec!["python".to_owned(), "rust".to_owned()],
+        }];
+
+        assert_eq!(
+            "username,display_name,challenge_id,challenge_name,completed_at,languages\n\
+             him,\"Him, \"\"the\"\" best\",1,Multiply,2023-01-31T12:00:00Z,python;rust\n",
+            csv(&completions).unwrap()
+        );
+    }
+}
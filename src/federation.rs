@@ -0,0 +1,234 @@
+//! Comparison of the team's progress against another instance of this bot, which publishes its own
+//! summary at the `/api/summary` endpoint. Both bots share a secret that the endpoint requires as
+//! bearer token.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::{fetch::FetchCache, storage::Repository};
+
+/// Another bot instance to compare the team's progress with.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rival {
+    /// Display name of the other team.
+    pub name: String,
+    /// Full URL to the summary endpoint of the other bot.
+    pub url: Url,
+    /// Shared secret that the other bot requires to serve its summary.
+    pub token: String,
+}
+
+/// Summary of a team's progress since the last scheduled report.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Summary {
+    /// Amount of tracked users.
+    pub users: usize,
+    /// Total challenges completed since the last report.
+    pub completed: usize,
+    /// Honor points gained since the last report.
+    pub honor_gained: u64,
+    /// Current honor of each tracked user, used to calculate the gained honor on the next run.
+    #[serde(skip)]
+    pub honor: BTreeMap<String, u32>,
+}
+
+/// Collect the summary of all tracked users since the last scheduled report.
+pub async fn collect(repo: &RwLock<Repository>, fetch: &FetchCache) -> Result<Summary> {
+    let (users, last_run, previous) = {
+        let repo = repo.read().await;
+        (
+            repo.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            repo.last_run(),
+            repo.honor().clone(),
+        )
+    };
+
+    let mut summary = Summary::default();
+
+    for user in users {
+        let info = fetch.user(&user).await?;
+        let challenges = fetch.completed_challenges(&user).await?;
+
+        summary.users += 1;
+        summary.completed += challenges
+            .data
+            .iter()
+            .filter(|c| last_run.is_none_or(|lr| c.completed_at >= lr))
+            .count();
+        summary.honor_gained += u64::from(
+            info.honor
+                .saturating_sub(previous.get(&user).copied().unwrap_or(info.honor)),
+        );
+        summary.honor.insert(user, info.honor);
+    }
+
+    Ok(summary)
+}
+
+/// Load the summary of another bot instance.
+pub async fn fetch(client: &Client, rival: &Rival) -> Result<Summary> {
+    let resp = client
+        .get(rival.url.clone())
+        .bearer_auth(&rival.token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(resp.json().await?)
+}
+
+/// Create a report section that compares the own team's summary against the rival's one.
+pub fn compare(own: &Summary, rival_name: &str, rival: &Summary) -> Result<String> {
+    let mut section = format!("\n\n*Us vs. {rival_name}*");
+
+    write!(
+        &mut section,
+        "\nCompleted challenges: *{}* vs. *{}* {}",
+        own.completed,
+        rival.completed,
+        medal(own.completed.cmp(&rival.completed))
+    )?;
+    write!(
+        &mut section,
+        "\nHonor gained: *{}* vs. *{}* {}",
+        own.honor_gained,
+        rival.honor_gained,
+        medal(own.honor_gained.cmp(&rival.honor_gained))
+    )?;
+
+    Ok(section)
+}
+
+/// Pick an emoji that shows whether the own team is ahead or behind.
+const fn medal(ordering: std::cmp::Ordering) -> &'static str {
+    match ordering {
+        std::cmp::Ordering::Greater => ":trophy:",
+        std::cmp::Ordering::Equal => ":handshake:",
+        std::cmp::Ordering::Less => ":muscle:",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::prelude::*;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::api::codewars::CodewarsClient;
+
+    #[tokio::test]
+    async fn collect_since_last_run() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/alice"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "username": "alice",
+                "name": null,
+                "honor": 544,
+                "clan": "",
+                "leaderboardPosition": null,
+                "skills": null,
+                "ranks": {
+                    "overall": { "rank": -4, "name": "4 kyu", "color": "blue", "score": 1600 },
+                    "languages": {}
+                },
+                "codeChallenges": { "totalAuthored": 0, "totalCompleted": 2 }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/alice/code-challenges/completed"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "totalPages": 1,
+                "totalItems": 2,
+                "data": [
+                    { "id": "1", "completedAt": "2020-02-12T10:00:00Z", "completedLanguages": [] },
+                    { "id": "2", "completedAt": "2020-02-01T10:00:00Z", "completedLanguages": [] }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let base_url = Url::parse(&format!("{}/api/v1/", server.uri())).unwrap();
+        let fetch = FetchCache::new(
+            CodewarsClient::with_base_url(Client::new(), base_url),
+            Duration::ZERO,
+            None,
+        );
+        let mut repo = Repository::in_memory();
+        repo.add_user("alice", None).await.unwrap();
+        repo.set_last_run(Utc.ymd(2020, 2, 10).and_hms(0, 0, 0))
+            .await
+            .unwrap();
+        repo.set_honor([("alice".to_owned(), 500)].into())
+            .await
+            .unwrap();
+
+        let summary = collect(&RwLock::new(repo), &fetch).await.unwrap();
+        assert_eq!(1, summary.users);
+        assert_eq!(1, summary.completed);
+        assert_eq!(44, summary.honor_gained);
+        assert_eq!(Some(&544), summary.honor.get("alice"));
+    }
+
+    #[tokio::test]
+    async fn fetch_with_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/summary"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "users": 3,
+                "completed": 12,
+                "honor_gained": 150
+            })))
+            .mount(&server)
+            .await;
+
+        let mut rival = Rival {
+            name: "Team B".to_owned(),
+            url: Url::parse(&format!("{}/api/summary", server.uri())).unwrap(),
+            token: "secret".to_owned(),
+        };
+        let summary = fetch(&Client::new(), &rival).await.unwrap();
+        assert_eq!(3, summary.users);
+        assert_eq!(12, summary.completed);
+        assert_eq!(150, summary.honor_gained);
+
+        rival.token = "wrong".to_owned();
+        assert!(fetch(&Client::new(), &rival).await.is_err());
+    }
+
+    #[test]
+    fn compare_with_rival() {
+        let own = Summary {
+            users: 2,
+            completed: 5,
+            honor_gained: 40,
+            ..Summary::default()
+        };
+        let rival = Summary {
+            users: 3,
+            completed: 5,
+            honor_gained: 90,
+            ..Summary::default()
+        };
+
+        assert_eq!(
+            "\n\n*Us vs. Team B*\
+             \nCompleted challenges: *5* vs. *5* :handshake:\
+             \nHonor gained: *40* vs. *90* :muscle:",
+            compare(&own, "Team B", &rival).unwrap()
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Caching of fetched Codewars data, so every user's data is only requested once within a short
+//! time, even if several report sections or back-to-back commands need it. Completed challenges
+//! are taken from the local history instead, once a user was synced. Details of challenges rarely
+//! change and are kept until the service restarts.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    api::codewars::{CodeChallenge, CodewarsClient, CompletedChallenges, Result, User},
+    history::History,
+};
+
+/// A cached value together with the time it was fetched.
+struct Entry<T> {
+    fetched: Instant,
+    value: Arc<T>,
+}
+
+/// Cached entries of a single kind of data, keyed by username.
+type Entries<T> = Mutex<HashMap<String, Entry<T>>>;
+
+/// Codewars data of all users that was fetched recently. Entries are kept for the configured time
+/// to live and fetched again afterwards. A time to live of zero disables the cache.
+pub struct FetchCache {
+    client: CodewarsClient,
+    ttl: Duration,
+    history: Option<Arc<Mutex<History>>>,
+    users: Entries<User>,
+    challenges: Entries<CompletedChallenges>,
+    katas: Entries<CodeChallenge>,
+}
+
+impl FetchCache {
+    /// Create a new empty cache that uses the given client to fetch data and keeps it for the
+    /// given time. If a history is given, completed challenges of synced users are read from it.
+    pub fn new(
+        client: CodewarsClient,
+        ttl: Duration,
+        history: Option<Arc<Mutex<History>>>,
+    ) -> Self {
+        Self {
+            client,
+            ttl,
+            history,
+            users: Mutex::default(),
+            challenges: Mutex::default(),
+            katas: Mutex::default(),
+        }
+    }
+
+    /// Get the Codewars client that is used to fetch data.
+    pub const fn client(&self) -> &CodewarsClient {
+        &self.client
+    }
+
+    /// Get the profile of a user, fetching it only if it wasn't requested recently.
+    pub async fn user(&self, username: &str) -> Result<Arc<User>> {
+        self.cached(&self.users, username, self.ttl, self.client.user(username))
+            .await
+    }
+
+    /// Get the completed challenges of a user from the local history, or fetch them only if they
+    /// weren't requested recently.
+    pub async fn completed_challenges(&self, username: &str) -> Result<Arc<CompletedChallenges>> {
+        if let Some(history) = &self.history {
+            if let Some(challenges) = history.lock().await.completed_challenges(username) {
+                return Ok(Arc::new(challenges));
+            }
+        }
+
+        self.cached(
+            &self.challenges,
+            username,
+            self.ttl,
+            self.client.completed_challenges(username, 0),
+        )
+        .await
+    }
+
+    /// Get the details of a challenge, fetching them only once.
+    pub async fn code_challenge(&self, id: &str) -> Result<Arc<CodeChallenge>> {
+        self.cached(
+            &self.katas,
+            id,
+            Duration::MAX,
+            self.client.code_challenge(id),
+        )
+        .await
+    }
+
+    /// Get a cached value if it is still fresh, or fetch and cache it otherwise. Expired entries
+    /// of other keys are cleaned up at the same time.
+    async fn cached<T: Send + Sync>(
+        &self,
+        entries: &Entries<T>,
+        key: &str,
+        ttl: Duration,
+        fetch: impl Future<Output = Result<T>> + Send,
+    ) -> Result<Arc<T>> {
+        if let Some(entry) = entries.lock().await.get(key) {
+            if entry.fetched.elapsed() < ttl {
+                return Ok(entry.value.clone());
+            }
+        }
+
+        let value = Arc::new(fetch.await?);
+        let mut entries = entries.lock().await;
+        entries.retain(|_, entry| entry.fetched.elapsed() < ttl);
+        entries.insert(
+            key.to_owned(),
+            Entry {
+                fetched: Instant::now(),
+                value: value.clone(),
+            },
+        );
+
+        Ok(value)
+    }
+}
+
+/// Create a cache that fetches from a mocked Codewars API, with the given completed challenges of
+/// each user as pairs of ID and completion time. Any other requests fail.
+#[cfg(test)]
+pub async fn mock(users: &[(&str, &[(&str, &str)])]) -> (wiremock::MockServer, FetchCache) {
+    use reqwest::Client;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    let server = MockServer::start().await;
+    for (user, challenges) in users {
+        let data = challenges
+            .iter()
+            .map(|(id, completed_at)| {
+                json!({
+                    "id": id,
+                    "name": format!("Kata {id}"),
+                    "completedAt": completed_at,
+                    "completedLanguages": ["rust"]
+                })
+            })
+            .collect::<Vec<_>>();
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/api/v1/users/{user}/code-challenges/completed"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "totalPages": 1,
+                "totalItems": data.len(),
+                "data": data
+            })))
+            .mount(&server)
+            .await;
+    }
+
+    let base_url = Url::parse(&format!("{}/api/v1/", server.uri())).unwrap();
+    let fetch = FetchCache::new(
+        CodewarsClient::with_base_url(Client::new(), base_url),
+        Duration::ZERO,
+        None,
+    );
+
+    (server, fetch)
+}
@@ -0,0 +1,100 @@
+//! Coalescing of identical computations that run at the same time. If several users ask for the
+//! same slow report at once, it is only created once and everyone gets the same result.
+
+use std::{collections::HashMap, hash::Hash};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use tokio::sync::Mutex;
+
+/// A computation that is in flight, with a unique ID to tell it apart from later ones of the same
+/// key.
+struct Call<V> {
+    id: u64,
+    result: Shared<BoxFuture<'static, V>>,
+}
+
+/// Computations that are currently in flight, by their key.
+pub struct SingleFlight<K, V> {
+    calls: Mutex<(u64, HashMap<K, Call<V>>)>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone + Send,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::new((0, HashMap::new())),
+        }
+    }
+
+    /// Run the computation, unless another one with the same key is in flight already. In that
+    /// case, its result is awaited instead and `compute` is dropped without running it.
+    ///
+    /// Results are not cached, so a call with the same key that starts after the computation
+    /// finished runs it again. The computation is boxed, as it is kept until it finished and
+    /// would otherwise make the future of every caller as large as itself.
+    pub async fn run(&self, key: K, compute: BoxFuture<'static, V>) -> V {
+        let (id, result) = {
+            let mut calls = self.calls.lock().await;
+            let (next_id, calls) = &mut *calls;
+            let call = calls.entry(key.clone()).or_insert_with(|| {
+                *next_id += 1;
+                Call {
+                    id: *next_id,
+                    result: compute.shared(),
+                }
+            });
+            (call.id, call.result.clone())
+        };
+
+        let value = result.await;
+
+        let mut calls = self.calls.lock().await;
+        if calls.1.get(&key).is_some_and(|call| call.id == id) {
+            calls.1.remove(&key);
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn coalesce_concurrent_calls() {
+        let flight = SingleFlight::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let compute = |value: &'static str| {
+            let runs = runs.clone();
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                value
+            }
+            .boxed()
+        };
+
+        let (a, b, c) = tokio::join!(
+            flight.run("stats", compute("first")),
+            flight.run("stats", compute("second")),
+            flight.run("other", compute("third")),
+        );
+        assert_eq!(("first", "first", "third"), (a, b, c));
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+
+        assert_eq!("fourth", flight.run("stats", compute("fourth")).await);
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+    }
+}
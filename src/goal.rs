@@ -0,0 +1,91 @@
+//! Weekly goal of completed katas for the whole team, to track the progress against it.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use chrono::prelude::*;
+
+use crate::fetch::FetchCache;
+
+/// Amount of segments in the progress bar.
+const SEGMENTS: u32 = 5;
+
+/// Determine the start of the week that the given time is in, which is Monday at midnight.
+pub fn week_start(now: DateTime<Local>) -> NaiveDateTime {
+    (now.date().naive_local() - chrono::Duration::days(now.weekday().num_days_from_monday().into()))
+        .and_hms(0, 0, 0)
+}
+
+/// Count the katas that all users completed together since the given time.
+pub async fn completed(fetch: &FetchCache, users: &[String], since: NaiveDateTime) -> Result<u32> {
+    let mut count = 0;
+
+    for user in users {
+        let challenges = fetch.completed_challenges(user).await?;
+        count += challenges
+            .data
+            .iter()
+            .filter(|c| c.completed_at.with_timezone(&Local).naive_local() >= since)
+            .count();
+    }
+
+    Ok(u32::try_from(count).unwrap_or(u32::MAX))
+}
+
+/// Render the progress towards the target, like `7/15` followed by a progress bar.
+pub fn render(done: u32, target: u32) -> String {
+    let mut out = format!("{done}/{target} ");
+    let filled = (done.saturating_mul(SEGMENTS) / target).min(SEGMENTS);
+    let partial = filled < SEGMENTS && done.saturating_mul(SEGMENTS) % target != 0;
+
+    for segment in 0..SEGMENTS {
+        out.push_str(if segment < filled {
+            ":large_green_square:"
+        } else if segment == filled && partial {
+            ":large_yellow_square:"
+        } else {
+            ":white_large_square:"
+        });
+    }
+
+    if done >= target {
+        write!(out, " :tada:").ok();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_on_monday() {
+        let now = Local.ymd(2021, 3, 4).and_hms(15, 30, 0);
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 3, 1).and_hms(0, 0, 0),
+            week_start(now)
+        );
+
+        let now = Local.ymd(2021, 3, 1).and_hms(0, 0, 0);
+        assert_eq!(
+            NaiveDate::from_ymd(2021, 3, 1).and_hms(0, 0, 0),
+            week_start(now)
+        );
+    }
+
+    #[test]
+    fn render_progress() {
+        assert_eq!(
+            "7/15 :large_green_square::large_green_square::large_yellow_square:\
+             :white_large_square::white_large_square:",
+            render(7, 15)
+        );
+        assert_eq!(
+            "0/10 :white_large_square::white_large_square::white_large_square:\
+             :white_large_square::white_large_square:",
+            render(0, 10)
+        );
+        assert!(render(20, 15).ends_with(":large_green_square: :tada:"));
+    }
+}
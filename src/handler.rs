@@ -0,0 +1,685 @@
+//! Handling of incoming Slack events and button clicks. Messages are parsed into commands, which are
+//! executed with usage tracking, confirmation of destructive commands and a time limit, and then
+//! answered in the thread they came from.
+
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use codewars_bot::commands;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    Mutex, RwLock,
+};
+use tracing::{error, warn};
+
+use crate::{
+    api::slack::{
+        event::{AppHomeOpened, AppMention, MemberJoinedChannel, MessageEvent},
+        interaction::{BlockActions, Interaction, ViewSubmission},
+    },
+    builtin, changes,
+    confirm::{self, Confirmations, Lookup, Pending},
+    dispatch::Dispatcher,
+    fetch::FetchCache,
+    history::History,
+    home::{self, Form},
+    mrkdwn::{self, escape},
+    plugins::{Invocation, Plugins},
+    ratelimit::{Limited, RateLimiter},
+    registry::{Handler, Registry},
+    scheduling::Frequency,
+    server::Incoming,
+    stats,
+    storage::{AuditEntry, Outcome, Repository},
+    tasks::StatsTask,
+};
+
+/// Shared state that is needed to process commands received from Slack.
+pub struct Context {
+    /// Recently fetched Codewars data.
+    pub fetch: Arc<FetchCache>,
+    /// Local copy of completed challenges, if enabled.
+    pub history: Option<Arc<Mutex<History>>>,
+    /// Persisted bot settings.
+    pub settings: Arc<RwLock<Repository>>,
+    /// Fan-out dispatcher for scheduled messages and replies to commands.
+    pub dispatcher: Arc<Dispatcher>,
+    /// Task for the scheduled stats report, to run it on demand.
+    pub stats_task: StatsTask,
+    /// Command shortcuts defined in the settings.
+    pub aliases: BTreeMap<String, String>,
+    /// Katas that the `suggest` command picks from.
+    pub suggestions: Vec<String>,
+    /// Slack members that may run admin only commands.
+    pub admins: Vec<String>,
+    /// Maximum time that a command may take, before it is cancelled.
+    pub timeout: std::time::Duration,
+    /// Start time of the service, to calculate the uptime.
+    pub started: Instant,
+    /// Sender to update the weekly stats schedules.
+    pub s_tx: UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    /// Sender to update the notification schedule.
+    pub n_tx: UnboundedSender<Option<u8>>,
+    /// Commands that wait for the user's confirmation.
+    pub confirmations: Confirmations,
+    /// Limit of messages with commands per user.
+    pub rate_limiter: RateLimiter,
+    /// Statistics that are collected right now, shared with the stats API.
+    pub stats_flight: Arc<stats::Flight>,
+    /// Commands that are provided by plugins.
+    pub plugins: Plugins<Self>,
+}
+
+/// Create the registry with the handlers of all supported Slack events. New members are only
+/// welcomed if enabled.
+pub fn event_handlers(welcome: bool) -> Registry<Context> {
+    let registry = Registry::new()
+        .on(AppMention::TYPE, OnMention)
+        .on(MessageEvent::TYPE, OnDirectMessage)
+        .on(AppHomeOpened::TYPE, OnHomeOpened);
+
+    if welcome {
+        registry.on(MemberJoinedChannel::TYPE, OnMemberJoined)
+    } else {
+        registry
+    }
+}
+
+pub async fn handle_events(
+    ctx: Context,
+    handlers: Registry<Context>,
+    mut rx: UnboundedReceiver<Incoming>,
+) {
+    while let Some(incoming) = rx.recv().await {
+        match incoming {
+            Incoming::Event(id, event) => handlers.dispatch(&ctx, &id, event).await,
+            Incoming::Interaction(Interaction::BlockActions(actions)) => {
+                handle_interaction(&ctx, actions).await;
+            }
+            Incoming::Interaction(Interaction::ViewSubmission(submission)) => {
+                handle_submission(&ctx, submission).await;
+            }
+        }
+    }
+}
+
+/// Run the commands of a message that mentions the bot in a channel.
+struct OnMention;
+
+#[async_trait]
+impl Handler<Context> for OnMention {
+    type Event = AppMention;
+
+    async fn handle(&self, ctx: &Context, id: &str, event: AppMention) {
+        let message = Message {
+            id,
+            user: &event.user,
+            text: &event.text,
+            channel: &event.channel,
+            ts: &event.ts,
+            direct: false,
+        };
+        handle_message(ctx, &message).await;
+    }
+}
+
+/// Run the commands of a direct message to the bot.
+struct OnDirectMessage;
+
+#[async_trait]
+impl Handler<Context> for OnDirectMessage {
+    type Event = MessageEvent;
+
+    async fn handle(&self, ctx: &Context, id: &str, event: MessageEvent) {
+        if !event.is_direct() {
+            return;
+        }
+
+        let message = Message {
+            id,
+            user: &event.user,
+            text: &event.text,
+            channel: &event.channel,
+            ts: &event.ts,
+            direct: true,
+        };
+        handle_message(ctx, &message).await;
+    }
+}
+
+/// Publish the Home tab with the current configuration, whenever a user opens it.
+struct OnHomeOpened;
+
+#[async_trait]
+impl Handler<Context> for OnHomeOpened {
+    type Event = AppHomeOpened;
+
+    async fn handle(&self, ctx: &Context, _id: &str, event: AppHomeOpened) {
+        if event.tab != "home" {
+            return;
+        }
+
+        publish_home(ctx, &event.user).await;
+    }
+}
+
+/// Publish the Home tab with the current configuration and setup checklist for a user.
+async fn publish_home(ctx: &Context, user: &str) {
+    let view = home::view(&*ctx.settings.read().await, ctx.dispatcher.any_paused());
+    ctx.dispatcher.publish_home(user, &view).await;
+}
+
+/// Welcome new members of the team channel.
+struct OnMemberJoined;
+
+#[async_trait]
+impl Handler<Context> for OnMemberJoined {
+    type Event = MemberJoinedChannel;
+
+    async fn handle(&self, ctx: &Context, _id: &str, event: MemberJoinedChannel) {
+        ctx.dispatcher.send_primary(&welcome(&event.user)).await;
+    }
+}
+
+/// Short introduction for a new member of the team channel.
+fn welcome(user: &str) -> String {
+    format!(
+        "Welcome <@{user}> :wave: I report the team's progress on Codewars. Track yourself with \
+         `add <codewars-user>` and send `help` to see everything else I can do."
+    )
+}
+
+/// Answer to all commands, except for ending it, while the bot is under maintenance.
+pub const MAINTENANCE_NOTICE: &str =
+    ":construction: I'm under maintenance right now, please try again later";
+
+/// Emoji that the bot reacts with while processing commands.
+const REACTION_PROCESSING: &str = "hourglass_flowing_sand";
+/// Emoji that the bot reacts with once all commands succeeded.
+const REACTION_SUCCESS: &str = "white_check_mark";
+/// Emoji that the bot reacts with if any command failed.
+const REACTION_FAILURE: &str = "x";
+
+/// A message to the bot that contains commands, either as mention in a channel or as direct
+/// message.
+struct Message<'a> {
+    /// Unique ID of the event that delivered the message.
+    id: &'a str,
+    /// ID of the user who sent the message.
+    user: &'a str,
+    /// Message content.
+    text: &'a str,
+    /// Conversation that the message was sent in.
+    channel: &'a str,
+    /// Timestamp of the message, which identifies it within the conversation.
+    ts: &'a str,
+    /// Whether the message was sent directly to the bot, instead of mentioning it in a channel.
+    direct: bool,
+}
+
+impl Message<'_> {
+    /// Send a response back to where the message came from. Responses to mentions are sent to the
+    /// team channel.
+    async fn respond(&self, dispatcher: &Dispatcher, text: &str) {
+        if self.direct {
+            dispatcher.send_direct(self.channel, text).await;
+        } else {
+            dispatcher.send_primary(text).await;
+        }
+    }
+
+    /// Send the response of a command, or a generic apology if the command failed.
+    async fn reply(&self, dispatcher: &Dispatcher, response: Result<String>) {
+        match response {
+            Ok(text) => self.respond(dispatcher, &text).await,
+            Err(e) => {
+                error!("Error during command processing: {}", e);
+                self.respond(
+                    dispatcher,
+                    &format!(
+                        "Sorry <@{}>, something went wrong while processing your command",
+                        self.user
+                    ),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+/// Process a single message to the bot, within a span that identifies the event, the sender and
+/// the command, so all log output of the command can be correlated.
+///
+/// Messages in the team channel must start with a mention of the bot, while direct messages
+/// contain only the commands.
+#[tracing::instrument(skip_all, fields(id = %message.id, user = %message.user, command))]
+async fn handle_message(ctx: &Context, message: &Message<'_>) {
+    let Context {
+        settings,
+        dispatcher,
+        aliases,
+        plugins,
+        ..
+    } = ctx;
+
+    let text = match message.text.find('>') {
+        _ if message.direct => message.text,
+        Some(idx) => &message.text[idx + 1..],
+        None => {
+            message
+                .respond(
+                    dispatcher,
+                    &format!("<@{}> messages must start with a mention", message.user),
+                )
+                .await;
+            return;
+        }
+    };
+
+    let mut parts = commands::split(text);
+    if parts.is_empty() {
+        parts.push(text);
+    }
+
+    // Nothing is executed unless all commands of the message are valid.
+    let cmds = match parts
+        .iter()
+        .map(|part| plugins.parse(commands::expand(part, aliases)))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(cmds) => cmds,
+        Err(commands::Error::UnknownCommand) if parts.len() == 1 => {
+            message
+                .reply(dispatcher, builtin::hint(settings).await)
+                .await;
+            return;
+        }
+        Err(e) => {
+            let response = Ok(format!(
+                "Unknown command:\n```{}```",
+                escape(&e.to_string())
+            ));
+            message.reply(dispatcher, response).await;
+            return;
+        }
+    };
+
+    let count = u32::try_from(cmds.len()).unwrap_or(u32::MAX);
+    if let Err(limited) = ctx.rate_limiter.check(message.user, count).await {
+        let response = Ok(match limited {
+            Limited::TooMany(max) => format!(
+                "<@{}> that's too many commands at once, please send at most {max} per message",
+                message.user
+            ),
+            Limited::Wait(wait) => format!(
+                "<@{}> you are sending commands a bit fast, please try again in {} seconds \
+                 :turtle:",
+                message.user,
+                wait.as_secs() + 1
+            ),
+        });
+        message.reply(dispatcher, response).await;
+        return;
+    }
+
+    tracing::Span::current().record(
+        "command",
+        &cmds
+            .iter()
+            .map(Invocation::name)
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+
+    // Slow commands can take a while, so the user sees that the message was received.
+    dispatcher
+        .react(message.channel, message.ts, REACTION_PROCESSING)
+        .await;
+
+    let success = run_commands(ctx, message, &parts, cmds).await;
+
+    dispatcher
+        .unreact(message.channel, message.ts, REACTION_PROCESSING)
+        .await;
+    dispatcher
+        .react(
+            message.channel,
+            message.ts,
+            if success {
+                REACTION_SUCCESS
+            } else {
+                REACTION_FAILURE
+            },
+        )
+        .await;
+}
+
+/// Run the commands of a message one after another and send their responses. Returns whether all
+/// of them succeeded.
+async fn run_commands(
+    ctx: &Context,
+    message: &Message<'_>,
+    parts: &[&str],
+    mut cmds: Vec<Invocation>,
+) -> bool {
+    let dispatcher = &ctx.dispatcher;
+
+    if cmds.len() == 1 {
+        let cmd = cmds.remove(0);
+        if cmd.needs_confirmation() {
+            ask_confirmation(ctx, message, 0, parts[0], cmd).await;
+            return true;
+        }
+
+        let (response, announcement) = run_command(ctx, message.user, parts[0], cmd).await;
+        let success = response.is_ok();
+        message.reply(dispatcher, response).await;
+        if let Some(announcement) = announcement {
+            dispatcher.send(&announcement).await;
+        }
+        return success;
+    }
+
+    // Several commands are executed one after another and answered with a single summary, to not
+    // flood the channel.
+    let mut success = true;
+    let mut summary = Vec::new();
+    let mut announcements = Vec::new();
+
+    for (index, (part, cmd)) in parts.iter().zip(cmds).enumerate() {
+        if cmd.needs_confirmation() {
+            ask_confirmation(ctx, message, index, part, cmd).await;
+            summary.push(format!(
+                "> `{}`\n_Waiting for confirmation_",
+                mrkdwn::code(part.trim())
+            ));
+            continue;
+        }
+
+        let (response, announcement) = run_command(ctx, message.user, part, cmd).await;
+        let response = response.unwrap_or_else(|e| {
+            error!("Error during command processing: {}", e);
+            success = false;
+            format!(
+                "Sorry <@{}>, something went wrong while processing this command",
+                message.user
+            )
+        });
+
+        summary.push(format!("> `{}`\n{response}", mrkdwn::code(part.trim())));
+        announcements.extend(announcement);
+    }
+
+    message.respond(dispatcher, &summary.join("\n\n")).await;
+    if !announcements.is_empty() {
+        dispatcher.send(&announcements.join("\n\n")).await;
+    }
+
+    success
+}
+
+/// Ask the user to confirm a command with Yes/No buttons, instead of running it right away.
+async fn ask_confirmation(
+    ctx: &Context,
+    message: &Message<'_>,
+    index: usize,
+    part: &str,
+    cmd: Invocation,
+) {
+    let key = format!("{}-{index}", message.id);
+    let question = format!(
+        "<@{}> do you really want to run `{}`?",
+        message.user,
+        mrkdwn::code(part.trim())
+    );
+    let blocks = confirm::blocks(&question, &key);
+
+    ctx.confirmations
+        .request(
+            key,
+            Pending::new(message.user, cmd, part, message.channel, message.direct),
+        )
+        .await;
+    ctx.dispatcher
+        .send_blocks(
+            message.direct.then_some(message.channel),
+            &question,
+            &blocks,
+        )
+        .await;
+}
+
+/// Run or cancel a command, once the user clicked one of the buttons of its confirmation. The
+/// question is replaced with the answer, so the buttons can't be clicked again.
+///
+/// Buttons of the setup checklist in the Home tab open the form of their step instead.
+async fn handle_interaction(ctx: &Context, actions: BlockActions) {
+    let dispatcher = &ctx.dispatcher;
+
+    for action in actions.actions {
+        if action.action_id.starts_with(home::ACTION_SETUP) {
+            if let Some(form) = Form::find(&action.value) {
+                dispatcher
+                    .open_view(&actions.trigger_id, &form.modal())
+                    .await;
+            }
+            continue;
+        }
+
+        let confirmed = match action.action_id.as_str() {
+            confirm::ACTION_CONFIRM => true,
+            confirm::ACTION_CANCEL => false,
+            _ => continue,
+        };
+        let Some(response_url) = &actions.response_url else {
+            continue;
+        };
+
+        let pending = match ctx
+            .confirmations
+            .take(&action.value, &actions.user.id)
+            .await
+        {
+            Lookup::Found(pending) => pending,
+            Lookup::Expired => {
+                dispatcher
+                    .replace(
+                        response_url,
+                        "This confirmation expired, please send the command again",
+                    )
+                    .await;
+                continue;
+            }
+            Lookup::OtherUser => continue,
+        };
+
+        let Pending {
+            user,
+            command,
+            text,
+            channel,
+            direct,
+            ..
+        } = pending;
+
+        if !confirmed {
+            dispatcher
+                .replace(
+                    response_url,
+                    &format!("<@{user}> cancelled `{}`", mrkdwn::code(&text)),
+                )
+                .await;
+            continue;
+        }
+
+        dispatcher
+            .replace(
+                response_url,
+                &format!("<@{user}> confirmed `{}`", mrkdwn::code(&text)),
+            )
+            .await;
+
+        let message = Message {
+            id: &action.value,
+            user: &user,
+            text: &text,
+            channel: &channel,
+            ts: "",
+            direct,
+        };
+        let (response, announcement) = run_command(ctx, &user, &text, command).await;
+        message.reply(dispatcher, response).await;
+        if let Some(announcement) = announcement {
+            dispatcher.send(&announcement).await;
+        }
+    }
+}
+
+/// Run the command of a submitted setup form and send the response to the user as direct message.
+/// Afterwards, the Home tab is updated to show the new state of the checklist.
+async fn handle_submission(ctx: &Context, submission: ViewSubmission) {
+    let dispatcher = &ctx.dispatcher;
+    let user = &submission.user.id;
+    let Some(form) = Form::find(&submission.view.callback_id) else {
+        return;
+    };
+    let Some(text) = form.command(Form::input(&submission.view.state.values)) else {
+        return;
+    };
+
+    let response = match ctx.plugins.parse(&text) {
+        Ok(cmd) => {
+            let (response, announcement) = run_command(ctx, user, &text, cmd).await;
+            if let Some(announcement) = announcement {
+                dispatcher.send(&announcement).await;
+            }
+            response.unwrap_or_else(|e| {
+                error!("Error during command processing: {}", e);
+                String::from("Sorry, something went wrong while processing the form")
+            })
+        }
+        Err(e) => format!("The form isn't valid:\n```{}```", escape(&e.to_string())),
+    };
+
+    if let Err(e) = dispatcher.send_to_user(user, &response).await {
+        error!("Error sending form response to {}: {}", user, e);
+    }
+
+    publish_home(ctx, user).await;
+}
+
+/// Record the usage of a command and execute it, within the time limit if it only fetches data.
+/// Besides the response, any changes to the configuration are returned as announcement for the
+/// team. The command `text` is kept in the audit log, together with its outcome.
+async fn run_command(
+    ctx: &Context,
+    user: &str,
+    text: &str,
+    cmd: Invocation,
+) -> (Result<String>, Option<String>) {
+    let Context {
+        settings, timeout, ..
+    } = ctx;
+
+    if let Err(e) = settings
+        .write()
+        .await
+        .record_usage(cmd.name(), cmd.option())
+        .await
+    {
+        warn!("Error recording command usage: {}", e);
+    }
+
+    let name = cmd.name();
+    let limit = cmd.is_fetch_only().then_some(*timeout);
+    let hint = if matches!(&cmd, Invocation::Builtin(cmd) if cmd.has_dates()) {
+        "Try a narrower date range."
+    } else {
+        "Please try again later."
+    };
+    let before = changes::Snapshot::new(&*settings.read().await);
+
+    let (response, outcome) =
+        if let Some(res) = within_limit(limit, Box::pin(execute(ctx, user, cmd))).await {
+            let outcome = if res.is_ok() {
+                Outcome::Success
+            } else {
+                Outcome::Failure
+            };
+            (res, outcome)
+        } else {
+            warn!("Cancelled `{}` command after {:?}", name, timeout);
+            if let Err(e) = settings.write().await.record_timeout(name).await {
+                warn!("Error recording command timeout: {}", e);
+            }
+
+            (
+                Ok(format!("Sorry <@{user}>, this is taking too long. {hint}")),
+                Outcome::Timeout,
+            )
+        };
+
+    let entry = AuditEntry {
+        at: Utc::now(),
+        user: user.to_owned(),
+        command: text.trim().to_owned(),
+        outcome,
+    };
+    if let Err(e) = settings.write().await.record_audit(entry).await {
+        warn!("Error recording command in the audit log: {}", e);
+    }
+
+    let after = changes::Snapshot::new(&*settings.read().await);
+
+    (response, changes::announce(user, &before, &after))
+}
+
+/// Wait for a command to finish, or cancel it once the time limit is reached. Returns `None` if the
+/// command was cancelled. Without a limit, the command always runs to completion.
+async fn within_limit<T>(
+    limit: Option<std::time::Duration>,
+    command: impl std::future::Future<Output = T>,
+) -> Option<T> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, command).await.ok(),
+        None => Some(command.await),
+    }
+}
+
+/// Execute a single command from the given Slack user and create the response message.
+async fn execute(ctx: &Context, user: &str, cmd: Invocation) -> Result<String> {
+    match cmd {
+        Invocation::Builtin(cmd) => builtin::execute(ctx, user, cmd).await,
+        Invocation::Plugin(_) if ctx.settings.read().await.maintenance() => {
+            Ok(String::from(MAINTENANCE_NOTICE))
+        }
+        Invocation::Plugin(call) => ctx.plugins.execute(ctx, user, call).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_slow_commands() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        };
+
+        assert_eq!(
+            None,
+            within_limit(Some(Duration::from_millis(10)), slow()).await
+        );
+        assert_eq!(Some("done"), within_limit(None, slow()).await);
+        assert_eq!(
+            Some("done"),
+            within_limit(Some(Duration::from_secs(5)), slow()).await
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! Central registry of all commands with their descriptions, split into pages by category for the
+//! `help` command. Each entry carries an example that must parse, so the help can't drift from the
+//! actual grammar.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::Result;
+
+use crate::{commands, mrkdwn};
+
+/// Group of related commands, each shown on its own help page.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+    Tracking,
+    Reports,
+    Schedules,
+    Admin,
+}
+
+impl Category {
+    /// All categories in the order of their pages.
+    pub const ALL: [Self; 4] = [Self::Tracking, Self::Reports, Self::Schedules, Self::Admin];
+
+    /// Readable name of the category.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Tracking => "Tracking",
+            Self::Reports => "Reports",
+            Self::Schedules => "Schedules",
+            Self::Admin => "Admin",
+        }
+    }
+}
+
+/// Description of a single command.
+pub struct Entry {
+    /// Category that the command is listed under.
+    pub category: Category,
+    /// Syntax of the command with placeholders, like `add <user>`.
+    pub syntax: &'static str,
+    /// Short description of what the command does.
+    pub description: &'static str,
+    /// Further details, each shown as bullet point.
+    pub details: &'static [&'static str],
+    /// Valid example of the command, shown below the description.
+    pub example: &'static str,
+}
+
+/// All built-in commands that the bot understands. Plugins bring the entries of their commands.
+pub const ENTRIES: &[Entry] = &[
+    Entry {
+        category: Category::Tracking,
+        syntax: "add <user>",
+        description: "Add a Codewars user to the statistics report.",
+        details: &[
+            "Users that don't exist on Codewars are rejected, to catch typos early.",
+            "Usernames with spaces are put in quotes, like `add \"John Doe\"`.",
+        ],
+        example: "add dnaka91",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "remove <user>",
+        description: "Remove a Codewars user from the statistics again.",
+        details: &["Asks for confirmation with buttons first."],
+        example: "remove dnaka91",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "rename <old> <new>",
+        description: "Change the username of a user who renamed their Codewars account, keeping \
+                      their details and history.",
+        details: &["Usernames that don't exist on Codewars are rejected."],
+        example: "rename dnaka91 dnaka92",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "clear [all]",
+        description: "Remove all users from the statistics, to start a new season fresh.",
+        details: &[
+            "With `all`, the schedule goes back to weekly on Sunday at 10:00 and notifications \
+             are turned off as well.",
+            "Asks for confirmation with buttons first.",
+        ],
+        example: "clear all",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "displayname <user> [<name>]",
+        description: "Show a different name, which can include emoji, instead of the Codewars \
+                      username in reports.",
+        details: &["The name is optional and leaving it out shows the Codewars username again."],
+        example: "displayname dnaka91 Dominik :crab:",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "note <user> [<note>]",
+        description: "Keep a note about a user, like the team they belong to, which is shown in \
+                      the list of users.",
+        details: &["Leaving out the note removes it again."],
+        example: "note dnaka91 Joined for the Rust track",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "list",
+        description: "List all tracked users, together with who added them and when.",
+        details: &[],
+        example: "list",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "search <term>",
+        description: "Search the challenges that tracked users completed by name, to find out \
+                      whether anyone solved a certain kata already.",
+        details: &[],
+        example: "search fizz buzz",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "who solved <kata>",
+        description: "List the tracked users that completed a kata and in which languages.",
+        details: &["The `<kata>` can be the name, slug or ID of the kata."],
+        example: "who solved multiply",
+    },
+    Entry {
+        category: Category::Tracking,
+        syntax: "suggest [<kyu>[-<kyu>] kyu]",
+        description: "Suggest a kata as challenge of the day, that none of the tracked users \
+                      solved yet.",
+        details: &["The kyu range is optional, for example `5 kyu` or `6-4 kyu`."],
+        example: "suggest 6-4 kyu",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "stats [top <n>] [since <date> [until <date>]] [to me]",
+        description: "Show the current statistics of all tracked users.",
+        details: &[
+            "The format of `<date>` is `YYYY/MM/DD`, for example `2020/02/12` or `2020/1/2`.",
+            "The dates are optional, and the end date is included in the statistics.",
+            "With `top <n>`, only the `n` users with the most completed challenges are shown.",
+            "With `to me` (or `--dm`), the statistics are sent as direct message instead.",
+        ],
+        example: "stats top 5 since 2020/02/12",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "stats by tag [since <date> [until <date>]]",
+        description: "Show the topics that the team practiced, by counting the completed \
+                      challenges for each kata tag.",
+        details: &[
+            "Without a start date, the challenges of the last 7 days are counted.",
+            "The end date is included, like for `stats`.",
+        ],
+        example: "stats by tag since 2020/02/12",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "languages",
+        description: "Show the team's ranks and scores for each language, with the strongest and \
+                      weakest language of the team.",
+        details: &["The ranks come from the Codewars profiles of the tracked users."],
+        example: "languages",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "review [<year>]",
+        description: "Show the year in review with totals, streaks, the most improved user, the \
+                      used languages and the hardest kata solved.",
+        details: &[
+            "The year is optional and defaults to the current year.",
+            "The review of the past year is sent automatically on the first day of the new year.",
+        ],
+        example: "review 2023",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "poll start [<duration>]",
+        description: "Start a poll for the next kata of the week, with three katas that none of \
+                      the tracked users solved yet.",
+        details: &[
+            "The poll runs for a day, unless a `<duration>` like `12h` or `3d` is given.",
+            "The kata with the most votes is announced automatically when the poll ends.",
+        ],
+        example: "poll start 12h",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "poll [status]",
+        description: "Show the candidates and current votes of the running poll.",
+        details: &[],
+        example: "poll",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "vote <number>",
+        description: "Vote for one of the poll's katas by its number. Voting again replaces the \
+                      previous vote.",
+        details: &[],
+        example: "vote 2",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "goal set <count> per week",
+        description: "Set the amount of katas that the team wants to complete each week.",
+        details: &[
+            "The progress is part of the weekly stats.",
+            "A count of `0` removes the goal.",
+        ],
+        example: "goal set 15 per week",
+    },
+    Entry {
+        category: Category::Reports,
+        syntax: "goal status",
+        description: "Show the team's progress towards the weekly goal.",
+        details: &[],
+        example: "goal status",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "schedule <frequency> [at <time>]",
+        description: "Set a schedule to send the latest stats, replacing all other schedules.",
+        details: &[
+            "The `<frequency>` is `daily`, `on <weekday>` or `monthly on <day>`.",
+            "The format of `<weekday>` is the weekday name in short or long form, for example \
+             `wed` or `Friday`.",
+            "Days after the end of a shorter month, like the `31`, fall on its last day.",
+            "The format of `<time>` is `HH:MM`, for example `12:25` or `01:00`.",
+            "The time is optional and defaults to `10:00`.",
+            "Asks for confirmation with buttons first.",
+        ],
+        example: "schedule on Friday at 12:25",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "schedule add <frequency> [at <time>]",
+        description: "Add another schedule, for example a digest on Monday and detailed stats \
+                      on the first of each month.",
+        details: &[],
+        example: "schedule add monthly on 1 at 09:00",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "schedule list",
+        description: "List all schedules with their number and next run.",
+        details: &[],
+        example: "schedule list",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "schedule remove <number>",
+        description: "Remove a schedule by its number from `schedule list`.",
+        details: &["Asks for confirmation with buttons first."],
+        example: "schedule remove 2",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "run now [--no-mark]",
+        description: "Send the scheduled stats report immediately.",
+        details: &[
+            "The `--no-mark` flag keeps the time of the last run unchanged, so the next scheduled \
+             report still covers the same period.",
+        ],
+        example: "run now --no-mark",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify <on|off>",
+        description: "Send notifications whenever new challenges are completed.",
+        details: &[
+            "Users that are about to lose a daily streak of 3 days or more are reminded at 20:00.",
+        ],
+        example: "notify on",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify digest <interval>",
+        description: "Send notifications as a single digest of all challenges completed within \
+                      the interval, instead of a list on every check.",
+        details: &[
+            "The format of `<interval>` is a number with `h`, `d` or `w` for hours, days or \
+             weeks, for example `12h` or `1d`.",
+            "Use `notify on` to go back to lists on every check.",
+        ],
+        example: "notify digest 1d",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify every <hours>",
+        description: "Check for new challenges to notify about every few hours, between `1` and \
+                      `24`.",
+        details: &[
+            "The default is set in the settings and is `3` hours, unless configured \
+                    otherwise.",
+        ],
+        example: "notify every 6",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify snooze <duration|off>",
+        description: "Hold back notifications for a while, for example `notify snooze 2h`.",
+        details: &[
+            "Challenges completed in the meantime are sent in one batch afterwards.",
+            "Use `notify snooze off` to end the snooze early.",
+        ],
+        example: "notify snooze 2h",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify quiet <start>-<end>",
+        description: "Hold back notifications every day between two times, for example \
+                      `notify quiet 22:00-08:00`.",
+        details: &[
+            "Challenges completed in the meantime are sent in one batch afterwards.",
+            "Use `notify quiet off` to send notifications at any time again.",
+        ],
+        example: "notify quiet 22:00-08:00",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify filter kyu >= <kyu>",
+        description: "Only notify about challenges of the given kyu or harder, for example \
+                      `notify filter kyu >= 5`.",
+        details: &["Use `notify filter kyu off` to notify about challenges of any kyu again."],
+        example: "notify filter kyu >= 5",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "notify filter lang <language>...",
+        description: "Only notify about challenges completed in one of the languages, for \
+                      example `notify filter lang rust go`.",
+        details: &[
+            "Use `notify filter lang off` to notify about challenges in any language \
+                    again.",
+        ],
+        example: "notify filter lang rust go",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "subscribe me <user>",
+        description: "Get a direct message whenever your own Codewars account completes a kata \
+                      or ranks up, even if notifications are off.",
+        details: &["Use `unsubscribe me` to stop the direct messages again."],
+        example: "subscribe me some_user",
+    },
+    Entry {
+        category: Category::Schedules,
+        syntax: "unsubscribe me",
+        description: "Stop the direct messages about your own progress.",
+        details: &[],
+        example: "unsubscribe me",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "status",
+        description: "Show the current configuration of the bot, like the schedule and \
+                      notification settings.",
+        details: &[],
+        example: "status",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "botstats",
+        description: "Show how often each command and option was used.",
+        details: &[],
+        example: "botstats",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "audit [last <n>]",
+        description: "Show who ran which commands recently and whether they succeeded.",
+        details: &[
+            "Shows the last 10 commands, unless another amount of up to 99 is given.",
+            "Only admins from the settings may run it.",
+        ],
+        example: "audit last 20",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "retrains <on|off>",
+        description: "Include challenges that were completed again (re-trained) in reports, or \
+                      leave them out.",
+        details: &[],
+        example: "retrains off",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "maintenance <on|off>",
+        description: "Put the bot under maintenance, which answers all other commands with a \
+                      notice and suspends all scheduled messages, or end the maintenance again.",
+        details: &["Only admins from the settings may run it."],
+        example: "maintenance on",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "set webhook <url>",
+        description: "Post reports and notifications to a new Slack webhook URL, instead of the \
+                      one from the settings.",
+        details: &[
+            "A test message is posted to it first and the current webhook is kept if \
+                    that fails.",
+            "Only admins from the settings may run it.",
+        ],
+        example: "set webhook https://hooks.slack.com/services/T000/B000/XXXX",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "webhook add <url>",
+        description: "Post reports and notifications to another Slack webhook URL as well.",
+        details: &[
+            "A test message is posted to it first.",
+            "Only admins from the settings may run it.",
+        ],
+        example: "webhook add https://hooks.slack.com/services/T000/B111/YYYY",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "webhook remove <url>",
+        description: "Stop posting to a webhook URL that was added with `webhook add`.",
+        details: &[
+            "Asks for confirmation with buttons first.",
+            "Webhooks from the settings can only be removed there.",
+            "Only admins from the settings may run it.",
+        ],
+        example: "webhook remove https://hooks.slack.com/services/T000/B111/YYYY",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "setup",
+        description: "Show a checklist of the steps to fully configure the bot, with the command \
+                      for each open step.",
+        details: &[],
+        example: "setup",
+    },
+    Entry {
+        category: Category::Admin,
+        syntax: "help [page <number>]",
+        description: "Show this help, one page per category of commands.",
+        details: &[],
+        example: "help page 2",
+    },
+];
+
+/// Render a single page of the help, counting from one, with the commands of plugins listed after
+/// the built-in ones of each category. The command shortcuts of the team are listed on the first
+/// page.
+pub fn render(
+    page: usize,
+    aliases: &BTreeMap<String, String>,
+    plugins: &[&Entry],
+) -> Result<String> {
+    let Some(category) = page.checked_sub(1).and_then(|idx| Category::ALL.get(idx)) else {
+        return Ok(format!(
+            "There are only {} help pages, try `help page 1` to start at the beginning",
+            Category::ALL.len()
+        ));
+    };
+
+    let mut response = String::new();
+
+    if page == 1 {
+        response.push_str(
+            "Hello there, I'm a Codewars bot. You can use me by mentioning me, followed by a \
+             command.\nFor example `@codewarsbot stats`.\n\n",
+        );
+    }
+
+    write!(
+        response,
+        "*{} commands* (page {page} of {}):",
+        category.name(),
+        Category::ALL.len()
+    )?;
+
+    for entry in ENTRIES
+        .iter()
+        .chain(plugins.iter().copied())
+        .filter(|e| e.category == *category)
+    {
+        write!(
+            response,
+            "\n\n```{}```\n{}",
+            entry.syntax, entry.description
+        )?;
+        for detail in entry.details {
+            write!(response, "\n- {detail}")?;
+        }
+        write!(response, "\n_Example:_ `{}`", entry.example)?;
+    }
+
+    if page == 1 {
+        let keywords = commands::keyword_aliases()
+            .into_iter()
+            .filter_map(|keyword| {
+                let (main, aliases) = keyword.split_first()?;
+                let aliases = aliases
+                    .iter()
+                    .map(|alias| format!("`{alias}`"))
+                    .collect::<Vec<_>>();
+                Some(format!("{} for `{main}`", aliases.join(", ")))
+            })
+            .collect::<Vec<_>>();
+        write!(response, "\n\n*Aliases:* {}", keywords.join("; "))?;
+        response.push_str(
+            "\n\nSeveral commands can be sent at once, separated by `;` or line breaks, like \
+             `add alice; add bob; stats`.",
+        );
+    }
+
+    if page == 1 && !aliases.is_empty() {
+        response.push_str("\n\n*Shortcuts configured for this team:*\n");
+        for (name, expansion) in aliases {
+            write!(
+                response,
+                "\n`{}` → `{}`",
+                mrkdwn::code(name),
+                mrkdwn::code(expansion)
+            )?;
+        }
+    }
+
+    let others = Category::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| *c != category)
+        .map(|(idx, c)| format!("`help page {}` for {}", idx + 1, c.name()))
+        .collect::<Vec<_>>();
+    write!(response, "\n\nMore commands: {}", others.join(", "))?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn examples_parse() {
+        for entry in ENTRIES {
+            assert!(
+                commands::parse(entry.example).is_ok(),
+                "example of `{}` doesn't parse",
+                entry.syntax
+            );
+        }
+    }
+
+    #[test]
+    fn all_commands_listed() {
+        let grammar = include_str!("commands.pest")
+            .lines()
+            .find_map(|line| line.strip_prefix("command = { SOI ~ ("))
+            .and_then(|line| line.strip_suffix(") ~ EOI }"))
+            .unwrap()
+            .split('|')
+            .map(|rule| rule.trim().to_owned())
+            .collect::<BTreeSet<_>>();
+
+        let listed = ENTRIES
+            .iter()
+            .filter_map(|entry| commands::rule(entry.example))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(grammar, listed);
+    }
+
+    #[test]
+    fn render_pages() {
+        assert!(render(1, &BTreeMap::new(), &[])
+            .unwrap()
+            .contains("```add <user>```"));
+        assert!(render(1, &BTreeMap::new(), &[])
+            .unwrap()
+            .contains("`rm`, `del` for `remove`"));
+        assert!(render(4, &BTreeMap::new(), &[])
+            .unwrap()
+            .contains("```setup```"));
+
+        let plugin = Entry {
+            category: Category::Reports,
+            syntax: "echo <text>",
+            description: "Repeat the text.",
+            details: &[],
+            example: "echo hello",
+        };
+        assert!(render(2, &BTreeMap::new(), &[&plugin])
+            .unwrap()
+            .contains("```echo <text>```"));
+        assert!(render(0, &BTreeMap::new(), &[])
+            .unwrap()
+            .starts_with("There are only"));
+        assert!(render(5, &BTreeMap::new(), &[])
+            .unwrap()
+            .starts_with("There are only"));
+    }
+}
@@ -0,0 +1,384 @@
+//! Local copy of the tracked users' completed challenges. A background task keeps it up to date by
+//! only fetching challenges that were completed since the last sync, so reports can be created
+//! without waiting for the Codewars API.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    sync::{Mutex, RwLock},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    api::codewars::{CodewarsClient, CompletedChallenge, CompletedChallenges},
+    events::{self, Completion},
+    lease::Leader,
+    storage::{self, Repository},
+};
+
+/// File name of the history, which is stored next to the state file.
+const HISTORY_FILE: &str = "history.toml";
+
+/// Determine the location of the history file based on the location of the state file.
+pub fn path(state_path: &Path) -> PathBuf {
+    state_path.with_file_name(HISTORY_FILE)
+}
+
+/// All synced completed challenges, per user.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    /// Location of the file that this history is persisted to.
+    #[serde(skip)]
+    path: PathBuf,
+    /// Synced data of each tracked user.
+    users: BTreeMap<String, UserHistory>,
+}
+
+/// Synced data of a single user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserHistory {
+    /// Last time the user's challenges were synced.
+    pub synced_at: DateTime<Utc>,
+    /// All completed challenges, with the latest completion first.
+    pub challenges: Vec<CompletedChallenge>,
+}
+
+impl History {
+    /// Load the history from the given file location, or create a new empty one if the file
+    /// doesn't exist yet.
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let mut history: Self = if path.exists() {
+            toml::from_slice(&fs::read(&path).await?)?
+        } else {
+            Self::default()
+        };
+
+        history.path = path;
+
+        Ok(history)
+    }
+
+    /// Get the synced challenges of a user in the same form as the Codewars API returns them, or
+    /// `None` if the user wasn't synced yet.
+    pub fn completed_challenges(&self, username: &str) -> Option<CompletedChallenges> {
+        self.users.get(username).map(|user| CompletedChallenges {
+            total_pages: 1,
+            total_items: u32::try_from(user.challenges.len()).unwrap_or(u32::MAX),
+            data: user.challenges.clone(),
+        })
+    }
+
+    /// Get the synced challenges of all users, with the latest completion first.
+    pub fn users(&self) -> impl Iterator<Item = (&str, &[CompletedChallenge])> {
+        self.users
+            .iter()
+            .map(|(user, history)| (user.as_str(), history.challenges.as_slice()))
+    }
+
+    /// Move the synced challenges of a user, who changed their username, to the new name.
+    pub async fn rename_user(&mut self, from: &str, to: &str) -> Result<()> {
+        if let Some(user) = self.users.remove(from) {
+            self.users.insert(to.to_owned(), user);
+            storage::write_file(&self.path, self).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Find all synced challenges whose name or slug contains the term, ignoring case, together
+    /// with the users that completed them.
+    pub fn search(&self, term: &str) -> Vec<(&CompletedChallenge, Vec<&str>)> {
+        let term = term.to_lowercase();
+        let matches = |value: &Option<String>| {
+            value
+                .as_deref()
+                .is_some_and(|v| v.to_lowercase().contains(&term))
+        };
+
+        let mut found = BTreeMap::<&str, (&CompletedChallenge, Vec<&str>)>::new();
+        for (user, history) in &self.users {
+            for challenge in &history.challenges {
+                if matches(&challenge.name) || matches(&challenge.slug) {
+                    found
+                        .entry(&challenge.id)
+                        .or_insert_with(|| (challenge, Vec::new()))
+                        .1
+                        .push(user);
+                }
+            }
+        }
+
+        found.into_values().collect()
+    }
+}
+
+/// Periodically sync the completed challenges of all tracked users into the local history. Newly
+/// found completions are appended to the event log, if one is given. Only the leader syncs, as the
+/// history file is shared with the other instances.
+pub async fn run(
+    history: Arc<Mutex<History>>,
+    client: CodewarsClient,
+    repo: Arc<RwLock<Repository>>,
+    interval: Duration,
+    event_log: Option<PathBuf>,
+    leader: Leader,
+) {
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        if !leader.is_leader() {
+            continue;
+        }
+
+        let users = repo
+            .read()
+            .await
+            .users()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        if let Err(e) = sync(&history, &client, &users, event_log.as_deref()).await {
+            error!("Error syncing completed challenges: {}", e);
+        }
+    }
+}
+
+/// Fetch the challenges that the given users completed since the last sync and merge them into the
+/// history. Users that are not in the list anymore are removed from it.
+async fn sync(
+    history: &Mutex<History>,
+    client: &CodewarsClient,
+    users: &[String],
+    event_log: Option<&Path>,
+) -> Result<()> {
+    let mut synced = 0;
+
+    for user in users {
+        let known = history
+            .lock()
+            .await
+            .users
+            .get(user)
+            .map(|h| {
+                h.challenges
+                    .iter()
+                    .map(|c| (c.id.clone(), c.completed_at))
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+
+        let new = match fetch_new(client, user, &known).await {
+            Ok(new) => new,
+            Err(e) => {
+                warn!("Error syncing completed challenges of {}: {}", user, e);
+                continue;
+            }
+        };
+
+        synced += new.len();
+
+        if let Some(path) = event_log {
+            let detected_at = Utc::now();
+            let completions = new
+                .iter()
+                .rev()
+                .map(|challenge| Completion {
+                    user,
+                    challenge,
+                    retrain: known.iter().any(|(id, _)| *id == challenge.id),
+                    initial: known.is_empty(),
+                    detected_at,
+                })
+                .collect::<Vec<_>>();
+
+            if let Err(e) = events::append(path, &completions).await {
+                warn!(
+                    "Error writing completions of {} to the event log: {}",
+                    user, e
+                );
+            }
+        }
+
+        let mut history = history.lock().await;
+        let entry = history
+            .users
+            .entry(user.clone())
+            .or_insert_with(|| UserHistory {
+                synced_at: Utc::now(),
+                challenges: Vec::new(),
+            });
+
+        // Re-trained challenges show up again with a new completion time, so older entries of the
+        // same challenge are replaced.
+        entry
+            .challenges
+            .retain(|c| !new.iter().any(|n| n.id == c.id));
+        entry.challenges.splice(0..0, new);
+        entry.synced_at = Utc::now();
+    }
+
+    let mut history = history.lock().await;
+    history.users.retain(|user, _| users.contains(user));
+    storage::write_file(&history.path, &*history).await?;
+
+    info!("Synced {} new completed challenges", synced);
+
+    Ok(())
+}
+
+/// Fetch the challenges of a user that are newer than the already known ones. Usually the first
+/// page reaches a known challenge, otherwise all pages are fetched, several at a time.
+async fn fetch_new(
+    client: &CodewarsClient,
+    username: &str,
+    known: &HashSet<(String, DateTime<Utc>)>,
+) -> Result<Vec<CompletedChallenge>> {
+    let is_known = |c: &CompletedChallenge| known.contains(&(c.id.clone(), c.completed_at));
+
+    let first = client.completed_challenges(username, 0).await?;
+    let challenges = if first.total_pages <= 1 || first.data.iter().any(is_known) {
+        first
+    } else {
+        client.completed_challenges_all(username).await?
+    };
+
+    Ok(challenges
+        .data
+        .into_iter()
+        .take_while(|c| !is_known(c))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{self, method, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fetch_new_pages() {
+        let server = MockServer::start().await;
+        let challenge = |id: &str, completed_at: &str| json!({ "id": id, "completedAt": completed_at, "completedLanguages": ["rust"] });
+        for (page, data) in [
+            (0, vec![challenge("3", "2020-02-12T10:00:00Z")]),
+            (
+                1,
+                vec![
+                    challenge("2", "2020-02-11T10:00:00Z"),
+                    challenge("1", "2020-02-10T10:00:00Z"),
+                ],
+            ),
+        ] {
+            Mock::given(method("GET"))
+                .and(matchers::path(
+                    "/api/v1/users/him/code-challenges/completed",
+                ))
+                .and(query_param("page", page.to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "totalPages": 2,
+                    "totalItems": 3,
+                    "data": data
+                })))
+                .mount(&server)
+                .await;
+        }
+
+        let base_url = Url::parse(&format!("{}/api/v1/", server.uri())).unwrap();
+        let client = CodewarsClient::with_base_url(Client::new(), base_url);
+
+        let known = [("1".to_owned(), Utc.ymd(2020, 2, 10).and_hms(10, 0, 0))].into();
+        let new = fetch_new(&client, "him", &known).await.unwrap();
+        let ids = new.iter().map(|c| c.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(["3", "2"], ids.as_slice());
+
+        let known = [("3".to_owned(), Utc.ymd(2020, 2, 12).and_hms(10, 0, 0))].into();
+        assert!(fetch_new(&client, "him", &known).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path(&dir.path().join("state.toml"));
+
+        let mut history = History::load(path.clone()).await.unwrap();
+        history.users.insert(
+            "him".to_owned(),
+            UserHistory {
+                synced_at: Utc::now(),
+                challenges: vec![CompletedChallenge {
+                    id: "1".to_owned(),
+                    name: Some("Kata".to_owned()),
+                    slug: Some("kata".to_owned()),
+                    completed_at: Utc::now(),
+                    completed_languages: ["rust".to_owned()].into(),
+                }],
+            },
+        );
+        storage::write_file(&history.path, &history).await.unwrap();
+
+        let history = History::load(path).await.unwrap();
+        let challenges = history.completed_challenges("him").unwrap();
+        assert_eq!(1, challenges.total_items);
+        assert_eq!("1", challenges.data[0].id);
+        assert!(history.completed_challenges("her").is_none());
+    }
+
+    #[test]
+    fn search_by_name_and_slug() {
+        let challenge = |id: &str, name: &str| CompletedChallenge {
+            id: id.to_owned(),
+            name: Some(name.to_owned()),
+            slug: Some(name.to_lowercase().replace(' ', "-")),
+            completed_at: Utc::now(),
+            completed_languages: HashSet::new(),
+        };
+        let history = History {
+            path: PathBuf::new(),
+            users: BTreeMap::from([
+                (
+                    "her".to_owned(),
+                    UserHistory {
+                        synced_at: Utc::now(),
+                        challenges: vec![challenge("1", "Sum of Intervals")],
+                    },
+                ),
+                (
+                    "him".to_owned(),
+                    UserHistory {
+                        synced_at: Utc::now(),
+                        challenges: vec![
+                            challenge("1", "Sum of Intervals"),
+                            challenge("2", "Multiply"),
+                        ],
+                    },
+                ),
+            ]),
+        };
+
+        let found = history.search("sum OF");
+        assert_eq!(1, found.len());
+        assert_eq!("1", found[0].0.id);
+        assert_eq!(vec!["her", "him"], found[0].1);
+
+        assert_eq!(1, history.search("sum-of-int").len());
+        assert!(history.search("unknown").is_empty());
+    }
+}
@@ -0,0 +1,434 @@
+//! The bot's App Home tab in Slack, which shows the current configuration and a short introduction,
+//! so new team members can discover the bot without searching the chat history.
+//!
+//! The tab also shows the setup checklist, with buttons that open a form for each step. Submitted
+//! forms are turned into the command of that step.
+
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+
+use crate::{
+    builtin, mrkdwn,
+    scheduling::{FrequencyScheduler, Scheduler},
+    storage::{self, Repository},
+};
+
+/// Maximum amount of characters that Slack accepts in a single section of a view.
+const MAX_SECTION_LEN: usize = 3000;
+
+/// Prefix of the action IDs of the checklist buttons, followed by the key of the step's form.
+pub const ACTION_SETUP: &str = "setup-";
+
+/// ID of the block and element of the single input in a form.
+const INPUT_ID: &str = "input";
+
+/// Whether a step of the setup checklist is done.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// Nothing left to do.
+    Done,
+    /// The step must be done for the bot to work.
+    Missing,
+    /// The step works, but likely isn't configured as intended yet.
+    Warning,
+}
+
+impl Status {
+    /// Emoji that shows the status in a message.
+    const fn emoji(self) -> &'static str {
+        match self {
+            Self::Done => ":white_check_mark:",
+            Self::Missing => ":x:",
+            Self::Warning => ":warning:",
+        }
+    }
+}
+
+/// A single step of the setup checklist.
+pub struct Step {
+    /// Whether the step is done.
+    pub status: Status,
+    /// Current state of the step and how to complete it with a command.
+    pub text: String,
+    /// Form that completes or changes the step, if there is anything to do.
+    pub form: Option<&'static Form>,
+}
+
+impl Step {
+    /// Describe the step in a single line, starting with its status.
+    pub fn line(&self) -> String {
+        format!("{} {}", self.status.emoji(), self.text)
+    }
+}
+
+/// Form in a modal, that runs a command once submitted.
+pub struct Form {
+    /// Unique key of the form, used as callback ID of the modal.
+    pub key: &'static str,
+    /// Label of the button that opens the form.
+    button: &'static str,
+    /// Title of the modal.
+    title: &'static str,
+    /// Label and placeholder of the input, if the command takes an argument.
+    input: Option<(&'static str, &'static str)>,
+    /// The command, that the input is appended to.
+    command: &'static str,
+}
+
+const FORM_WEBHOOK: Form = Form {
+    key: "webhook",
+    button: "Set webhook",
+    title: "Set webhook",
+    input: Some(("Slack webhook URL", "https://hooks.slack.com/services/...")),
+    command: "set webhook",
+};
+const FORM_USER: Form = Form {
+    key: "user",
+    button: "Add user",
+    title: "Track a user",
+    input: Some(("Codewars username", "codewarrior")),
+    command: "add",
+};
+const FORM_SCHEDULE: Form = Form {
+    key: "schedule",
+    button: "Add schedule",
+    title: "Schedule the report",
+    input: Some(("Frequency", "weekly on sunday at 10:00")),
+    command: "schedule add",
+};
+const FORM_NOTIFY: Form = Form {
+    key: "notify",
+    button: "Turn on",
+    title: "Notifications",
+    input: None,
+    command: "notify on",
+};
+
+/// All forms that the checklist links to.
+const FORMS: [&Form; 4] = [&FORM_WEBHOOK, &FORM_USER, &FORM_SCHEDULE, &FORM_NOTIFY];
+
+impl Form {
+    /// Find the form with the given key.
+    pub fn find(key: &str) -> Option<&'static Self> {
+        FORMS.into_iter().find(|form| form.key == key)
+    }
+
+    /// Create the modal view of the form, ready to open with `views.open`.
+    pub fn modal(&self) -> Value {
+        let body = match self.input {
+            Some((label, placeholder)) => json!({
+                "type": "input",
+                "block_id": INPUT_ID,
+                "label": { "type": "plain_text", "text": label },
+                "element": {
+                    "type": "plain_text_input",
+                    "action_id": INPUT_ID,
+                    "placeholder": { "type": "plain_text", "text": placeholder },
+                },
+            }),
+            None => section(format!("This runs `{}` for the team.", self.command)),
+        };
+
+        json!({
+            "type": "modal",
+            "callback_id": self.key,
+            "title": { "type": "plain_text", "text": self.title },
+            "submit": { "type": "plain_text", "text": "Submit" },
+            "close": { "type": "plain_text", "text": "Cancel" },
+            "blocks": [body],
+        })
+    }
+
+    /// Create the command for the submitted form, from the value of its input. Returns `None` if
+    /// the form needs an input, but it is empty.
+    pub fn command(&self, input: Option<&str>) -> Option<String> {
+        match (self.input, input.map(str::trim)) {
+            (None, _) => Some(self.command.to_owned()),
+            (Some(_), Some(input)) if !input.is_empty() => {
+                Some(format!("{} {input}", self.command))
+            }
+            (Some(_), _) => None,
+        }
+    }
+
+    /// Value of the form's input from the state of a submitted view.
+    pub fn input(values: &Value) -> Option<&str> {
+        values[INPUT_ID][INPUT_ID]["value"].as_str()
+    }
+}
+
+/// Check the configuration for the steps that a new team usually needs to go through. Webhooks are
+/// reported as broken if any of them is paused.
+pub fn checklist(repo: &Repository, webhook_paused: bool) -> Vec<Step> {
+    let schedules = repo.schedules();
+    let mut steps = Vec::with_capacity(4);
+
+    steps.push(if webhook_paused {
+        Step {
+            status: Status::Missing,
+            text: String::from(
+                "A webhook is paused, configure a new webhook URL with `set webhook <url>` or in \
+                 the settings to resume it",
+            ),
+            form: Some(&FORM_WEBHOOK),
+        }
+    } else {
+        Step {
+            status: Status::Done,
+            text: String::from("Webhooks are configured and working"),
+            form: Some(&FORM_WEBHOOK),
+        }
+    });
+
+    steps.push(match repo.users().count() {
+        0 => Step {
+            status: Status::Missing,
+            text: String::from("No users are tracked yet, add one with `add <user>`"),
+            form: Some(&FORM_USER),
+        },
+        count => Step {
+            status: Status::Done,
+            text: format!("`{count}` users are tracked"),
+            form: Some(&FORM_USER),
+        },
+    });
+
+    steps.push(if schedules.is_empty() {
+        Step {
+            status: Status::Missing,
+            text: String::from(
+                "No report is scheduled, add one with `schedule add <frequency> [at <time>]`",
+            ),
+            form: Some(&FORM_SCHEDULE),
+        }
+    } else if schedules == [storage::Schedule::default()] {
+        Step {
+            status: Status::Warning,
+            text: format!(
+                "The report still uses the default schedule ({}), change it with \
+                 `schedule <frequency> [at <time>]`",
+                builtin::schedule_names(schedules),
+            ),
+            form: Some(&FORM_SCHEDULE),
+        }
+    } else {
+        Step {
+            status: Status::Done,
+            text: format!(
+                "The report is scheduled {}",
+                builtin::schedule_names(schedules)
+            ),
+            form: Some(&FORM_SCHEDULE),
+        }
+    });
+
+    steps.push(if repo.notify() {
+        Step {
+            status: Status::Done,
+            text: String::from("Notifications for new challenges are on"),
+            form: None,
+        }
+    } else {
+        Step {
+            status: Status::Warning,
+            text: String::from(
+                "Notifications for new challenges are off, enable them with `notify on`",
+            ),
+            form: Some(&FORM_NOTIFY),
+        }
+    });
+
+    steps
+}
+
+/// Create the Home view with the live configuration of the given repository, ready to publish
+/// with `views.publish`.
+pub fn view(repo: &Repository, webhook_paused: bool) -> Value {
+    let mut blocks = vec![
+        json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": "Codewars Bot" },
+        }),
+        section(
+            "Mention me in the team channel or send me a direct message with a command, like \
+             `stats` or `add <user>`. Send `help` to list all commands."
+                .to_owned(),
+        ),
+        json!({ "type": "divider" }),
+        section(String::from("*Setup checklist*")),
+    ];
+
+    blocks.extend(checklist(repo, webhook_paused).iter().map(|step| {
+        let mut block = section(step.line());
+        if let Some(form) = step.form {
+            block["accessory"] = json!({
+                "type": "button",
+                "action_id": format!("{ACTION_SETUP}{}", form.key),
+                "text": { "type": "plain_text", "text": form.button },
+                "value": form.key,
+            });
+        }
+        block
+    }));
+
+    blocks.extend([
+        json!({ "type": "divider" }),
+        section(users(repo)),
+        section(schedules(repo)),
+        section(notifications(repo)),
+    ]);
+
+    json!({
+        "type": "home",
+        "blocks": blocks,
+    })
+}
+
+/// A section block with Markdown content, shortened to the maximum length if needed.
+fn section(mut text: String) -> Value {
+    if let Some((idx, _)) = text.char_indices().nth(MAX_SECTION_LEN - 1) {
+        text.truncate(idx);
+        text.push('…');
+    }
+
+    json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": text },
+    })
+}
+
+/// All tracked users, with their display name if they have one.
+fn users(repo: &Repository) -> String {
+    let users = repo
+        .users()
+        .map(|user| {
+            repo.display_names().get(user).map_or_else(
+                || format!("`{}`", mrkdwn::code(user)),
+                |name| format!("{} (`{}`)", mrkdwn::escape(name), mrkdwn::code(user)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if users.is_empty() {
+        return String::from("*Tracked users*\nNobody yet, add someone with `add <user>`");
+    }
+
+    format!("*Tracked users* ({})\n{}", users.len(), users.join(", "))
+}
+
+/// All report schedules with their next run.
+fn schedules(repo: &Repository) -> String {
+    if repo.schedules().is_empty() {
+        return String::from(
+            "*Schedules*\nNo reports are scheduled, add one with `schedule add <frequency>`",
+        );
+    }
+
+    let mut text = String::from("*Schedules*");
+    for schedule in repo.schedules() {
+        let next_run = FrequencyScheduler::next(schedule.input());
+        write!(
+            text,
+            "\n• `{schedule}`, next run on `{}`",
+            next_run.format("%Y/%m/%d %H:%M")
+        )
+        .ok();
+    }
+
+    text
+}
+
+/// Whether notifications about completed challenges are enabled and how often they are checked.
+fn notifications(repo: &Repository) -> String {
+    if repo.notify() {
+        format!(
+            "*Notifications*\nEnabled, checked every `{}` hours",
+            repo.notify_interval()
+        )
+    } else {
+        String::from("*Notifications*\nDisabled, turn them on with `notify on`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn show_configuration() {
+        let mut repo = Repository::in_memory();
+        repo.add_user("him", None).await.unwrap();
+        repo.set_display_name("him", Some("Him :crab:".to_owned()))
+            .await
+            .unwrap();
+
+        let view = view(&repo, false);
+        let text = view["blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|block| block["text"]["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert_eq!("home", view["type"]);
+        assert!(text.contains("Him :crab: (`him`)"), "{text}");
+        assert!(text.contains("`on Sundays at 10:00`"), "{text}");
+        assert!(text.contains("turn them on with `notify on`"), "{text}");
+    }
+
+    #[tokio::test]
+    async fn link_checklist_to_forms() {
+        let mut repo = Repository::in_memory();
+        repo.set_notify(true).await.unwrap();
+
+        let view = view(&repo, true);
+        let steps = view["blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|block| {
+                let text = block["text"]["text"].as_str()?;
+                Some((text, block["accessory"]["action_id"].as_str()))
+            })
+            .filter(|(text, _)| text.starts_with(':'))
+            .collect::<Vec<_>>();
+
+        assert_eq!(4, steps.len(), "{steps:?}");
+        assert!(steps[0].0.starts_with(":x: A webhook is paused"));
+        assert_eq!(Some("setup-webhook"), steps[0].1);
+        assert_eq!(Some("setup-user"), steps[1].1);
+        assert!(steps[2].0.starts_with(":warning: The report still uses"));
+        assert_eq!(Some("setup-schedule"), steps[2].1);
+        assert_eq!(
+            (
+                ":white_check_mark: Notifications for new challenges are on",
+                None
+            ),
+            steps[3]
+        );
+    }
+
+    #[test]
+    fn create_form_commands() {
+        let form = Form::find("user").unwrap();
+        assert_eq!("user", form.modal()["callback_id"]);
+        assert_eq!(Some("add him".to_owned()), form.command(Some(" him ")));
+        assert_eq!(None, form.command(Some(" ")));
+        assert_eq!(None, form.command(None));
+
+        let form = Form::find("notify").unwrap();
+        assert_eq!(Some("notify on".to_owned()), form.command(None));
+        assert!(Form::find("unknown").is_none());
+    }
+
+    #[test]
+    fn shorten_long_sections() {
+        let text = section("a".repeat(MAX_SECTION_LEN + 10));
+        assert_eq!(
+            MAX_SECTION_LEN,
+            text["text"]["text"].as_str().unwrap().chars().count()
+        );
+    }
+}
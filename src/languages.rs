@@ -0,0 +1,157 @@
+//! Summary of the languages that the team uses on Codewars, from the per-language ranks and scores
+//! of the tracked users' profiles. It shows which languages the team is strongest and weakest in,
+//! to decide what to practice next.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{api::codewars::Level, fetch::FetchCache, mrkdwn, ranks, storage::Repository};
+
+/// Ranks and scores of a single language across the team.
+#[derive(Debug)]
+pub struct Summary {
+    pub language: String,
+    /// Amount of users that have a rank in the language.
+    pub users: usize,
+    /// Sum of all users' scores in the language.
+    pub score: u32,
+    /// Highest rank in the language, with the user that reached it.
+    pub best: (Level, String),
+}
+
+/// Collect the language ranks and scores of all tracked users, with the language that has the
+/// highest score across the team first. Users whose profile can't be fetched are left out.
+pub async fn collect(repo: &RwLock<Repository>, fetch: &FetchCache) -> Vec<Summary> {
+    let users = repo
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    let mut summaries = HashMap::<String, Summary>::new();
+
+    for user in users {
+        let profile = match fetch.user(&user).await {
+            Ok(profile) => profile,
+            Err(e) => {
+                warn!("Skipping user {} in the language summary: {}", user, e);
+                continue;
+            }
+        };
+
+        for (language, rank) in &profile.ranks.languages {
+            let summary = summaries
+                .entry(language.clone())
+                .or_insert_with(|| Summary {
+                    language: language.clone(),
+                    users: 0,
+                    score: 0,
+                    best: (rank.rank, user.clone()),
+                });
+            summary.users += 1;
+            summary.score += rank.score;
+            if rank.rank > summary.best.0 {
+                summary.best = (rank.rank, user.clone());
+            }
+        }
+    }
+
+    let mut summaries = summaries.into_values().collect::<Vec<_>>();
+    summaries.sort_by(|a, b| {
+        Reverse(a.score)
+            .cmp(&Reverse(b.score))
+            .then_with(|| a.language.cmp(&b.language))
+    });
+    summaries
+}
+
+/// Render the language summary as chat message, pointing out the strongest and weakest language.
+pub fn render(summaries: &[Summary], names: &BTreeMap<String, String>) -> String {
+    let (Some(strongest), Some(weakest)) = (summaries.first(), summaries.last()) else {
+        return String::from("Nobody here has a rank in any language yet");
+    };
+
+    let mut response = String::from(":earth_africa: *Languages of the team*, strongest first:");
+    for summary in summaries {
+        let (rank, user) = &summary.best;
+        write!(
+            response,
+            "\n• *{}*: {} points by {} {}, best is {} {} by {}",
+            mrkdwn::escape(&summary.language),
+            summary.score,
+            summary.users,
+            if summary.users == 1 { "user" } else { "users" },
+            ranks::emoji(rank.color()),
+            rank,
+            mrkdwn::escape(names.get(user).unwrap_or(user)),
+        )
+        .ok();
+    }
+
+    write!(
+        response,
+        "\n\n:muscle: The team is strongest in *{}*",
+        mrkdwn::escape(&strongest.language)
+    )
+    .ok();
+    if summaries.len() > 1 {
+        write!(
+            response,
+            "\n:seedling: The team is weakest in *{}*, maybe practice that next?",
+            mrkdwn::escape(&weakest.language)
+        )
+        .ok();
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_summary() {
+        assert_eq!(
+            "Nobody here has a rank in any language yet",
+            render(&[], &BTreeMap::new())
+        );
+
+        let summaries = [
+            Summary {
+                language: "rust".to_owned(),
+                users: 2,
+                score: 300,
+                best: (Level::Kyu(4), "alice".to_owned()),
+            },
+            Summary {
+                language: "go".to_owned(),
+                users: 1,
+                score: 20,
+                best: (Level::Kyu(8), "bob".to_owned()),
+            },
+        ];
+        let names = [("alice".to_owned(), "Alice".to_owned())].into();
+
+        let text = render(&summaries, &names);
+        assert!(
+            text.contains("• *rust*: 300 points by 2 users, best is "),
+            "{text}"
+        );
+        assert!(
+            text.contains(" 4 kyu by Alice\n• *go*: 20 points by 1 user,"),
+            "{text}"
+        );
+        assert!(text.contains("strongest in *rust*"), "{text}");
+        assert!(
+            text.ends_with("weakest in *go*, maybe practice that next?"),
+            "{text}"
+        );
+    }
+}
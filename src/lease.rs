@@ -0,0 +1,204 @@
+//! Leader election for running several instances against the same shared state. Instances compete
+//! for a lease that is stored next to the state file, and only the instance holding it runs
+//! schedulers and processes events. The others wait on standby and take over once the lease
+//! expires, for example when the leader is restarted for an upgrade.
+//!
+//! The lease is a plain file without locking, so leadership is best effort. When two instances
+//! write the lease at the same time, both may act as leader until the next renewal, which is up
+//! to a third of the lease time later. Leader-only work like a scheduled report might then rarely
+//! happen twice.
+
+use std::{
+    collections::hash_map::RandomState,
+    env, fmt,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::watch};
+use tracing::{info, warn};
+
+use crate::storage;
+
+/// File name of the lease, which is stored next to the state file.
+const LEASE_FILE: &str = "lease.toml";
+
+/// Determine the location of the lease file based on the location of the state file.
+pub fn path(state_path: &Path) -> PathBuf {
+    state_path.with_file_name(LEASE_FILE)
+}
+
+/// Create a unique name for this instance, from the hostname and a random suffix. The process ID
+/// isn't unique enough, as it's the same in every container.
+pub fn instance_id() -> String {
+    let host = env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_owned())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "instance".to_owned());
+    // The standard hasher is seeded randomly, which is enough to tell instances apart.
+    let suffix = RandomState::new().build_hasher().finish();
+
+    format!("{host}-{suffix:016x}")
+}
+
+/// The lease as it is persisted in the shared storage.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    /// Identifier of the instance that holds the lease.
+    holder: String,
+    /// Time when the lease expires if it isn't renewed.
+    expires_at: DateTime<Utc>,
+}
+
+/// Handle to check whether this instance is the current leader.
+#[derive(Clone, Debug)]
+pub struct Leader(watch::Receiver<bool>);
+
+impl Leader {
+    /// Create a handle for a single instance, that is always the leader.
+    pub fn always() -> Self {
+        Self(watch::channel(true).1)
+    }
+
+    /// Tell whether this instance is currently the leader.
+    pub fn is_leader(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until this instance becomes the leader after being on standby before.
+    pub async fn elected(&mut self) -> Result<()> {
+        loop {
+            self.0.changed().await?;
+            if *self.0.borrow_and_update() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Error for events that reach an instance on standby, so they can be retried against the
+/// leader.
+#[derive(Debug)]
+pub struct Standby;
+
+impl fmt::Display for Standby {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("instance is on standby")
+    }
+}
+
+impl std::error::Error for Standby {}
+
+/// Start competing for the lease at the given location and keep renewing it. The returned handle
+/// tells whether this instance currently holds the lease. Any errors while accessing the lease
+/// put the instance on standby, as it can't tell whether another instance took over.
+pub async fn elect(path: PathBuf, id: String, ttl: Duration) -> Leader {
+    let leader = acquire(&path, &id, ttl).await.unwrap_or_else(|e| {
+        warn!("Error acquiring the leader lease: {}", e);
+        false
+    });
+    let (tx, rx) = watch::channel(leader);
+
+    info!("Starting as {}", if leader { "leader" } else { "standby" });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ttl / 3);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let leader = acquire(&path, &id, ttl).await.unwrap_or_else(|e| {
+                warn!("Error renewing the leader lease: {}", e);
+                false
+            });
+
+            tx.send_if_modified(|current| {
+                if *current == leader {
+                    return false;
+                }
+
+                info!("Switching to {}", if leader { "leader" } else { "standby" });
+                *current = leader;
+                true
+            });
+        }
+    });
+
+    Leader(rx)
+}
+
+/// Try to acquire or renew the lease for the instance with the given identifier. This only
+/// succeeds if nobody holds the lease or it expired. Reading and writing the lease isn't atomic,
+/// so this can't fully rule out two leaders, as described in the module documentation.
+async fn acquire(path: &Path, id: &str, ttl: Duration) -> Result<bool> {
+    let now = Utc::now();
+
+    if let Some(lease) = load(path).await? {
+        if lease.holder != id && lease.expires_at > now {
+            return Ok(false);
+        }
+    }
+
+    storage::write_file(
+        path,
+        &Lease {
+            holder: id.to_owned(),
+            expires_at: now + chrono::Duration::from_std(ttl)?,
+        },
+    )
+    .await?;
+
+    // Another instance might have written the lease at the same time, in which case only the
+    // last write counts.
+    Ok(load(path).await?.is_some_and(|lease| lease.holder == id))
+}
+
+/// Load the lease from the given location, if any instance created it yet.
+async fn load(path: &Path) -> Result<Option<Lease>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(toml::from_slice(&fs::read(path).await?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_one_leader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path(&dir.path().join("state.toml"));
+        let ttl = Duration::from_secs(60);
+
+        assert!(acquire(&path, "a", ttl).await.unwrap());
+        assert!(!acquire(&path, "b", ttl).await.unwrap());
+        assert!(acquire(&path, "a", ttl).await.unwrap());
+
+        storage::write_file(
+            &path,
+            &Lease {
+                holder: "a".to_owned(),
+                expires_at: Utc::now() - chrono::Duration::seconds(1),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(acquire(&path, "b", ttl).await.unwrap());
+        assert!(!acquire(&path, "a", ttl).await.unwrap());
+    }
+
+    #[test]
+    fn unique_instance_ids() {
+        assert_ne!(instance_id(), instance_id());
+    }
+}
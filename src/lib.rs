@@ -0,0 +1,11 @@
+//! Reusable parts of the Codewars bot, like the Codewars API client, the Slack event types, the
+//! command parser, the report schedulers and the persisted state, so other bots can build on them.
+
+#![forbid(unsafe_code)]
+#![deny(clippy::all, clippy::pedantic)]
+#![warn(clippy::nursery)]
+
+pub mod api;
+pub mod commands;
+pub mod scheduling;
+pub mod storage;
@@ -10,413 +10,999 @@
 //! ## Slack commands
 //
 //! The service currently knows all the following commands that can be triggered by sending a Slack
-//! message with `@<botname> <command>`:
+//! message with `@<botname> <command>`. Some keywords have shorter aliases, like `rm` or `del` for
+//! `remove`, `sched` for `schedule` and `ls` for `list`.
+//!
+//! Commands can also be sent as direct message to the bot, without the mention. The response is
+//! sent back in the direct message conversation, which needs a bot token.
+//!
+//! Several commands can be sent in one message, separated by semicolons or line breaks, like
+//! `add alice; add bob; stats`. They run one after another and are answered together, but only if
+//! all of them are valid.
+//!
 //!
 //! ### `add <user>`
 //!
-//! Add a Codewars user to the statistics report.
+//! Add a Codewars user to the statistics report. Users that don't exist on Codewars are rejected.
+//! - Usernames with spaces are put in quotes, like `add "John Doe"`.
 //!
 //! ### `remove <user>`
 //!
 //! Remove a Codewars user from the statistics again.
+//! - Asks for confirmation with buttons first.
+//!
+//! ### `rename <old> <new>`
+//!
+//! Change the username of a user who renamed their Codewars account, keeping their details and
+//! history.
+//! - Usernames that don't exist on Codewars are rejected.
+//!
+//! ### `clear [all]`
+//!
+//! Remove all users from the statistics, to start a new season fresh.
+//! - With `all`, the schedule goes back to weekly on Sunday at 10:00 and notifications are turned
+//!   off as well.
+//! - Asks for confirmation with buttons first.
 //!
-//! ### `stats [since <date>]`
+//! ### `displayname <user> [<name>]`
+//!
+//! Show a different name, which can include emoji, instead of the Codewars username in reports.
+//! - The name is optional and leaving it out shows the Codewars username again.
+//!
+//! ### `note <user> [<note>]`
+//!
+//! Keep a note about a user, like the team they belong to, which is shown in the list of users.
+//! - Leaving out the note removes it again.
+//!
+//! ### `list`
+//!
+//! List all tracked users, together with who added them and when.
+//!
+//! ### `stats [top <n>] [since <date> [until <date>]] [to me]`
 //!
 //! Show the current statistics of all tracked users.
 //! - The format of `<date>` is `YYYY/MM/DD`, for example `2020/02/12` or `2020/1/2`.
-//! - The date is optional.
+//! - The dates are optional, and the end date is included in the statistics.
+//! - With `top <n>`, only the `n` users with the most completed challenges are shown.
+//! - With `to me` (or `--dm`), the statistics are sent as direct message instead, which needs the
+//!   bot token.
+//!
+//! ### `stats by tag [since <date> [until <date>]]`
+//!
+//! Show the topics that the team practiced, by counting the completed challenges for each kata
+//! tag.
+//! - Without a start date, the challenges of the last 7 days are counted.
+//! - The end date is included, like for `stats`.
+//!
+//! ### `languages`
+//!
+//! Show the team's ranks and scores for each language, with the strongest and weakest language of
+//! the team.
+//! - The ranks come from the Codewars profiles of the tracked users.
+//!
+//! ### `search <term>`
+//!
+//! Search the challenges that tracked users completed by name, to find out whether anyone solved
+//! a certain kata already.
+//!
+//! ### `who solved <kata>`
+//!
+//! List the tracked users that completed a kata and in which languages.
+//! - The `<kata>` can be the name, slug or ID of the kata.
+//!
+//! ### `suggest [<kyu>[-<kyu>] kyu]`
+//!
+//! Suggest a kata as challenge of the day, that none of the tracked users solved yet.
+//! - The kyu range is optional, for example `5 kyu` or `6-4 kyu`.
+//!
+//! ### `competition start <duration>`
+//!
+//! Start a competition, that scores the katas completed by each tracked user until it ends.
+//! - The format of `<duration>` is a number with `h`, `d` or `w` for hours, days or weeks, for
+//!   example `36h` or `2w`.
+//! - Harder katas are worth more points, from 1 point for 8 kyu up to 8 points for 1 kyu.
+//! - The final result is posted automatically when the competition ends.
+//!
+//! ### `competition [status]`
+//!
+//! Show the current scoreboard of the running competition.
 //!
-//! ### `schedule on <weekday> [at <time>]`
+//! ### `competition end`
 //!
-//! Set a weekly schedule to send the latest stats.
+//! End the running competition early and post the final result.
+//!
+//! ### `export (csv | json) [since <date>]`
+//!
+//! Send a file with all challenges that the tracked users completed, optionally only since the
+//! given date.
+//! - The format of `<date>` is `YYYY/MM/DD`.
+//! - The file is sent as direct message and needs the bot token to be configured.
+//!
+//! ### `poll start [<duration>]`
+//!
+//! Start a poll for the next kata of the week, with three katas that none of the tracked users
+//! solved yet.
+//! - The poll runs for a day, unless a `<duration>` like `12h` or `3d` is given.
+//! - The kata with the most votes is announced automatically when the poll ends.
+//!
+//! ### `poll [status]`
+//!
+//! Show the candidates and current votes of the running poll.
+//!
+//! ### `vote <number>`
+//!
+//! Vote for one of the poll's katas by its number. Voting again replaces the previous vote.
+//!
+//! ### `goal set <count> per week`
+//!
+//! Set the amount of katas that the team wants to complete each week.
+//! - The progress is part of the weekly stats.
+//! - A count of `0` removes the goal.
+//!
+//! ### `goal status`
+//!
+//! Show the team's progress towards the weekly goal.
+//!
+//! ### `review [<year>]`
+//!
+//! Show the year in review with totals, streaks, the most improved user, the used languages and the
+//! hardest kata solved.
+//! - The year is optional and defaults to the current year.
+//! - The review of the past year is sent automatically on the first day of the new year.
+//!
+//! ### `schedule <frequency> [at <time>]`
+//!
+//! Set a schedule to send the latest stats.
+//! - The `<frequency>` is `daily`, `on <weekday>` or `monthly on <day>`.
 //! - The format of `<weekday>` is the weekday name in short or long form, for example `wed` or `Friday`.
+//! - Days after the end of a shorter month, like the `31`, fall on its last day.
 //! - The format of `<time>` is `HH:MM`, for example `12:25` or `01:00`.
 //! - The time is optional and defaults to `10:00`.
+//! - This replaces all other schedules.
+//! - Asks for confirmation with buttons first.
+//!
+//! ### `schedule add <frequency> [at <time>]`
+//!
+//! Add another schedule, for example a digest on Monday and detailed stats on the first of each
+//! month.
+//!
+//! ### `schedule list`
+//!
+//! List all schedules with their number and next run.
+//!
+//! ### `schedule remove <number>`
+//!
+//! Remove a schedule by its number from `schedule list`.
+//! - Asks for confirmation with buttons first.
 //!
 //! ### `notify <on|off>`
 //!
 //! Send notifications whenever new challenges are completed.
+//! - Users that are about to lose a daily streak of 3 days or more are reminded at 20:00.
+//!
+//! ### `notify digest <interval>`
+//!
+//! Send notifications as a single digest of all challenges completed within the interval, instead
+//! of a list on every check.
+//! - The format of `<interval>` is a number with `h`, `d` or `w` for hours, days or weeks, for
+//!   example `12h` or `1d`.
+//! - Use `notify on` to go back to lists on every check.
+//!
+//! ### `notify every <hours>`
+//!
+//! Check for new challenges to notify about every few hours, between `1` and `24`.
+//! - The default is set in the settings and is `3` hours, unless configured otherwise.
+//!
+//! ### `notify snooze <duration|off>`
 //!
-//! ### `help`
+//! Hold back notifications for a while, for example `notify snooze 2h`.
+//! - Challenges completed in the meantime are sent in one batch afterwards.
+//! - Use `notify snooze off` to end the snooze early.
 //!
-//! Show information about all available commands.
+//! ### `notify quiet <start>-<end>`
+//!
+//! Hold back notifications every day between two times, for example `notify quiet 22:00-08:00`.
+//! - Challenges completed in the meantime are sent in one batch afterwards.
+//! - Use `notify quiet off` to send notifications at any time again.
+//!
+//! ### `notify filter kyu >= <kyu>`
+//!
+//! Only notify about challenges of the given kyu or harder, for example `notify filter kyu >= 5`.
+//! - Use `notify filter kyu off` to notify about challenges of any kyu again.
+//!
+//! ### `notify filter lang <language>...`
+//!
+//! Only notify about challenges completed in one of the languages, for example
+//! `notify filter lang rust go`.
+//! - Use `notify filter lang off` to notify about challenges in any language again.
+//!
+//! ### `subscribe me <user>`
+//!
+//! Get a direct message whenever your own Codewars account completes a kata or ranks up, even if
+//! notifications are off.
+//! - Use `unsubscribe me` to stop the direct messages again.
+//!
+//! ### `status`
+//!
+//! Show the current configuration of the bot, like the schedule and notification settings.
+//!
+//! ### `botstats`
+//!
+//! Show how often each command and option was used.
+//!
+//! ### `audit [last <n>]`
+//!
+//! Show who ran which commands recently and whether they succeeded.
+//! - Shows the last 10 commands, unless another amount of up to 99 is given.
+//!
+//! ### `run now [--no-mark]`
+//!
+//! Send the scheduled stats report immediately.
+//! - The `--no-mark` flag keeps the time of the last run unchanged, so the next scheduled report
+//!   still covers the same period.
+//!
+//! ### `retrains <on|off>`
+//!
+//! Include challenges that were completed again (re-trained) in reports, or leave them out.
+//!
+//! ### `maintenance <on|off>`
+//!
+//! Put the bot under maintenance, which answers all other commands with a notice and suspends all
+//! scheduled messages, or end the maintenance again.
+//!
+//! ### `set webhook <url>`
+//!
+//! Post reports and notifications to a new Slack webhook URL, instead of the one from the settings.
+//! A test message is posted to it first and the current webhook is kept if that fails. Only the
+//! members listed in the `admins` setting may run it.
+//!
+//! ### `webhook add <url>`
+//!
+//! Post reports and notifications to another Slack webhook URL as well, after a test message was
+//! posted to it. Only admins may run it, like `set webhook`.
+//!
+//! ### `webhook remove <url>`
+//!
+//! Stop posting to a webhook URL that was added with `webhook add`. Webhooks from the settings can
+//! only be removed there. Only admins may run it.
+//!
+//! ### `setup`
+//!
+//! Show a checklist of the steps to fully configure the bot, with the command for each open step.
+//!
+//! ### `help [page <number>]`
+//!
+//! Show information about all available commands, one page per category of commands.
 
 #![forbid(unsafe_code)]
 #![deny(clippy::all, clippy::pedantic)]
 #![warn(clippy::nursery)]
 
-use std::{fmt::Write, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
-use anyhow::Result;
-use async_trait::async_trait;
-use chrono::{prelude::*, Duration};
-use log::error;
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+use clap::Parser;
+use codewars_bot::{api, commands, scheduling, storage};
+use reqwest::Client;
 use tokio::sync::{
-    mpsc::{self, UnboundedReceiver, UnboundedSender},
-    Mutex,
+    mpsc::{self, UnboundedSender},
+    Mutex, RwLock,
 };
-
-mod api;
-mod commands;
-mod scheduling;
+use tracing::{error, info, warn};
+use url::Url;
+
+mod activity;
+mod admin;
+mod builtin;
+mod changes;
+mod chart;
+mod cli;
+mod competition;
+mod confirm;
+mod dispatch;
+mod events;
+mod export;
+mod federation;
+mod fetch;
+mod flight;
+mod goal;
+mod handler;
+mod help;
+mod history;
+mod home;
+mod languages;
+mod lease;
+mod metrics;
+mod mrkdwn;
+mod plugins;
+mod poll;
+mod ranks;
+mod ratelimit;
+mod registry;
+mod report;
+mod review;
 mod server;
 mod settings;
-mod storage;
+mod stats;
+mod streaks;
+mod subscriptions;
+mod suggest;
+mod tasks;
+mod timefmt;
 
 use crate::{
-    api::{codewars, slack, slack::event::AppMention},
-    commands::Command,
+    activity::Feed,
+    admin::Admin,
+    api::{
+        codewars::{self, CodewarsClient},
+        slack::web::SlackClient,
+    },
+    cli::{Cli, Cmd},
+    competition::CompetitionPlugin,
+    confirm::Confirmations,
+    dispatch::{Dispatcher, Route, WebhookTarget},
+    export::ExportPlugin,
+    fetch::FetchCache,
+    flight::SingleFlight,
+    handler::{event_handlers, handle_events, Context},
+    history::History,
+    lease::Leader,
+    plugins::Plugins,
+    ratelimit::RateLimiter,
+    scheduling::{Frequency, Scheduler},
+    settings::Settings,
+    stats::Period,
     storage::Repository,
+    tasks::{schedule_inputs, NotifyTask, Report, ReviewTask, StatsTask, StreakTask},
 };
 
+/// Hour of the day at which users are warned about losing their streak.
+const STREAK_CHECK_HOUR: u32 = 20;
+/// Hour of the first day of the year at which the year in review is sent.
+const REVIEW_HOUR: u32 = 10;
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     dotenvy::dotenv().ok();
 
     let opt = settings::load()?;
+    codewars::set_retry(opt.codewars_retry);
+    codewars::set_max_in_flight(opt.codewars_in_flight);
+    ranks::set_emoji(opt.rank_emoji.clone());
+    timefmt::set_style(opt.timestamps);
+    let client = opt.http.client()?;
+
+    match cli.cmd.unwrap_or(Cmd::Serve) {
+        Cmd::Serve => {
+            setup_logger()?;
+            run_server(opt, client, cli.dry_run).await?;
+        }
+        Cmd::CheckConfig => check_config(opt).await?,
+        Cmd::Report {
+            from: Some(from),
+            to,
+            format,
+            output,
+            ..
+        } => {
+            report_offline(
+                opt,
+                from,
+                to.unwrap_or_else(|| Local::today().naive_local()),
+                format,
+                output,
+            )
+            .await?;
+        }
+        Cmd::Report { no_mark, .. } => {
+            setup_logger()?;
+            report_once(opt, client, !no_mark, cli.dry_run).await?;
+        }
+        Cmd::Stats {
+            user,
+            since,
+            until,
+            top,
+        } => {
+            let users = if user.is_empty() {
+                let path = opt.state_path.unwrap_or_else(storage::default_path);
+                let repo = Repository::load(path).await?;
+                repo.users().map(ToOwned::to_owned).collect()
+            } else {
+                user
+            };
+
+            println!(
+                "{}",
+                stats::render(
+                    &stats::collect(
+                        &users,
+                        &BTreeMap::new(),
+                        &FetchCache::new(
+                            CodewarsClient::new(client),
+                            std::time::Duration::ZERO,
+                            None
+                        ),
+                        Period::dates(since, until),
+                        top,
+                        None,
+                        None,
+                    )
+                    .await
+                )?
+            );
+        }
+    }
 
-    setup_logger()?;
+    Ok(())
+}
 
-    run_server(opt.port, opt.signing_key, opt.webhook_url).await?;
+/// Load the settings and state file and print a summary of them, to verify the configuration
+/// before starting the server.
+async fn check_config(opt: Settings) -> Result<()> {
+    let state_path = opt.state_path.unwrap_or_else(storage::default_path);
+    let repo = Repository::load(state_path.clone()).await?;
+
+    println!("Settings are valid");
+    println!("  port:                {}", opt.port);
+    println!("  webhook:             {}", opt.webhook_url.parse::<Url>()?);
+    println!("  additional webhooks: {}", opt.webhooks.len());
+    println!(
+        "  stats webhook:       {}",
+        opt.stats_webhook_url
+            .as_ref()
+            .map_or_else(|| "none".to_owned(), ToString::to_string)
+    );
+    println!(
+        "  notify webhook:      {}",
+        opt.notify_webhook_url
+            .as_ref()
+            .map_or_else(|| "none".to_owned(), ToString::to_string)
+    );
+    println!(
+        "  direct messages:     {}",
+        if opt.bot_token.is_some() {
+            "enabled"
+        } else {
+            "disabled (no bot token)"
+        }
+    );
+    println!(
+        "  report chart:        {}",
+        match (&opt.chart_channel, &opt.bot_token) {
+            (Some(channel), Some(_)) => format!("enabled (channel {channel})"),
+            (Some(_), None) => "disabled (no bot token)".to_owned(),
+            (None, _) => "disabled".to_owned(),
+        }
+    );
+    println!(
+        "  admin API:           {}",
+        if opt.admin_token.is_some() {
+            "enabled"
+        } else {
+            "disabled (no admin token)"
+        }
+    );
+    println!(
+        "  team summary:        {}",
+        if opt.summary_token.is_some() {
+            "enabled"
+        } else {
+            "disabled (no summary token)"
+        }
+    );
+    println!(
+        "  rate limit:          {}",
+        if opt.rate_limit.per_minute == 0 {
+            "disabled".to_owned()
+        } else {
+            format!(
+                "{} commands per minute, bursts of {}",
+                opt.rate_limit.per_minute, opt.rate_limit.burst
+            )
+        }
+    );
+    println!(
+        "  ops webhook:         {}",
+        opt.ops_webhook
+            .map_or_else(|| "none".to_owned(), |w| w.url.to_string())
+    );
+    println!(
+        "  rival:               {}",
+        opt.rival
+            .map_or_else(|| "none".to_owned(), |r| format!("{} ({})", r.name, r.url))
+    );
+    println!("  user agent:          {}", opt.http.user_agent());
+    println!(
+        "  proxy:               {}",
+        opt.http
+            .proxy
+            .map_or_else(|| "none".to_owned(), |p| p.to_string())
+    );
+    println!(
+        "  state file:          {} ({} tracked users)",
+        state_path.display(),
+        repo.users().count()
+    );
 
     Ok(())
 }
 
 fn setup_logger() -> Result<()> {
-    use yansi::Paint;
-
-    let colored = |l: log::Level| -> Paint<log::Level> {
-        match l {
-            log::Level::Trace => Paint::magenta(l),
-            log::Level::Debug => Paint::blue(l),
-            log::Level::Info => Paint::green(l),
-            log::Level::Warn => Paint::yellow(l),
-            log::Level::Error => Paint::red(l),
-        }
+    use tracing::Level;
+    use tracing_subscriber::{
+        filter::Targets,
+        fmt::{format::Writer, time::FormatTime},
+        prelude::*,
     };
 
-    fern::Dispatch::new()
-        .chain(
-            fern::Dispatch::new()
-                .format(move |out, message, record| {
-                    out.finish(format_args!(
-                        "{} {:5} {} > {}",
-                        chrono::Local::now().format("%H:%M:%S"),
-                        colored(record.level()),
-                        Paint::new(record.target()).bold(),
-                        message
-                    ));
-                })
-                .level(log::LevelFilter::Info)
-                .level_for("codewars_bot", log::LevelFilter::Trace)
-                .level_for("server", log::LevelFilter::Trace)
-                .chain(std::io::stdout()),
+    /// Local wall clock time, without the date, as prefix of each log line.
+    struct LocalTime;
+
+    impl FormatTime for LocalTime {
+        fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+            write!(w, "{}", Local::now().format("%H:%M:%S"))
+        }
+    }
+
+    tracing_subscriber::fmt()
+        .with_timer(LocalTime)
+        .with_max_level(Level::TRACE)
+        .finish()
+        .with(
+            Targets::new()
+                .with_default(Level::INFO)
+                .with_target("codewars_bot", Level::TRACE)
+                .with_target("server", Level::TRACE),
         )
-        .apply()
+        .try_init()
         .map_err(Into::into)
 }
 
-struct StatsTask {
-    repo: Arc<Mutex<Repository>>,
-    webhook_url: String,
+/// Create the dispatcher for the main webhook and all additional webhooks of the settings, followed
+/// by the ones that were added at runtime and the dedicated webhooks for stats and notifications. A
+/// main webhook that was set at runtime takes precedence over the one of the settings.
+async fn create_dispatcher(
+    opt: &Settings,
+    client: Client,
+    repo: Arc<RwLock<Repository>>,
+    dry_run: bool,
+) -> Result<Arc<Dispatcher>> {
+    let slack = opt
+        .bot_token
+        .clone()
+        .map(|token| SlackClient::new(client.clone(), token));
+
+    let (primary, webhooks) = {
+        let repo = repo.read().await;
+        // The channel is only known for the configured URL, not one that was set at runtime.
+        let primary = match repo.webhook_url() {
+            Some(url) => WebhookTarget::slack(url.clone()),
+            None => WebhookTarget {
+                channel: opt.webhook_channel.clone(),
+                ..WebhookTarget::slack(opt.webhook_url.parse()?)
+            },
+        };
+        let webhooks = repo
+            .webhooks()
+            .iter()
+            .cloned()
+            .map(WebhookTarget::slack)
+            .collect::<Vec<_>>();
+        (primary, webhooks)
+    };
+    let routed = [
+        (&opt.stats_webhook_url, Route::Stats),
+        (&opt.notify_webhook_url, Route::Notify),
+    ]
+    .into_iter()
+    .filter_map(|(url, route)| {
+        Some(WebhookTarget {
+            route: Some(route),
+            ..WebhookTarget::slack(url.clone()?)
+        })
+    });
+
+    Ok(Arc::new(
+        Dispatcher::new(
+            client,
+            std::iter::once(primary)
+                .chain(opt.webhooks.iter().cloned())
+                .chain(webhooks)
+                .chain(routed),
+            opt.ops_webhook.clone(),
+            repo,
+            opt.outbox_attempts,
+        )
+        .dry_run(dry_run)
+        .slack(slack),
+    ))
+}
+
+/// Create the cache for Codewars data with the time to live of the settings, optionally reading
+/// completed challenges from the local history.
+fn create_fetch_cache(
+    opt: &Settings,
+    client: Client,
+    history: Option<Arc<Mutex<History>>>,
+) -> FetchCache {
+    FetchCache::new(
+        CodewarsClient::new(client),
+        std::time::Duration::from_secs(opt.cache_ttl_secs),
+        history,
+    )
 }
 
-#[async_trait]
-impl<'a> scheduling::Task for StatsTask {
-    fn name() -> &'static str {
-        "stats"
+/// Start the leader election if it is enabled in the settings. Otherwise this is the only instance
+/// and always the leader.
+async fn elect_leader(opt: &Settings, state_path: &Path) -> Leader {
+    if opt.lease_secs == 0 {
+        return Leader::always();
     }
 
-    async fn run(&self) {
-        let start_time = Utc::now();
-        let since = self.repo.lock().await.last_run().map(|dt| dt.naive_local());
-
-        match stats(&self.repo, since).await {
-            Ok(msg) => {
-                webhook_send(&self.webhook_url, &msg).await;
-                if let Err(e) = self.repo.lock().await.set_last_run(start_time).await {
-                    error!("Error saving last run time: {}", e);
-                }
-            }
-            Err(e) => error!("Error collecting scheduled stats: {}", e),
-        }
-    }
+    lease::elect(
+        lease::path(state_path),
+        opt.instance_id.clone().unwrap_or_else(lease::instance_id),
+        std::time::Duration::from_secs(opt.lease_secs),
+    )
+    .await
 }
 
-struct NotifyTask {
-    repo: Arc<Mutex<Repository>>,
-    webhook_url: String,
+/// Load the local history and start syncing it in the background, if it is enabled in the
+/// settings.
+async fn start_history(
+    opt: &Settings,
+    client: &Client,
+    settings: &Arc<RwLock<Repository>>,
+    state_path: &Path,
+    leader: &Leader,
+) -> Result<Option<Arc<Mutex<History>>>> {
+    if opt.sync_interval_mins == 0 {
+        return Ok(None);
+    }
+
+    let history = Arc::new(Mutex::new(History::load(history::path(state_path)).await?));
+
+    tokio::spawn(history::run(
+        history.clone(),
+        CodewarsClient::new(client.clone()),
+        settings.clone(),
+        std::time::Duration::from_secs(opt.sync_interval_mins * 60),
+        opt.event_log.clone(),
+        leader.clone(),
+    ));
+
+    Ok(Some(history))
 }
 
-#[async_trait]
-impl<'a> scheduling::Task for NotifyTask {
-    fn name() -> &'static str {
-        "notify"
+/// Load the repository from the state file of the settings, apply the default notification interval
+/// and start the maintenance mode if it is enabled in the settings. For a dry run, the repository
+/// is only kept in memory after loading it.
+async fn load_repo(opt: &Settings, dry_run: bool) -> Result<Repository> {
+    let mut repo =
+        Repository::load(opt.state_path.clone().unwrap_or_else(storage::default_path)).await?;
+    if dry_run {
+        repo = repo.detach();
     }
 
-    async fn run(&self) {
-        match stats(
-            &self.repo,
-            Some(Local::now().naive_local() - Duration::hours(3)),
-        )
-        .await
-        {
-            Ok(msg) => webhook_send(&self.webhook_url, &msg).await,
-            Err(e) => error!("Error collecting stats for notification: {}", e),
-        }
+    repo.set_default_notify_interval(opt.notify_interval_hours);
+
+    if opt.maintenance {
+        repo.set_maintenance(true).await?;
     }
-}
 
-async fn run_server(port: u16, signing_key: String, webhook_url: String) -> Result<()> {
-    let settings = Repository::load().await?;
-    let settings = Arc::new(Mutex::new(settings));
-    let (tx, rx) = mpsc::unbounded_channel();
+    Ok(repo)
+}
 
-    let (s_tx, s_rx) = mpsc::unbounded_channel();
-    tokio::spawn(scheduling::run::<scheduling::WeeklyScheduler, _>(
-        s_rx,
-        StatsTask {
-            repo: settings.clone(),
-            webhook_url: webhook_url.clone(),
-        },
-    ));
+/// Load the repository like [`load_repo`] and batch the writes of its changes, unless disabled in
+/// the settings.
+async fn start_repo(opt: &Settings, dry_run: bool) -> Result<Arc<RwLock<Repository>>> {
+    let repo = Arc::new(RwLock::new(load_repo(opt, dry_run).await?));
 
-    let msg = {
-        let l = settings.lock().await;
-        let s = l.schedule();
-        (s.weekday, s.time)
-    };
-    s_tx.send(Some(msg))?;
+    if opt.save_delay_ms > 0 {
+        tokio::spawn(storage::save_batched(
+            repo.clone(),
+            std::time::Duration::from_millis(opt.save_delay_ms),
+        ));
+    }
 
-    let (n_tx, n_rx) = mpsc::unbounded_channel();
-    tokio::spawn(scheduling::run::<scheduling::HourlyScheduler, _>(
-        n_rx,
-        NotifyTask {
-            repo: settings.clone(),
-            webhook_url: webhook_url.clone(),
-        },
-    ));
+    Ok(repo)
+}
 
-    let msg = {
-        let l = settings.lock().await;
-        l.notify()
+/// Send the scheduled stats report a single time and exit, so the report can be driven by an
+/// external scheduler like cron instead. A skipped report is an error, so the scheduler notices.
+async fn report_once(opt: Settings, client: Client, mark: bool, dry_run: bool) -> Result<()> {
+    let repo = Arc::new(RwLock::new(load_repo(&opt, dry_run).await?));
+    let dispatcher = create_dispatcher(&opt, client.clone(), repo.clone(), dry_run).await?;
+    let fetch = Arc::new(create_fetch_cache(&opt, client.clone(), None));
+
+    let task = StatsTask {
+        client,
+        fetch,
+        repo,
+        dispatcher: dispatcher.clone(),
+        rival: opt.rival.map(Arc::new),
+        chart_channel: opt.chart_channel,
+        feed: Feed::new(),
+        leader: Leader::always(),
     };
-    if msg {
-        n_tx.send(Some(3))?;
+    if let Report::Skipped(reason) = task.report(mark).await? {
+        bail!("report skipped, {reason}");
     }
 
-    let server = tokio::spawn(server::run(port, signing_key, tx));
-    let handler = tokio::spawn(handle_events(webhook_url, settings.clone(), rx, s_tx, n_tx));
-
-    tokio::select! {
-        res = server => res?,
-        _ = handler => ()
+    if dispatcher
+        .stats()
+        .into_iter()
+        .any(|(_, _, _, failed, _)| failed > 0)
+    {
+        bail!("failed delivering the report to some of the webhooks");
     }
 
     Ok(())
 }
 
-async fn handle_events(
-    webhook_url: String,
-    settings: Arc<Mutex<Repository>>,
-    mut rx: UnboundedReceiver<AppMention>,
-    s_tx: UnboundedSender<Option<(Weekday, NaiveTime)>>,
-    n_tx: UnboundedSender<Option<u8>>,
-) {
-    while let Some(AppMention { user, text, .. }) = rx.recv().await {
-        let prefix = if let Some(idx) = text.find("> ") {
-            idx + 2
-        } else {
-            webhook_send(
-                &webhook_url,
-                &format!("<@{}> messages must start with a mention", user),
-            )
-            .await;
-            continue;
-        };
+/// Render a report over the given dates from the local history, and print it or write it to the
+/// output file.
+async fn report_offline(
+    opt: Settings,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: report::Format,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let state_path = opt.state_path.unwrap_or_else(storage::default_path);
+    let history_path = history::path(&state_path);
+    if !history_path.exists() {
+        bail!("no local history found at {}", history_path.display());
+    }
 
-        let response = match commands::parse(&text[prefix..]) {
-            Ok(cmd) => match cmd {
-                Command::AddUser(username) => add_user(&settings, username).await,
-                Command::RemoveUser(username) => remove_user(&settings, username).await,
-                Command::Stats(since) => stats(&settings, since.map(|d| d.and_hms(0, 0, 0))).await,
-                Command::Help => Ok(help()),
-                Command::Schedule(weekday, time) => schedule(&settings, &s_tx, weekday, time).await,
-                Command::Notify(on_off) => notify(&settings, &n_tx, on_off).await,
-            },
-            Err(e) => Ok(format!("Unknown command:\n```{}```", e)),
-        };
+    let repo = Repository::load(state_path).await?;
+    let history = History::load(history_path).await?;
+    let challenges = report::collect(&history, from, to);
+    let report = report::render(format, &challenges, &repo.display_names(), from, to)?;
 
-        match response {
-            Ok(message) => webhook_send(&webhook_url, &message).await,
-            Err(e) => {
-                error!("Error during command processing: {}", e);
-                webhook_send(
-                    &webhook_url,
-                    &format!(
-                        "Sorry <@{}>, something went wrong while processing your command",
-                        user
-                    ),
-                )
-                .await;
-            }
-        }
+    match output {
+        Some(path) => tokio::fs::write(path, report).await?,
+        None => print!("{report}"),
     }
-}
 
-async fn webhook_send(webhook_url: &str, text: &str) {
-    if let Err(e) = slack::webhook::send(webhook_url, text).await {
-        error!("Error during message sending by webhook: {}", e);
-    }
+    Ok(())
 }
 
-async fn add_user(settings: &Arc<Mutex<Repository>>, username: String) -> Result<String> {
-    Ok(if settings.lock().await.add_user(&username).await? {
-        format!("Added user `{}` to watchlist", username)
-    } else {
-        format!("User `{}` is already in the watchlist", username)
-    })
-}
+async fn run_server(mut opt: Settings, client: Client, dry_run: bool) -> Result<()> {
+    if dry_run {
+        // Everything that writes to files next to the state file is disabled for a dry run.
+        info!("Starting a dry run, messages are only logged and no state is persisted");
+        opt.lease_secs = 0;
+        opt.sync_interval_mins = 0;
+        opt.event_log = None;
+    }
 
-async fn remove_user(settings: &Arc<Mutex<Repository>>, username: String) -> Result<String> {
-    Ok(if settings.lock().await.remove_user(&username).await? {
-        format!("Removed user `{}` from watchlist", username)
-    } else {
-        format!("User `{}` is not in the watchlist", username)
-    })
-}
+    let started = Instant::now();
+    let settings = start_repo(&opt, dry_run).await?;
+    let state_path = opt.state_path.clone().unwrap_or_else(storage::default_path);
+    let leader = elect_leader(&opt, &state_path).await;
+    let dispatcher = create_dispatcher(&opt, client.clone(), settings.clone(), dry_run).await?;
+    let history = start_history(&opt, &client, &settings, &state_path, &leader).await?;
+    let fetch = Arc::new(create_fetch_cache(&opt, client.clone(), history.clone()));
+    tokio::spawn(dispatcher.clone().run_outbox(leader.clone()));
+    let feed = Feed::new();
+    let stats_flight = Arc::new(SingleFlight::new());
+    let (tx, rx) = mpsc::unbounded_channel();
 
-async fn stats(settings: &Arc<Mutex<Repository>>, since: Option<NaiveDateTime>) -> Result<String> {
-    use codewars::CompletedChallenge;
+    let stats_task = StatsTask {
+        client,
+        fetch: fetch.clone(),
+        repo: settings.clone(),
+        dispatcher: dispatcher.clone(),
+        rival: opt.rival.map(Arc::new),
+        chart_channel: opt.chart_channel.clone(),
+        feed: feed.clone(),
+        leader: leader.clone(),
+    };
 
-    type ChallengeFilter = Box<dyn FnMut(&CompletedChallenge) -> bool>;
+    let s_tx = start_stats_task(stats_task.clone()).await?;
+    let n_tx = start_notify_task(NotifyTask {
+        fetch: fetch.clone(),
+        repo: settings.clone(),
+        dispatcher: dispatcher.clone(),
+        feed: feed.clone(),
+        leader: leader.clone(),
+    })
+    .await?;
 
-    let mut response = String::from("Here are the current statistics:");
-    for user in settings.lock().await.users() {
-        let challenge_resp = codewars::completed_challenges(user).await?;
-        let mut challenges = challenge_resp.data;
-        challenges.sort_by(|a, b| a.completed_at.cmp(&b.completed_at));
-        challenges.reverse();
+    spawn_team_tasks(&fetch, &settings, &dispatcher, &leader, history.is_some());
 
-        write!(
-            &mut response,
-            "\n\n`{}` - {} total challenges",
-            user, challenge_resp.total_items
-        )?;
+    tokio::spawn(take_over(
+        leader.clone(),
+        settings.clone(),
+        s_tx.clone(),
+        n_tx.clone(),
+    ));
 
-        let (filter, n): (ChallengeFilter, usize) = since.map_or((Box::new(|_| true), 3), |date| {
-            (
-                Box::new(move |c| c.completed_at.naive_local() >= date),
-                usize::max_value(),
+    let server = tokio::spawn(server::run(
+        opt.port,
+        std::iter::once(opt.signing_key)
+            .chain(opt.previous_signing_key)
+            .collect(),
+        tx,
+        settings.clone(),
+        fetch.clone(),
+        stats_flight.clone(),
+        opt.metrics,
+        opt.event_stream,
+        opt.admin_token.as_deref().map(|token| {
+            Admin::new(
+                token,
+                settings.clone(),
+                fetch.clone(),
+                s_tx.clone(),
+                leader.clone(),
             )
-        });
-
-        for challenge in challenges.into_iter().filter(filter).take(n) {
-            if let Some(name) = challenge.name {
-                write!(
-                    &mut response,
-                    "\n*{}* solved at _{}_ in *{}*",
-                    name,
-                    challenge.completed_at.format("%Y/%m/%d"),
-                    challenge
-                        .completed_languages
-                        .into_iter()
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                )?;
-            }
-        }
+        }),
+        opt.summary_token,
+        feed,
+        leader,
+    ));
+    let handler = tokio::spawn(handle_events(
+        Context {
+            fetch,
+            history,
+            settings: settings.clone(),
+            dispatcher,
+            stats_task,
+            aliases: opt.aliases,
+            suggestions: opt.suggestions,
+            admins: opt.admins,
+            timeout: std::time::Duration::from_secs(opt.command_timeout_secs),
+            started,
+            s_tx,
+            n_tx,
+            confirmations: Confirmations::default(),
+            rate_limiter: RateLimiter::new(opt.rate_limit),
+            stats_flight: stats_flight.clone(),
+            plugins: Plugins::new()
+                .register(CompetitionPlugin)
+                .register(ExportPlugin),
+        },
+        event_handlers(opt.welcome),
+        rx,
+    ));
+
+    tokio::select! {
+        res = server => res?,
+        _ = handler => ()
     }
 
-    Ok(response)
+    // Changes may still wait for the next batched write.
+    let res = settings.read().await.flush().await;
+    res
 }
 
-fn help() -> String {
-    String::from(
-        "\
-Hello there, I'm a Codewars bot. You can use me by mentioning me, followed by a command.
-For example `@codewarsbot stats`.
+/// Start the task that sends the scheduled stats reports. The returned sender replaces the
+/// schedules.
+async fn start_stats_task(task: StatsTask) -> Result<UnboundedSender<Vec<(Frequency, NaiveTime)>>> {
+    let msg = schedule_inputs(&*task.repo.read().await);
 
-*Here are all the commands I know:*
+    let (s_tx, s_rx) = mpsc::unbounded_channel();
+    tokio::spawn(scheduling::run_many::<scheduling::FrequencyScheduler, _>(
+        s_rx, task,
+    ));
 
-```add <user>```
- Add a Codewars user to the statistics report.
+    s_tx.send(msg)?;
 
-```remove <user>```
-Remove a Codewars user from the statistics again.
+    Ok(s_tx)
+}
 
-```stats [since <date>]```
-Show the current statistics of all tracked users.
-- The format of `<date>` is `YYYY/MM/DD`, for example `2020/02/12` or `2020/1/2`.
-- The date is optional.
+/// Start the task that sends notifications about completed challenges, right away if they are
+/// enabled. The returned sender changes the notification interval.
+async fn start_notify_task(task: NotifyTask) -> Result<UnboundedSender<Option<u8>>> {
+    let msg = {
+        let repo = task.repo.read().await;
+        repo.notify().then(|| repo.notify_interval())
+    };
 
-```schedule on <weekday> [at <time>]```
-Set a weekly schedule to send the latest stats.
-- The format of `<weekday>` is the weekday name in short or long form, for example `wed` or `Friday`.
-- The format of `<time>` is `HH:MM`, for example `12:25` or `01:00`.
-- The time is optional and defaults to `10:00`.
+    let (n_tx, n_rx) = mpsc::unbounded_channel();
+    tokio::spawn(scheduling::run::<scheduling::HourlyScheduler, _>(
+        n_rx, task,
+    ));
 
-```notify <on|off>```
-Send notifications whenever new challenges are completed.
+    if msg.is_some() {
+        n_tx.send(msg)?;
+    }
 
-```help```
-Show this help.",
-    )
+    Ok(n_tx)
 }
 
-async fn schedule(
-    settings: &Arc<Mutex<Repository>>,
-    s_tx: &UnboundedSender<Option<(Weekday, NaiveTime)>>,
-    weekday: Weekday,
-    time: NaiveTime,
-) -> Result<String> {
-    Ok(
-        if settings
-            .lock()
-            .await
-            .set_schedule(storage::Schedule { weekday, time })
-            .await?
-        {
-            s_tx.send(Some((weekday, time))).ok();
-            format!(
-                "Weekly schedule updated to send stats on `{}s` at `{}`",
-                match weekday {
-                    Weekday::Mon => "Monday",
-                    Weekday::Tue => "Tuesday",
-                    Weekday::Wed => "Wednesday",
-                    Weekday::Thu => "Thursday",
-                    Weekday::Fri => "Friday",
-                    Weekday::Sat => "Saturday",
-                    Weekday::Sun => "Sunday",
-                },
-                time
-            )
-        } else {
-            String::from("Weekly schedule already set to this weekday & time")
+/// Start the tasks that run competitions and polls, check personal subscriptions, remind the team
+/// about streaks and send the year in review. Streaks need the local history, as the Codewars API only returns the latest
+/// completed challenges.
+fn spawn_team_tasks(
+    fetch: &Arc<FetchCache>,
+    repo: &Arc<RwLock<Repository>>,
+    dispatcher: &Arc<Dispatcher>,
+    leader: &Leader,
+    history: bool,
+) {
+    tokio::spawn(competition::run(
+        repo.clone(),
+        fetch.clone(),
+        dispatcher.clone(),
+        leader.clone(),
+    ));
+    tokio::spawn(poll::run(
+        repo.clone(),
+        fetch.clone(),
+        dispatcher.clone(),
+        leader.clone(),
+    ));
+    tokio::spawn(subscriptions::run(
+        repo.clone(),
+        fetch.clone(),
+        dispatcher.clone(),
+        leader.clone(),
+    ));
+
+    if history {
+        spawn_fixed::<scheduling::DailyScheduler, _>(
+            StreakTask {
+                fetch: fetch.clone(),
+                repo: repo.clone(),
+                dispatcher: dispatcher.clone(),
+                leader: leader.clone(),
+            },
+            NaiveTime::from_hms(STREAK_CHECK_HOUR, 0, 0),
+        );
+    }
+
+    spawn_fixed::<scheduling::YearlyScheduler, _>(
+        ReviewTask {
+            fetch: fetch.clone(),
+            repo: repo.clone(),
+            dispatcher: dispatcher.clone(),
+            leader: leader.clone(),
         },
-    )
+        NaiveTime::from_hms(REVIEW_HOUR, 0, 0),
+    );
 }
 
-async fn notify(
-    settings: &Arc<Mutex<Repository>>,
-    n_tx: &UnboundedSender<Option<u8>>,
-    on_off: bool,
-) -> Result<String> {
-    Ok(if settings.lock().await.set_notify(on_off).await? {
-        let msg = if on_off { Some(3) } else { None };
-        n_tx.send(msg).ok();
-        format!(
-            "Notifications {}",
-            if on_off { "enabled" } else { "disabled" }
-        )
-    } else {
-        format!(
-            "Notifications already {}",
-            if settings.lock().await.notify() {
-                "enabled"
-            } else {
-                "disabled"
-            }
-        )
-    })
+/// Start a task with a schedule that never changes while the service is running.
+fn spawn_fixed<S, T>(task: T, input: S::Input)
+where
+    S: Scheduler + 'static,
+    S::Input: 'static,
+    T: scheduling::Task + 'static,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(scheduling::run::<S, _>(rx, task));
+    tx.send(Some(input)).ok();
+}
+
+/// Reload the state whenever this instance becomes the leader, as the previous leader likely
+/// changed it in the meantime, and apply the current schedules.
+async fn take_over(
+    mut leader: Leader,
+    settings: Arc<RwLock<Repository>>,
+    s_tx: UnboundedSender<Vec<(Frequency, NaiveTime)>>,
+    n_tx: UnboundedSender<Option<u8>>,
+) {
+    while leader.elected().await.is_ok() {
+        let mut settings = settings.write().await;
+        if let Err(e) = settings.reload().await {
+            error!("Error reloading the state after becoming leader: {}", e);
+            continue;
+        }
+
+        s_tx.send(schedule_inputs(&settings)).ok();
+        n_tx.send(settings.notify().then(|| settings.notify_interval()))
+            .ok();
+    }
 }
@@ -0,0 +1,54 @@
+//! Export of the bot's internal statistics in the [Prometheus](https://prometheus.io) text format.
+
+use std::fmt::Write;
+
+use crate::storage::Repository;
+
+/// Render all metrics of the repository in the Prometheus text exposition format.
+pub fn render(repo: &Repository) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP codewars_bot_tracked_users Amount of tracked Codewars users.\n");
+    out.push_str("# TYPE codewars_bot_tracked_users gauge\n");
+    writeln!(out, "codewars_bot_tracked_users {}", repo.users().count()).ok();
+
+    out.push_str("# HELP codewars_bot_command_usage_total Amount of executed commands.\n");
+    out.push_str("# TYPE codewars_bot_command_usage_total counter\n");
+    for (command, usage) in repo.usage() {
+        writeln!(
+            out,
+            "codewars_bot_command_usage_total{{command=\"{command}\"}} {}",
+            usage.count
+        )
+        .ok();
+    }
+
+    out.push_str(
+        "# HELP codewars_bot_command_timeouts_total Amount of commands cancelled for taking too \
+         long.\n",
+    );
+    out.push_str("# TYPE codewars_bot_command_timeouts_total counter\n");
+    for (command, usage) in repo.usage() {
+        writeln!(
+            out,
+            "codewars_bot_command_timeouts_total{{command=\"{command}\"}} {}",
+            usage.timeouts
+        )
+        .ok();
+    }
+
+    out.push_str("# HELP codewars_bot_option_usage_total Amount of used command options.\n");
+    out.push_str("# TYPE codewars_bot_option_usage_total counter\n");
+    for (command, usage) in repo.usage() {
+        for (option, count) in &usage.options {
+            writeln!(
+                out,
+                "codewars_bot_option_usage_total{{command=\"{command}\",option=\"{option}\"}} \
+                 {count}"
+            )
+            .ok();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,34 @@
+//! Escaping of user provided and remote text, like usernames or kata names, before it is embedded
+//! into Slack's mrkdwn. Otherwise crafted names could mention everyone with `<!channel>` or break
+//! the formatting of the whole message.
+
+/// Escape the characters that have a special meaning in Slack messages, so user provided text is
+/// shown as-is.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape text that is shown within inline code. Backticks would end the code span early and line
+/// breaks aren't allowed in it, so they are replaced as well.
+pub fn code(text: &str) -> String {
+    escape(text).replace('`', "'").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_control_sequences() {
+        assert_eq!("&lt;!channel&gt; &amp; more", escape("<!channel> & more"));
+        assert_eq!("plain_name", escape("plain_name"));
+    }
+
+    #[test]
+    fn escape_code() {
+        assert_eq!("a'b' &lt;!here&gt;", code("a`b` <!here>"));
+        assert_eq!("two lines", code("two\nlines"));
+    }
+}
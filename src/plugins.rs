@@ -0,0 +1,258 @@
+//! Commands that live in their own module instead of the central grammar. Each plugin registers
+//! with the keyword that its commands start with, parses the rest of the message itself and brings
+//! its own help entries, so a feature like competitions doesn't need changes to the parser, the
+//! command handling or the help.
+
+use std::any::Any;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use codewars_bot::commands::{self, Command};
+
+use crate::help::Entry;
+
+/// A group of commands that start with the same keyword, with access to the shared context `C`.
+#[async_trait]
+pub trait Plugin<C: Sync>: Send + Sync {
+    /// Parsed form of the plugin's commands.
+    type Command: Send + 'static;
+
+    /// Keyword that all commands of the plugin start with, like `competition`.
+    fn name(&self) -> &'static str;
+
+    /// Help entries of all commands, which are listed together with the built-in commands.
+    fn usage(&self) -> &'static [Entry];
+
+    /// Parse the arguments that follow the keyword.
+    fn parse(&self, args: &str) -> commands::Result<Self::Command>;
+
+    /// Name of a single command, used to track its usage. Defaults to the keyword.
+    fn command_name(&self, _cmd: &Self::Command) -> &'static str {
+        self.name()
+    }
+
+    /// Whether the command must be confirmed before it runs.
+    fn needs_confirmation(&self, _cmd: &Self::Command) -> bool {
+        false
+    }
+
+    /// Execute a command from the given Slack user and create the response message.
+    async fn execute(&self, ctx: &C, user: &str, cmd: Self::Command) -> Result<String>;
+}
+
+/// A parsed command of a plugin, ready to run.
+pub struct Call {
+    /// Keyword of the plugin that parsed the command.
+    plugin: &'static str,
+    /// Name of the command, to track its usage.
+    name: &'static str,
+    /// Whether the command must be confirmed before it runs.
+    needs_confirmation: bool,
+    /// The command in the plugin's own type.
+    cmd: Box<dyn Any + Send>,
+}
+
+/// A [`Plugin`] with its command type erased, so plugins with different commands can be kept
+/// together.
+#[async_trait]
+trait AnyPlugin<C: Sync>: Send + Sync {
+    fn keyword(&self) -> &'static str;
+
+    fn entries(&self) -> &'static [Entry];
+
+    fn parse_call(&self, args: &str) -> commands::Result<Call>;
+
+    async fn execute_call(&self, ctx: &C, user: &str, cmd: Box<dyn Any + Send>) -> Result<String>;
+}
+
+#[async_trait]
+impl<C: Sync, P: Plugin<C>> AnyPlugin<C> for P {
+    fn keyword(&self) -> &'static str {
+        self.name()
+    }
+
+    fn entries(&self) -> &'static [Entry] {
+        self.usage()
+    }
+
+    fn parse_call(&self, args: &str) -> commands::Result<Call> {
+        let cmd = self.parse(args)?;
+
+        Ok(Call {
+            plugin: self.name(),
+            name: self.command_name(&cmd),
+            needs_confirmation: self.needs_confirmation(&cmd),
+            cmd: Box::new(cmd),
+        })
+    }
+
+    async fn execute_call(&self, ctx: &C, user: &str, cmd: Box<dyn Any + Send>) -> Result<String> {
+        let cmd = cmd
+            .downcast::<P::Command>()
+            .map_err(|_| anyhow!("command doesn't belong to the `{}` plugin", self.name()))?;
+
+        self.execute(ctx, user, *cmd).await
+    }
+}
+
+/// A parsed command, either one of the built-in commands or one of a plugin.
+pub enum Invocation {
+    Builtin(Command),
+    Plugin(Call),
+}
+
+impl Invocation {
+    /// Short name of the command, used to track its usage.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Builtin(cmd) => cmd.name(),
+            Self::Plugin(call) => call.name,
+        }
+    }
+
+    /// Optional arguments that were used with the command, to track their usage.
+    pub const fn option(&self) -> Option<&'static str> {
+        match self {
+            Self::Builtin(cmd) => cmd.option(),
+            Self::Plugin(_) => None,
+        }
+    }
+
+    /// Whether the command must be confirmed before it runs.
+    pub const fn needs_confirmation(&self) -> bool {
+        match self {
+            Self::Builtin(cmd) => cmd.needs_confirmation(),
+            Self::Plugin(call) => call.needs_confirmation,
+        }
+    }
+
+    /// Whether the command only fetches data and can be cancelled safely when it takes too long.
+    /// Plugin commands might change the state and always run to completion.
+    pub const fn is_fetch_only(&self) -> bool {
+        match self {
+            Self::Builtin(cmd) => cmd.is_fetch_only(),
+            Self::Plugin(_) => false,
+        }
+    }
+}
+
+/// All registered plugins.
+pub struct Plugins<C> {
+    plugins: Vec<Box<dyn AnyPlugin<C>>>,
+}
+
+impl<C: Sync> Plugins<C> {
+    /// Create a new registry without any plugins.
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Register a plugin, whose keyword must not be used by any built-in command or other plugin.
+    #[must_use]
+    pub fn register(mut self, plugin: impl Plugin<C> + 'static) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Help entries of all plugins' commands.
+    pub fn usage(&self) -> Vec<&'static Entry> {
+        self.plugins.iter().flat_map(|p| p.entries()).collect()
+    }
+
+    /// Parse a text message with the plugin of its first word, or as built-in command if no plugin
+    /// uses it as keyword.
+    pub fn parse(&self, text: &str) -> commands::Result<Invocation> {
+        let text = text.trim();
+        let (keyword, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+
+        self.plugins
+            .iter()
+            .find(|p| p.keyword() == keyword)
+            .map_or_else(
+                || commands::parse(text).map(Invocation::Builtin),
+                |plugin| plugin.parse_call(args.trim()).map(Invocation::Plugin),
+            )
+    }
+
+    /// Execute a command of one of the plugins and create the response message.
+    pub async fn execute(&self, ctx: &C, user: &str, call: Call) -> Result<String> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.keyword() == call.plugin)
+            .ok_or_else(|| anyhow!("plugin `{}` isn't registered", call.plugin))?;
+
+        plugin.execute_call(ctx, user, call.cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::help::Category;
+
+    struct Echo;
+
+    #[async_trait]
+    impl Plugin<Mutex<Vec<String>>> for Echo {
+        type Command = String;
+
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn usage(&self) -> &'static [Entry] {
+            &[Entry {
+                category: Category::Admin,
+                syntax: "echo <text>",
+                description: "Repeat the text.",
+                details: &[],
+                example: "echo hello",
+            }]
+        }
+
+        fn parse(&self, args: &str) -> commands::Result<String> {
+            if args.is_empty() {
+                return Err(commands::Error::InvalidArguments("echo <text>"));
+            }
+            Ok(args.to_owned())
+        }
+
+        async fn execute(
+            &self,
+            ctx: &Mutex<Vec<String>>,
+            user: &str,
+            cmd: String,
+        ) -> Result<String> {
+            ctx.lock().await.push(format!("{user}: {cmd}"));
+            Ok(cmd)
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_and_execute() {
+        let plugins = Plugins::new().register(Echo);
+        let echoed = Mutex::new(Vec::new());
+
+        let Ok(Invocation::Plugin(call)) = plugins.parse(" echo  hello there ") else {
+            panic!("not parsed by the plugin");
+        };
+        assert_eq!("echo", call.name);
+        assert_eq!(
+            "hello there",
+            plugins.execute(&echoed, "U01", call).await.unwrap()
+        );
+        assert_eq!(vec!["U01: hello there"], *echoed.lock().await);
+
+        assert!(plugins.parse("echo").is_err());
+        assert!(matches!(
+            plugins.parse("help"),
+            Ok(Invocation::Builtin(Command::Help))
+        ));
+        assert_eq!(1, plugins.usage().len());
+    }
+}
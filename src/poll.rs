@@ -0,0 +1,152 @@
+//! Polls to let the team vote for the next kata of the week. Each poll offers a few katas that
+//! nobody solved yet and the one with the most votes wins once the poll ends.
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+use crate::{
+    dispatch::Dispatcher,
+    fetch::FetchCache,
+    lease::Leader,
+    ranks,
+    storage::{Poll, Repository},
+};
+
+/// Amount of katas to choose from in a poll.
+pub const CANDIDATES: usize = 3;
+
+/// Count the votes for each candidate of the poll.
+pub fn tally(poll: &Poll) -> Vec<usize> {
+    let mut counts = vec![0; poll.candidates.len()];
+
+    for &choice in poll.votes.values() {
+        if let Some(count) = counts.get_mut(choice) {
+            *count += 1;
+        }
+    }
+
+    counts
+}
+
+/// Find the index of the candidate with the most votes. Ties go to the candidate that was listed
+/// first and without any votes there is no winner.
+pub fn winner(counts: &[usize]) -> Option<usize> {
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(idx, _)| idx)
+}
+
+/// Render the candidates of the poll with their current amount of votes.
+pub async fn render(fetch: &FetchCache, poll: &Poll) -> Result<String> {
+    let counts = tally(poll);
+    let mut out = String::new();
+
+    for (idx, (id, count)) in poll.candidates.iter().zip(counts).enumerate() {
+        let kata = fetch.code_challenge(id).await?;
+        writeln!(
+            out,
+            "{}. <{}|{}> {} {} - {} votes",
+            idx + 1,
+            kata.url,
+            crate::mrkdwn::escape(&kata.name),
+            ranks::emoji(kata.rank.id.color()),
+            kata.rank.id,
+            count,
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Interval to check whether the running poll ended.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically check whether the running poll ended and announce the new kata of the week. Only
+/// the leader ends polls, so the winner is announced once.
+pub async fn run(
+    repo: Arc<RwLock<Repository>>,
+    fetch: Arc<FetchCache>,
+    dispatcher: Arc<Dispatcher>,
+    leader: Leader,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if !leader.is_leader() {
+            continue;
+        }
+
+        let poll = {
+            let mut repo = repo.write().await;
+            if repo.poll().is_none_or(|p| p.ends_at > Utc::now()) {
+                continue;
+            }
+
+            match repo.end_poll().await {
+                Ok(Some(poll)) => poll,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Error ending the poll: {}", e);
+                    continue;
+                }
+            }
+        };
+
+        info!("Poll ended");
+
+        match result(&repo, &fetch, &poll).await {
+            Ok(msg) => dispatcher.send(&msg).await,
+            Err(e) => error!("Error announcing the poll result: {}", e),
+        }
+    }
+}
+
+/// Count the votes of an ended poll, save the winner as new kata of the week and create the
+/// announcement message.
+pub async fn result(repo: &RwLock<Repository>, fetch: &FetchCache, poll: &Poll) -> Result<String> {
+    let mut msg = String::from(":ballot_box_with_ballot: *The poll is closed!* Final votes:\n");
+    msg.push_str(&render(fetch, poll).await?);
+
+    match winner(&tally(poll)) {
+        Some(idx) => {
+            let kata = fetch.code_challenge(&poll.candidates[idx]).await?;
+            repo.write()
+                .await
+                .set_kata_of_the_week(kata.id.clone())
+                .await?;
+
+            write!(
+                msg,
+                ":star: The new kata of the week is <{}|{}>, happy solving!",
+                kata.url,
+                crate::mrkdwn::escape(&kata.name),
+            )?;
+        }
+        None => msg.push_str("Nobody voted, so the kata of the week stays the same"),
+    }
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_candidate_wins_ties() {
+        assert_eq!(Some(1), winner(&[1, 2, 0]));
+        assert_eq!(Some(0), winner(&[2, 1, 2]));
+        assert_eq!(Some(2), winner(&[0, 0, 1]));
+        assert_eq!(None, winner(&[0, 0, 0]));
+        assert_eq!(None, winner(&[]));
+    }
+}
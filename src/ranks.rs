@@ -0,0 +1,49 @@
+//! Mapping of Codewars rank colors to emoji, to make the difficulty of challenges and the rank of
+//! users visually scannable in reports.
+
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Emoji for each rank color, set once at start up.
+static EMOJI: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Default emoji for each rank color that Codewars uses.
+const DEFAULT_EMOJI: &[(&str, &str)] = &[
+    ("white", ":white_large_square:"),
+    ("yellow", ":large_yellow_square:"),
+    ("blue", ":large_blue_square:"),
+    ("purple", ":large_purple_square:"),
+    ("black", ":black_large_square:"),
+    ("red", ":large_red_square:"),
+];
+
+/// Configure the emoji for rank colors, overriding the defaults. Can only be set once, further
+/// calls are ignored.
+pub fn set_emoji(emoji: BTreeMap<String, String>) {
+    EMOJI.set(emoji).ok();
+}
+
+/// Get the emoji for a rank color, or an empty string if the color is unknown.
+pub fn emoji(color: &str) -> &'static str {
+    EMOJI
+        .get()
+        .and_then(|emoji| emoji.get(color))
+        .map(String::as_str)
+        .or_else(|| {
+            DEFAULT_EMOJI
+                .iter()
+                .find(|(c, _)| *c == color)
+                .map(|(_, e)| *e)
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_emoji() {
+        assert_eq!(":large_blue_square:", emoji("blue"));
+        assert_eq!("", emoji("green"));
+    }
+}
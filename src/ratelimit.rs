@@ -0,0 +1,158 @@
+//! Rate limiting of commands per Slack user, so a single user can't cause a storm of Codewars API
+//! calls by sending the same slow command over and over again.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// How many commands each user may run. Every command of a message counts on its own.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Amount of commands that can be run right after another, which is also the most commands
+    /// that a single message may contain.
+    pub burst: u32,
+    /// Amount of commands per minute that are allowed after the burst is used up. `0` disables the
+    /// rate limit.
+    pub per_minute: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            per_minute: 10,
+        }
+    }
+}
+
+/// Reason why the commands of a message were rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Limited {
+    /// The message contains more commands than the given burst, so it's never allowed.
+    TooMany(u32),
+    /// Not enough tokens are left, until the given time passed.
+    Wait(Duration),
+}
+
+/// Token bucket of a single user, that fills up again over time.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of all users that sent commands recently.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Take a token from the user's bucket for each of the commands in a message. If the bucket
+    /// doesn't have enough tokens, the time until they are available is returned instead.
+    pub async fn check(&self, user: &str, commands: u32) -> Result<(), Limited> {
+        if self.limit.per_minute == 0 {
+            return Ok(());
+        }
+
+        let burst = self.limit.burst.max(1);
+        if commands > burst {
+            return Err(Limited::TooMany(burst));
+        }
+
+        let cost = f64::from(commands);
+        let capacity = f64::from(burst);
+        let per_sec = f64::from(self.limit.per_minute) / 60.0;
+        let now = Instant::now();
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            capacity.min(elapsed.mul_add(per_sec, bucket.tokens))
+        };
+
+        let mut buckets = self.buckets.lock().await;
+        // Full buckets are the same as no bucket, so they are dropped to not keep every user that
+        // ever sent a command.
+        buckets.retain(|_, bucket| refill(bucket) < capacity);
+
+        let bucket = buckets.entry(user.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Limited::Wait(Duration::from_secs_f64(
+                (cost - bucket.tokens) / per_sec,
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn limit_per_user() {
+        let limiter = RateLimiter::new(RateLimit {
+            burst: 2,
+            per_minute: 600,
+        });
+
+        assert!(limiter.check("U01", 1).await.is_ok());
+        assert!(limiter.check("U01", 1).await.is_ok());
+        let Err(Limited::Wait(wait)) = limiter.check("U01", 1).await else {
+            panic!("expected to wait");
+        };
+        assert!(wait <= Duration::from_millis(100), "{wait:?}");
+        assert!(limiter.check("U02", 1).await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(limiter.check("U01", 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn charge_per_command() {
+        let limiter = RateLimiter::new(RateLimit {
+            burst: 3,
+            per_minute: 600,
+        });
+
+        assert!(limiter.check("U01", 2).await.is_ok());
+        let Err(Limited::Wait(wait)) = limiter.check("U01", 2).await else {
+            panic!("expected to wait");
+        };
+        assert!(wait <= Duration::from_millis(100), "{wait:?}");
+        assert!(limiter.check("U01", 1).await.is_ok());
+
+        assert_eq!(Err(Limited::TooMany(3)), limiter.check("U02", 4).await);
+        assert!(limiter.check("U02", 3).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn disabled() {
+        let limiter = RateLimiter::new(RateLimit {
+            burst: 1,
+            per_minute: 0,
+        });
+
+        for _ in 0..10 {
+            assert!(limiter.check("U01", 5).await.is_ok());
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Registry of handlers for Slack events. Each handler registers for a single event type, like
+//! `app_mention`, and receives the event already parsed into its own type. Supporting a new event
+//! only needs a new handler, while the server just passes all events through.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// Handler for a single type of Slack event, with access to the shared context `C`.
+#[async_trait]
+pub trait Handler<C: Sync>: Send + Sync {
+    /// Content of the event, that the raw JSON is parsed into.
+    type Event: DeserializeOwned + Send;
+
+    /// Handle a single event, that was delivered with the given unique event ID.
+    async fn handle(&self, ctx: &C, id: &str, event: Self::Event);
+}
+
+/// A [`Handler`] that parses the raw JSON itself, so handlers for different event types can be
+/// kept together.
+#[async_trait]
+trait RawHandler<C: Sync>: Send + Sync {
+    async fn handle_raw(&self, ctx: &C, id: &str, event: Value) -> serde_json::Result<()>;
+}
+
+#[async_trait]
+impl<C: Sync, H: Handler<C>> RawHandler<C> for H {
+    async fn handle_raw(&self, ctx: &C, id: &str, event: Value) -> serde_json::Result<()> {
+        let event = serde_json::from_value(event)?;
+        self.handle(ctx, id, event).await;
+        Ok(())
+    }
+}
+
+/// All registered handlers by their event type.
+pub struct Registry<C> {
+    handlers: HashMap<&'static str, Box<dyn RawHandler<C>>>,
+}
+
+impl<C: Sync> Registry<C> {
+    /// Create a new registry without any handlers.
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Register the handler for the given event type, replacing any previous handler for it.
+    #[must_use]
+    pub fn on(mut self, event_type: &'static str, handler: impl Handler<C> + 'static) -> Self {
+        self.handlers.insert(event_type, Box::new(handler));
+        self
+    }
+
+    /// Pass a raw event to the handler of its type. Events without a handler or that can't be
+    /// parsed are only logged.
+    pub async fn dispatch(&self, ctx: &C, id: &str, event: Value) {
+        let Some(event_type) = event
+            .get("type")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+        else {
+            warn!("Received event without a type");
+            return;
+        };
+
+        let Some(handler) = self.handlers.get(event_type.as_str()) else {
+            info!("Received unknown event ({})", event_type);
+            return;
+        };
+
+        if let Err(e) = handler.handle_raw(ctx, id, event).await {
+            warn!("Error parsing {} event: {}", event_type, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Greeting {
+        name: String,
+    }
+
+    struct Greeter;
+
+    #[async_trait]
+    impl Handler<Mutex<Vec<String>>> for Greeter {
+        type Event = Greeting;
+
+        async fn handle(&self, ctx: &Mutex<Vec<String>>, id: &str, event: Greeting) {
+            ctx.lock().await.push(format!("{id}: hello {}", event.name));
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_by_type() {
+        let registry = Registry::new().on("greeting", Greeter);
+        let greetings = Mutex::new(Vec::new());
+
+        registry
+            .dispatch(
+                &greetings,
+                "Ev01",
+                json!({ "type": "greeting", "name": "him" }),
+            )
+            .await;
+        registry
+            .dispatch(
+                &greetings,
+                "Ev02",
+                json!({ "type": "farewell", "name": "her" }),
+            )
+            .await;
+        registry
+            .dispatch(&greetings, "Ev03", json!({ "type": "greeting" }))
+            .await;
+
+        assert_eq!(vec!["Ev01: hello him"], *greetings.lock().await);
+    }
+}
@@ -0,0 +1,192 @@
+//! Offline reports over a longer time range, rendered from the local history instead of the
+//! Codewars API, for documents like a year in review.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use clap::ValueEnum;
+
+use crate::{api::codewars::CompletedChallenge, history::History};
+
+/// Output format of a report.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    /// Markdown document with a section for each user.
+    Md,
+    /// Comma separated values with one completed challenge per line.
+    Csv,
+}
+
+/// Collect the challenges that each user completed within the given dates, both inclusive, with
+/// the oldest first.
+pub fn collect(
+    history: &History,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> BTreeMap<&str, Vec<&CompletedChallenge>> {
+    history
+        .users()
+        .map(|(user, challenges)| {
+            let mut challenges = challenges
+                .iter()
+                .filter(|c| (from..=to).contains(&c.completed_at.date().naive_utc()))
+                .collect::<Vec<_>>();
+            challenges.sort_by_key(|c| c.completed_at);
+
+            (user, challenges)
+        })
+        .collect()
+}
+
+/// Render the collected challenges in the given format, using the display names of the users
+/// where set.
+pub fn render(
+    format: Format,
+    challenges: &BTreeMap<&str, Vec<&CompletedChallenge>>,
+    names: &BTreeMap<String, String>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<String> {
+    match format {
+        Format::Md => markdown(challenges, names, from, to),
+        Format::Csv => csv(challenges),
+    }
+}
+
+fn markdown(
+    challenges: &BTreeMap<&str, Vec<&CompletedChallenge>>,
+    names: &BTreeMap<String, String>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<String> {
+    let mut out = format!("# Codewars report from {from} to {to}\n");
+    let total = challenges.values().map(Vec::len).sum::<usize>();
+    writeln!(out, "\nThe team completed {total} challenges in total.")?;
+
+    for (user, challenges) in challenges {
+        match names.get(*user) {
+            Some(name) => writeln!(out, "\n## {name} (`{user}`)\n")?,
+            None => writeln!(out, "\n## `{user}`\n")?,
+        }
+
+        if challenges.is_empty() {
+            writeln!(out, "No completed challenges.")?;
+            continue;
+        }
+
+        writeln!(out, "{} completed challenges.\n", challenges.len())?;
+        writeln!(out, "| Date | Challenge | Languages |")?;
+        writeln!(out, "| ---- | --------- | --------- |")?;
+
+        for challenge in challenges {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                challenge.completed_at.format("%Y-%m-%d"),
+                challenge
+                    .name
+                    .as_deref()
+                    .unwrap_or(&challenge.id)
+                    .replace('|', "\\|"),
+                languages(challenge).join(", ")
+            )?;
+        }
+    }
+
+    Ok(out)
+}
+
+fn csv(challenges: &BTreeMap<&str, Vec<&CompletedChallenge>>) -> Result<String> {
+    let mut out = String::from("user,completed_at,id,name,slug,languages\n");
+
+    for (user, challenges) in challenges {
+        for challenge in challenges {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                csv_field(user),
+                challenge.completed_at.to_rfc3339(),
+                csv_field(&challenge.id),
+                csv_field(challenge.name.as_deref().unwrap_or_default()),
+                csv_field(challenge.slug.as_deref().unwrap_or_default()),
+                csv_field(&languages(challenge).join(";")),
+            )?;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Get the completed languages of a challenge in a stable order.
+fn languages(challenge: &CompletedChallenge) -> Vec<&str> {
+    let mut languages = challenge
+        .completed_languages
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    languages.sort_unstable();
+    languages
+}
+
+/// Quote a CSV field if it contains any special characters.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenges() -> (CompletedChallenge, CompletedChallenge) {
+        (
+            CompletedChallenge {
+                id: "1".to_owned(),
+                name: Some("Sum, Product".to_owned()),
+                slug: Some("sum-product".to_owned()),
+                completed_at: Utc.ymd(2023, 3, 1).and_hms(12, 0, 0),
+                completed_languages: ["rust".to_owned(), "go".to_owned()].into(),
+            },
+            CompletedChallenge {
+                id: "2".to_owned(),
+                name: Some("Multiply".to_owned()),
+                slug: Some("multiply".to_owned()),
+                completed_at: Utc.ymd(2024, 1, 1).and_hms(12, 0, 0),
+                completed_languages: ["rust".to_owned()].into(),
+            },
+        )
+    }
+
+    #[test]
+    fn render_csv() {
+        let (a, _) = challenges();
+        let collected = BTreeMap::from([("him", vec![&a])]);
+        let from = NaiveDate::from_ymd(2023, 1, 1);
+        let to = NaiveDate::from_ymd(2023, 12, 31);
+
+        assert_eq!(
+            "user,completed_at,id,name,slug,languages\n\
+             him,2023-03-01T12:00:00+00:00,1,\"Sum, Product\",sum-product,go;rust\n",
+            render(Format::Csv, &collected, &BTreeMap::new(), from, to).unwrap()
+        );
+    }
+
+    #[test]
+    fn render_markdown() {
+        let (a, _) = challenges();
+        let collected = BTreeMap::from([("him", vec![&a]), ("her", vec![])]);
+        let names = BTreeMap::from([("him".to_owned(), "Him".to_owned())]);
+        let from = NaiveDate::from_ymd(2023, 1, 1);
+        let to = NaiveDate::from_ymd(2023, 12, 31);
+
+        let md = render(Format::Md, &collected, &names, from, to).unwrap();
+        assert!(md.starts_with("# Codewars report from 2023-01-01 to 2023-12-31\n"));
+        assert!(md.contains("## Him (`him`)"));
+        assert!(md.contains("| 2023-03-01 | Sum, Product | go, rust |"));
+        assert!(md.contains("## `her`\n\nNo completed challenges."));
+    }
+}
@@ -0,0 +1,270 @@
+//! Year in review, an annual summary of the team's achievements with totals, streaks, the most
+//! improved user, how the used languages changed and the hardest kata solved.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use tracing::warn;
+
+use crate::{api::codewars::CompletedChallenge, fetch::FetchCache, mrkdwn, ranks, streaks};
+
+/// Amount of users listed as top solvers.
+const TOP_SOLVERS: usize = 3;
+
+/// Achievements of a single user within the year.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct UserSummary {
+    /// Amount of challenges completed within the year.
+    pub completed: usize,
+    /// Amount of challenges completed within the previous year.
+    pub previous: usize,
+    /// Longest daily streak within the year.
+    pub best_streak: u32,
+}
+
+/// Summary of the whole team within the year.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Summary<'a> {
+    /// Achievements of each user.
+    pub users: BTreeMap<&'a str, UserSummary>,
+    /// Amount of completions per language within the year and the previous year.
+    pub languages: BTreeMap<&'a str, (usize, usize)>,
+    /// IDs of all katas completed within the year, with the users that completed them.
+    pub katas: BTreeMap<&'a str, BTreeSet<&'a str>>,
+}
+
+/// Summarize the completed challenges of each user for the given year.
+pub fn summarize(year: i32, challenges: &BTreeMap<String, Vec<CompletedChallenge>>) -> Summary<'_> {
+    let mut summary = Summary::default();
+    let year_of = |c: &CompletedChallenge| c.completed_at.with_timezone(&Local).year();
+
+    for (user, challenges) in challenges {
+        let current = challenges
+            .iter()
+            .filter(|c| year_of(c) == year)
+            .collect::<Vec<_>>();
+        let previous = challenges
+            .iter()
+            .filter(|c| year_of(c) == year - 1)
+            .collect::<Vec<_>>();
+
+        for challenge in &current {
+            summary.katas.entry(&challenge.id).or_default().insert(user);
+            for language in &challenge.completed_languages {
+                summary.languages.entry(language).or_default().0 += 1;
+            }
+        }
+        for challenge in &previous {
+            for language in &challenge.completed_languages {
+                summary.languages.entry(language).or_default().1 += 1;
+            }
+        }
+
+        let days = current
+            .iter()
+            .map(|c| c.completed_at.with_timezone(&Local).date().naive_local())
+            .collect();
+
+        summary.users.insert(
+            user,
+            UserSummary {
+                completed: current.len(),
+                previous: previous.len(),
+                best_streak: streaks::compute(&days, NaiveDate::from_ymd(year, 12, 31))
+                    .daily
+                    .best,
+            },
+        );
+    }
+
+    summary
+}
+
+/// Create the year in review message for the given users and year.
+pub async fn create(
+    fetch: &FetchCache,
+    users: &[String],
+    names: &BTreeMap<String, String>,
+    year: i32,
+) -> Result<String> {
+    let mut challenges = BTreeMap::new();
+    for user in users {
+        challenges.insert(
+            user.clone(),
+            fetch.completed_challenges(user).await?.data.clone(),
+        );
+    }
+
+    let summary = summarize(year, &challenges);
+    let name = |user: &str| {
+        names.get(user).map_or_else(
+            || format!("`{}`", mrkdwn::code(user)),
+            |n| format!("*{}*", mrkdwn::escape(n)),
+        )
+    };
+    let total = summary.users.values().map(|u| u.completed).sum::<usize>();
+
+    let mut out = format!(":sparkles: *Year in review {year}* :sparkles:\n");
+    if total == 0 {
+        out.push_str("Nobody completed any challenges this year.");
+        return Ok(out);
+    }
+
+    writeln!(
+        out,
+        "The team completed *{total}* challenges, {} of them different katas.",
+        summary.katas.len()
+    )?;
+
+    let mut solvers = summary.users.iter().collect::<Vec<_>>();
+    solvers.sort_by(|a, b| b.1.completed.cmp(&a.1.completed).then(a.0.cmp(b.0)));
+    out.push_str("\n*Top solvers*");
+    for (place, (user, stats)) in solvers.iter().take(TOP_SOLVERS).enumerate() {
+        write!(
+            out,
+            "\n{}. {} - {} challenges",
+            place + 1,
+            name(user),
+            stats.completed
+        )?;
+    }
+
+    if let Some((user, stats)) = summary
+        .users
+        .iter()
+        .filter(|(_, s)| s.best_streak > 1)
+        .max_by_key(|(_, s)| s.best_streak)
+    {
+        write!(
+            out,
+            "\n\n*Longest streak*\n:fire: {} solved katas {} days in a row",
+            name(user),
+            stats.best_streak
+        )?;
+    }
+
+    if let Some((user, stats)) = summary
+        .users
+        .iter()
+        .filter(|(_, s)| s.completed > s.previous)
+        .max_by_key(|(_, s)| s.completed - s.previous)
+    {
+        write!(
+            out,
+            "\n\n*Most improved*\n:chart_with_upwards_trend: {} with {} more challenges than in {}",
+            name(user),
+            stats.completed - stats.previous,
+            year - 1
+        )?;
+    }
+
+    let mut languages = summary
+        .languages
+        .iter()
+        .filter(|(_, (current, _))| *current > 0)
+        .collect::<Vec<_>>();
+    languages.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+    out.push_str("\n\n*Languages*");
+    for (language, (current, previous)) in languages {
+        write!(out, "\n• {language}: {current}")?;
+        if *previous > 0 {
+            #[allow(clippy::cast_possible_wrap)]
+            let diff = *current as i64 - *previous as i64;
+            write!(out, " ({diff:+} compared to {})", year - 1)?;
+        }
+    }
+
+    if let Some(hardest) = hardest(fetch, &summary).await {
+        out.push_str("\n\n*Hardest kata*\n");
+        out.push_str(&hardest);
+    }
+
+    Ok(out)
+}
+
+/// Find the kata with the highest rank that was completed within the year, and describe it
+/// together with the users that solved it.
+async fn hardest(fetch: &FetchCache, summary: &Summary<'_>) -> Option<String> {
+    let mut ranks = HashMap::new();
+
+    for id in summary.katas.keys() {
+        match fetch.code_challenge(id).await {
+            Ok(kata) => {
+                ranks.insert(*id, kata);
+            }
+            Err(e) => warn!("Error fetching details of kata {}: {}", id, e),
+        }
+    }
+
+    let (id, kata) = ranks.into_iter().max_by_key(|(_, kata)| kata.rank.id)?;
+    let solvers = summary.katas.get(id)?;
+
+    Some(format!(
+        "{} <{}|{}> ({}), solved by {}",
+        ranks::emoji(kata.rank.id.color()),
+        kata.url,
+        mrkdwn::escape(&kata.name),
+        kata.rank.id,
+        solvers
+            .iter()
+            .map(|u| format!("`{}`", mrkdwn::code(u)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn challenge(id: &str, year: i32, day: u32, language: &str) -> CompletedChallenge {
+        CompletedChallenge {
+            id: id.to_owned(),
+            name: None,
+            slug: None,
+            completed_at: Local
+                .ymd(year, 3, day)
+                .and_hms(12, 0, 0)
+                .with_timezone(&Utc),
+            completed_languages: HashSet::from([language.to_owned()]),
+        }
+    }
+
+    #[test]
+    fn summarize_year() {
+        let challenges = BTreeMap::from([
+            (
+                "her".to_owned(),
+                vec![
+                    challenge("1", 2023, 1, "rust"),
+                    challenge("2", 2023, 2, "rust"),
+                    challenge("3", 2023, 3, "go"),
+                    challenge("4", 2022, 3, "go"),
+                ],
+            ),
+            ("him".to_owned(), vec![challenge("1", 2023, 10, "rust")]),
+        ]);
+
+        let summary = summarize(2023, &challenges);
+
+        assert_eq!(
+            UserSummary {
+                completed: 3,
+                previous: 1,
+                best_streak: 3,
+            },
+            summary.users["her"]
+        );
+        assert_eq!(1, summary.users["him"].completed);
+        assert_eq!((3, 0), summary.languages["rust"]);
+        assert_eq!((1, 1), summary.languages["go"]);
+        assert_eq!(3, summary.katas.len());
+        assert_eq!(2, summary.katas["1"].len());
+    }
+}
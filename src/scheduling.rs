@@ -1,10 +1,13 @@
 //! Schedulers to execute tasks on a fixed basis with the option to reschedule any time.
 
 use async_trait::async_trait;
-use chrono::{prelude::*, Duration, Local, NaiveTime, Weekday};
+use chrono::{prelude::*, Duration, Local, LocalResult, NaiveTime, Weekday};
 use futures::prelude::*;
-use log::{debug, trace};
-use tokio::{sync::mpsc::UnboundedReceiver, time::Duration as TokioDuration};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    time::Duration as TokioDuration,
+};
+use tracing::{debug, info_span, trace, Instrument};
 
 /// A task that is to be executed. It is used together with a [`Scheduler`] in the [`run`] function
 /// to run any task on a fixed schedule.
@@ -17,10 +20,27 @@ pub trait Task: Send + Sync {
     async fn run(&self);
 }
 
-/// Create an endless schedule for a given task. The task is executed regularly based on the rules
-/// of a [`Scheduler`]. The schedule can be updated any time by sending new inputs through the
-/// provided channel.
-#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+/// Longest time to sleep at once while waiting for the next event. The wall clock is checked again
+/// after each sleep, so events still run in time after a suspend or when the system clock changes.
+const MAX_SLEEP: TokioDuration = TokioDuration::from_secs(60);
+
+/// Sleep until the wall clock reaches the given point in time, in chunks of at most
+/// [`MAX_SLEEP`].
+async fn sleep_until(target: DateTime<Local>) {
+    while let Ok(remaining) = (target - Local::now()).to_std() {
+        if remaining.is_zero() {
+            break;
+        }
+
+        tokio::time::sleep(remaining.min(MAX_SLEEP)).await;
+    }
+}
+
+/// Create an endless schedule for a given task.
+///
+/// The task is executed regularly based on the rules of a [`Scheduler`]. The schedule can be
+/// updated any time by sending new inputs through the provided channel, where `None` disables the
+/// schedule until the next input.
 pub async fn run<S, T>(mut rx: UnboundedReceiver<Option<S::Input>>, task: T)
 where
     S: Scheduler,
@@ -34,41 +54,31 @@ where
         schedule = tokio::select! {
             _ = delayed.clone() => {
                 trace!("Executing {} task", T::name());
-                task.run().await;
+                task.run().instrument(info_span!("task", name = T::name())).await;
 
                 schedule
             },
-            Some(Some(s)) = rx.recv() => {
+            Some(s) = rx.recv() => {
                 trace!("Got new {} schedule", T::name());
                 handle.abort();
 
-                schedule = Some(s);
+                schedule = s;
                 schedule
             }
         };
 
         if let Some(schedule) = schedule {
-            let duration = S::next(schedule);
-            let duration = if let Ok(d) = duration.to_std() {
-                d
-            } else {
-                TokioDuration::from_secs(duration.num_seconds() as u64)
-            };
-
-            let (d, h) = future::abortable(tokio::time::sleep(duration));
+            let next = S::next(schedule);
+
+            let (d, h) = future::abortable(sleep_until(next));
             delayed = d.boxed().shared();
             handle = h;
 
             debug!(
                 "Next scheduled {} task in {} ({})",
                 T::name(),
-                humantime::Duration::from(duration),
-                Local::now()
-                    + if let Ok(d) = Duration::from_std(duration) {
-                        d
-                    } else {
-                        Duration::seconds(duration.as_secs() as i64)
-                    }
+                humantime::Duration::from((next - Local::now()).to_std().unwrap_or_default()),
+                next
             );
         } else {
             let (d, h) = future::abortable(future::pending());
@@ -80,13 +90,66 @@ where
     }
 }
 
+/// Run a task on several independent schedules.
+///
+/// Each input of the list gets its own [`run`] loop and every new list reschedules the existing
+/// loops, starts new ones or disables the ones that aren't needed anymore.
+pub async fn run_many<S, T>(mut rx: UnboundedReceiver<Vec<S::Input>>, task: T)
+where
+    S: Scheduler + 'static,
+    S::Input: 'static,
+    T: Task + Clone + 'static,
+{
+    let mut senders = Vec::new();
+
+    while let Some(inputs) = rx.recv().await {
+        for (idx, input) in inputs.iter().enumerate() {
+            if idx == senders.len() {
+                let (tx, rx) = mpsc::unbounded_channel();
+                tokio::spawn(run::<S, _>(rx, task.clone()));
+                senders.push(tx);
+            }
+            senders[idx].send(Some(*input)).ok();
+        }
+
+        for tx in senders.iter().skip(inputs.len()) {
+            tx.send(None).ok();
+        }
+    }
+}
+
 /// A scheduler that calculates the duration to wait until next occurrence of an event. It is
 /// generic over the input data which allows to create schedules out of any kind of data.
 pub trait Scheduler: Send {
     type Input: Copy + Send;
 
-    /// Calculate the wait duration until the next event should be triggered.
-    fn next(input: Self::Input) -> Duration;
+    /// Calculate the point in time when the next event should be triggered.
+    fn next(input: Self::Input) -> DateTime<Local>;
+}
+
+/// Turn a local date and time into an exact point in time of the time zone. Times that are
+/// skipped when the clock moves forward, like 02:30 when the clocks jump from 02:00 to 03:00, are
+/// moved forward by the same amount. Times that occur twice when the clock moves back resolve
+/// to the first occurrence, so events still run only once.
+fn resolve<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => Some(dt),
+        LocalResult::None => tz
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest(),
+    }
+}
+
+/// Find the first of the local dates, at the given local time, that is after `now`.
+fn next_after<Tz, I>(now: &DateTime<Tz>, dates: I, time: NaiveTime) -> Option<DateTime<Tz>>
+where
+    Tz: TimeZone,
+    I: IntoIterator<Item = NaiveDate>,
+{
+    dates
+        .into_iter()
+        .filter_map(|date| resolve(&now.timezone(), date.and_time(time)))
+        .find(|next| next > now)
 }
 
 /// A scheduler that schedules events on a fixed weekday and time.
@@ -95,19 +158,132 @@ pub struct WeeklyScheduler;
 impl Scheduler for WeeklyScheduler {
     type Input = (Weekday, NaiveTime);
 
-    fn next((weekday, time): Self::Input) -> Duration {
-        let now = Local::now().naive_local();
-        let mut next = now.date();
+    fn next((weekday, time): Self::Input) -> DateTime<Local> {
+        next_weekly(&Local::now(), weekday, time)
+    }
+}
 
-        if now.weekday() == weekday && now.time() >= time {
-            next += Duration::weeks(1);
-        } else {
-            while next.weekday() != weekday {
-                next = next.succ();
-            }
+/// Find the next occurrence of the weekday and time after `now`, in the time zone of `now`.
+fn next_weekly<Tz: TimeZone>(
+    now: &DateTime<Tz>,
+    weekday: Weekday,
+    time: NaiveTime,
+) -> DateTime<Tz> {
+    let today = now.naive_local().date();
+    let dates = (0..=7)
+        .map(|days| today + Duration::days(days))
+        .filter(|date| date.weekday() == weekday);
+
+    next_after(now, dates, time).unwrap_or_else(|| now.clone() + Duration::weeks(1))
+}
+
+/// Full English name of the weekday.
+#[must_use]
+pub const fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// A scheduler that schedules events once a month, on a fixed day and time. Days that don't exist
+/// in shorter months, like the 31st, fall on the last day of the month instead.
+pub struct MonthlyScheduler;
+
+impl Scheduler for MonthlyScheduler {
+    type Input = (u32, NaiveTime);
+
+    fn next((day, time): Self::Input) -> DateTime<Local> {
+        next_monthly(&Local::now(), day, time)
+    }
+}
+
+/// Find the next occurrence of the day of the month and time after `now`, in the time zone of
+/// `now`.
+fn next_monthly<Tz: TimeZone>(now: &DateTime<Tz>, day: u32, time: NaiveTime) -> DateTime<Tz> {
+    let local = now.naive_local();
+    let (year, month) = if local.month() == 12 {
+        (local.year() + 1, 1)
+    } else {
+        (local.year(), local.month() + 1)
+    };
+    let dates = [
+        day_of_month(local.year(), local.month(), day),
+        day_of_month(year, month, day),
+    ];
+
+    next_after(now, dates, time).unwrap_or_else(|| now.clone() + Duration::days(31))
+}
+
+/// Get the given day of the month, or the last day if the month is shorter.
+fn day_of_month(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or_else(|| NaiveDate::from_ymd(year, month, 1))
+}
+
+/// How often a report is sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Frequency {
+    /// Every day.
+    Daily,
+    /// Once a week on the weekday.
+    Weekly(Weekday),
+    /// Once a month on the day of the month.
+    Monthly(u32),
+}
+
+/// A scheduler that schedules events by any [`Frequency`], delegating to the [`DailyScheduler`],
+/// [`WeeklyScheduler`] or [`MonthlyScheduler`].
+pub struct FrequencyScheduler;
+
+impl Scheduler for FrequencyScheduler {
+    type Input = (Frequency, NaiveTime);
+
+    fn next((frequency, time): Self::Input) -> DateTime<Local> {
+        match frequency {
+            Frequency::Daily => DailyScheduler::next(time),
+            Frequency::Weekly(weekday) => WeeklyScheduler::next((weekday, time)),
+            Frequency::Monthly(day) => MonthlyScheduler::next((day, time)),
         }
+    }
+}
+
+/// A scheduler that schedules events every day at a fixed time.
+pub struct DailyScheduler;
+
+impl Scheduler for DailyScheduler {
+    type Input = NaiveTime;
+
+    fn next(time: Self::Input) -> DateTime<Local> {
+        let now = Local::now();
+        let today = now.naive_local().date();
+
+        next_after(&now, [today, today.succ()], time).unwrap_or_else(|| now + Duration::days(1))
+    }
+}
+
+/// A scheduler that schedules events once a year, on the first day of the year at a fixed time.
+pub struct YearlyScheduler;
+
+impl Scheduler for YearlyScheduler {
+    type Input = NaiveTime;
 
-        next.and_time(time) - now
+    fn next(time: Self::Input) -> DateTime<Local> {
+        let now = Local::now();
+        let year = now.year();
+        let dates = [
+            NaiveDate::from_ymd(year, 1, 1),
+            NaiveDate::from_ymd(year + 1, 1, 1),
+        ];
+
+        next_after(&now, dates, time).unwrap_or_else(|| now + Duration::days(365))
     }
 }
 
@@ -117,16 +293,17 @@ pub struct HourlyScheduler;
 impl Scheduler for HourlyScheduler {
     type Input = u8;
 
-    fn next(duration: Self::Input) -> Duration {
-        Duration::hours(duration.into())
+    fn next(duration: Self::Input) -> DateTime<Local> {
+        Local::now() + Duration::hours(duration.into())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Once;
-
-    use tokio::sync::mpsc;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Once,
+    };
 
     use super::*;
 
@@ -137,11 +314,12 @@ mod tests {
     impl Scheduler for FakeScheduler {
         type Input = ();
 
-        fn next(_: Self::Input) -> Duration {
-            Duration::milliseconds(50)
+        fn next((): Self::Input) -> DateTime<Local> {
+            Local::now() + Duration::milliseconds(50)
         }
     }
 
+    #[derive(Clone)]
     struct FakeTask;
 
     #[async_trait]
@@ -157,8 +335,11 @@ mod tests {
 
     fn init() {
         INIT.call_once(|| {
-            std::env::set_var("RUST_LOG", "codewars_bot");
-            env_logger::try_init().ok();
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::TRACE)
+                .with_test_writer()
+                .try_init()
+                .ok();
         });
     }
 
@@ -174,4 +355,182 @@ mod tests {
 
         tokio::time::sleep(TokioDuration::from_millis(110)).await;
     }
+
+    #[tokio::test]
+    async fn sleep_until_wall_clock() {
+        let start = std::time::Instant::now();
+        sleep_until(Local::now() - Duration::minutes(1)).await;
+        assert!(start.elapsed() < TokioDuration::from_millis(10));
+
+        sleep_until(Local::now() + Duration::milliseconds(30)).await;
+        assert!(start.elapsed() >= TokioDuration::from_millis(30));
+    }
+
+    /// Central European time zone of 2021, where the clocks move forward at 2021/03/28 02:00 and
+    /// back at 2021/10/31 03:00.
+    #[derive(Clone, Copy, Debug)]
+    struct Cet;
+
+    impl Cet {
+        fn offset(utc: &NaiveDateTime) -> FixedOffset {
+            let summer = NaiveDate::from_ymd(2021, 3, 28).and_hms(1, 0, 0)
+                ..NaiveDate::from_ymd(2021, 10, 31).and_hms(1, 0, 0);
+
+            FixedOffset::east(if summer.contains(utc) { 7200 } else { 3600 })
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Self
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms(12, 0, 0))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let offsets = [7200, 3600]
+                .into_iter()
+                .map(FixedOffset::east)
+                .filter(|offset| Self::offset(&(*local - *offset)) == *offset)
+                .collect::<Vec<_>>();
+
+            match offsets[..] {
+                [offset] => LocalResult::Single(offset),
+                [earlier, later] => LocalResult::Ambiguous(earlier, later),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            Self::offset(&utc.and_hms(0, 0, 0))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset(utc)
+        }
+    }
+
+    fn cet(month: u32, day: u32, hour: u32, min: u32) -> DateTime<Cet> {
+        Cet.ymd(2021, month, day).and_hms(hour, min, 0)
+    }
+
+    #[test]
+    fn weekly_keeps_local_time_across_dst() {
+        let time = NaiveTime::from_hms(10, 0, 0);
+
+        let next = next_weekly(&cet(3, 27, 10, 0), Weekday::Sun, time);
+        assert_eq!(cet(3, 28, 10, 0), next);
+        assert_eq!(Duration::hours(23), next - cet(3, 27, 10, 0));
+
+        let next = next_weekly(&cet(10, 30, 10, 0), Weekday::Sun, time);
+        assert_eq!(cet(10, 31, 10, 0), next);
+        assert_eq!(Duration::hours(25), next - cet(10, 30, 10, 0));
+    }
+
+    #[test]
+    fn weekly_skipped_and_repeated_times() {
+        let skipped = next_weekly(
+            &cet(3, 27, 10, 0),
+            Weekday::Sun,
+            NaiveTime::from_hms(2, 30, 0),
+        );
+        assert_eq!(cet(3, 28, 3, 30), skipped);
+
+        let repeated = next_weekly(
+            &cet(10, 30, 10, 0),
+            Weekday::Sun,
+            NaiveTime::from_hms(2, 30, 0),
+        );
+        assert_eq!(Utc.ymd(2021, 10, 31).and_hms(0, 30, 0), repeated);
+    }
+
+    #[test]
+    fn weekly_around_midnight() {
+        let midnight = NaiveTime::from_hms(0, 0, 0);
+
+        assert_eq!(
+            cet(6, 7, 0, 0),
+            next_weekly(&cet(6, 6, 23, 59), Weekday::Mon, midnight)
+        );
+        assert_eq!(
+            cet(6, 14, 0, 0),
+            next_weekly(&cet(6, 7, 0, 0), Weekday::Mon, midnight)
+        );
+        assert_eq!(
+            cet(6, 13, 23, 59),
+            next_weekly(
+                &cet(6, 7, 0, 0),
+                Weekday::Sun,
+                NaiveTime::from_hms(23, 59, 0)
+            )
+        );
+    }
+
+    #[test]
+    fn monthly_clamps_to_last_day() {
+        let time = NaiveTime::from_hms(10, 0, 0);
+        let at = |y, m, d, h| Utc.ymd(y, m, d).and_hms(h, 0, 0);
+
+        assert_eq!(
+            at(2021, 1, 15, 10),
+            next_monthly(&at(2021, 1, 15, 9), 15, time)
+        );
+        assert_eq!(
+            at(2021, 2, 15, 10),
+            next_monthly(&at(2021, 1, 15, 10), 15, time)
+        );
+        assert_eq!(
+            at(2021, 2, 28, 10),
+            next_monthly(&at(2021, 1, 31, 11), 31, time)
+        );
+        assert_eq!(
+            at(2024, 2, 29, 10),
+            next_monthly(&at(2024, 2, 1, 0), 31, time)
+        );
+        assert_eq!(
+            at(2022, 1, 1, 10),
+            next_monthly(&at(2021, 12, 1, 12), 1, time)
+        );
+    }
+
+    #[derive(Clone)]
+    struct CountingTask(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Task for CountingTask {
+        fn name() -> &'static str {
+            "counting"
+        }
+
+        async fn run(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn run_several_schedules() {
+        init();
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(run_many::<FakeScheduler, _>(
+            rx,
+            CountingTask(count.clone()),
+        ));
+
+        tx.send(vec![(), ()]).unwrap();
+        tokio::time::sleep(TokioDuration::from_millis(75)).await;
+        assert_eq!(2, count.load(Ordering::SeqCst));
+
+        tx.send(Vec::new()).unwrap();
+        tokio::time::sleep(TokioDuration::from_millis(10)).await;
+        let disabled = count.load(Ordering::SeqCst);
+        tokio::time::sleep(TokioDuration::from_millis(110)).await;
+        assert_eq!(disabled, count.load(Ordering::SeqCst));
+    }
 }
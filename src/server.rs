@@ -1,36 +1,114 @@
 //! Implementation of a HTTP server to listen for message events from Slack. It also features a
 //! landing page to introduce features of the service.
 
-use log::{info, warn};
-use tokio::sync::mpsc::UnboundedSender;
+use std::{future, sync::Arc};
+
+use serde_json::Value;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc::UnboundedSender, watch, RwLock};
+use tracing::{info, warn};
 use warp::Filter;
 
 use self::handlers::State;
-use crate::api::slack::event::AppMention;
+use crate::{
+    activity::Feed, admin::Admin, api::slack::interaction::Interaction, fetch::FetchCache,
+    lease::Leader, stats, storage::Repository,
+};
+
+/// Requests from Slack that the bot reacts to.
+#[derive(Debug)]
+pub enum Incoming {
+    /// An event, like a mention or direct message, with its unique ID. The event is passed on as
+    /// raw JSON, to be parsed by the handler for its type.
+    Event(String, Value),
+    /// A user clicked a button in one of the bot's messages or views, or submitted a modal.
+    Interaction(Interaction),
+}
 
-/// Run the server on the given port. A signing key is required to verify events come from Slack and
-/// any successfully parsed events are sent back through the given sender.
-pub async fn run(port: u16, signing_key: String, sender: UnboundedSender<AppMention>) {
+/// Run the server on the given port. Signing keys are required to verify events and interactions
+/// come from Slack and any events and button clicks are sent back through the given sender.
+/// Requests signed with any of the keys are accepted, to allow rotating the key without downtime.
+/// Events are rejected while this instance is on standby, so Slack retries them against the
+/// leader. The admin API, which includes the statistics, and the team summary are only served if
+/// their token is given. If the event stream is enabled, activities published to the feed are
+/// streamed to all connected displays, until the server shuts down.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    port: u16,
+    signing_keys: Vec<String>,
+    sender: UnboundedSender<Incoming>,
+    repo: Arc<RwLock<Repository>>,
+    fetch: Arc<FetchCache>,
+    stats_flight: Arc<stats::Flight>,
+    metrics: bool,
+    event_stream: bool,
+    admin: Option<Admin>,
+    summary_token: Option<String>,
+    feed: Feed,
+    leader: Leader,
+) {
+    let state = State {
+        signing_keys: signing_keys.into(),
+        sender,
+        leader,
+    };
+    let (closing_tx, closing_rx) = watch::channel(false);
     let routes = filters::index()
         .or(filters::favicon())
-        .or(filters::event(State {
-            signing_key,
-            sender,
-        }))
+        .or(filters::event(state.clone()))
+        .or(filters::interactive(state))
+        .or(filters::summary(repo.clone(), fetch.clone(), summary_token))
+        .or(filters::stats(
+            repo.clone(),
+            fetch,
+            stats_flight,
+            admin.clone(),
+        ))
+        .or(filters::metrics(repo, metrics))
+        .or(filters::admin(admin))
+        .or(filters::stream(feed, closing_rx, event_stream))
         .map(filters::with_sec_headers)
         .with(warp::log("server"));
 
+    // Open streams would keep the server from shutting down, so they are ended first.
+    let shutdown = async move {
+        shutdown_signal().await;
+        closing_tx.send(true).ok();
+    };
     let (addr, server) =
-        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], port), shutdown_signal());
+        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], port), shutdown);
 
     info!("listening on {}", addr);
     server.await;
 }
 
-/// The signal to wait for that triggers a shutdown of the server.
+/// The signals to wait for that trigger a shutdown of the server. Besides CTRL+C, it listens for
+/// `SIGTERM` on unix systems, which container runtimes like Docker send to stop a container.
 async fn shutdown_signal() {
-    if tokio::signal::ctrl_c().await.is_err() {
-        warn!("failed to install CTRL+C signal handler");
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            warn!("failed to install CTRL+C signal handler");
+            future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            terminate.recv().await;
+        } else {
+            warn!("failed to install SIGTERM signal handler");
+            future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
     }
 
     info!("shutting down");
@@ -39,11 +117,19 @@ async fn shutdown_signal() {
 mod filters {
     //! All the routes that this server supports.
 
-    use std::convert::Infallible;
+    use std::{convert::Infallible, sync::Arc};
 
+    use tokio::sync::{watch, RwLock};
     use warp::Filter;
 
     use super::handlers::{self, State};
+    use crate::{
+        activity::Feed,
+        admin::{Admin, AuditParam, Request, UserParam},
+        fetch::FetchCache,
+        stats,
+        storage::Repository,
+    };
 
     /// Landing page at `/` with usage information.
     pub fn index() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -73,13 +159,167 @@ mod filters {
             .and(warp::path!("event"))
             .and(warp::header("x-slack-signature"))
             .and(warp::header("x-slack-request-timestamp"))
-            .and(warp::body::content_length_limit(1024 * 5))
+            .and(warp::body::content_length_limit(1024 * 16))
             .and(warp::body::bytes())
             .and(with_state(state))
             .map(handlers::event)
             .map(handlers::error)
     }
 
+    /// Endpoint at `/interactive` that receives interactions of users with messages and views, like
+    /// clicks on buttons or submitted modals.
+    pub fn interactive(
+        state: State,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::post()
+            .and(warp::path!("interactive"))
+            .and(warp::header("x-slack-signature"))
+            .and(warp::header("x-slack-request-timestamp"))
+            .and(warp::body::content_length_limit(1024 * 16))
+            .and(warp::body::bytes())
+            .and(with_state(state))
+            .map(handlers::interactive)
+            .map(handlers::error)
+    }
+
+    /// Endpoint at `/api/summary` that publishes the team's progress for other bot instances. The
+    /// endpoint only exists if a `token` is configured, and every request must carry it.
+    pub fn summary(
+        repo: Arc<RwLock<Repository>>,
+        fetch: Arc<FetchCache>,
+        token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let token = token.map(Arc::<str>::from);
+
+        warp::get()
+            .and(warp::path!("api" / "summary"))
+            .and(warp::any().and_then(move || {
+                let token = token.clone();
+                async move { token.ok_or_else(warp::reject::not_found) }
+            }))
+            .and(warp::header::optional("authorization"))
+            .and(warp::any().map(move || repo.clone()))
+            .and(warp::any().map(move || fetch.clone()))
+            .then(handlers::summary)
+    }
+
+    /// Endpoint at `/api/stats` that serves the same statistics as the `stats` command as JSON,
+    /// for external dashboards. Like the other admin endpoints, it only exists if the admin API is
+    /// enabled, and every request must carry the admin token. Statistics that are requested at the
+    /// same time, here or with the `stats` command, are only collected once.
+    pub fn stats(
+        repo: Arc<RwLock<Repository>>,
+        fetch: Arc<FetchCache>,
+        flight: Arc<stats::Flight>,
+        admin: Option<Admin>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::get()
+            .and(warp::path!("api" / "stats"))
+            .and(warp::any().and_then(move || {
+                let admin = admin.clone();
+                async move { admin.ok_or_else(warp::reject::not_found) }
+            }))
+            .and(warp::header::optional("authorization"))
+            .and(warp::query())
+            .and(warp::any().map(move || repo.clone()))
+            .and(warp::any().map(move || fetch.clone()))
+            .and(warp::any().map(move || flight.clone()))
+            .then(handlers::stats)
+    }
+
+    /// Endpoint at `/metrics` that exports usage statistics for Prometheus. The endpoint only
+    /// exists if explicitly `enabled`.
+    pub fn metrics(
+        repo: Arc<RwLock<Repository>>,
+        enabled: bool,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::get()
+            .and(warp::path!("metrics"))
+            .and(warp::any().and_then(move || async move {
+                if enabled {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }))
+            .untuple_one()
+            .and(warp::any().map(move || repo.clone()))
+            .then(handlers::metrics)
+    }
+
+    /// Endpoints at `/api/users`, `/api/schedule`, `/api/config` and `/api/audit` to manage the bot
+    /// without Slack. The endpoints only exist if the admin API is enabled, and every request must carry
+    /// the admin token.
+    pub fn admin(
+        admin: Option<Admin>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let requests = warp::get()
+            .and(warp::path!("api" / "users"))
+            .map(|| Request::ListUsers)
+            .or(warp::post()
+                .and(warp::path!("api" / "users"))
+                .and(warp::body::content_length_limit(1024))
+                .and(warp::body::json())
+                .map(|param: UserParam| Request::AddUser(param.username)))
+            .unify()
+            .or(warp::delete()
+                .and(warp::path!("api" / "users"))
+                .and(warp::query())
+                .map(|param: UserParam| Request::RemoveUser(param.username)))
+            .unify()
+            .or(warp::get()
+                .and(warp::path!("api" / "schedule"))
+                .map(|| Request::GetSchedule))
+            .unify()
+            .or(warp::put()
+                .and(warp::path!("api" / "schedule"))
+                .and(warp::body::content_length_limit(1024 * 16))
+                .and(warp::body::json())
+                .map(Request::SetSchedule))
+            .unify()
+            .or(warp::get()
+                .and(warp::path!("api" / "config"))
+                .map(|| Request::GetConfig))
+            .unify()
+            .or(warp::get()
+                .and(warp::path!("api" / "audit"))
+                .and(warp::query())
+                .map(|param: AuditParam| Request::GetAudit(param.last)))
+            .unify();
+
+        warp::any()
+            .and_then(move || {
+                let admin = admin.clone();
+                async move { admin.ok_or_else(warp::reject::not_found) }
+            })
+            .and(warp::header::optional("authorization"))
+            .and(requests)
+            .then(handlers::admin)
+    }
+
+    /// Endpoint at `/events/stream` that streams the bot's activity as server-sent events, for
+    /// wallboard displays. Streams end once the `closing` flag is set. The endpoint only exists if
+    /// explicitly `enabled`.
+    pub fn stream(
+        feed: Feed,
+        closing: watch::Receiver<bool>,
+        enabled: bool,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::get()
+            .and(warp::path!("events" / "stream"))
+            .and(warp::any().and_then(move || async move {
+                if enabled {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }))
+            .untuple_one()
+            .and(warp::any().map(move || feed.clone()))
+            .and(warp::any().map(move || closing.clone()))
+            .map(handlers::stream)
+    }
+
     /// Attach the [`State`] to an existing filter.
     fn with_state(state: State) -> impl Filter<Extract = (State,), Error = Infallible> + Clone {
         warp::any().map(move || state.clone())
@@ -105,7 +345,7 @@ mod filters {
         let mut res = reply.into_response();
         let headers = res.headers_mut();
 
-        for (k, v) in SEC_HEADERS.iter() {
+        for (k, v) in SEC_HEADERS {
             headers.insert(*k, HeaderValue::from_static(v));
         }
 
@@ -118,13 +358,34 @@ mod handlers {
 
     #![allow(clippy::needless_pass_by_value)]
 
-    use anyhow::Result;
-    use bytes::Bytes;
-    use log::{error, info, trace};
-    use tokio::sync::mpsc::UnboundedSender;
-    use warp::http::{header, Response, StatusCode};
+    use std::sync::Arc;
 
-    use crate::api::slack::event::{self, AppMention, Callback, Event};
+    use anyhow::{anyhow, Result};
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use serde_json::json;
+    use tokio::sync::{mpsc::UnboundedSender, watch, RwLock};
+    use tracing::{error, info, trace};
+    use warp::{
+        http::{header, HeaderValue, Response, StatusCode},
+        Reply,
+    };
+
+    use super::Incoming;
+    use crate::{
+        activity::Feed,
+        admin::{self, Admin, Request},
+        api::slack::{
+            event::{self, Callback},
+            interaction,
+        },
+        federation,
+        fetch::FetchCache,
+        lease::{Leader, Standby},
+        metrics,
+        stats::{self, Query},
+        storage::Repository,
+    };
 
     /// Static HTML of the index page.
     const INDEX_HTML: &[u8] = include_bytes!("../assets/index.html");
@@ -140,10 +401,13 @@ mod handlers {
     /// to properly handle Slack events.
     #[derive(Debug, Clone)]
     pub struct State {
-        /// Key to verify events really come from Slack.
-        pub signing_key: String,
+        /// Keys to verify events really come from Slack, the current one first and optionally
+        /// the previous one while rotating the key.
+        pub signing_keys: Arc<[String]>,
         /// Channel to send back successfully parsed messages.
-        pub sender: UnboundedSender<AppMention>,
+        pub sender: UnboundedSender<Incoming>,
+        /// Whether this instance is the leader and may process events.
+        pub leader: Leader,
     }
 
     /// Landing page with usage instructions.
@@ -174,7 +438,7 @@ mod handlers {
         body: Bytes,
         state: State,
     ) -> Result<Option<String>> {
-        event::verify_signature(state.signing_key.as_bytes(), &signature, &timestamp, &body)?;
+        verify(&state, &signature, &timestamp, &body)?;
 
         let content = serde_json::from_slice(&body)?;
 
@@ -183,17 +447,13 @@ mod handlers {
                 trace!("Received URL verification request");
                 Ok(Some(uv.challenge))
             }
-            Callback::Event(value) => {
-                match event::parse_event(value)? {
-                    Event::AppMention(am) => {
-                        trace!("Received app mention event");
-                        tokio::spawn(async move {
-                            trace!("{:?}", am);
-                            state.sender.send(am).unwrap();
-                        });
-                    }
-                    Event::Unknown(name) => info!("Received unknown event ({})", name),
-                }
+            Callback::Event(..) if !state.leader.is_leader() => Err(Standby.into()),
+            Callback::Event(id, value) => {
+                trace!("Received event {}", id);
+                tokio::spawn(async move {
+                    trace!("{:?}", value);
+                    state.sender.send(Incoming::Event(id, value)).unwrap();
+                });
 
                 Ok(None)
             }
@@ -204,6 +464,146 @@ mod handlers {
         }
     }
 
+    /// Interactivity endpoint that handles clicks on buttons of the bot's messages.
+    pub fn interactive(
+        signature: String,
+        timestamp: String,
+        body: Bytes,
+        state: State,
+    ) -> Result<Option<String>> {
+        verify(&state, &signature, &timestamp, &body)?;
+
+        if !state.leader.is_leader() {
+            return Err(Standby.into());
+        }
+
+        if let Some(interaction) = interaction::parse(&body)? {
+            trace!("Received interaction");
+            state.sender.send(Incoming::Interaction(interaction))?;
+        } else {
+            info!("Received unsupported interaction");
+        }
+
+        Ok(None)
+    }
+
+    /// Verify that a request was signed by Slack with any of the signing keys.
+    fn verify(state: &State, signature: &str, timestamp: &str, body: &[u8]) -> Result<()> {
+        state
+            .signing_keys
+            .iter()
+            .map(|key| event::verify_signature(key.as_bytes(), signature, timestamp, body))
+            .reduce(Result::or)
+            .ok_or_else(|| anyhow!("no signing key configured"))??;
+
+        Ok(())
+    }
+
+    /// Summary of the team's progress since the last scheduled report, which is only handled if
+    /// the request carries the shared token.
+    pub async fn summary(
+        token: Arc<str>,
+        authorization: Option<String>,
+        repo: Arc<RwLock<Repository>>,
+        fetch: Arc<FetchCache>,
+    ) -> warp::reply::Response {
+        if !admin::bearer_matches(&token, authorization.as_deref()) {
+            return unauthorized();
+        }
+
+        match federation::collect(&repo, &fetch).await {
+            Ok(summary) => warp::reply::json(&summary).into_response(),
+            Err(e) => {
+                error!("Error collecting team summary: {:?}", e);
+                warp::reply::with_status(warp::reply::json(&()), StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response()
+            }
+        }
+    }
+
+    /// Statistics of the tracked users' challenges within the queried dates. Completions aren't
+    /// recorded, so re-trains are only told apart by what the bot knows already. Only handled if
+    /// the request carries the admin token.
+    pub async fn stats(
+        admin: Admin,
+        authorization: Option<String>,
+        query: Query,
+        repo: Arc<RwLock<Repository>>,
+        fetch: Arc<FetchCache>,
+        flight: Arc<stats::Flight>,
+    ) -> warp::reply::Response {
+        if !admin.authorized(authorization.as_deref()) {
+            return unauthorized();
+        }
+
+        if let (Some(since), Some(until)) = (query.since, query.until) {
+            if until < since {
+                return warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": "until must not be before since" })),
+                    StatusCode::BAD_REQUEST,
+                )
+                .into_response();
+            }
+        }
+
+        let args = (query.since, query.until, query.top);
+        let stats = stats::collect_shared(&flight, &repo, &fetch, args).await;
+
+        warp::reply::json(&*stats).into_response()
+    }
+
+    /// Stream of all activities from now on, with regular comments to keep the connection alive.
+    pub fn stream(feed: Feed, mut closing: watch::Receiver<bool>) -> impl warp::Reply {
+        let events = feed.subscribe().take_until(async move {
+            closing.changed().await.ok();
+        });
+
+        warp::sse::reply(warp::sse::keep_alive().stream(events))
+    }
+
+    /// Usage statistics in the Prometheus text format.
+    pub async fn metrics(repo: Arc<RwLock<Repository>>) -> impl warp::Reply {
+        warp::reply::with_header(
+            metrics::render(&*repo.read().await),
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )
+    }
+
+    /// Request to the admin API, which is only handled if it carries the admin token.
+    pub async fn admin(
+        admin: Admin,
+        authorization: Option<String>,
+        request: Request,
+    ) -> warp::reply::Response {
+        if !admin.authorized(authorization.as_deref()) {
+            return unauthorized();
+        }
+
+        match admin.handle(request).await {
+            Ok((StatusCode::NO_CONTENT, _)) => StatusCode::NO_CONTENT.into_response(),
+            Ok((status, body)) => {
+                warp::reply::with_status(warp::reply::json(&body), status).into_response()
+            }
+            Err(e) => {
+                error!("Error handling admin request: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+
+    /// Rejection of a request that doesn't carry the expected bearer token.
+    fn unauthorized() -> warp::reply::Response {
+        let mut res = warp::reply::with_status(
+            warp::reply::json(&json!({ "error": "missing or invalid token" })),
+            StatusCode::UNAUTHORIZED,
+        )
+        .into_response();
+        res.headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        res
+    }
+
     /// Error wrapper that turns any [`Result`]<[`Option`]<`T`>> into a proper HTTP response. The
     /// contained value must be a [`warp::Reply`] and have a default value.
     pub fn error<T>(resp: Result<Option<T>>) -> impl warp::Reply
@@ -212,6 +612,10 @@ mod handlers {
     {
         let (status, content) = match resp {
             Ok(opt) => (StatusCode::OK, opt.unwrap_or_default()),
+            Err(e) if e.is::<Standby>() => {
+                trace!("Rejecting event on standby");
+                (StatusCode::SERVICE_UNAVAILABLE, Default::default())
+            }
             Err(e) => {
                 error!("Error during event processing: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, Default::default())
@@ -1,21 +1,151 @@
 //! Global server settings loaded at start up and used to configure the service and provide required
 //! information for its functionality.
 
-use std::fs;
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
 
-use anyhow::{bail, Result};
+use anyhow::{ensure, Context, Result};
 use serde::Deserialize;
+use toml::{value::Table, Value};
+use url::Url;
+
+use crate::{
+    api::{codewars::Retry, Http},
+    commands,
+    dispatch::WebhookTarget,
+    federation::Rival,
+    ratelimit::RateLimit,
+    timefmt::Timestamps,
+};
 
 /// All settings that are loaded at start up and required by the service to function.
 #[derive(Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Settings {
     /// Port to listen for connections. Defaults to `8080` if not set.
     #[serde(default = "default_port")]
     pub port: u16,
     /// Signing key to verify HTTP calls come from Slack.
     pub signing_key: String,
+    /// Previous signing key that is still accepted while rotating the key, so the new key can be
+    /// configured before it is activated in Slack. Remove it once the rotation is done.
+    pub previous_signing_key: Option<String>,
     /// Webhook URL to post messages to a Slack channel.
     pub webhook_url: String,
+    /// ID of the channel that the webhook URL posts to. Together with the bot token, messages that
+    /// are too long for the webhook are uploaded there as a file with a short summary.
+    pub webhook_channel: Option<String>,
+    /// Bot token (starting with `xoxb-`) to reply to direct messages, react to commands and publish
+    /// the Home tab through the Slack Web API. Direct messages can't be answered without it.
+    pub bot_token: Option<String>,
+    /// ID of the channel to upload a chart of the completed challenges per user to, with every
+    /// scheduled report. Needs the bot token.
+    pub chart_channel: Option<String>,
+    /// Token that requests to the admin API and the statistics API must carry as bearer token.
+    /// Both APIs are disabled if not set.
+    pub admin_token: Option<String>,
+    /// Location of the state file. Defaults to a file in `/var/lib/codewars-bot` or the platform
+    /// specific data directory if not set.
+    pub state_path: Option<PathBuf>,
+    /// Additional webhooks that scheduled reports and notifications are duplicated to.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookTarget>,
+    /// Webhook URL to post scheduled stats reports to, instead of the other webhooks.
+    pub stats_webhook_url: Option<Url>,
+    /// Webhook URL to post notifications about completed challenges to, instead of the other
+    /// webhooks. Useful to keep them in a separate channel, as they can be noisy.
+    pub notify_webhook_url: Option<Url>,
+    /// Webhook to alert admins about problems, like an archived channel for one of the webhooks.
+    pub ops_webhook: Option<WebhookTarget>,
+    /// Another bot instance to compare the team's progress with in scheduled reports.
+    pub rival: Option<Rival>,
+    /// Shared secret that other bot instances must carry as bearer token to load the team's
+    /// summary. The summary endpoint is disabled if not set.
+    pub summary_token: Option<String>,
+    /// Shortcuts for commands, mapping the alias name to the full command with fixed arguments,
+    /// like `week = "stats since 2020/01/06"`.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Retry policy for failed Codewars API calls.
+    #[serde(default)]
+    pub codewars_retry: Retry,
+    /// Maximum amount of pages that are fetched at the same time from the Codewars API, when the
+    /// local history needs more than the latest page of a user's completed challenges. Defaults to
+    /// `4` if not set.
+    #[serde(default = "default_codewars_in_flight")]
+    pub codewars_in_flight: usize,
+    /// How many more times a message is delivered from the outbox after all retries failed.
+    /// Defaults to `10` if not set and `0` disables the outbox.
+    #[serde(default = "default_outbox_attempts")]
+    pub outbox_attempts: u8,
+    /// How long fetched Codewars data is reused, in seconds. Defaults to `300` if not set and `0`
+    /// disables the cache.
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Delay in milliseconds to batch changes of the state into a single write of the state file.
+    /// Defaults to `1000` if not set and `0` writes every change right away.
+    #[serde(default = "default_save_delay_ms")]
+    pub save_delay_ms: u64,
+    /// Interval in minutes to sync completed challenges into the local history. Defaults to `15`
+    /// if not set and `0` disables the history.
+    #[serde(default = "default_sync_interval_mins")]
+    pub sync_interval_mins: u64,
+    /// Emoji to show for each rank color, like `blue = ":large_blue_square:"`. Colors that are
+    /// not set use the default emoji.
+    #[serde(default)]
+    pub rank_emoji: BTreeMap<String, String>,
+    /// Slugs or IDs of katas that the `suggest` command picks from, in addition to the katas
+    /// authored by tracked users.
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+    /// Slack member IDs, like `U0123ABCD`, of the admins that may change where the bot posts to,
+    /// put it under maintenance or read the audit log. Nobody may if not set.
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Location of a file to append every detected challenge completion to, as JSON lines. Needs
+    /// the local history to be enabled.
+    pub event_log: Option<PathBuf>,
+    /// How many commands each Slack user may run, to protect the Codewars API from bursts of slow
+    /// commands.
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    /// Maximum time in seconds that a command that only fetches data may take, before it is
+    /// cancelled. Defaults to `30` if not set and must be at least `1`.
+    #[serde(default = "default_command_timeout_secs")]
+    pub command_timeout_secs: u64,
+    /// Interval in hours to check for newly completed challenges to notify about. Defaults to `3`
+    /// if not set and can be changed at runtime with the `notify every` command.
+    #[serde(default = "default_notify_interval_hours")]
+    pub notify_interval_hours: u8,
+    /// Whether to start the bot under maintenance, which can be turned off again with the
+    /// `maintenance off` command.
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Duration in seconds of the leader lease, when running several instances against the same
+    /// state. Only the leader runs schedules and processes events. Defaults to `0`, which disables
+    /// the election for a single instance.
+    #[serde(default)]
+    pub lease_secs: u64,
+    /// Unique name of this instance in the leader election. Defaults to the hostname with a random
+    /// suffix.
+    pub instance_id: Option<String>,
+    /// Timeouts and proxy for all outgoing HTTP requests.
+    #[serde(default)]
+    pub http: Http,
+    /// Whether to export command usage statistics at the `/metrics` endpoint. Disabled by default.
+    #[serde(default)]
+    pub metrics: bool,
+    /// Whether to stream the bot's activity as server-sent events at the `/events/stream`
+    /// endpoint. Disabled by default, as anyone that can reach the server could follow it.
+    #[serde(default)]
+    pub event_stream: bool,
+    /// Whether to welcome new members of the bot's channel with a short introduction. Enabled by
+    /// default.
+    #[serde(default = "default_welcome")]
+    pub welcome: bool,
+    /// How to show timestamps in messages, either `relative` to the current time or with Slack's
+    /// date formatting as `slack`. Defaults to `relative` if not set.
+    #[serde(default)]
+    pub timestamps: Timestamps,
 }
 
 /// Default value for the port.
@@ -23,7 +153,110 @@ const fn default_port() -> u16 {
     8080
 }
 
-/// Load the settings from a TOML file in several common known locations.
+/// Default value for the maximum amount of pages fetched at the same time.
+const fn default_codewars_in_flight() -> usize {
+    4
+}
+
+/// Default value for the outbox attempts.
+const fn default_outbox_attempts() -> u8 {
+    10
+}
+
+/// Default value for the cache's time to live.
+const fn default_cache_ttl_secs() -> u64 {
+    300
+}
+
+/// Default value for the notification interval.
+const fn default_notify_interval_hours() -> u8 {
+    3
+}
+
+/// Default value for the save delay.
+const fn default_save_delay_ms() -> u64 {
+    1000
+}
+
+/// Default value for the sync interval.
+const fn default_sync_interval_mins() -> u64 {
+    15
+}
+
+/// Default value for the command timeout.
+const fn default_command_timeout_secs() -> u64 {
+    30
+}
+
+/// Default value for the welcome message.
+const fn default_welcome() -> bool {
+    true
+}
+
+/// Kind of value that an environment variable is converted into.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// Plain string value, taken as-is.
+    String,
+    /// Integer value.
+    Integer,
+    /// Boolean value, either `true` or `false`.
+    Boolean,
+    /// Structured value, written as inline TOML like `{ name = "a", url = "b" }` or `[...]`.
+    Inline,
+}
+
+/// Mapping of environment variables to the setting they override.
+const ENV_VARS: &[(&str, &str, Kind)] = &[
+    ("PORT", "port", Kind::Integer),
+    ("SIGNING_KEY", "signing_key", Kind::String),
+    ("PREVIOUS_SIGNING_KEY", "previous_signing_key", Kind::String),
+    ("WEBHOOK_URL", "webhook_url", Kind::String),
+    ("WEBHOOK_CHANNEL", "webhook_channel", Kind::String),
+    ("BOT_TOKEN", "bot_token", Kind::String),
+    ("CHART_CHANNEL", "chart_channel", Kind::String),
+    ("ADMIN_TOKEN", "admin_token", Kind::String),
+    ("STATE_PATH", "state_path", Kind::String),
+    ("WEBHOOKS", "webhooks", Kind::Inline),
+    ("STATS_WEBHOOK_URL", "stats_webhook_url", Kind::String),
+    ("NOTIFY_WEBHOOK_URL", "notify_webhook_url", Kind::String),
+    ("OPS_WEBHOOK", "ops_webhook", Kind::Inline),
+    ("RIVAL", "rival", Kind::Inline),
+    ("SUMMARY_TOKEN", "summary_token", Kind::String),
+    ("METRICS", "metrics", Kind::Boolean),
+    ("EVENT_STREAM", "event_stream", Kind::Boolean),
+    ("WELCOME", "welcome", Kind::Boolean),
+    ("TIMESTAMPS", "timestamps", Kind::String),
+    ("ALIASES", "aliases", Kind::Inline),
+    ("CODEWARS_RETRY", "codewars_retry", Kind::Inline),
+    ("CODEWARS_IN_FLIGHT", "codewars_in_flight", Kind::Integer),
+    ("HTTP", "http", Kind::Inline),
+    ("OUTBOX_ATTEMPTS", "outbox_attempts", Kind::Integer),
+    ("MAINTENANCE", "maintenance", Kind::Boolean),
+    (
+        "NOTIFY_INTERVAL_HOURS",
+        "notify_interval_hours",
+        Kind::Integer,
+    ),
+    ("CACHE_TTL_SECS", "cache_ttl_secs", Kind::Integer),
+    ("SAVE_DELAY_MS", "save_delay_ms", Kind::Integer),
+    ("SYNC_INTERVAL_MINS", "sync_interval_mins", Kind::Integer),
+    ("RATE_LIMIT", "rate_limit", Kind::Inline),
+    (
+        "COMMAND_TIMEOUT_SECS",
+        "command_timeout_secs",
+        Kind::Integer,
+    ),
+    ("EVENT_LOG", "event_log", Kind::String),
+    ("RANK_EMOJI", "rank_emoji", Kind::Inline),
+    ("SUGGESTIONS", "suggestions", Kind::Inline),
+    ("ADMINS", "admins", Kind::Inline),
+    ("LEASE_SECS", "lease_secs", Kind::Integer),
+    ("INSTANCE_ID", "instance_id", Kind::String),
+];
+
+/// Load the settings from a TOML file in several common known locations. Any values can be
+/// overridden by environment variables, which allows to run the service without a settings file.
 pub fn load() -> Result<Settings> {
     let locations = &[
         concat!("/etc/", env!("CARGO_PKG_NAME"), "/config.toml"),
@@ -32,8 +265,106 @@ pub fn load() -> Result<Settings> {
     ];
     let buf = locations.iter().find_map(|loc| fs::read(loc).ok());
 
-    match buf {
-        Some(buf) => Ok(toml::from_slice(&buf)?),
-        None => bail!("failed finding settings"),
+    let mut table: Table = match buf {
+        Some(buf) => toml::from_slice(&buf)?,
+        None => Table::new(),
+    };
+
+    apply_env(&mut table, |name| env::var(name).ok())?;
+
+    let settings: Settings = Value::Table(table)
+        .try_into()
+        .context("failed loading settings from file or environment")?;
+
+    validate(&settings)?;
+
+    Ok(settings)
+}
+
+/// Check values of the settings that are valid TOML, but can't be used by the service.
+fn validate(settings: &Settings) -> Result<()> {
+    commands::validate_aliases(&settings.aliases).map_err(anyhow::Error::msg)?;
+    ensure!(
+        settings.command_timeout_secs > 0,
+        "the command timeout must be at least 1 second"
+    );
+
+    Ok(())
+}
+
+/// Override values in the given settings table with values from the environment.
+fn apply_env(table: &mut Table, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+    for (name, key, kind) in ENV_VARS {
+        if let Some(raw) = var(name) {
+            let value = match kind {
+                Kind::String => Value::String(raw),
+                Kind::Integer => Value::Integer(
+                    raw.parse()
+                        .with_context(|| format!("{name} must be an integer"))?,
+                ),
+                Kind::Boolean => Value::Boolean(
+                    raw.parse()
+                        .with_context(|| format!("{name} must be `true` or `false`"))?,
+                ),
+                Kind::Inline => toml::from_str::<Table>(&format!("value = {raw}"))
+                    .ok()
+                    .and_then(|mut t| t.remove("value"))
+                    .with_context(|| format!("{name} must be an inline TOML value"))?,
+            };
+
+            table.insert((*key).to_owned(), value);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_overrides_file() {
+        let mut table: Table = toml::from_str(
+            r#"
+            port = 9000
+            signing_key = "file"
+            webhook_url = "https://example.com/file"
+            "#,
+        )
+        .unwrap();
+
+        apply_env(&mut table, |name| match name {
+            "PORT" => Some("8000".to_owned()),
+            "SIGNING_KEY" => Some("env".to_owned()),
+            "RIVAL" => Some(
+                r#"{ name = "B", url = "https://example.com/api/summary", token = "t" }"#
+                    .to_owned(),
+            ),
+            _ => None,
+        })
+        .unwrap();
+
+        let settings: Settings = Value::Table(table).try_into().unwrap();
+
+        assert_eq!(8000, settings.port);
+        assert_eq!("env", settings.signing_key);
+        assert_eq!("https://example.com/file", settings.webhook_url);
+        assert_eq!("B", settings.rival.unwrap().name);
+        assert!(settings.welcome);
+    }
+
+    #[test]
+    fn reject_zero_timeout() {
+        let settings: Settings = toml::from_str(
+            r#"
+            signing_key = "key"
+            webhook_url = "https://example.com/hook"
+            command_timeout_secs = 0
+            "#,
+        )
+        .unwrap();
+
+        assert!(validate(&settings).is_err());
     }
 }
@@ -0,0 +1,794 @@
+//! Statistics of the tracked users' completed challenges. The data is collected once and can then
+//! be rendered as chat message or served as JSON, for example to external dashboards.
+
+use std::{cmp::Reverse, collections::BTreeMap, fmt::Write, sync::Arc};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{
+    api::codewars::{self, CompletedChallenge, Level},
+    fetch::FetchCache,
+    flight::SingleFlight,
+    mrkdwn::{self, escape},
+    ranks,
+    storage::{NotifyFilter, Repository},
+    timefmt,
+};
+
+/// Time range of the challenges to include in the statistics, where either end is optional.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Period {
+    /// Start of the range, inclusive.
+    since: Option<NaiveDateTime>,
+    /// End of the range, exclusive.
+    until: Option<NaiveDateTime>,
+}
+
+impl Period {
+    /// Create a range that starts at the given time and has no end.
+    pub const fn since(since: Option<NaiveDateTime>) -> Self {
+        Self { since, until: None }
+    }
+
+    /// Create a range over the given dates, including the whole last day.
+    pub fn dates(since: Option<NaiveDate>, until: Option<NaiveDate>) -> Self {
+        Self {
+            since: since.map(|d| d.and_hms(0, 0, 0)),
+            until: until.map(|d| d.succ().and_hms(0, 0, 0)),
+        }
+    }
+
+    /// Whether the range is unbounded on both ends.
+    const fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether the given time is within the range.
+    fn contains(&self, time: NaiveDateTime) -> bool {
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time < until)
+    }
+}
+
+/// Known first completions of each user's challenges, to tell apart re-trains in reports.
+pub struct Retrains {
+    first: BTreeMap<String, BTreeMap<String, DateTime<Utc>>>,
+    /// Whether to leave re-trains out of the report instead of marking them.
+    exclude: bool,
+}
+
+impl Retrains {
+    /// Take the known first completions and the re-train setting from the repository.
+    pub fn new(repo: &Repository) -> Self {
+        Self {
+            first: repo.first_completions().clone(),
+            exclude: repo.exclude_retrains(),
+        }
+    }
+
+    /// Whether the challenge was completed before already and this completion is a re-train.
+    fn is_retrain(&self, user: &str, challenge: &CompletedChallenge) -> bool {
+        self.first
+            .get(user)
+            .and_then(|known| known.get(&challenge.id))
+            .is_some_and(|first| challenge.completed_at > *first)
+    }
+}
+
+/// Query parameters of the statistics API, with the same meaning as for the `stats` command.
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub top: Option<usize>,
+}
+
+/// Statistics of all users within a period.
+#[derive(Serialize)]
+pub struct Stats {
+    /// Amount of most active users that the statistics are limited to.
+    pub top: Option<usize>,
+    /// Statistics of each user, in the order of the tracked users or with the most active first.
+    pub users: Vec<UserStats>,
+    /// Users whose challenges couldn't be fetched.
+    pub failed: Vec<Failure>,
+}
+
+/// Statistics of a single user.
+#[derive(Serialize)]
+pub struct UserStats {
+    pub username: String,
+    /// Name to show instead of the Codewars username.
+    pub display_name: Option<String>,
+    /// Overall rank of the user, unless the profile couldn't be fetched.
+    pub rank: Option<Level>,
+    /// Amount of challenges that the user completed in total.
+    pub total_challenges: u32,
+    /// Amount of challenges that the user completed within the period, unless the period is
+    /// unbounded.
+    pub period_challenges: Option<usize>,
+    /// Amount of challenges completed within the period for each rank, easiest first. Empty if the
+    /// period is unbounded.
+    pub difficulty: BTreeMap<Level, usize>,
+    /// Challenges completed within the period, with the latest first. Without a start date, only
+    /// the latest few are included.
+    pub challenges: Vec<Challenge>,
+}
+
+/// A single completed challenge.
+#[derive(Serialize)]
+pub struct Challenge {
+    pub id: String,
+    pub name: String,
+    pub slug: Option<String>,
+    /// Rank of the challenge, unless its details couldn't be fetched.
+    pub rank: Option<Level>,
+    pub completed_at: DateTime<Utc>,
+    /// Languages that the challenge was completed in, sorted by name.
+    pub languages: Vec<String>,
+    /// Whether the challenge was completed before already.
+    pub retrain: bool,
+}
+
+/// A user whose challenges couldn't be fetched.
+#[derive(Serialize)]
+pub struct Failure {
+    pub username: String,
+    /// Readable reason for the failure.
+    pub reason: String,
+}
+
+impl Failure {
+    fn new(username: String, error: codewars::Error) -> Self {
+        Self {
+            username,
+            reason: match error {
+                codewars::Error::UserNotFound(_) => String::from("doesn't exist on Codewars"),
+                e => format!("{:#}", anyhow::Error::from(e)),
+            },
+        }
+    }
+}
+
+/// Dates and limit of statistics, that identify identical ones.
+pub type Args = (Option<NaiveDate>, Option<NaiveDate>, Option<usize>);
+
+/// Statistics of all tracked users that are collected right now, by their dates and limit. The
+/// `stats` command and the stats API share them, so statistics that are requested by both at the
+/// same time are only collected once.
+pub type Flight = SingleFlight<Args, Arc<Stats>>;
+
+/// Collect the statistics of all tracked users. The first completions of all challenges are
+/// recorded beforehand, to tell apart re-trains.
+pub async fn collect_tracked(
+    repo: &RwLock<Repository>,
+    fetch: &FetchCache,
+    period: Period,
+    top: Option<usize>,
+    filter: Option<&NotifyFilter>,
+) -> Result<Stats> {
+    record_first_completions(repo, fetch).await?;
+
+    Ok(collect_watched(repo, fetch, period, top, filter).await)
+}
+
+/// Collect the statistics of all tracked users like [`collect_watched`], unless the same ones are
+/// collected right now already. In that case, their result is shared instead of fetching
+/// everything twice.
+pub async fn collect_shared(
+    flight: &Flight,
+    repo: &Arc<RwLock<Repository>>,
+    fetch: &Arc<FetchCache>,
+    (since, until, top): Args,
+) -> Arc<Stats> {
+    let repo = repo.clone();
+    let fetch = fetch.clone();
+
+    flight
+        .run(
+            (since, until, top),
+            async move {
+                Arc::new(
+                    collect_watched(&repo, &fetch, Period::dates(since, until), top, None).await,
+                )
+            }
+            .boxed(),
+        )
+        .await
+}
+
+/// Record the first completions of all challenges of the tracked users, to tell apart re-trains.
+pub async fn record_first_completions(repo: &RwLock<Repository>, fetch: &FetchCache) -> Result<()> {
+    let users = repo
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    // Users that fail to fetch are listed at the end of the statistics.
+    for user in &users {
+        let Ok(challenges) = fetch.completed_challenges(user).await else {
+            continue;
+        };
+        repo.write()
+            .await
+            .record_completions(
+                user,
+                challenges
+                    .data
+                    .iter()
+                    .map(|c| (c.id.as_str(), c.completed_at)),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Collect the statistics of all tracked users, with re-trains told apart by the first completions
+/// recorded so far.
+pub async fn collect_watched(
+    repo: &RwLock<Repository>,
+    fetch: &FetchCache,
+    period: Period,
+    top: Option<usize>,
+    filter: Option<&NotifyFilter>,
+) -> Stats {
+    let (users, names, retrains) = {
+        let repo = repo.read().await;
+        (
+            repo.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            repo.display_names(),
+            Retrains::new(&repo),
+        )
+    };
+
+    collect(&users, &names, fetch, period, top, Some(&retrains), filter).await
+}
+
+/// Collect the statistics of the given users, optionally limited to the most active ones. Only
+/// challenges that match the filter are included, if one is given.
+pub async fn collect(
+    users: &[String],
+    names: &BTreeMap<String, String>,
+    fetch: &FetchCache,
+    period: Period,
+    top: Option<usize>,
+    retrains: Option<&Retrains>,
+    filter: Option<&NotifyFilter>,
+) -> Stats {
+    let users = match top {
+        Some(top) => top_users(users, fetch, period, top).await,
+        None => users.to_vec(),
+    };
+    let mut stats = Stats {
+        top,
+        users: Vec::with_capacity(users.len()),
+        failed: Vec::new(),
+    };
+
+    for user in users {
+        let challenge_resp = match fetch.completed_challenges(&user).await {
+            Ok(challenges) => challenges,
+            Err(e) => {
+                warn!("Skipping user {} in the statistics: {}", user, e);
+                stats.failed.push(Failure::new(user, e));
+                continue;
+            }
+        };
+        let is_retrain = |c: &CompletedChallenge| retrains.is_some_and(|r| r.is_retrain(&user, c));
+        let mut challenges = challenge_resp
+            .data
+            .iter()
+            .filter(|c| !(retrains.is_some_and(|r| r.exclude) && is_retrain(c)))
+            .collect::<Vec<_>>();
+        challenges.sort_by_key(|a| a.completed_at);
+        challenges.reverse();
+
+        // Without a start date, only the latest few challenges are listed.
+        let n = if period.since.is_some() {
+            usize::MAX
+        } else {
+            3
+        };
+        let mut listed = Vec::new();
+        let mut completed = 0;
+        let mut difficulty = BTreeMap::new();
+
+        for challenge in challenges
+            .into_iter()
+            .filter(|c| period.contains(c.completed_at.naive_local()))
+        {
+            // Challenges past the listed ones are only counted, so their details are only needed
+            // for the filter and the difficulty within a bounded period.
+            let listing = listed.len() < n;
+            let rank = if listing || filter.is_some() || !period.is_unbounded() {
+                fetch
+                    .code_challenge(&challenge.id)
+                    .await
+                    .ok()
+                    .map(|kata| kata.rank.id)
+            } else {
+                None
+            };
+            if filter.is_some_and(|f| !f.matches(rank, &challenge.completed_languages)) {
+                continue;
+            }
+
+            completed += 1;
+            if let Some(rank) = rank.filter(|_| !period.is_unbounded()) {
+                *difficulty.entry(rank).or_default() += 1;
+            }
+            if !listing {
+                continue;
+            }
+            let Some(name) = &challenge.name else {
+                continue;
+            };
+
+            let mut languages = challenge
+                .completed_languages
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            languages.sort_unstable();
+
+            listed.push(Challenge {
+                id: challenge.id.clone(),
+                name: name.clone(),
+                slug: challenge.slug.clone(),
+                rank,
+                completed_at: challenge.completed_at,
+                languages,
+                retrain: is_retrain(challenge),
+            });
+        }
+
+        stats.users.push(UserStats {
+            display_name: names.get(&user).cloned(),
+            rank: fetch
+                .user(&user)
+                .await
+                .ok()
+                .map(|info| info.ranks.overall.rank),
+            total_challenges: challenge_resp.total_items,
+            period_challenges: (!period.is_unbounded()).then_some(completed),
+            difficulty,
+            challenges: listed,
+            username: user,
+        });
+    }
+
+    sort_by_activity(&mut stats.users);
+    stats
+}
+
+/// Describe how many challenges of each rank were completed, like `8kyu×3 7kyu×1`.
+fn difficulty(counts: &BTreeMap<Level, usize>) -> String {
+    counts
+        .iter()
+        .map(|(rank, count)| match rank {
+            Level::Kyu(kyu) => format!("{kyu}kyu×{count}"),
+            Level::Dan(dan) => format!("{dan}dan×{count}"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Sort the users with the most challenges completed within the period first, or the most
+/// challenges overall if the period is unbounded. Users with the same amount keep their order.
+fn sort_by_activity(users: &mut [UserStats]) {
+    users.sort_by_key(|user| {
+        Reverse(
+            user.period_challenges
+                .unwrap_or_else(|| usize::try_from(user.total_challenges).unwrap_or(usize::MAX)),
+        )
+    });
+}
+
+/// Pick the given amount of users with the most challenges completed within the period, with the
+/// most active user first. Users whose challenges can't be fetched count as inactive.
+async fn top_users(
+    users: &[String],
+    fetch: &FetchCache,
+    period: Period,
+    top: usize,
+) -> Vec<String> {
+    let mut counts = Vec::with_capacity(users.len());
+
+    for user in users {
+        let count = match fetch.completed_challenges(user).await {
+            Ok(challenges) if period.is_unbounded() => {
+                usize::try_from(challenges.total_items).unwrap_or(usize::MAX)
+            }
+            Ok(challenges) => challenges
+                .data
+                .iter()
+                .filter(|c| period.contains(c.completed_at.naive_local()))
+                .count(),
+            Err(_) => 0,
+        };
+        counts.push((user, count));
+    }
+
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+        .into_iter()
+        .take(top)
+        .map(|(user, _)| user.clone())
+        .collect()
+}
+
+/// Count the challenges that the tracked users completed within the period for each tag of their
+/// katas, with the most practiced tag first. Users and katas that can't be fetched are left out.
+pub async fn collect_tags(
+    repo: &RwLock<Repository>,
+    fetch: &FetchCache,
+    period: Period,
+) -> Vec<(String, usize)> {
+    let users = repo
+        .read()
+        .await
+        .users()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    let mut counts = BTreeMap::<String, usize>::new();
+
+    for user in &users {
+        let challenges = match fetch.completed_challenges(user).await {
+            Ok(challenges) => challenges,
+            Err(e) => {
+                warn!("Skipping user {} in the tag statistics: {}", user, e);
+                continue;
+            }
+        };
+
+        for challenge in challenges
+            .data
+            .iter()
+            .filter(|c| period.contains(c.completed_at.naive_local()))
+        {
+            let Ok(kata) = fetch.code_challenge(&challenge.id).await else {
+                continue;
+            };
+            for tag in &kata.tags {
+                *counts.entry(tag.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
+
+/// Maximum amount of tags listed in the tag statistics.
+const TAG_LIMIT: usize = 10;
+
+/// Render the completed challenges per tag as chat message.
+pub fn render_tags(tags: &[(String, usize)]) -> String {
+    if tags.is_empty() {
+        return String::from("Nobody completed a tagged challenge in this period");
+    }
+
+    let mut response = String::from(":label: *Topics the team practiced:*");
+    for (tag, count) in tags.iter().take(TAG_LIMIT) {
+        write!(response, "\n• {}: {count}", escape(tag)).ok();
+    }
+    if tags.len() > TAG_LIMIT {
+        write!(response, "\n_…and {} more_", tags.len() - TAG_LIMIT).ok();
+    }
+
+    response
+}
+
+/// Render the statistics as chat message.
+pub fn render(stats: &Stats) -> Result<String> {
+    let mut response = stats.top.map_or_else(
+        || String::from("Here are the current statistics:"),
+        |top| format!("Here are the current statistics of the top {top} users:"),
+    );
+
+    for user in &stats.users {
+        match &user.display_name {
+            Some(name) => write!(response, "\n\n{}", escape(name))?,
+            None => write!(response, "\n\n`{}`", mrkdwn::code(&user.username))?,
+        }
+        if let Some(rank) = user.rank {
+            write!(response, " {} {}", ranks::emoji(rank.color()), rank)?;
+        }
+        match user.period_challenges {
+            Some(count) => write!(
+                response,
+                " - {count} this period / {} total challenges",
+                user.total_challenges
+            )?,
+            None => write!(response, " - {} total challenges", user.total_challenges)?,
+        }
+        if !user.difficulty.is_empty() {
+            write!(response, "\n_{}_", difficulty(&user.difficulty))?;
+        }
+
+        for challenge in &user.challenges {
+            let emoji = challenge.rank.map_or("", |rank| ranks::emoji(rank.color()));
+
+            write!(
+                response,
+                "\n{}*{}* solved _{}_ in *{}*{}",
+                if emoji.is_empty() {
+                    String::new()
+                } else {
+                    format!("{emoji} ")
+                },
+                escape(&challenge.name),
+                timefmt::format(challenge.completed_at),
+                solution_links(challenge),
+                if challenge.retrain {
+                    " _(re-train)_"
+                } else {
+                    ""
+                }
+            )?;
+        }
+    }
+
+    if !stats.failed.is_empty() {
+        response.push_str("\n\n:warning: *Some users couldn't be fetched and are missing:*");
+        for failure in &stats.failed {
+            write!(
+                response,
+                "\n• `{}`: {}",
+                mrkdwn::code(&failure.username),
+                escape(&failure.reason)
+            )?;
+        }
+    }
+
+    Ok(response)
+}
+
+/// List the languages that a challenge was completed in, each linked to the kata's solutions in
+/// that language, so teammates can compare their approaches right away.
+fn solution_links(challenge: &Challenge) -> String {
+    let kata = challenge.slug.as_deref().unwrap_or(&challenge.id);
+
+    challenge
+        .languages
+        .iter()
+        .map(|lang| {
+            codewars::solutions_url(kata, lang)
+                .map_or_else(|_| lang.clone(), |url| format!("<{url}|{lang}>"))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path},
+        Mock, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::fetch;
+
+    #[tokio::test]
+    async fn mark_and_exclude_retrains() {
+        let (_server, fetch) = fetch::mock(&[(
+            "him",
+            &[("1", "2020-02-12T10:00:00Z"), ("2", "2020-02-11T10:00:00Z")],
+        )])
+        .await;
+        let users = ["him".to_owned()];
+        let mut retrains = Retrains {
+            first: [(
+                "him".to_owned(),
+                [("1".to_owned(), Utc.ymd(2020, 1, 1).and_hms(0, 0, 0))].into(),
+            )]
+            .into(),
+            exclude: false,
+        };
+
+        let stats = collect(
+            &users,
+            &BTreeMap::new(),
+            &fetch,
+            Period::default(),
+            None,
+            Some(&retrains),
+            None,
+        )
+        .await;
+        let listed = stats.users[0]
+            .challenges
+            .iter()
+            .map(|c| (c.id.as_str(), c.retrain))
+            .collect::<Vec<_>>();
+        assert_eq!([("1", true), ("2", false)], listed.as_slice());
+        let text = render(&stats).unwrap();
+        assert_eq!(1, text.matches("_(re-train)_").count(), "{text}");
+
+        retrains.exclude = true;
+        let stats = collect(
+            &users,
+            &BTreeMap::new(),
+            &fetch,
+            Period::default(),
+            None,
+            Some(&retrains),
+            None,
+        )
+        .await;
+        let listed = stats.users[0]
+            .challenges
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(["2"], listed.as_slice());
+    }
+
+    #[tokio::test]
+    async fn keep_going_after_failed_users() {
+        let (server, fetch) = fetch::mock(&[("him", &[("1", "2020-02-12T10:00:00Z")])]).await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/broken/code-challenges/completed"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let repo = RwLock::new(Repository::in_memory());
+        for user in ["broken", "ghost", "him"] {
+            repo.write().await.add_user(user, None).await.unwrap();
+        }
+
+        let stats = collect_tracked(&repo, &fetch, Period::default(), None, None)
+            .await
+            .unwrap();
+        let users = stats
+            .users
+            .iter()
+            .map(|u| u.username.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(["him"], users.as_slice());
+
+        let text = render(&stats).unwrap();
+        assert!(text.contains("Kata 1"), "{text}");
+        assert!(
+            text.contains("*Some users couldn't be fetched and are missing:*"),
+            "{text}"
+        );
+        assert!(text.contains("`broken`"), "{text}");
+        assert!(
+            text.contains("`ghost`: doesn't exist on Codewars"),
+            "{text}"
+        );
+    }
+
+    #[tokio::test]
+    async fn share_concurrent_collections() {
+        let (server, fetch) = fetch::mock(&[]).await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/users/him/code-challenges/completed"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({
+                        "totalPages": 1,
+                        "totalItems": 0,
+                        "data": []
+                    }))
+                    .set_delay(std::time::Duration::from_millis(100)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let repo = Arc::new(RwLock::new(Repository::in_memory()));
+        repo.write().await.add_user("him", None).await.unwrap();
+        let fetch = Arc::new(fetch);
+        let flight = Flight::new();
+
+        // The cache is disabled, so only the shared collection avoids a second request.
+        let (a, b) = tokio::join!(
+            collect_shared(&flight, &repo, &fetch, (None, None, None)),
+            collect_shared(&flight, &repo, &fetch, (None, None, None)),
+        );
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn render_stats() {
+        let stats = Stats {
+            top: None,
+            users: vec![UserStats {
+                username: "him".to_owned(),
+                display_name: Some("Him <3".to_owned()),
+                rank: Some(Level::Kyu(5)),
+                total_challenges: 12,
+                period_challenges: Some(1),
+                difficulty: [(Level::Kyu(8), 3), (Level::Kyu(5), 1), (Level::Dan(1), 1)].into(),
+                challenges: vec![Challenge {
+                    id: "1".to_owned(),
+                    name: "Multiply".to_owned(),
+                    slug: Some("multiply".to_owned()),
+                    rank: None,
+                    completed_at: Utc.ymd(2020, 2, 12).and_hms(10, 0, 0),
+                    languages: vec!["rust".to_owned()],
+                    retrain: true,
+                }],
+            }],
+            failed: vec![Failure {
+                username: "<!channel> `her`".to_owned(),
+                reason: "doesn't exist on Codewars".to_owned(),
+            }],
+        };
+
+        let text = render(&stats).unwrap();
+        assert!(text.starts_with("Here are the current statistics:\n\nHim &lt;3 "));
+        assert!(text.contains(
+            " 5 kyu - 1 this period / 12 total challenges\n_8kyu×3 5kyu×1 1dan×1_\n*Multiply* solved _on 2020/02/12_"
+        ));
+        assert!(text.contains("|rust>* _(re-train)_"));
+        assert!(text.ends_with("\n• `&lt;!channel&gt; 'her'`: doesn't exist on Codewars"));
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(-5, json["users"][0]["rank"]);
+        assert_eq!("Multiply", json["users"][0]["challenges"][0]["name"]);
+        assert_eq!(3, json["users"][0]["difficulty"]["-8"]);
+    }
+
+    #[test]
+    fn render_tag_stats() {
+        assert_eq!(
+            "Nobody completed a tagged challenge in this period",
+            render_tags(&[])
+        );
+
+        let tags = (1..=12)
+            .rev()
+            .map(|count| (format!("Tag {count}"), count))
+            .collect::<Vec<_>>();
+        let text = render_tags(&tags);
+        assert!(text.starts_with(":label: *Topics the team practiced:*\n• Tag 12: 12\n"));
+        assert!(text.ends_with("\n• Tag 3: 3\n_…and 2 more_"));
+    }
+
+    #[test]
+    fn sort_most_active_first() {
+        let user = |username: &str, period_challenges| UserStats {
+            username: username.to_owned(),
+            display_name: None,
+            rank: None,
+            total_challenges: 100,
+            period_challenges,
+            difficulty: BTreeMap::new(),
+            challenges: Vec::new(),
+        };
+        let mut users = vec![user("a", Some(1)), user("b", Some(5)), user("c", Some(1))];
+
+        sort_by_activity(&mut users);
+        let order = users
+            .iter()
+            .map(|u| u.username.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(["b", "a", "c"], order.as_slice());
+    }
+
+    #[test]
+    fn period_includes_last_day() {
+        let period = Period::dates(
+            Some(NaiveDate::from_ymd(2020, 2, 1)),
+            Some(NaiveDate::from_ymd(2020, 2, 29)),
+        );
+
+        assert!(period.contains(NaiveDate::from_ymd(2020, 2, 1).and_hms(0, 0, 0)));
+        assert!(period.contains(NaiveDate::from_ymd(2020, 2, 29).and_hms(23, 59, 59)));
+        assert!(!period.contains(NaiveDate::from_ymd(2020, 3, 1).and_hms(0, 0, 0)));
+        assert!(Period::since(None).is_unbounded());
+    }
+}
@@ -1,48 +1,533 @@
 //! Storage for all bot related settings that are persisted as a single TOML file.
 
-use std::{collections::BTreeSet, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use chrono::prelude::*;
-use once_cell::sync::Lazy;
+use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use tokio::{fs, sync::Mutex};
+use tokio::{
+    fs,
+    sync::{Mutex, Notify, RwLock},
+};
+use toml::value::{Table, Value};
+use tracing::error;
+use unicode_normalization::UnicodeNormalization;
+use url::Url;
+
+use crate::{
+    api::codewars::Level,
+    scheduling::{self, Frequency},
+};
 
 const STATE_DIR: &str = concat!("/var/lib/", env!("CARGO_PKG_NAME"));
-const STATE_FILE: &str = concat!("/var/lib/", env!("CARGO_PKG_NAME"), "/state.toml");
-const TEMP_FILE: &str = concat!("/var/lib/", env!("CARGO_PKG_NAME"), "/~temp-state.toml");
+const STATE_FILE: &str = "state.toml";
+
+/// Amount of executed commands kept in the audit log, before the oldest ones are dropped.
+const AUDIT_LIMIT: usize = 500;
+/// Maximum amount of first completions that are kept per user. The oldest ones are dropped first,
+/// so only re-trains of long ago completed challenges go unnoticed.
+const FIRST_COMPLETIONS_LIMIT: usize = 5000;
+
+/// Migrations that upgrade the content of older state files, where the entry at index `n` upgrades
+/// a file from version `n` to `n + 1`. Files without a version are at version `0`.
+///
+/// Changes to the structure of the state that old files can't be deserialized into need a new
+/// migration at the end of this list. Existing migrations must never change.
+const MIGRATIONS: &[fn(&mut Table)] = &[merge_schedule, user_details];
+
+/// Current version of the state file's structure.
+const VERSION: usize = MIGRATIONS.len();
+
+/// Version 1: Move the single `schedule` of earlier versions into the list of `schedules`.
+fn merge_schedule(state: &mut Table) {
+    if let Some(schedule) = state.remove("schedule") {
+        state
+            .entry("schedules")
+            .or_insert_with(|| Value::Array(vec![schedule]));
+    }
+}
+
+/// Version 2: Turn the list of `users` into a table of user details and move their
+/// `display_names` into it.
+fn user_details(state: &mut Table) {
+    let mut names = match state.remove("display_names") {
+        Some(Value::Table(names)) => names,
+        _ => Table::new(),
+    };
+
+    match state.remove("users") {
+        Some(Value::Array(users)) => {
+            let users = users
+                .into_iter()
+                .map(|user| {
+                    let username = user.as_str().map(ToOwned::to_owned).unwrap_or_default();
+                    let mut details = Table::new();
+                    if let Some(name) = names.remove(&username) {
+                        details.insert("display_name".to_owned(), name);
+                    }
+                    (username, Value::Table(details))
+                })
+                .collect();
+            state.insert("users".to_owned(), Value::Table(users));
+        }
+        Some(users) => {
+            state.insert("users".to_owned(), users);
+        }
+        None => {}
+    }
+}
+
+/// Upgrade the content of a state file to the current version. Files of a newer version are
+/// rejected, as they may contain data that would be lost.
+fn migrate(state: &mut Table) -> Result<()> {
+    let version = match state.get("version") {
+        Some(version) => version
+            .as_integer()
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or_else(|| anyhow!("invalid state file version `{version}`"))?,
+        None => 0,
+    };
+
+    if version > VERSION {
+        bail!(
+            "state file has version {version}, but this release only supports up to version \
+             {VERSION}"
+        );
+    }
+
+    for migration in &MIGRATIONS[version..] {
+        migration(state);
+    }
+
+    state.insert(
+        "version".to_owned(),
+        Value::Integer(i64::try_from(VERSION)?),
+    );
+    Ok(())
+}
+
+/// Determine the default location of the state file. The system wide state directory is used if it
+/// exists, otherwise the platform specific data directory of the current user.
+#[must_use]
+pub fn default_path() -> PathBuf {
+    let dir = if Path::new(STATE_DIR).exists() {
+        PathBuf::from(STATE_DIR)
+    } else {
+        ProjectDirs::from("", "", env!("CARGO_PKG_NAME")).map_or_else(
+            || PathBuf::from(STATE_DIR),
+            |dirs| dirs.data_dir().to_owned(),
+        )
+    };
+
+    dir.join(STATE_FILE)
+}
+
+/// Write the value as TOML to the given file. The content is written to a temporary file first and
+/// then moved to the final location, so the file is never left half written.
+///
+/// # Errors
+///
+/// Fails if the value can't be serialized or the file can't be written.
+pub async fn write_file(path: &Path, value: &(impl Serialize + Sync)) -> Result<()> {
+    static LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+    let content = toml::to_string_pretty(value)?;
+    let _guard = LOCK.lock().await;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = dir.join(format!(
+        "~temp-{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+
+    fs::create_dir_all(dir).await?;
+    fs::write(&temp_file, &content).await?;
+    fs::rename(&temp_file, path).await?;
+
+    Ok(())
+}
+
+/// Batch the changes of the repository into a single write every `delay`.
+///
+/// Without batching, the whole state file is written on every change. Changes that are still
+/// pending when the service stops must be written with [`Repository::flush`].
+pub async fn save_batched(repo: Arc<RwLock<Repository>>, delay: Duration) {
+    let changed = Arc::new(Notify::new());
+    repo.write().await.batch = Some(Batch {
+        changed: changed.clone(),
+        dirty: AtomicBool::new(false),
+    });
+
+    loop {
+        changed.notified().await;
+        tokio::time::sleep(delay).await;
+
+        // Only reading the state allows stats and other readers to continue during the write.
+        if let Err(e) = repo.read().await.flush().await {
+            error!("Error saving the state: {}", e);
+            // Try again after the next delay, even if nothing changes in the meantime.
+            changed.notify_one();
+        }
+    }
+}
 
 /// The repository is the single access point for all the **dynamic** settings regarding this bot.
 /// Any changes to the settings through this repository are directly persisted to the TOML file.
 ///
 /// Any manual changes to the file while the bot is running are not recognized and the application
 /// must be restarted afterwards.
+///
+/// Plain values must be declared before any nested tables, as TOML requires them to come first.
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Repository {
-    /// List of users that are watched and used in any Codewars related actions.
-    users: BTreeSet<String>,
+    /// Location of the state file that this repository is persisted to, or `None` if it only lives
+    /// in memory.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// State of batched writes, if changes are batched instead of written right away.
+    #[serde(skip)]
+    batch: Option<Batch>,
+    /// Version of the state's structure, to upgrade files of older versions on load.
+    version: usize,
     /// Whether to notify about any Codewars events related to the watched `users`.
     notify: bool,
+    /// Interval in hours to check for new challenges to notify about, overriding the default from
+    /// the settings.
+    notify_interval: Option<u8>,
+    /// Default interval in hours to check for new challenges, as configured in the settings.
+    #[serde(skip)]
+    default_notify_interval: u8,
+    /// Interval in hours to batch notifications into a single digest, instead of sending a list
+    /// on every check.
+    digest_hours: Option<u32>,
+    /// Last time a notification digest was sent.
+    digest_sent_at: Option<DateTime<Utc>>,
+    /// Time until which notifications are snoozed.
+    snooze_until: Option<DateTime<Utc>>,
+    /// Time of the first notification check that was held back by a snooze or the quiet hours.
+    /// All challenges completed since then are sent in one batch afterwards.
+    held_since: Option<DateTime<Utc>>,
+    /// Whether re-trained challenges are left out of reports.
+    exclude_retrains: bool,
+    /// Whether the bot is under maintenance, which suspends all scheduled messages.
+    maintenance: bool,
+    /// Webhook URL of the channel to post reports to, overriding the one from the settings.
+    webhook_url: Option<Url>,
+    /// Additional Slack webhook URLs to post reports and notifications to, besides the ones from
+    /// the settings.
+    webhooks: Vec<Url>,
+    /// Amount of katas that the team wants to complete each week.
+    goal: Option<u32>,
+    /// ID of the kata that the team picked as the kata of the week.
+    kata_of_the_week: Option<String>,
     /// Last time the schedule was successfully sent.
     last_run: Option<DateTime<Utc>>,
-    /// The schedule for weekly statistics messages.
-    schedule: Schedule,
+    /// Restrictions on the completed challenges that trigger a notification.
+    notify_filter: NotifyFilter,
+    /// Daily time window during which notifications are held back.
+    quiet_hours: Option<QuietHours>,
+    /// The currently running competition, if any.
+    competition: Option<Competition>,
+    /// The currently running poll for the next kata of the week, if any.
+    poll: Option<Poll>,
+    /// Honor of each watched user at the time of the last scheduled stats run.
+    honor: BTreeMap<String, u32>,
+    /// Usage count of each command and its options, without any personal information.
+    usage: BTreeMap<String, Usage>,
+    /// Users that are watched and used in any Codewars related actions, with their details.
+    users: BTreeMap<String, UserInfo>,
+    /// Personal subscriptions by the Slack ID of the subscribed member.
+    subscriptions: BTreeMap<String, Subscription>,
+    /// First known completion time of each challenge, per user, limited to the latest
+    /// [`FIRST_COMPLETIONS_LIMIT`] ones. Later completions of the same challenge are considered
+    /// re-trains.
+    first_completions: BTreeMap<String, BTreeMap<String, DateTime<Utc>>>,
+    /// The schedules for statistics messages. Left out when empty, as an empty array would
+    /// be a plain value after the tables.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    schedules: Vec<Schedule>,
+    /// Messages that couldn't be delivered yet and wait for another attempt. Left out when empty,
+    /// as an empty array would be a plain value after the tables.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    outbox: Vec<QueuedMessage>,
+    /// The most recently executed commands, oldest first. Left out when empty, as an empty array
+    /// would be a plain value after the tables.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    audit: Vec<AuditEntry>,
+}
+
+/// State of batched writes of the repository.
+#[derive(Debug)]
+struct Batch {
+    /// Signal for the task that writes the batched changes.
+    changed: Arc<Notify>,
+    /// Whether there are changes that weren't written yet.
+    dirty: AtomicBool,
+}
+
+/// Normalize a username by trimming surrounding whitespace and composing its Unicode characters,
+/// while keeping its case.
+#[must_use]
+pub fn normalize_username(username: &str) -> String {
+    username.trim().nfc().collect()
+}
+
+/// Key to compare usernames regardless of their case and Unicode form.
+fn username_key(username: &str) -> String {
+    normalize_username(username).to_lowercase()
+}
+
+/// Move the value of a key to another key, unless the other key has a value already.
+fn rename_key<V>(map: &mut BTreeMap<String, V>, from: &str, to: &str) {
+    if let Some(value) = map.remove(from) {
+        map.entry(to.to_owned()).or_insert(value);
+    }
+}
+
+/// Details about a watched user.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[serde(default)]
+pub struct UserInfo {
+    /// Name to show in reports instead of the Codewars username.
+    pub display_name: Option<String>,
+    /// Slack ID of the member that added the user. Unknown for users that were added through the
+    /// admin API or before this was recorded.
+    pub added_by: Option<String>,
+    /// Time when the user was added, if known.
+    pub added_at: Option<DateTime<Utc>>,
+    /// Free-form note about the user, like the team they belong to.
+    pub note: Option<String>,
+}
+
+impl UserInfo {
+    /// Fill in any details that are missing from another entry of the same user.
+    fn merge(&mut self, other: Self) {
+        self.display_name = self.display_name.take().or(other.display_name);
+        self.added_by = self.added_by.take().or(other.added_by);
+        self.added_at = self.added_at.take().or(other.added_at);
+        self.note = self.note.take().or(other.note);
+    }
+}
+
+/// Personal subscription of a Slack member to the progress of their own Codewars account, which
+/// is delivered as direct messages.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Codewars username that the member linked to themselves.
+    pub username: String,
+    /// Last time the account was checked for completed challenges.
+    pub checked_at: DateTime<Utc>,
+    /// Overall rank at the last check, to detect rank ups. Unknown before the first check.
+    pub rank: Option<Level>,
 }
 
-/// The schedule for weekly statistics reports.
-#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+/// A command that was executed, as recorded in the audit log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Time when the command finished.
+    pub at: DateTime<Utc>,
+    /// Slack ID of the member who sent the command.
+    pub user: String,
+    /// The command as the member wrote it.
+    pub command: String,
+    /// How the command ended.
+    pub outcome: Outcome,
+}
+
+/// The result of an executed command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+    /// The command was cancelled, because it took too long.
+    Timeout,
+}
+
+/// A message that couldn't be delivered to a webhook yet.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    /// Unique identifier of the message within the outbox.
+    pub id: u64,
+    /// Webhook that the message is delivered to.
+    pub url: Url,
+    /// Text content of the message.
+    pub text: String,
+    /// How many delivery attempts failed so far.
+    pub attempts: u8,
+    /// Earliest time for the next delivery attempt.
+    pub next_attempt: DateTime<Utc>,
+}
+
+/// Usage statistics of a single command.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// How often the command was used in total.
+    pub count: u64,
+    /// How often the command was cancelled for taking too long.
+    #[serde(default)]
+    pub timeouts: u64,
+    /// How often each of the command's options was used.
+    #[serde(default)]
+    pub options: BTreeMap<String, u64>,
+}
+
+/// The schedule for statistics reports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredSchedule", into = "StoredSchedule")]
 pub struct Schedule {
-    /// Day of the week when the reports should be send.
-    pub weekday: Weekday,
-    /// Exact time at the `weekday` when the reports should be send.
+    /// How often the reports should be send.
+    pub frequency: Frequency,
+    /// Exact time at the scheduled days when the reports should be send.
     pub time: NaiveTime,
 }
 
+impl Schedule {
+    /// Input for the [`FrequencyScheduler`](crate::scheduling::FrequencyScheduler).
+    #[must_use]
+    pub const fn input(&self) -> (Frequency, NaiveTime) {
+        (self.frequency, self.time)
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.frequency {
+            Frequency::Daily => write!(f, "daily")?,
+            Frequency::Weekly(weekday) => write!(f, "on {}s", scheduling::weekday_name(weekday))?,
+            Frequency::Monthly(day) => write!(f, "monthly on day {day}")?,
+        }
+
+        write!(f, " at {}", self.time.format("%R"))
+    }
+}
+
+/// Persisted form of a [`Schedule`], where a weekday makes it a weekly schedule, a day of the month
+/// a monthly one and neither a daily one. Schedules of earlier versions were always weekly and
+/// keep working this way.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct StoredSchedule {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weekday: Option<Weekday>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    day: Option<u32>,
+    time: NaiveTime,
+}
+
+impl From<StoredSchedule> for Schedule {
+    fn from(stored: StoredSchedule) -> Self {
+        Self {
+            frequency: match (stored.weekday, stored.day) {
+                (Some(weekday), _) => Frequency::Weekly(weekday),
+                (None, Some(day)) => Frequency::Monthly(day),
+                (None, None) => Frequency::Daily,
+            },
+            time: stored.time,
+        }
+    }
+}
+
+impl From<Schedule> for StoredSchedule {
+    fn from(schedule: Schedule) -> Self {
+        let (weekday, day) = match schedule.frequency {
+            Frequency::Daily => (None, None),
+            Frequency::Weekly(weekday) => (Some(weekday), None),
+            Frequency::Monthly(day) => (None, Some(day)),
+        };
+
+        Self {
+            weekday,
+            day,
+            time: schedule.time,
+        }
+    }
+}
+
+/// Restrictions on the completed challenges that trigger a notification. Without any restriction,
+/// all challenges are included.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyFilter {
+    /// Easiest kyu of challenges to include, so `5` includes 5 kyu and any harder challenges.
+    pub min_kyu: Option<u8>,
+    /// Languages that a challenge must be completed in to be included.
+    pub languages: BTreeSet<String>,
+}
+
+impl NotifyFilter {
+    /// Whether a challenge with the given rank, that was completed in the given languages, passes
+    /// the filter. Challenges with an unknown rank are never left out because of their rank.
+    #[must_use]
+    pub fn matches(&self, rank: Option<Level>, languages: &HashSet<String>) -> bool {
+        self.min_kyu
+            .zip(rank)
+            .is_none_or(|(kyu, rank)| rank >= Level::Kyu(kyu))
+            && (self.languages.is_empty() || languages.iter().any(|l| self.languages.contains(l)))
+    }
+}
+
+/// Daily time window during which notifications are held back, for example at night. The window
+/// may span midnight, like from 22:00 to 08:00.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Local time when the quiet hours start.
+    pub start: NaiveTime,
+    /// Local time when the quiet hours end.
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Whether the given local time is within the quiet hours.
+    #[must_use]
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&time)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// A competition between the watched users, that scores the challenges completed within its time
+/// window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Competition {
+    /// Time when the competition was started.
+    pub started_at: DateTime<Utc>,
+    /// Time when the competition ends and the final result is posted.
+    pub ends_at: DateTime<Utc>,
+    /// Challenges that each participant completed before the start. Completing them again during
+    /// the competition doesn't count.
+    pub baseline: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// A poll to pick the next kata of the week from a few candidates.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Poll {
+    /// Time when the votes are counted and the winner is announced.
+    pub ends_at: DateTime<Utc>,
+    /// IDs of the katas to vote for.
+    pub candidates: Vec<String>,
+    /// Index of the voted candidate, per Slack user.
+    pub votes: BTreeMap<String, usize>,
+}
+
 impl Default for Schedule {
     fn default() -> Self {
         Self {
-            weekday: Weekday::Sun,
+            frequency: Frequency::Weekly(Weekday::Sun),
             time: NaiveTime::from_hms(10, 0, 0),
         }
     }
@@ -51,86 +536,386 @@ impl Default for Schedule {
 impl Repository {
     /// Load all settings from the given file location. If the file doesn't exist, a new empty
     /// `Repository` with defaults is created instead.
-    pub async fn load() -> Result<Self> {
-        let repo = if Path::new(STATE_FILE).exists() {
-            let settings = fs::read(STATE_FILE).await?;
-            toml::from_slice(&settings)?
+    ///
+    /// Files of older versions are upgraded to the current version, which is persisted with the
+    /// next change, so loading alone never writes to the file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or isn't a valid state file.
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let mut repo: Self = if path.exists() {
+            let mut state = toml::from_slice::<Table>(&fs::read(&path).await?)?;
+            migrate(&mut state)?;
+            Value::Table(state).try_into()?
         } else {
-            Self::default()
+            Self::in_memory()
         };
 
+        repo.path = Some(path);
+
+        // Usernames of earlier versions were stored as typed. They are normalized on load and
+        // persisted with the next change, so loading alone never writes to the file.
+        repo.normalize_users();
+
         Ok(repo)
     }
 
+    /// Create a new `Repository` with defaults that is never persisted.
+    ///
+    /// All changes only live in memory until the repository is dropped, which is useful for
+    /// tests and dry runs.
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self {
+            version: VERSION,
+            schedules: vec![Schedule::default()],
+            ..Self::default()
+        }
+    }
+
+    /// Stop persisting this repository, keeping its current state but only applying any further
+    /// changes in memory.
+    #[must_use]
+    pub fn detach(mut self) -> Self {
+        self.path = None;
+        self
+    }
+
+    /// Load the state file again, to pick up changes that another instance made to it. Does
+    /// nothing for repositories that only live in memory.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read or isn't a valid state file.
+    pub async fn reload(&mut self) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+
+        self.flush().await?;
+
+        let default_notify_interval = self.default_notify_interval;
+        let batch = self.batch.take();
+        *self = Self::load(path).await?;
+        self.default_notify_interval = default_notify_interval;
+        self.batch = batch;
+        Ok(())
+    }
+
     /// Persist the current settings to disk. The file location is the same where it was loaded
-    /// from before.
+    /// from before. If writes are batched, the change is only marked to be written soon.
     async fn save(&self) -> Result<()> {
-        static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
 
-        let _guard = LOCK.lock().await;
+        if let Some(batch) = &self.batch {
+            batch.dirty.store(true, Ordering::Release);
+            batch.changed.notify_one();
+            return Ok(());
+        }
 
-        fs::create_dir_all(STATE_DIR).await?;
+        write_file(path, self).await
+    }
 
-        let settings = toml::to_string_pretty(self)?;
+    /// Write any changes that are still pending from batching right away. As this only needs
+    /// shared access, readers of the repository aren't blocked while the file is written.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the state file can't be written.
+    pub async fn flush(&self) -> Result<()> {
+        let (Some(path), Some(batch)) = (&self.path, &self.batch) else {
+            return Ok(());
+        };
 
-        fs::write(TEMP_FILE, &settings).await?;
-        fs::rename(TEMP_FILE, STATE_FILE).await?;
+        if !batch.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
 
+        if let Err(e) = write_file(path, self).await {
+            batch.dirty.store(true, Ordering::Release);
+            return Err(e);
+        }
         Ok(())
     }
 
     /// Add a new user to the list of watched Codewars users. All commands that involve Codewars
     /// stats will include this new user in the queries. If the `username` was already in the list,
     /// nothing happens.
-    pub async fn add_user(&mut self, username: &str) -> Result<bool> {
-        if self.users.insert(username.to_owned()) {
-            self.save().await?;
-            Ok(true)
-        } else {
-            Ok(false)
+    ///
+    /// Usernames are compared regardless of their case, and stored in their normalized form as
+    /// they were first added. The Slack member that added the user is recorded as `added_by`, if
+    /// known.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn add_user(&mut self, username: &str, added_by: Option<&str>) -> Result<bool> {
+        let username = normalize_username(username);
+        if self.find_user(&username).is_some() {
+            return Ok(false);
         }
+
+        self.users.insert(
+            username,
+            UserInfo {
+                added_by: added_by.map(ToOwned::to_owned),
+                added_at: Some(Utc::now()),
+                ..UserInfo::default()
+            },
+        );
+        self.save().await?;
+        Ok(true)
     }
 
-    /// Remove a previously added user from the watchlist. If the `username` wasn't in the list,
-    /// nothing happens.
+    /// Remove a previously added user from the watchlist, regardless of the case of the
+    /// `username`. If the user wasn't in the list, nothing happens.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
     pub async fn remove_user(&mut self, username: &str) -> Result<bool> {
-        if self.users.remove(username) {
+        let Some(username) = self.find_user(username).map(ToOwned::to_owned) else {
+            return Ok(false);
+        };
+
+        self.users.remove(&username);
+        self.first_completions.remove(&username);
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Change the username of a watched user, keeping all the data that belongs to them. Nothing
+    /// happens if the user isn't watched or the new username belongs to another watched user.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn rename_user(&mut self, from: &str, to: &str) -> Result<bool> {
+        let Some(from) = self.find_user(from).map(ToOwned::to_owned) else {
+            return Ok(false);
+        };
+        let to = normalize_username(to);
+        if from == to || self.find_user(&to).is_some_and(|user| user != from) {
+            return Ok(false);
+        }
+
+        rename_key(&mut self.users, &from, &to);
+        rename_key(&mut self.honor, &from, &to);
+        rename_key(&mut self.first_completions, &from, &to);
+        if let Some(competition) = &mut self.competition {
+            rename_key(&mut competition.baseline, &from, &to);
+        }
+        for subscription in self.subscriptions.values_mut() {
+            if subscription.username == from {
+                subscription.username.clone_from(&to);
+            }
+        }
+
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Get the personal subscriptions by the Slack ID of each subscribed member.
+    #[must_use]
+    pub const fn subscriptions(&self) -> &BTreeMap<String, Subscription> {
+        &self.subscriptions
+    }
+
+    /// Subscribe a Slack member to the progress of a Codewars account, replacing any previous
+    /// subscription of the member. Only challenges completed from now on are reported.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn subscribe(&mut self, member: &str, username: String) -> Result<()> {
+        self.subscriptions.insert(
+            member.to_owned(),
+            Subscription {
+                username,
+                checked_at: Utc::now(),
+                rank: None,
+            },
+        );
+        self.save().await
+    }
+
+    /// Remove the subscription of a Slack member, returning whether there was one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn unsubscribe(&mut self, member: &str) -> Result<bool> {
+        if self.subscriptions.remove(member).is_none() {
+            return Ok(false);
+        }
+
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Remember the time and rank of the latest check of a member's subscription. Nothing happens
+    /// if the member unsubscribed in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn update_subscription(
+        &mut self,
+        member: &str,
+        checked_at: DateTime<Utc>,
+        rank: Level,
+    ) -> Result<()> {
+        let Some(subscription) = self.subscriptions.get_mut(member) else {
+            return Ok(());
+        };
+
+        subscription.checked_at = checked_at;
+        subscription.rank = Some(rank);
+        self.save().await
+    }
+
+    /// Remove all users from the watchlist at once, returning how many there were.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn clear_users(&mut self) -> Result<usize> {
+        let count = self.users.len();
+        if count > 0 {
+            self.users.clear();
             self.save().await?;
-            Ok(true)
-        } else {
-            Ok(false)
+        }
+        Ok(count)
+    }
+
+    /// Find the watched user that matches the given username regardless of its case, and return
+    /// it in the form it was added with.
+    #[must_use]
+    pub fn find_user(&self, username: &str) -> Option<&str> {
+        let key = username_key(username);
+        self.users
+            .keys()
+            .find(|user| username_key(user) == key)
+            .map(String::as_str)
+    }
+
+    /// Normalize all watched usernames and merge the ones that only differ in their case,
+    /// together with their per-user data.
+    fn normalize_users(&mut self) {
+        for (user, info) in std::mem::take(&mut self.users) {
+            let normalized = normalize_username(&user);
+            let kept = if let Some(existing) = self.find_user(&normalized).map(ToOwned::to_owned) {
+                if let Some(kept) = self.users.get_mut(&existing) {
+                    kept.merge(info);
+                }
+                existing
+            } else {
+                self.users.insert(normalized.clone(), info);
+                normalized
+            };
+
+            if kept != user {
+                rename_key(&mut self.honor, &user, &kept);
+                rename_key(&mut self.first_completions, &user, &kept);
+            }
         }
     }
 
     /// Create an iterator over all currently watched usernames. The iterator is distinct, so every
     /// username will only occur once.
     pub fn users(&self) -> impl Iterator<Item = &'_ str> {
-        self.users.iter().map(String::as_str)
+        self.users.keys().map(String::as_str)
     }
 
-    /// Get the current schedule for weekly Codewars statistics.
-    pub const fn schedule(&self) -> &Schedule {
-        &self.schedule
+    /// Get the details of a watched user, given in the form it was added with.
+    #[must_use]
+    pub fn user_info(&self, username: &str) -> Option<&UserInfo> {
+        self.users.get(username)
     }
 
-    /// Set a new schedule for the weekly Codewars report.
+    /// Get all current schedules for Codewars statistics.
+    #[must_use]
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    /// Set a new schedule for the Codewars report, replacing all existing schedules.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
     pub async fn set_schedule(&mut self, schedule: Schedule) -> Result<bool> {
-        if self.schedule == schedule {
+        self.set_schedules(vec![schedule]).await
+    }
+
+    /// Replace all schedules for the Codewars report at once, dropping any duplicates.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_schedules(&mut self, mut schedules: Vec<Schedule>) -> Result<bool> {
+        let mut seen = Vec::with_capacity(schedules.len());
+        schedules.retain(|schedule| {
+            let new = !seen.contains(schedule);
+            seen.push(*schedule);
+            new
+        });
+
+        if self.schedules == schedules {
             Ok(false)
         } else {
-            self.schedule = schedule;
+            self.schedules = schedules;
             self.save().await?;
             Ok(true)
         }
     }
 
+    /// Add another schedule for the Codewars report. If the same schedule exists already,
+    /// nothing happens.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn add_schedule(&mut self, schedule: Schedule) -> Result<bool> {
+        if self.schedules.contains(&schedule) {
+            Ok(false)
+        } else {
+            self.schedules.push(schedule);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Remove the schedule at the given position, returning it if it existed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn remove_schedule(&mut self, index: usize) -> Result<Option<Schedule>> {
+        if index >= self.schedules.len() {
+            return Ok(None);
+        }
+
+        let schedule = self.schedules.remove(index);
+        self.save().await?;
+        Ok(Some(schedule))
+    }
+
     /// Tell whether notifications about any new Codewars events for any watched user should be
     /// reported.
+    #[must_use]
     pub const fn notify(&self) -> bool {
         self.notify
     }
 
     /// Set whether messages should be send for any new Codewars events of the watched users.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
     pub async fn set_notify(&mut self, notify: bool) -> Result<bool> {
         if self.notify == notify {
             Ok(false)
@@ -141,12 +926,56 @@ impl Repository {
         }
     }
 
+    /// Get the interval in hours of notification digests, if enabled.
+    #[must_use]
+    pub const fn digest_hours(&self) -> Option<u32> {
+        self.digest_hours
+    }
+
+    /// Set the interval in hours of notification digests, or disable them. The next digest covers
+    /// the time since enabling it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_digest_hours(&mut self, hours: Option<u32>) -> Result<bool> {
+        if self.digest_hours == hours {
+            Ok(false)
+        } else {
+            self.digest_hours = hours;
+            self.digest_sent_at = hours.map(|_| Utc::now());
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the last time a notification digest was sent.
+    #[must_use]
+    pub const fn digest_sent_at(&self) -> Option<DateTime<Utc>> {
+        self.digest_sent_at
+    }
+
+    /// Set the last time a notification digest was sent.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_digest_sent_at(&mut self, sent_at: DateTime<Utc>) -> Result<()> {
+        self.digest_sent_at = Some(sent_at);
+        self.save().await
+    }
+
     /// Get the time of the last scheduled stats run.
+    #[must_use]
     pub const fn last_run(&self) -> Option<DateTime<Utc>> {
         self.last_run
     }
 
     /// Set the last run of scheduled stats.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
     pub async fn set_last_run(&mut self, last_run: DateTime<Utc>) -> Result<bool> {
         if self.last_run == Some(last_run) {
             Ok(false)
@@ -156,4 +985,918 @@ impl Repository {
             Ok(true)
         }
     }
+
+    /// Get the usage statistics of each command.
+    #[must_use]
+    pub const fn usage(&self) -> &BTreeMap<String, Usage> {
+        &self.usage
+    }
+
+    /// Count a command that was cancelled for taking too long.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn record_timeout(&mut self, command: &str) -> Result<()> {
+        self.usage.entry(command.to_owned()).or_default().timeouts += 1;
+        self.save().await
+    }
+
+    /// Count the usage of a command and its option, if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn record_usage(&mut self, command: &str, option: Option<&str>) -> Result<()> {
+        let usage = self.usage.entry(command.to_owned()).or_default();
+        usage.count += 1;
+        if let Some(option) = option {
+            *usage.options.entry(option.to_owned()).or_default() += 1;
+        }
+
+        self.save().await
+    }
+
+    /// Remember the first completion time of the given challenges for a user. Only the latest
+    /// [`FIRST_COMPLETIONS_LIMIT`] are kept. Returns whether any new information was recorded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn record_completions(
+        &mut self,
+        username: &str,
+        completions: impl IntoIterator<Item = (&str, DateTime<Utc>)>,
+    ) -> Result<bool> {
+        let known = self
+            .first_completions
+            .entry(username.to_owned())
+            .or_default();
+        let mut changed = false;
+
+        for (id, completed_at) in completions {
+            let first = known.entry(id.to_owned()).or_insert_with(|| {
+                changed = true;
+                completed_at
+            });
+            if completed_at < *first {
+                *first = completed_at;
+                changed = true;
+            }
+        }
+
+        if let Some(excess) = known.len().checked_sub(FIRST_COMPLETIONS_LIMIT) {
+            let mut oldest = known
+                .iter()
+                .map(|(id, first)| (*first, id.clone()))
+                .collect::<Vec<_>>();
+            oldest.sort_unstable();
+            for (_, id) in oldest.into_iter().take(excess) {
+                known.remove(&id);
+            }
+        }
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Get the first known completion time of each challenge, per user.
+    #[must_use]
+    pub const fn first_completions(&self) -> &BTreeMap<String, BTreeMap<String, DateTime<Utc>>> {
+        &self.first_completions
+    }
+
+    /// Tell whether re-trained challenges are left out of reports.
+    #[must_use]
+    pub const fn exclude_retrains(&self) -> bool {
+        self.exclude_retrains
+    }
+
+    /// Set whether re-trained challenges are left out of reports.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_exclude_retrains(&mut self, exclude: bool) -> Result<bool> {
+        if self.exclude_retrains == exclude {
+            Ok(false)
+        } else {
+            self.exclude_retrains = exclude;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the names to show in reports instead of the Codewars username, for all users that have
+    /// one.
+    #[must_use]
+    pub fn display_names(&self) -> BTreeMap<String, String> {
+        self.users
+            .iter()
+            .filter_map(|(user, info)| Some((user.clone(), info.display_name.clone()?)))
+            .collect()
+    }
+
+    /// Set the name to show in reports for a watched user, or go back to the Codewars username if
+    /// `None`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_display_name(&mut self, username: &str, name: Option<String>) -> Result<bool> {
+        let Some(info) = self.users.get_mut(username) else {
+            return Ok(false);
+        };
+        if info.display_name == name {
+            return Ok(false);
+        }
+
+        info.display_name = name;
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Set the note about a watched user, or remove it if `None`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_note(&mut self, username: &str, note: Option<String>) -> Result<bool> {
+        let Some(info) = self.users.get_mut(username) else {
+            return Ok(false);
+        };
+        if info.note == note {
+            return Ok(false);
+        }
+
+        info.note = note;
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Tell whether the bot is under maintenance.
+    #[must_use]
+    pub const fn maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    /// Turn the maintenance mode on or off.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_maintenance(&mut self, maintenance: bool) -> Result<bool> {
+        if self.maintenance == maintenance {
+            Ok(false)
+        } else {
+            self.maintenance = maintenance;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the webhook URL that was configured at runtime, if any.
+    #[must_use]
+    pub const fn webhook_url(&self) -> Option<&Url> {
+        self.webhook_url.as_ref()
+    }
+
+    /// Set the webhook URL of the channel to post reports to, taking precedence over the one from
+    /// the settings.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_webhook_url(&mut self, url: Url) -> Result<bool> {
+        if self.webhook_url.as_ref() == Some(&url) {
+            Ok(false)
+        } else {
+            self.webhook_url = Some(url);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the additional webhook URLs that were added at runtime.
+    #[must_use]
+    pub fn webhooks(&self) -> &[Url] {
+        &self.webhooks
+    }
+
+    /// Add another webhook URL to post reports and notifications to.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn add_webhook(&mut self, url: Url) -> Result<bool> {
+        if self.webhooks.contains(&url) {
+            return Ok(false);
+        }
+
+        self.webhooks.push(url);
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Remove a webhook URL that was added at runtime.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn remove_webhook(&mut self, url: &Url) -> Result<bool> {
+        let len = self.webhooks.len();
+        self.webhooks.retain(|webhook| webhook != url);
+        if self.webhooks.len() == len {
+            return Ok(false);
+        }
+
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Get the interval in hours to check for new challenges to notify about. It's at least one
+    /// hour, even if configured otherwise.
+    #[must_use]
+    pub fn notify_interval(&self) -> u8 {
+        self.notify_interval
+            .unwrap_or(self.default_notify_interval)
+            .max(1)
+    }
+
+    /// Set the default interval in hours to check for new challenges, that is used until another
+    /// interval is set. This value is not persisted.
+    pub const fn set_default_notify_interval(&mut self, hours: u8) {
+        self.default_notify_interval = hours;
+    }
+
+    /// Set the interval in hours to check for new challenges to notify about.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_notify_interval(&mut self, hours: u8) -> Result<bool> {
+        if self.notify_interval() == hours {
+            Ok(false)
+        } else {
+            self.notify_interval = Some(hours);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the time until which notifications are snoozed.
+    #[must_use]
+    pub const fn snooze_until(&self) -> Option<DateTime<Utc>> {
+        self.snooze_until
+    }
+
+    /// Snooze notifications until the given time, or stop snoozing them.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_snooze_until(&mut self, until: Option<DateTime<Utc>>) -> Result<bool> {
+        if self.snooze_until == until {
+            Ok(false)
+        } else {
+            self.snooze_until = until;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the daily time window during which notifications are held back.
+    #[must_use]
+    pub const fn quiet_hours(&self) -> Option<QuietHours> {
+        self.quiet_hours
+    }
+
+    /// Set or remove the daily time window during which notifications are held back.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_quiet_hours(&mut self, quiet_hours: Option<QuietHours>) -> Result<bool> {
+        if self.quiet_hours == quiet_hours {
+            Ok(false)
+        } else {
+            self.quiet_hours = quiet_hours;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Whether notifications are held back at the given time, either by a snooze or the quiet
+    /// hours.
+    #[must_use]
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        self.snooze_until.is_some_and(|until| now < until)
+            || self
+                .quiet_hours
+                .is_some_and(|quiet| quiet.contains(now.with_timezone(&Local).time()))
+    }
+
+    /// Get the time of the first notification check that was held back.
+    #[must_use]
+    pub const fn held_since(&self) -> Option<DateTime<Utc>> {
+        self.held_since
+    }
+
+    /// Set or clear the time of the first notification check that was held back.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_held_since(&mut self, held_since: Option<DateTime<Utc>>) -> Result<()> {
+        if self.held_since != held_since {
+            self.held_since = held_since;
+            self.save().await?;
+        }
+        Ok(())
+    }
+
+    /// Get the restrictions on challenges that trigger a notification.
+    #[must_use]
+    pub const fn notify_filter(&self) -> &NotifyFilter {
+        &self.notify_filter
+    }
+
+    /// Set or remove the easiest kyu of challenges that trigger a notification.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_notify_min_kyu(&mut self, kyu: Option<u8>) -> Result<bool> {
+        if self.notify_filter.min_kyu == kyu {
+            Ok(false)
+        } else {
+            self.notify_filter.min_kyu = kyu;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Set the languages that challenges must be completed in to trigger a notification, where no
+    /// languages remove the restriction.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_notify_languages(&mut self, languages: BTreeSet<String>) -> Result<bool> {
+        if self.notify_filter.languages == languages {
+            Ok(false)
+        } else {
+            self.notify_filter.languages = languages;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the weekly goal of completed katas for the team.
+    #[must_use]
+    pub const fn goal(&self) -> Option<u32> {
+        self.goal
+    }
+
+    /// Set or remove the weekly goal of completed katas for the team.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_goal(&mut self, goal: Option<u32>) -> Result<bool> {
+        if self.goal == goal {
+            Ok(false)
+        } else {
+            self.goal = goal;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the currently running competition.
+    #[must_use]
+    pub const fn competition(&self) -> Option<&Competition> {
+        self.competition.as_ref()
+    }
+
+    /// Start a new competition, unless another one is still running.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn start_competition(&mut self, competition: Competition) -> Result<bool> {
+        if self.competition.is_some() {
+            Ok(false)
+        } else {
+            self.competition = Some(competition);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// End the currently running competition and return it, if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn end_competition(&mut self) -> Result<Option<Competition>> {
+        let competition = self.competition.take();
+        if competition.is_some() {
+            self.save().await?;
+        }
+        Ok(competition)
+    }
+
+    /// Get the currently running poll.
+    #[must_use]
+    pub const fn poll(&self) -> Option<&Poll> {
+        self.poll.as_ref()
+    }
+
+    /// Start a new poll, unless another one is still running.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn start_poll(&mut self, poll: Poll) -> Result<bool> {
+        if self.poll.is_some() {
+            Ok(false)
+        } else {
+            self.poll = Some(poll);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Record the vote of a Slack user in the running poll, replacing any previous vote of the
+    /// same user. The `choice` must be a valid index into the candidates.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn vote(&mut self, user: &str, choice: usize) -> Result<bool> {
+        match &mut self.poll {
+            Some(poll) if poll.votes.get(user) != Some(&choice) => {
+                poll.votes.insert(user.to_owned(), choice);
+                self.save().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// End the currently running poll and return it, if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn end_poll(&mut self) -> Result<Option<Poll>> {
+        let poll = self.poll.take();
+        if poll.is_some() {
+            self.save().await?;
+        }
+        Ok(poll)
+    }
+
+    /// Get the ID of the current kata of the week.
+    #[must_use]
+    pub fn kata_of_the_week(&self) -> Option<&str> {
+        self.kata_of_the_week.as_deref()
+    }
+
+    /// Set the ID of the new kata of the week.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_kata_of_the_week(&mut self, id: String) -> Result<bool> {
+        if self.kata_of_the_week.as_ref() == Some(&id) {
+            Ok(false)
+        } else {
+            self.kata_of_the_week = Some(id);
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the honor of each watched user at the time of the last scheduled stats run.
+    #[must_use]
+    pub const fn honor(&self) -> &BTreeMap<String, u32> {
+        &self.honor
+    }
+
+    /// Set the honor of each watched user for the last run of scheduled stats.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn set_honor(&mut self, honor: BTreeMap<String, u32>) -> Result<bool> {
+        if self.honor == honor {
+            Ok(false)
+        } else {
+            self.honor = honor;
+            self.save().await?;
+            Ok(true)
+        }
+    }
+
+    /// Get the most recently executed commands, oldest first.
+    #[must_use]
+    pub fn audit(&self) -> &[AuditEntry] {
+        &self.audit
+    }
+
+    /// Add an executed command to the audit log, dropping the oldest entries once the log is full.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn record_audit(&mut self, entry: AuditEntry) -> Result<()> {
+        self.audit.push(entry);
+        let overflow = self.audit.len().saturating_sub(AUDIT_LIMIT);
+        self.audit.drain(..overflow);
+
+        self.save().await
+    }
+
+    /// Get all messages that wait for another delivery attempt.
+    #[must_use]
+    pub fn outbox(&self) -> &[QueuedMessage] {
+        &self.outbox
+    }
+
+    /// Add a message to the outbox, to deliver it again at a later time.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn enqueue(
+        &mut self,
+        url: Url,
+        text: String,
+        attempts: u8,
+        next_attempt: DateTime<Utc>,
+    ) -> Result<()> {
+        let id = self
+            .outbox
+            .iter()
+            .map(|m| m.id + 1)
+            .max()
+            .unwrap_or_default();
+        self.outbox.push(QueuedMessage {
+            id,
+            url,
+            text,
+            attempts,
+            next_attempt,
+        });
+        self.save().await
+    }
+
+    /// Remove a message from the outbox, either because it was delivered or is given up.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn dequeue(&mut self, id: u64) -> Result<()> {
+        self.outbox.retain(|m| m.id != id);
+        self.save().await
+    }
+
+    /// Count another failed delivery attempt for a message in the outbox and set the time for the
+    /// next attempt.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the change can't be saved to the state file.
+    pub async fn postpone(&mut self, id: u64, next_attempt: DateTime<Utc>) -> Result<()> {
+        if let Some(message) = self.outbox.iter_mut().find(|m| m.id == id) {
+            message.attempts += 1;
+            message.next_attempt = next_attempt;
+        }
+        self.save().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(STATE_FILE);
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert!(repo.add_user("him", None).await.unwrap());
+        let webhook = Url::parse("https://example.com/hook").unwrap();
+        assert!(repo.add_webhook(webhook.clone()).await.unwrap());
+        assert!(!repo.add_webhook(webhook.clone()).await.unwrap());
+
+        let mut repo = Repository::load(path).await.unwrap();
+        assert_eq!(vec!["him"], repo.users().collect::<Vec<_>>());
+        assert_eq!(std::slice::from_ref(&webhook), repo.webhooks());
+        assert!(repo.remove_webhook(&webhook).await.unwrap());
+        assert!(repo.webhooks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn usernames_ignore_case() {
+        let mut repo = Repository::in_memory();
+        assert!(repo.add_user(" KazK ", None).await.unwrap());
+        assert!(!repo.add_user("kazk", None).await.unwrap());
+        assert!(repo.add_user("Ame\u{301}lie", None).await.unwrap());
+        assert!(!repo.add_user("am\u{e9}lie", None).await.unwrap());
+        assert_eq!(
+            vec!["Am\u{e9}lie", "KazK"],
+            repo.users().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("KazK"), repo.find_user("KAZK"));
+
+        assert!(repo.remove_user("kazk").await.unwrap());
+        assert!(repo.find_user("KazK").is_none());
+    }
+
+    #[tokio::test]
+    async fn limit_first_completions() {
+        let mut repo = Repository::in_memory();
+        repo.add_user("him", None).await.unwrap();
+        let start = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+        let ids = (0..=FIRST_COMPLETIONS_LIMIT)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+
+        let completions = ids.iter().enumerate().map(|(i, id)| {
+            (
+                id.as_str(),
+                start + chrono::Duration::minutes(i.try_into().unwrap()),
+            )
+        });
+        assert!(repo.record_completions("him", completions).await.unwrap());
+
+        let known = &repo.first_completions()["him"];
+        assert_eq!(FIRST_COMPLETIONS_LIMIT, known.len());
+        assert!(!known.contains_key("0"));
+        assert!(known.contains_key("1"));
+
+        assert!(repo.remove_user("him").await.unwrap());
+        assert!(repo.first_completions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn limit_audit() {
+        let mut repo = Repository::in_memory();
+        for i in 0..=AUDIT_LIMIT {
+            repo.record_audit(AuditEntry {
+                at: Utc::now(),
+                user: "U01".to_owned(),
+                command: format!("vote {i}"),
+                outcome: Outcome::Success,
+            })
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(AUDIT_LIMIT, repo.audit().len());
+        assert_eq!("vote 1", repo.audit()[0].command);
+    }
+
+    #[tokio::test]
+    async fn rename_user() {
+        let mut repo = Repository::in_memory();
+        assert!(repo.add_user("him", Some("U01")).await.unwrap());
+        assert!(repo.add_user("her", None).await.unwrap());
+        assert!(repo
+            .set_honor(BTreeMap::from([("him".to_owned(), 10)]))
+            .await
+            .unwrap());
+
+        assert!(repo.rename_user("HIM", "Him2").await.unwrap());
+        assert_eq!(vec!["Him2", "her"], repo.users().collect::<Vec<_>>());
+        assert_eq!(
+            Some("U01"),
+            repo.user_info("Him2").unwrap().added_by.as_deref()
+        );
+        assert_eq!(Some(&10), repo.honor().get("Him2"));
+
+        assert!(repo.rename_user("Him2", "him2").await.unwrap());
+        assert!(!repo.rename_user("him2", "HER").await.unwrap());
+        assert!(!repo.rename_user("nobody", "someone").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn migrate_usernames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        fs::write(
+            &path,
+            r#"
+            users = ["KazK", "kazk", " him "]
+
+            [display_names]
+            kazk = "Kaz"
+            "#,
+        )
+        .await
+        .unwrap();
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert_eq!(vec!["KazK", "him"], repo.users().collect::<Vec<_>>());
+        assert_eq!(Some(&"Kaz".to_owned()), repo.display_names().get("KazK"));
+
+        assert!(repo.add_user("her", Some("U01")).await.unwrap());
+        let content = fs::read_to_string(path).await.unwrap();
+        assert!(!content.contains("kazk"), "{content}");
+        assert!(
+            content.contains("[users.KazK]\ndisplay_name = 'Kaz'\n"),
+            "{content}"
+        );
+
+        let her = repo.user_info("her").unwrap();
+        assert_eq!(Some("U01"), her.added_by.as_deref());
+        assert!(her.added_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+
+        let mut repo = Repository::in_memory();
+        assert!(repo.add_user("him", None).await.unwrap());
+        repo.reload().await.unwrap();
+        assert_eq!(vec!["him"], repo.users().collect::<Vec<_>>());
+        assert_eq!(1, repo.schedules().len());
+
+        let mut repo = Repository::load(path.clone()).await.unwrap().detach();
+        assert!(repo.add_user("her", None).await.unwrap());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn batch_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        let repo = Arc::new(RwLock::new(Repository::load(path.clone()).await.unwrap()));
+        tokio::spawn(save_batched(repo.clone(), Duration::from_millis(100)));
+        tokio::task::yield_now().await;
+
+        assert!(repo.write().await.add_user("him", None).await.unwrap());
+        assert!(repo.write().await.add_user("her", None).await.unwrap());
+        assert!(!path.exists());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let saved = Repository::load(path.clone()).await.unwrap();
+        assert_eq!(vec!["her", "him"], saved.users().collect::<Vec<_>>());
+
+        let mut repo = repo.write().await;
+        assert!(repo.remove_user("him").await.unwrap());
+        repo.flush().await.unwrap();
+        let saved = Repository::load(path).await.unwrap();
+        assert_eq!(vec!["her"], saved.users().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn multiple_schedules() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        let friday = Schedule {
+            frequency: Frequency::Weekly(Weekday::Fri),
+            time: NaiveTime::from_hms(16, 0, 0),
+        };
+        let monthly = Schedule {
+            frequency: Frequency::Monthly(1),
+            time: NaiveTime::from_hms(9, 0, 0),
+        };
+        let daily = Schedule {
+            frequency: Frequency::Daily,
+            time: NaiveTime::from_hms(8, 30, 0),
+        };
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert_eq!([Schedule::default()], repo.schedules());
+        assert!(repo.add_schedule(friday).await.unwrap());
+        assert!(!repo.add_schedule(friday).await.unwrap());
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert_eq!([Schedule::default(), friday], repo.schedules());
+        assert_eq!(
+            Some(Schedule::default()),
+            repo.remove_schedule(0).await.unwrap()
+        );
+        assert_eq!(None, repo.remove_schedule(1).await.unwrap());
+        assert_eq!(Some(friday), repo.remove_schedule(0).await.unwrap());
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert!(repo.schedules().is_empty());
+        assert!(repo.add_schedule(monthly).await.unwrap());
+        assert!(repo.add_schedule(daily).await.unwrap());
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert_eq!([monthly, daily], repo.schedules());
+        assert_eq!("monthly on day 1 at 09:00", monthly.to_string());
+        assert!(repo
+            .set_schedules(vec![daily, friday, daily])
+            .await
+            .unwrap());
+        assert!(!repo.set_schedules(vec![daily, friday]).await.unwrap());
+
+        let repo = Repository::load(path.clone()).await.unwrap();
+        assert_eq!([daily, friday], repo.schedules());
+
+        fs::write(
+            &path,
+            "[schedule]\nweekday = \"Fri\"\ntime = \"16:00:00\"\n",
+        )
+        .await
+        .unwrap();
+        let repo = Repository::load(path).await.unwrap();
+        assert_eq!([friday], repo.schedules());
+    }
+
+    #[tokio::test]
+    async fn migrate_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+
+        fs::write(&path, "users = [\"him\"]\n").await.unwrap();
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        assert!(repo.add_user("her", None).await.unwrap());
+        let content = fs::read_to_string(&path).await.unwrap();
+        assert!(
+            content.starts_with(&format!("version = {VERSION}\n")),
+            "{content}"
+        );
+
+        fs::write(&path, format!("version = {}\n", VERSION + 1))
+            .await
+            .unwrap();
+        let err = Repository::load(path.clone()).await.unwrap_err();
+        assert!(err.to_string().contains("only supports up to"), "{err}");
+
+        fs::write(&path, "version = -1\n").await.unwrap();
+        assert!(Repository::load(path).await.is_err());
+    }
+
+    #[test]
+    fn notify_filter_matches() {
+        let filter = NotifyFilter {
+            min_kyu: Some(5),
+            languages: BTreeSet::from(["rust".to_owned()]),
+        };
+        let rust = HashSet::from(["rust".to_owned(), "go".to_owned()]);
+        let go = HashSet::from(["go".to_owned()]);
+
+        assert!(filter.matches(Some(Level::Kyu(5)), &rust));
+        assert!(filter.matches(Some(Level::Kyu(2)), &rust));
+        assert!(filter.matches(Some(Level::Dan(1)), &rust));
+        assert!(filter.matches(None, &rust));
+        assert!(!filter.matches(Some(Level::Kyu(6)), &rust));
+        assert!(!filter.matches(Some(Level::Kyu(4)), &go));
+        assert!(NotifyFilter::default().matches(Some(Level::Kyu(8)), &go));
+    }
+
+    #[test]
+    fn quiet_hours_span_midnight() {
+        let night = QuietHours {
+            start: NaiveTime::from_hms(22, 0, 0),
+            end: NaiveTime::from_hms(8, 0, 0),
+        };
+        assert!(night.contains(NaiveTime::from_hms(23, 30, 0)));
+        assert!(night.contains(NaiveTime::from_hms(3, 0, 0)));
+        assert!(!night.contains(NaiveTime::from_hms(8, 0, 0)));
+        assert!(!night.contains(NaiveTime::from_hms(12, 0, 0)));
+
+        let lunch = QuietHours {
+            start: NaiveTime::from_hms(12, 0, 0),
+            end: NaiveTime::from_hms(13, 0, 0),
+        };
+        assert!(lunch.contains(NaiveTime::from_hms(12, 30, 0)));
+        assert!(!lunch.contains(NaiveTime::from_hms(23, 0, 0)));
+    }
+
+    #[tokio::test]
+    async fn outbox_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        let url = Url::parse("https://example.com/hook").unwrap();
+
+        let mut repo = Repository::load(path.clone()).await.unwrap();
+        repo.enqueue(url.clone(), "first".to_owned(), 0, Utc::now())
+            .await
+            .unwrap();
+        repo.enqueue(url, "second".to_owned(), 0, Utc::now())
+            .await
+            .unwrap();
+        repo.dequeue(0).await.unwrap();
+        repo.postpone(1, Utc::now()).await.unwrap();
+
+        let repo = Repository::load(path).await.unwrap();
+        assert_eq!(1, repo.outbox().len());
+        assert_eq!("second", repo.outbox()[0].text);
+        assert_eq!(1, repo.outbox()[0].attempts);
+    }
 }
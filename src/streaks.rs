@@ -0,0 +1,219 @@
+//! Daily and weekly solving streaks of each user, derived from the completion times of their
+//! challenges. A streak stays alive until the end of the next day or week, so it only breaks once a
+//! whole day or week passed without any completed challenge.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use anyhow::Result;
+use chrono::{prelude::*, Duration};
+
+use crate::{api::codewars::CompletedChallenge, fetch::FetchCache, mrkdwn};
+
+/// Minimum length of a daily streak to warn about losing it.
+const MIN_WARN_DAYS: u32 = 3;
+
+/// Current and best length of a single kind of streak.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Streak {
+    /// Length of the streak that is still alive.
+    pub current: u32,
+    /// Length of the longest streak ever.
+    pub best: u32,
+}
+
+/// Daily and weekly streaks of a user.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Streaks {
+    /// Consecutive days with at least one completed challenge.
+    pub daily: Streak,
+    /// Consecutive weeks with at least one completed challenge.
+    pub weekly: Streak,
+}
+
+/// Get the local days on which any of the challenges was completed.
+pub fn days(challenges: &[CompletedChallenge]) -> BTreeSet<NaiveDate> {
+    challenges
+        .iter()
+        .map(|c| c.completed_at.with_timezone(&Local).date().naive_local())
+        .collect()
+}
+
+/// Calculate the streaks from the days with completed challenges, as of today.
+pub fn compute(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> Streaks {
+    let weeks = days.iter().map(|&day| week_start(day)).collect();
+
+    Streaks {
+        daily: streak(days, today, Duration::days(1)),
+        weekly: streak(&weeks, week_start(today), Duration::weeks(1)),
+    }
+}
+
+/// Tell the length of the daily streak if it ends today, because no challenge was completed yet.
+/// Short streaks are left out, as they aren't worth a warning.
+pub fn at_risk(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> Option<u32> {
+    if days.contains(&today) {
+        return None;
+    }
+
+    let current = compute(days, today).daily.current;
+    (current >= MIN_WARN_DAYS).then_some(current)
+}
+
+/// Get the Monday of the week that the day is in.
+fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.weekday().num_days_from_monday().into())
+}
+
+/// Find the longest run of consecutive units, and the run that ends in the current or the
+/// previous unit.
+fn streak(units: &BTreeSet<NaiveDate>, current: NaiveDate, step: Duration) -> Streak {
+    let mut streak = Streak::default();
+    let mut run = 0;
+    let mut last = None;
+
+    for &unit in units {
+        run = if last.is_some_and(|last| unit - last == step) {
+            run + 1
+        } else {
+            1
+        };
+        streak.best = streak.best.max(run);
+        last = Some(unit);
+    }
+
+    if last.is_some_and(|last| last == current || last == current - step) {
+        streak.current = run;
+    }
+
+    streak
+}
+
+/// Render the streaks of all users that have any, for the weekly report.
+pub async fn summary(
+    fetch: &FetchCache,
+    users: &[String],
+    names: &BTreeMap<String, String>,
+) -> Result<String> {
+    let today = Local::today().naive_local();
+    let mut out = String::new();
+
+    for user in users {
+        let challenges = fetch.completed_challenges(user).await?;
+        let streaks = compute(&days(&challenges.data), today);
+
+        if streaks.weekly.best == 0 {
+            continue;
+        }
+
+        write!(
+            out,
+            "\n• {}: {}{} days (best {}), {} weeks (best {})",
+            mrkdwn::escape(names.get(user).unwrap_or(user)),
+            if streaks.daily.current >= MIN_WARN_DAYS {
+                ":fire: "
+            } else {
+                ""
+            },
+            streaks.daily.current,
+            streaks.daily.best,
+            streaks.weekly.current,
+            streaks.weekly.best,
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Create a warning for each user that is about to lose a daily streak today.
+pub async fn warnings(
+    fetch: &FetchCache,
+    users: &[String],
+    names: &BTreeMap<String, String>,
+) -> Result<Vec<String>> {
+    let today = Local::today().naive_local();
+    let mut warnings = Vec::new();
+
+    for user in users {
+        let challenges = fetch.completed_challenges(user).await?;
+        if let Some(current) = at_risk(&days(&challenges.data), today) {
+            warnings.push(format!(
+                ":hourglass_flowing_sand: {} is about to lose a {current} day streak, solve a kata \
+                 today to keep it alive!",
+                mrkdwn::escape(names.get(user).unwrap_or(user)),
+            ));
+        }
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2021, 3, d)
+    }
+
+    #[test]
+    fn current_and_best() {
+        let days = [1, 2, 3, 4, 10, 11, 12].into_iter().map(day).collect();
+
+        let streaks = compute(&days, day(13));
+        assert_eq!(
+            Streak {
+                current: 3,
+                best: 4
+            },
+            streaks.daily
+        );
+        assert_eq!(
+            Streak {
+                current: 2,
+                best: 2
+            },
+            streaks.weekly
+        );
+
+        let streaks = compute(&days, day(14));
+        assert_eq!(
+            Streak {
+                current: 0,
+                best: 4
+            },
+            streaks.daily
+        );
+        assert_eq!(
+            Streak {
+                current: 2,
+                best: 2
+            },
+            streaks.weekly
+        );
+
+        let streaks = compute(&days, day(29));
+        assert_eq!(
+            Streak {
+                current: 0,
+                best: 2
+            },
+            streaks.weekly
+        );
+    }
+
+    #[test]
+    fn warn_about_ending_streaks() {
+        let days = [1, 2, 3].into_iter().map(day).collect();
+
+        assert_eq!(Some(3), at_risk(&days, day(4)));
+        assert_eq!(None, at_risk(&days, day(3)));
+        assert_eq!(None, at_risk(&days, day(5)));
+        assert_eq!(
+            None,
+            at_risk(&[2, 3].into_iter().map(day).collect(), day(4))
+        );
+    }
+}
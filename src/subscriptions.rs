@@ -0,0 +1,165 @@
+//! Personal subscriptions, that keep members up to date about the progress of their own Codewars
+//! account with direct messages, regardless of whether the team's notifications are turned on.
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{
+    api::codewars::{CompletedChallenge, Level},
+    dispatch::Dispatcher,
+    fetch::FetchCache,
+    lease::Leader,
+    ranks,
+    storage::{Repository, Subscription},
+};
+
+/// Interval to check the subscribed accounts for progress.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Periodically check the accounts of all subscribed members and send them their progress. Only
+/// the leader checks them, so nobody gets the same message twice.
+pub async fn run(
+    repo: Arc<RwLock<Repository>>,
+    fetch: Arc<FetchCache>,
+    dispatcher: Arc<Dispatcher>,
+    leader: Leader,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let subscriptions = {
+            let repo = repo.read().await;
+            if repo.maintenance() || !leader.is_leader() {
+                continue;
+            }
+            repo.subscriptions().clone()
+        };
+
+        for (member, subscription) in subscriptions {
+            if let Err(e) = check(&repo, &fetch, &dispatcher, &member, &subscription).await {
+                warn!("Error checking the subscription of {}: {:#}", member, e);
+            }
+        }
+    }
+}
+
+/// Check a single subscription for newly completed challenges and rank ups, and send them to the
+/// member.
+async fn check(
+    repo: &RwLock<Repository>,
+    fetch: &FetchCache,
+    dispatcher: &Dispatcher,
+    member: &str,
+    subscription: &Subscription,
+) -> Result<()> {
+    let user = fetch.user(&subscription.username).await?;
+    let challenges = fetch.completed_challenges(&subscription.username).await?;
+    let rank = user.ranks.overall.rank;
+
+    let completed = challenges
+        .data
+        .iter()
+        .filter(|c| c.completed_at > subscription.checked_at)
+        .collect::<Vec<_>>();
+
+    if completed.is_empty() && subscription.rank == Some(rank) {
+        return Ok(());
+    }
+
+    if let Some(msg) = render(&completed, subscription.rank, rank) {
+        dispatcher.send_to_user(member, &msg).await?;
+    }
+
+    // Fetched challenges can be slightly outdated, so the check continues after the latest one
+    // that was reported instead of the current time.
+    let checked_at = completed
+        .iter()
+        .map(|c| c.completed_at)
+        .max()
+        .unwrap_or(subscription.checked_at);
+
+    repo.write()
+        .await
+        .update_subscription(member, checked_at, rank)
+        .await
+}
+
+/// Render the progress of a subscribed account since the last check, or nothing if there is none.
+fn render(
+    completed: &[&CompletedChallenge],
+    previous: Option<Level>,
+    rank: Level,
+) -> Option<String> {
+    let mut msg = String::new();
+
+    if !completed.is_empty() {
+        msg.push_str(":tada: You completed new katas on Codewars:");
+        for challenge in completed {
+            let name = challenge
+                .name
+                .as_deref()
+                .or(challenge.slug.as_deref())
+                .unwrap_or(&challenge.id);
+            let mut languages = challenge
+                .completed_languages
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            languages.sort_unstable();
+
+            write!(
+                msg,
+                "\n• *{}* in {}",
+                crate::mrkdwn::escape(name),
+                languages.join(", ")
+            )
+            .ok();
+        }
+    }
+
+    if previous.is_some_and(|previous| rank > previous) {
+        if !msg.is_empty() {
+            msg.push_str("\n\n");
+        }
+        write!(
+            msg,
+            ":arrow_up: You ranked up to {} {}, congratulations!",
+            ranks::emoji(rank.color()),
+            rank
+        )
+        .ok();
+    }
+
+    (!msg.is_empty()).then_some(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn render_progress() {
+        let challenge = CompletedChallenge {
+            id: "1".to_owned(),
+            name: Some("Multiply".to_owned()),
+            slug: Some("multiply".to_owned()),
+            completed_at: Utc::now(),
+            completed_languages: ["rust".to_owned(), "go".to_owned()].into(),
+        };
+
+        assert_eq!(None, render(&[], None, Level::Kyu(5)));
+        assert_eq!(None, render(&[], Some(Level::Kyu(5)), Level::Kyu(5)));
+
+        let msg = render(&[&challenge], Some(Level::Kyu(6)), Level::Kyu(5)).unwrap();
+        assert!(msg.contains("• *Multiply* in go, rust"), "{msg}");
+        assert!(msg.contains("You ranked up to"), "{msg}");
+        assert!(msg.ends_with("5 kyu, congratulations!"), "{msg}");
+    }
+}
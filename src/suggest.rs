@@ -0,0 +1,94 @@
+//! Suggestions of katas that none of the tracked users solved yet, to give the team a shared
+//! challenge of the day.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use chrono::prelude::*;
+use tracing::warn;
+
+use crate::{
+    api::codewars::{CodeChallenge, Level},
+    fetch::FetchCache,
+};
+
+/// Pick up to `count` katas that none of the users solved yet, optionally limited to a range of
+/// kyu. Candidates are the katas authored by the users and the configured pool of katas, filtered by
+/// the languages that the users solved challenges in before.
+///
+/// The same katas are picked for the whole day, as long as the candidates don't change.
+pub async fn suggest(
+    fetch: &FetchCache,
+    users: &[String],
+    pool: &[String],
+    kyu: Option<RangeInclusive<u8>>,
+    count: usize,
+) -> Result<Vec<Arc<CodeChallenge>>> {
+    let mut solved = HashSet::new();
+    let mut languages = HashSet::new();
+
+    for user in users {
+        for challenge in &fetch.completed_challenges(user).await?.data {
+            solved.insert(challenge.id.clone());
+            languages.extend(challenge.completed_languages.iter().cloned());
+        }
+    }
+
+    let fits = |rank: Level, kata_languages: &HashSet<String>| {
+        kyu.as_ref()
+            .is_none_or(|kyu| matches!(rank, Level::Kyu(rank) if kyu.contains(&rank)))
+            && (languages.is_empty() || !kata_languages.is_disjoint(&languages))
+    };
+
+    let mut candidates = BTreeSet::new();
+
+    for user in users {
+        match fetch.client().authored_challenges(user).await {
+            Ok(authored) => candidates.extend(
+                authored
+                    .data
+                    .into_iter()
+                    .filter(|kata| fits(kata.rank, &kata.languages))
+                    .map(|kata| kata.id),
+            ),
+            Err(e) => warn!("Error fetching authored challenges of {}: {}", user, e),
+        }
+    }
+
+    for slug in pool {
+        match fetch.code_challenge(slug).await {
+            Ok(kata) if fits(kata.rank.id, &kata.languages) => {
+                candidates.insert(kata.id.clone());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Error fetching suggested kata {}: {}", slug, e),
+        }
+    }
+
+    let candidates = candidates
+        .into_iter()
+        .filter(|id| !solved.contains(id))
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let day = usize::try_from(Utc::now().num_days_from_ce()).unwrap_or_default();
+    let mut katas = Vec::with_capacity(count);
+
+    for id in candidates
+        .iter()
+        .cycle()
+        .skip(day % candidates.len())
+        .take(count.min(candidates.len()))
+    {
+        katas.push(fetch.code_challenge(id).await?);
+    }
+
+    Ok(katas)
+}
@@ -0,0 +1,574 @@
+//! Scheduled tasks of the bot, like the stats report, notifications about completed challenges,
+//! streak reminders and the year in review. Tasks are skipped during maintenance and on standby.
+
+use std::{fmt::Write, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration};
+use codewars_bot::{scheduling, storage};
+use reqwest::Client;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::{
+    activity::{Feed, ReportKind},
+    builtin, chart,
+    dispatch::{Dispatcher, Route},
+    federation::{self, Rival},
+    fetch::FetchCache,
+    goal,
+    lease::Leader,
+    mrkdwn::{self, escape},
+    scheduling::Frequency,
+    stats::{self, Period},
+    storage::{NotifyFilter, Repository},
+    streaks,
+};
+
+/// Outcome of a stats report.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Report {
+    /// The report was sent.
+    Sent,
+    /// The report was skipped, for the given reason.
+    Skipped(&'static str),
+}
+
+#[derive(Clone)]
+pub struct StatsTask {
+    pub client: Client,
+    pub fetch: Arc<FetchCache>,
+    pub repo: Arc<RwLock<Repository>>,
+    pub dispatcher: Arc<Dispatcher>,
+    pub rival: Option<Arc<Rival>>,
+    /// Channel to upload the chart of each report to.
+    pub chart_channel: Option<String>,
+    pub feed: Feed,
+    pub leader: Leader,
+}
+
+impl StatsTask {
+    /// Collect and send the stats since the last run. If `mark` is set, the current time is saved
+    /// as the new last run time afterwards. Nothing is sent during maintenance or on standby.
+    pub async fn report(&self, mark: bool) -> Result<Report> {
+        let start_time = Utc::now();
+        let since = {
+            let repo = self.repo.read().await;
+            if repo.maintenance() {
+                info!("Skipping stats report during maintenance");
+                return Ok(Report::Skipped("the bot is under maintenance"));
+            }
+            if !self.leader.is_leader() {
+                info!("Skipping stats report on standby");
+                return Ok(Report::Skipped("this instance is on standby"));
+            }
+            repo.last_run().map(|dt| dt.naive_local())
+        };
+
+        let stats =
+            stats::collect_tracked(&self.repo, &self.fetch, Period::since(since), None, None)
+                .await?;
+        let mut msg = stats::render(&stats)?;
+
+        match streak_summary(&self.repo, &self.fetch).await {
+            Ok(summary) if !summary.is_empty() => write!(msg, "\n\n*Streaks:*{summary}")?,
+            Ok(_) => {}
+            Err(e) => warn!("Error collecting streaks: {}", e),
+        }
+
+        match goal_progress(&self.repo, &self.fetch).await {
+            Ok(Some(progress)) => write!(msg, "\n\n*Team goal:* {progress}")?,
+            Ok(None) => {}
+            Err(e) => warn!("Error collecting progress of the team goal: {}", e),
+        }
+
+        let kata_of_the_week = self
+            .repo
+            .read()
+            .await
+            .kata_of_the_week()
+            .map(ToOwned::to_owned);
+        if let Some(id) = kata_of_the_week {
+            match self.fetch.code_challenge(&id).await {
+                Ok(kata) => write!(
+                    msg,
+                    "\n\n*Kata of the week:* <{}|{}>",
+                    kata.url,
+                    escape(&kata.name)
+                )?,
+                Err(e) => warn!("Error fetching the kata of the week: {}", e),
+            }
+        }
+
+        let summary = match federation::collect(&self.repo, &self.fetch).await {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                warn!("Error collecting team summary: {}", e);
+                None
+            }
+        };
+
+        if let (Some(own), Some(rival)) = (&summary, &self.rival) {
+            match federation::fetch(&self.client, rival).await {
+                Ok(other) => msg.push_str(&federation::compare(own, &rival.name, &other)?),
+                Err(e) => warn!("Error fetching summary of {}: {}", rival.name, e),
+            }
+        }
+
+        self.dispatcher.send_routed(Route::Stats, &msg).await;
+        self.feed.publish_report(ReportKind::Stats, &msg);
+
+        if let Some(channel) = &self.chart_channel {
+            if let Err(e) = self.upload_chart(channel, &stats).await {
+                warn!("Error uploading the report chart: {:#}", e);
+            }
+        }
+
+        if mark {
+            let mut repo = self.repo.write().await;
+            repo.set_last_run(start_time).await?;
+            if let Some(summary) = summary {
+                repo.set_honor(summary.honor).await?;
+            }
+        }
+
+        Ok(Report::Sent)
+    }
+}
+
+impl StatsTask {
+    /// Upload a chart of the challenges that each user completed within the report's period.
+    /// Nothing is uploaded if nobody completed any challenge.
+    async fn upload_chart(&self, channel: &str, stats: &stats::Stats) -> Result<()> {
+        let counts = stats
+            .users
+            .iter()
+            .map(|user| {
+                let label = user
+                    .display_name
+                    .as_deref()
+                    .map_or_else(|| format!("`{}`", mrkdwn::code(&user.username)), escape);
+                (label, user.challenges.len())
+            })
+            .collect::<Vec<_>>();
+
+        if counts.iter().all(|(_, count)| *count == 0) {
+            return Ok(());
+        }
+
+        self.dispatcher
+            .upload(
+                channel,
+                "report.png",
+                chart::render(&counts)?,
+                &chart::legend(&counts)?,
+            )
+            .await
+    }
+}
+
+#[async_trait]
+impl scheduling::Task for StatsTask {
+    fn name() -> &'static str {
+        "stats"
+    }
+
+    async fn run(&self) {
+        if let Err(e) = self.report(true).await {
+            error!("Error running scheduled stats: {}", e);
+        }
+    }
+}
+
+pub struct NotifyTask {
+    pub fetch: Arc<FetchCache>,
+    pub repo: Arc<RwLock<Repository>>,
+    pub dispatcher: Arc<Dispatcher>,
+    pub feed: Feed,
+    pub leader: Leader,
+}
+
+#[async_trait]
+impl scheduling::Task for NotifyTask {
+    fn name() -> &'static str {
+        "notify"
+    }
+
+    async fn run(&self) {
+        let (digest, filter) = {
+            let repo = self.repo.read().await;
+            if repo.maintenance() {
+                info!("Skipping notifications during maintenance");
+                return;
+            }
+            if !self.leader.is_leader() {
+                info!("Skipping notifications on standby");
+                return;
+            }
+            if repo.is_quiet(Utc::now()) {
+                info!("Holding back notifications");
+                (None, None)
+            } else {
+                (
+                    repo.digest_hours()
+                        .map(|hours| (hours, repo.digest_sent_at())),
+                    Some(repo.notify_filter().clone()),
+                )
+            }
+        };
+
+        let Some(filter) = filter else {
+            // Digests cover everything since the last one anyway, so only lists need to remember
+            // when they were first held back.
+            if digest.is_none() {
+                let mut repo = self.repo.write().await;
+                if repo.held_since().is_none() {
+                    if let Err(e) = repo.set_held_since(Some(Utc::now())).await {
+                        error!("Error saving held back notifications: {}", e);
+                    }
+                }
+            }
+            return;
+        };
+
+        if let Some((hours, sent_at)) = digest {
+            if let Err(e) = self.send_digest(hours, sent_at).await {
+                error!("Error sending notification digest: {}", e);
+            }
+            return;
+        }
+
+        if let Err(e) = self.send_list(&filter).await {
+            error!("Error collecting stats for notification: {}", e);
+        }
+    }
+}
+
+impl NotifyTask {
+    /// Send the list of challenges completed since the last check. If notifications were held
+    /// back before, all challenges since the first held back check are sent in one batch.
+    async fn send_list(&self, filter: &NotifyFilter) -> Result<()> {
+        let (held_since, interval) = {
+            let repo = self.repo.read().await;
+            (repo.held_since(), repo.notify_interval())
+        };
+        let since = held_since.map_or_else(Local::now, |held| held.with_timezone(&Local))
+            - Duration::hours(interval.into());
+
+        let stats = stats::collect_tracked(
+            &self.repo,
+            &self.fetch,
+            Period::since(Some(since.naive_local())),
+            None,
+            Some(filter),
+        )
+        .await?;
+        self.feed.publish_completions(&stats);
+
+        let msg = stats::render(&stats)?;
+        self.dispatcher.send_routed(Route::Notify, &msg).await;
+        self.feed.publish_report(ReportKind::Notification, &msg);
+
+        self.repo.write().await.set_held_since(None).await
+    }
+
+    /// Send a digest of all challenges completed since the last one, if the digest interval
+    /// passed. Nothing is sent if nobody completed any challenge in the meantime.
+    async fn send_digest(&self, hours: u32, sent_at: Option<DateTime<Utc>>) -> Result<()> {
+        let now = Utc::now();
+        let Some(since) = digest_window(now, hours, sent_at) else {
+            return Ok(());
+        };
+
+        if let Some(msg) = digest(&self.repo, &self.fetch, since).await {
+            self.dispatcher.send_routed(Route::Notify, &msg).await;
+            self.feed.publish_report(ReportKind::Digest, &msg);
+        }
+
+        self.repo.write().await.set_digest_sent_at(now).await
+    }
+}
+
+/// Start of the period that the next digest covers, if the digest interval passed since the last
+/// one was sent. Without any digest sent yet, it covers the last interval.
+fn digest_window(
+    now: DateTime<Utc>,
+    hours: u32,
+    sent_at: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    let interval = Duration::hours(hours.into());
+    let since = sent_at.unwrap_or_else(|| now - interval);
+
+    // Checks happen at fixed intervals, so a little tolerance avoids skipping a whole check
+    // interval when the timer fires slightly early.
+    (now - since + Duration::minutes(1) >= interval).then_some(since)
+}
+
+/// Daily check for users that are about to lose their solving streak, to remind them in the
+/// evening.
+pub struct StreakTask {
+    pub fetch: Arc<FetchCache>,
+    pub repo: Arc<RwLock<Repository>>,
+    pub dispatcher: Arc<Dispatcher>,
+    pub leader: Leader,
+}
+
+#[async_trait]
+impl scheduling::Task for StreakTask {
+    fn name() -> &'static str {
+        "streaks"
+    }
+
+    async fn run(&self) {
+        let (users, names) = {
+            let repo = self.repo.read().await;
+            if repo.maintenance() || !repo.notify() || !self.leader.is_leader() {
+                return;
+            }
+
+            (
+                repo.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+                repo.display_names(),
+            )
+        };
+
+        match streaks::warnings(&self.fetch, &users, &names).await {
+            Ok(warnings) if !warnings.is_empty() => {
+                self.dispatcher
+                    .send_routed(Route::Notify, &warnings.join("\n"))
+                    .await;
+            }
+            Ok(_) => {}
+            Err(e) => error!("Error checking streaks: {}", e),
+        }
+    }
+}
+
+/// Annual task that sends the year in review for the past year, on the first day of the new year.
+pub struct ReviewTask {
+    pub fetch: Arc<FetchCache>,
+    pub repo: Arc<RwLock<Repository>>,
+    pub dispatcher: Arc<Dispatcher>,
+    pub leader: Leader,
+}
+
+#[async_trait]
+impl scheduling::Task for ReviewTask {
+    fn name() -> &'static str {
+        "review"
+    }
+
+    async fn run(&self) {
+        if self.repo.read().await.maintenance() || !self.leader.is_leader() {
+            info!("Skipping year in review");
+            return;
+        }
+
+        match builtin::review(&self.repo, &self.fetch, Local::now().year() - 1).await {
+            Ok(msg) => self.dispatcher.send_routed(Route::Stats, &msg).await,
+            Err(e) => error!("Error creating the year in review: {}", e),
+        }
+    }
+}
+
+/// Render the current and best streaks of all users.
+pub async fn streak_summary(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+) -> Result<String> {
+    let (users, names) = {
+        let settings = settings.read().await;
+        (
+            settings.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            settings.display_names(),
+        )
+    };
+
+    streaks::summary(fetch, &users, &names).await
+}
+
+/// Render the team's progress towards the weekly goal, if one is set.
+pub async fn goal_progress(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+) -> Result<Option<String>> {
+    let (target, users) = {
+        let settings = settings.read().await;
+        (
+            settings.goal(),
+            settings.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+        )
+    };
+
+    let Some(target) = target else {
+        return Ok(None);
+    };
+
+    let done = goal::completed(fetch, &users, goal::week_start(Local::now())).await?;
+
+    Ok(Some(goal::render(done, target)))
+}
+
+/// Create a digest of all challenges that the users completed since the given time, or nothing if
+/// there are none. Users whose challenges can't be fetched are left out.
+async fn digest(
+    settings: &Arc<RwLock<Repository>>,
+    fetch: &FetchCache,
+    since: DateTime<Utc>,
+) -> Option<String> {
+    let (users, names, filter) = {
+        let settings = settings.read().await;
+        (
+            settings.users().map(ToOwned::to_owned).collect::<Vec<_>>(),
+            settings.display_names(),
+            settings.notify_filter().clone(),
+        )
+    };
+
+    let mut total = 0;
+    let mut lines = Vec::new();
+
+    for user in &users {
+        let challenges = match fetch.completed_challenges(user).await {
+            Ok(challenges) => challenges,
+            Err(e) => {
+                warn!("Skipping user {} in the digest: {}", user, e);
+                continue;
+            }
+        };
+        let mut solved = Vec::new();
+
+        for c in challenges.data.iter().filter(|c| c.completed_at >= since) {
+            let rank = if filter.min_kyu.is_some() {
+                fetch
+                    .code_challenge(&c.id)
+                    .await
+                    .ok()
+                    .map(|kata| kata.rank.id)
+            } else {
+                None
+            };
+
+            if filter.matches(rank, &c.completed_languages) {
+                solved.push(escape(c.name.as_deref().unwrap_or(&c.id)));
+            }
+        }
+
+        if solved.is_empty() {
+            continue;
+        }
+
+        total += solved.len();
+        lines.push(format!(
+            "• *{}*: {}",
+            escape(names.get(user).unwrap_or(user)),
+            solved.join(", ")
+        ));
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        ":newspaper: *Digest:* {total} challenges completed by {} users since {}\n{}",
+        lines.len(),
+        since.with_timezone(&Local).format("%Y/%m/%d %H:%M"),
+        lines.join("\n")
+    ))
+}
+
+/// Frequency and time of every report, as input for the schedulers.
+pub fn schedule_inputs(settings: &Repository) -> Vec<(Frequency, NaiveTime)> {
+    settings
+        .schedules()
+        .iter()
+        .map(storage::Schedule::input)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dispatch::{WebhookKind, WebhookTarget},
+        fetch,
+    };
+
+    #[test]
+    fn digest_after_interval() {
+        let now = Utc.ymd(2020, 2, 12).and_hms(10, 0, 0);
+        let ago = |hours| Some(now - chrono::Duration::hours(hours));
+
+        assert_eq!(ago(24), digest_window(now, 24, None));
+        assert_eq!(ago(24), digest_window(now, 24, ago(24)));
+        assert_eq!(ago(30), digest_window(now, 24, ago(30)));
+        assert_eq!(None, digest_window(now, 24, ago(23)));
+        assert_eq!(
+            Some(now - chrono::Duration::seconds(3570)),
+            digest_window(now, 1, Some(now - chrono::Duration::seconds(3570)))
+        );
+    }
+
+    #[tokio::test]
+    async fn send_digest_without_failed_users() {
+        let (_server, fetch) = fetch::mock(&[(
+            "alice",
+            &[("1", "2020-02-12T09:00:00Z"), ("2", "2020-02-01T09:00:00Z")],
+        )])
+        .await;
+        let mut repo = Repository::in_memory();
+        repo.add_user("alice", None).await.unwrap();
+        repo.add_user("bob", None).await.unwrap();
+        let repo = Arc::new(RwLock::new(repo));
+
+        let since = Utc.ymd(2020, 2, 11).and_hms(10, 0, 0);
+        let msg = digest(&repo, &fetch, since).await.unwrap();
+        assert!(
+            msg.contains("1 challenges completed by 1 users since"),
+            "{msg}"
+        );
+        assert!(msg.ends_with("\n• *alice*: Kata 1"), "{msg}");
+
+        let task = NotifyTask {
+            fetch: Arc::new(fetch),
+            repo: repo.clone(),
+            dispatcher: Arc::new(
+                Dispatcher::new(
+                    Client::new(),
+                    [WebhookTarget {
+                        url: "http://127.0.0.1:9/unreachable".parse().unwrap(),
+                        kind: WebhookKind::Slack,
+                        retries: 0,
+                        route: None,
+                        channel: None,
+                    }],
+                    None,
+                    repo.clone(),
+                    0,
+                )
+                .dry_run(true),
+            ),
+            feed: Feed::new(),
+            leader: Leader::always(),
+        };
+        let sent = |task: &NotifyTask| {
+            task.dispatcher
+                .stats()
+                .into_iter()
+                .map(|(_, _, sent, _, _)| sent)
+                .sum::<u64>()
+        };
+
+        task.send_digest(24, Some(Utc::now() - chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        assert_eq!(0, sent(&task));
+        assert!(repo.read().await.digest_sent_at().is_none());
+
+        task.send_digest(24, Some(since)).await.unwrap();
+        assert_eq!(1, sent(&task));
+        assert!(repo.read().await.digest_sent_at().is_some());
+    }
+}
@@ -0,0 +1,87 @@
+//! Formatting of timestamps in messages, relative to the current time like `2 days ago`, which is
+//! easier to grasp than a plain date. Optionally Slack's date formatting is used, so every viewer
+//! sees the time in their own timezone.
+
+use std::sync::OnceLock;
+
+use chrono::prelude::*;
+use serde::Deserialize;
+
+/// Style of timestamps, set once at start up.
+static STYLE: OnceLock<Timestamps> = OnceLock::new();
+
+/// Amount of days after which the plain date is shown, instead of the relative time.
+const MAX_RELATIVE_DAYS: i64 = 28;
+
+/// How timestamps are shown in messages.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Timestamps {
+    /// Relative to the current time, like `2 days ago`.
+    #[default]
+    Relative,
+    /// Slack's date formatting, in the timezone of each viewer.
+    Slack,
+}
+
+/// Configure the style of timestamps. Can only be set once, further calls are ignored.
+pub fn set_style(style: Timestamps) {
+    STYLE.set(style).ok();
+}
+
+/// Format a timestamp for a message, relative to the current time. With Slack's date formatting
+/// enabled, the relative time is only the fallback for clients that can't show the date.
+pub fn format(at: DateTime<Utc>) -> String {
+    let text = relative(at, Utc::now());
+
+    if STYLE.get().copied().unwrap_or_default() == Timestamps::Slack {
+        format!(
+            "<!date^{}^{{date_short_pretty}} at {{time}}|{text}>",
+            at.timestamp()
+        )
+    } else {
+        text
+    }
+}
+
+/// Describe the time between the timestamp and now, like `3 hours ago`. Timestamps that are too
+/// far back are shown as plain date instead.
+pub fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now - at;
+
+    let (amount, unit) = if elapsed.num_minutes() < 1 {
+        return String::from("just now");
+    } else if elapsed.num_hours() < 1 {
+        (elapsed.num_minutes(), "minute")
+    } else if elapsed.num_days() < 1 {
+        (elapsed.num_hours(), "hour")
+    } else if elapsed.num_days() < 7 {
+        (elapsed.num_days(), "day")
+    } else if elapsed.num_days() < MAX_RELATIVE_DAYS {
+        (elapsed.num_weeks(), "week")
+    } else {
+        return format!("on {}", at.with_timezone(&Local).format("%Y/%m/%d"));
+    };
+
+    format!("{amount} {unit}{} ago", if amount == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn relative_times() {
+        let now = Utc.ymd(2020, 2, 12).and_hms(10, 0, 0);
+        let ago = |duration| relative(now - duration, now);
+
+        assert_eq!("just now", ago(Duration::seconds(30)));
+        assert_eq!("1 minute ago", ago(Duration::seconds(90)));
+        assert_eq!("5 hours ago", ago(Duration::hours(5)));
+        assert_eq!("2 days ago", ago(Duration::hours(50)));
+        assert_eq!("3 weeks ago", ago(Duration::days(21)));
+        assert!(ago(Duration::days(60)).starts_with("on 2019/12/1"));
+    }
+}
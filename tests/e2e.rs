@@ -0,0 +1,725 @@
+//! End-to-end tests that run the bot binary against a fake Slack. Signed `app_mention` events are
+//! posted to the bot's event endpoint, and the messages that it sends back are captured by a local
+//! mock server that acts as the webhook.
+
+use std::{
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tempfile::TempDir;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// Key that the bot uses to verify events, and the tests to sign them.
+const SIGNING_KEY: &str = "e2e-signing-key";
+/// Maximum time to wait for the bot to start up or send its messages.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running bot instance with its own state directory and webhook.
+struct Bot {
+    process: Child,
+    port: u16,
+    webhook: MockServer,
+    client: Client,
+    dir: TempDir,
+}
+
+impl Bot {
+    /// Start the bot with a fresh state and wait until it accepts connections.
+    async fn start() -> Self {
+        Self::start_with(&[]).await
+    }
+
+    /// Start the bot like [`Self::start`], with additional environment variables for its settings.
+    async fn start_with(env: &[(&str, &str)]) -> Self {
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/webhook"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&webhook)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/respond"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&webhook)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        // The working directory and environment are isolated, so no local settings or `.env` file
+        // is picked up.
+        let process = Command::new(env!("CARGO_BIN_EXE_codewars-bot"))
+            .arg("serve")
+            .current_dir(dir.path())
+            .env_clear()
+            .env("PORT", port.to_string())
+            .env("SIGNING_KEY", SIGNING_KEY)
+            .env("WEBHOOK_URL", format!("{}/webhook", webhook.uri()))
+            .env("STATE_PATH", dir.path().join("state.toml"))
+            .env("SYNC_INTERVAL_MINS", "0")
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let bot = Self {
+            process,
+            port,
+            webhook,
+            client: Client::new(),
+            dir,
+        };
+        bot.wait_ready().await;
+        bot
+    }
+
+    async fn wait_ready(&self) {
+        let start = Instant::now();
+        while self.client.get(self.url("/")).send().await.is_err() {
+            assert!(start.elapsed() < TIMEOUT, "bot didn't start in time");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Wait until the state file contains the given text, as changes are written with a delay.
+    async fn state_containing(&self, text: &str) -> String {
+        let start = Instant::now();
+        loop {
+            let state =
+                std::fs::read_to_string(self.dir.path().join("state.toml")).unwrap_or_default();
+            if state.contains(text) {
+                return state;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "state not saved in time:\n{state}"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Post a callback to the event endpoint, signed with the given key.
+    async fn post_signed(&self, key: &str, callback: &Value) -> Response {
+        self.post_body(key, "/event", serde_json::to_vec(callback).unwrap())
+            .await
+    }
+
+    /// Post a signed request with the given raw body to an endpoint of the bot.
+    async fn post_body(&self, key: &str, path: &str, body: Vec<u8>) -> Response {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(format!("v0:{timestamp}:").as_bytes());
+        mac.update(&body);
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+
+        self.client
+            .post(self.url(path))
+            .header("x-slack-signature", signature)
+            .header("x-slack-request-timestamp", timestamp)
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// Mention the bot with the given message, like a user would in Slack.
+    async fn mention(&self, text: &str) {
+        let status = self
+            .post_signed(
+                SIGNING_KEY,
+                &json!({
+                    "type": "event_callback",
+                    "event_id": "Ev01",
+                    "event": {
+                        "type": "app_mention",
+                        "user": "U01",
+                        "text": format!("<@UBOT> {text}"),
+                        "channel": "C01",
+                        "ts": "1700000000.000100",
+                    },
+                }),
+            )
+            .await
+            .status();
+
+        assert_eq!(StatusCode::OK, status);
+    }
+
+    /// Click a button of one of the bot's messages, like a user would in Slack. Responses to the
+    /// click are sent to the `/respond` path of the webhook server.
+    async fn click(&self, action_id: &str, value: &str) {
+        let payload = json!({
+            "type": "block_actions",
+            "user": { "id": "U01" },
+            "actions": [{ "action_id": action_id, "value": value }],
+            "response_url": format!("{}/respond", self.webhook.uri()),
+        });
+        let body = format!(
+            "payload={}",
+            url::form_urlencoded::byte_serialize(payload.to_string().as_bytes())
+                .collect::<String>()
+        );
+
+        let status = self
+            .post_body(SIGNING_KEY, "/interactive", body.into_bytes())
+            .await
+            .status();
+
+        assert_eq!(StatusCode::OK, status);
+    }
+
+    /// Wait until the webhook received the given amount of messages in total and return all of
+    /// them, oldest first.
+    async fn messages(&self, count: usize) -> Vec<String> {
+        let start = Instant::now();
+        loop {
+            let messages = self
+                .webhook
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .map(|req| {
+                    let body = req.body_json::<Value>().unwrap();
+                    body["text"].as_str().unwrap().to_owned()
+                })
+                .collect::<Vec<_>>();
+
+            if messages.len() >= count {
+                return messages;
+            }
+
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "expected {count} messages, but got {messages:?}"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+#[tokio::test]
+async fn url_verification() {
+    let bot = Bot::start().await;
+    let body = json!({
+        "type": "url_verification",
+        "token": "token",
+        "challenge": "e2e-challenge",
+    });
+
+    let resp = bot
+        .client
+        .post(bot.url("/event"))
+        .header("x-slack-signature", "v0=00")
+        .header("x-slack-request-timestamp", "0")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert!(!resp.status().is_success());
+
+    let resp = bot.post_signed(SIGNING_KEY, &body).await;
+    assert_eq!(StatusCode::OK, resp.status());
+    assert_eq!("e2e-challenge", resp.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn reject_wrong_signature() {
+    let bot = Bot::start().await;
+    let status = bot
+        .post_signed(
+            "wrong-key",
+            &json!({
+                "type": "event_callback",
+                "event_id": "Ev01",
+                "event": {
+                    "type": "app_mention",
+                    "user": "U01",
+                    "text": "<@UBOT> help",
+                    "channel": "C01",
+                    "ts": "1700000000.000100",
+                },
+            }),
+        )
+        .await
+        .status();
+
+    assert!(!status.is_success());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(bot.webhook.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn accept_previous_signing_key() {
+    let bot = Bot::start_with(&[("PREVIOUS_SIGNING_KEY", "e2e-previous-key")]).await;
+    let event = serde_json::to_vec(&json!({
+        "type": "url_verification",
+        "token": "token",
+        "challenge": "e2e-challenge",
+    }))
+    .unwrap();
+    let interaction = format!(
+        "payload={}",
+        url::form_urlencoded::byte_serialize(br#"{"type":"view_closed"}"#).collect::<String>()
+    )
+    .into_bytes();
+
+    for (path, body) in [("/event", event), ("/interactive", interaction)] {
+        for key in [SIGNING_KEY, "e2e-previous-key"] {
+            let status = bot.post_body(key, path, body.clone()).await.status();
+            assert_eq!(StatusCode::OK, status, "{path} signed with {key}");
+        }
+
+        let status = bot
+            .post_body("e2e-unknown-key", path, body.clone())
+            .await
+            .status();
+        assert!(!status.is_success(), "{path} signed with an unknown key");
+    }
+}
+
+#[tokio::test]
+async fn reply_to_help() {
+    let bot = Bot::start().await;
+    bot.mention("help").await;
+
+    let messages = bot.messages(1).await;
+    assert!(messages[0].contains("add <user>"), "{}", messages[0]);
+}
+
+#[tokio::test]
+async fn welcome_new_members() {
+    let bot = Bot::start().await;
+    let status = bot
+        .post_signed(
+            SIGNING_KEY,
+            &json!({
+                "type": "event_callback",
+                "event_id": "Ev02",
+                "event": {
+                    "type": "member_joined_channel",
+                    "user": "U02",
+                    "channel": "C01",
+                    "channel_type": "C",
+                },
+            }),
+        )
+        .await
+        .status();
+    assert_eq!(StatusCode::OK, status);
+
+    let messages = bot.messages(1).await;
+    assert!(messages[0].starts_with("Welcome <@U02>"), "{}", messages[0]);
+    assert!(
+        messages[0].contains("`add <codewars-user>`"),
+        "{}",
+        messages[0]
+    );
+}
+
+#[tokio::test]
+async fn accept_home_opened_with_view() {
+    let bot = Bot::start().await;
+
+    // Slack includes the currently published Home view, which easily exceeds a few kilobytes.
+    let blocks = (0..40)
+        .map(|i| {
+            json!({
+                "type": "section",
+                "block_id": format!("block{i}"),
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!(":white_check_mark: `user{i}` completed a few katas this week"),
+                    "verbatim": false,
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    let callback = json!({
+        "token": "XXYYZZ",
+        "team_id": "T01",
+        "api_app_id": "A01",
+        "type": "event_callback",
+        "event_id": "Ev03",
+        "event_time": 1_700_000_000,
+        "event": {
+            "type": "app_home_opened",
+            "user": "U01",
+            "channel": "D01",
+            "tab": "home",
+            "event_ts": "1700000000.000100",
+            "view": {
+                "id": "V01",
+                "team_id": "T01",
+                "type": "home",
+                "blocks": blocks,
+                "private_metadata": "",
+                "callback_id": "",
+                "state": { "values": {} },
+                "hash": "1700000000.abcdef",
+                "clear_on_close": false,
+                "notify_on_close": false,
+                "root_view_id": "V01",
+                "app_id": "A01",
+                "bot_id": "B01",
+            },
+        },
+    });
+    assert!(callback.to_string().len() > 5 * 1024);
+
+    let status = bot.post_signed(SIGNING_KEY, &callback).await.status();
+    assert_eq!(StatusCode::OK, status);
+}
+
+#[tokio::test]
+async fn reply_to_unknown_command() {
+    let bot = Bot::start().await;
+    bot.mention("stats since yesterday").await;
+
+    let messages = bot.messages(1).await;
+    assert!(
+        messages[0].starts_with("Unknown command"),
+        "{}",
+        messages[0]
+    );
+}
+
+#[tokio::test]
+async fn add_and_list_schedules() {
+    let bot = Bot::start().await;
+
+    bot.mention("schedule add daily at 08:00").await;
+    // The confirmation is followed by an announcement for the team.
+    let messages = bot.messages(2).await;
+    assert!(messages[0].contains("daily at 08:00"), "{}", messages[0]);
+
+    bot.mention("schedule list").await;
+    let messages = bot.messages(3).await;
+    assert!(
+        messages[2].contains("on Sundays at 10:00"),
+        "{}",
+        messages[2]
+    );
+    assert!(messages[2].contains("daily at 08:00"), "{}", messages[2]);
+
+    bot.state_containing("08:00").await;
+}
+
+#[tokio::test]
+async fn submit_setup_form() {
+    let bot = Bot::start().await;
+
+    let payload = json!({
+        "type": "view_submission",
+        "user": { "id": "U01" },
+        "view": {
+            "callback_id": "schedule",
+            "state": {
+                "values": {
+                    "input": { "input": { "type": "plain_text_input", "value": "daily at 08:00" } },
+                },
+            },
+        },
+    });
+    let body = format!(
+        "payload={}",
+        url::form_urlencoded::byte_serialize(payload.to_string().as_bytes()).collect::<String>()
+    );
+    let status = bot
+        .post_body(SIGNING_KEY, "/interactive", body.into_bytes())
+        .await
+        .status();
+    assert_eq!(StatusCode::OK, status);
+
+    // The response goes to the user directly, only the announcement reaches the team channel.
+    let messages = bot.messages(1).await;
+    assert!(messages[0].contains("daily at 08:00"), "{}", messages[0]);
+    bot.state_containing("08:00").await;
+}
+
+#[tokio::test]
+async fn confirm_destructive_commands() {
+    let bot = Bot::start().await;
+
+    bot.mention("remove him").await;
+    let messages = bot.messages(1).await;
+    assert!(
+        messages[0].contains("do you really want to run `remove him`?"),
+        "{}",
+        messages[0]
+    );
+
+    bot.click("cancel", "Ev01-0").await;
+    let messages = bot.messages(2).await;
+    assert_eq!("<@U01> cancelled `remove him`", messages[1]);
+
+    // The confirmation was answered already.
+    bot.click("confirm", "Ev01-0").await;
+    let messages = bot.messages(3).await;
+    assert!(messages[2].contains("expired"), "{}", messages[2]);
+
+    bot.mention("remove him").await;
+    bot.messages(4).await;
+    bot.click("confirm", "Ev01-0").await;
+    let messages = bot.messages(6).await;
+    assert_eq!("<@U01> confirmed `remove him`", messages[4]);
+    assert_eq!("User `him` is not in the watchlist", messages[5]);
+}
+
+#[tokio::test]
+async fn admin_api() {
+    let bot = Bot::start_with(&[("ADMIN_TOKEN", "e2e-admin-token")]).await;
+
+    let resp = bot.client.get(bot.url("/api/config")).send().await.unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+    let resp = bot
+        .client
+        .put(bot.url("/api/schedule"))
+        .bearer_auth("e2e-admin-token")
+        .json(&json!([{ "weekday": "Mon", "time": "09:30:00" }, { "time": "18:00:00" }]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, resp.status());
+
+    let config = bot
+        .client
+        .get(bot.url("/api/config"))
+        .bearer_auth("e2e-admin-token")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(json!("Mon"), config["schedules"][0]["weekday"]);
+    assert_eq!(json!("18:00:00"), config["schedules"][1]["time"]);
+    assert_eq!(json!([]), config["users"]);
+
+    let resp = bot
+        .client
+        .delete(bot.url("/api/users?username=him"))
+        .bearer_auth("e2e-admin-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, resp.status());
+
+    bot.mention("botstats").await;
+    bot.messages(1).await;
+    let audit = bot
+        .client
+        .get(bot.url("/api/audit?last=1"))
+        .bearer_auth("e2e-admin-token")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(json!("U01"), audit[0]["user"]);
+    assert_eq!(json!("botstats"), audit[0]["command"]);
+    assert_eq!(json!("success"), audit[0]["outcome"]);
+
+    bot.state_containing("09:30").await;
+}
+
+#[tokio::test]
+async fn stats_api() {
+    let bot = Bot::start_with(&[("ADMIN_TOKEN", "e2e-admin-token")]).await;
+
+    let resp = bot.client.get(bot.url("/api/stats")).send().await.unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+    let resp = bot
+        .client
+        .get(bot.url("/api/stats"))
+        .bearer_auth("wrong-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+    let stats = bot
+        .client
+        .get(bot.url("/api/stats?since=2020-01-01&top=3"))
+        .bearer_auth("e2e-admin-token")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(json!({ "top": 3, "users": [], "failed": [] }), stats);
+
+    let resp = bot
+        .client
+        .get(bot.url("/api/stats?since=2020-01-02&until=2020-01-01"))
+        .bearer_auth("e2e-admin-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+}
+
+#[tokio::test]
+async fn optional_endpoints_disabled() {
+    let bot = Bot::start().await;
+
+    for path in ["/api/stats", "/events/stream"] {
+        let resp = bot.client.get(bot.url(path)).send().await.unwrap();
+        assert!(resp.status().is_client_error(), "{path}: {}", resp.status());
+    }
+}
+
+#[test]
+fn fail_report_during_maintenance() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_codewars-bot"))
+        .arg("report")
+        .current_dir(dir.path())
+        .env_clear()
+        .env("SIGNING_KEY", SIGNING_KEY)
+        .env("WEBHOOK_URL", "http://127.0.0.1:9/webhook")
+        .env("STATE_PATH", dir.path().join("state.toml"))
+        .env("MAINTENANCE", "true")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("report skipped"), "{stderr}");
+}
+
+#[tokio::test]
+async fn activity_stream() {
+    let bot = Bot::start_with(&[("EVENT_STREAM", "true")]).await;
+
+    let resp = bot
+        .client
+        .get(bot.url("/events/stream"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, resp.status());
+    assert_eq!(
+        "text/event-stream",
+        resp.headers()[reqwest::header::CONTENT_TYPE]
+    );
+}
+
+#[tokio::test]
+async fn run_several_commands() {
+    let bot = Bot::start().await;
+
+    bot.mention("schedule add daily at 08:00; sched ls").await;
+    // A single summary is followed by the announcement of the new schedule.
+    let messages = bot.messages(2).await;
+    assert!(
+        messages[0].contains("> `schedule add daily at 08:00`"),
+        "{}",
+        messages[0]
+    );
+    assert!(messages[0].contains("> `sched ls`"), "{}", messages[0]);
+    assert!(
+        messages[0].contains("on Sundays at 10:00"),
+        "{}",
+        messages[0]
+    );
+
+    bot.mention("schedule list\nstats since yesterday").await;
+    let messages = bot.messages(3).await;
+    assert!(
+        messages[2].starts_with("Unknown command"),
+        "{}",
+        messages[2]
+    );
+}
+
+#[tokio::test]
+async fn restrict_maintenance_mode() {
+    let bot = Bot::start_with(&[("ADMINS", r#"["U02"]"#)]).await;
+
+    bot.mention("maintenance on").await;
+    bot.mention("schedule list").await;
+    let messages = bot.messages(2).await;
+    assert!(messages[0].contains("only admins"), "{}", messages[0]);
+    assert!(
+        messages[1].contains("on Sundays at 10:00"),
+        "{}",
+        messages[1]
+    );
+}
+
+#[tokio::test]
+async fn restrict_audit_log() {
+    let bot = Bot::start_with(&[("ADMINS", r#"["U02"]"#)]).await;
+
+    bot.mention("audit").await;
+    let messages = bot.messages(1).await;
+    assert!(messages[0].contains("only admins"), "{}", messages[0]);
+
+    let bot = Bot::start_with(&[("ADMINS", r#"["U01"]"#)]).await;
+
+    bot.mention("schedule list").await;
+    bot.mention("audit").await;
+    let messages = bot.messages(2).await;
+    assert!(messages[1].contains("schedule list"), "{}", messages[1]);
+}
+
+#[tokio::test]
+async fn restrict_webhook_commands() {
+    let bot = Bot::start_with(&[("ADMINS", r#"["U02"]"#)]).await;
+
+    bot.mention("set webhook https://hooks.slack.com/services/T000/B000/XXXX")
+        .await;
+    bot.mention("webhook add https://hooks.slack.com/services/T000/B111/YYYY")
+        .await;
+    let messages = bot.messages(2).await;
+    for message in messages {
+        assert!(message.contains("only admins"), "{message}");
+    }
+
+    let bot = Bot::start_with(&[("ADMINS", r#"["U01"]"#)]).await;
+
+    bot.mention("set webhook http://hooks.slack.com/services/T000/B000/XXXX")
+        .await;
+    bot.mention("set webhook https://example.com/services/T000/B000/XXXX")
+        .await;
+    bot.mention("webhook add https://example.com/services/T000/B111/YYYY")
+        .await;
+    let messages = bot.messages(3).await;
+    for message in messages {
+        assert!(message.contains("Only Slack webhook URLs"), "{message}");
+    }
+}